@@ -15,18 +15,45 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
-use std::time::Duration;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use async_std::sync::Arc;
 use async_std::task::{sleep, spawn};
+use serde::{Deserialize, Serialize};
+use tide::{Body, Request, Response, Server};
 
 use crate::broker::{BrokerBuilder, Topic};
-use crate::measurement::{Measurement, Timestamp};
+use crate::measurement::{Measurement, Timestamp, Validity};
+use crate::subsystems::{report, SubsystemStatus};
 
 const HISTORY_LENGTH: usize = 200;
 const SLOW_INTERVAL: Duration = Duration::from_millis(100);
 
+/// Rate at which new values are published to the broker (in samples per
+/// second) unless configured otherwise via the channel's `sample_rate` topic.
+const DEFAULT_SAMPLE_RATE: f32 = 10.0;
+
+/// Default rate for channels that change slowly, like the IOBus supply
+const SLOW_SAMPLE_RATE: f32 = 1.0;
+
+const MIN_SAMPLE_RATE: f32 = 0.1;
+const MAX_SAMPLE_RATE: f32 = 10_000.0;
+
+/// Length of the rolling window the statistics of a channel are calculated
+/// over (in seconds) unless configured otherwise.
+const DEFAULT_STATISTICS_WINDOW: f32 = 10.0;
+const MIN_STATISTICS_WINDOW: f32 = 0.1;
+const MAX_STATISTICS_WINDOW: f32 = 60.0 * 60.0;
+
+/// Minimum time between two updates of the statistics topic of a channel
+const STATISTICS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// ADC values that are older than this mean that the ADC thread has stalled
+/// or died.
+const MAX_AGE: Duration = Duration::from_secs(1);
+
 #[cfg(test)]
 mod iio {
     mod test;
@@ -45,8 +72,82 @@ mod iio {
     pub use hardware::*;
 }
 
+mod filter;
+mod history;
+mod ripple;
+mod samples;
+mod sensors;
+mod statistics;
+
 pub use iio::{CalibratedChannel, IioThread};
 
+use filter::{Filter, FilterConfig};
+use history::{export, ExportFormat};
+pub use history::{Bucket, History, Resolution};
+pub use sensors::Sensor;
+use statistics::{Statistics, Window};
+
+/// Consecutive samples of one or more ADC channels, as read from the ADC
+/// in one go
+#[derive(Clone, Debug)]
+pub struct SampleBlock {
+    /// Time the first sample was taken at
+    pub start: Timestamp,
+    /// Time between two samples
+    pub period: Duration,
+    /// The samples of each channel, in the order they were subscribed in
+    pub values: Vec<Vec<f32>>,
+    /// Whether any of the samples of a channel was out of range
+    pub validity: Vec<Validity>,
+}
+
+impl SampleBlock {
+    /// The number of samples per channel
+    pub fn len(&self) -> usize {
+        self.values.first().map(Vec::len).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The time the `i`-th sample was taken at
+    pub fn ts(&self, i: usize) -> Timestamp {
+        Timestamp::new(self.start.as_instant() + self.period * (i as u32))
+    }
+
+    /// The values of all channels at the `i`-th sample
+    pub fn sample(&self, i: usize) -> impl Iterator<Item = f32> + '_ {
+        self.values.iter().map(move |values| values[i])
+    }
+}
+
+/// The samples of an ADC channel as read from the ADC in one block
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlockSamples {
+    /// Time the first sample was taken at
+    pub ts: Timestamp,
+    /// Time between two samples in seconds
+    pub period: f64,
+    pub values: Vec<f32>,
+    pub validity: Validity,
+}
+
+/// Metadata of an ADC channel, so that clients do not have to hardcode
+/// e.g. that `iobus-curr` is measured in amperes.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ChannelInfo {
+    pub name: String,
+    /// The topic the values of the channel are published at
+    pub topic: String,
+    pub unit: String,
+    /// The physical value of one step of the raw ADC value (if known)
+    pub scale: Option<f32>,
+    /// The range of values the channel is designed to measure
+    pub min: f32,
+    pub max: f32,
+}
+
 /// A reference to an ADC channel.
 ///
 /// The channel can be used in two different ways:
@@ -55,10 +156,331 @@ pub use iio::{CalibratedChannel, IioThread};
 ///   time access to the most recent ADC value.
 /// * The `topic` way uses the tacd broker system and allow you to subscribe
 ///   to a stream of new values.
+///   The rate at which new values are published is configured per channel
+///   via the `sample_rate` topic (in samples per second). The ADC is
+///   sampled at least at that rate and the published values are averages
+///   over `1 / sample_rate` seconds.
+///
+/// Every sample read from the ADC is also published unfiltered in
+/// `block`, one block of samples at a time.
+///
+/// The values can optionally be smoothed by a `filter` before they are
+/// published.
+/// The published values are also recorded in a downsampled `history`,
+/// so that they can be plotted later on.
+/// All samples read from the ADC are summarized in `statistics` over a
+/// rolling window of `statistics_window` seconds.
 #[derive(Clone)]
 pub struct AdcChannel {
     pub fast: CalibratedChannel,
     pub topic: Arc<Topic<Measurement>>,
+    pub block: Arc<Topic<BlockSamples>>,
+    pub info: ChannelInfo,
+    pub sample_rate: Arc<Topic<f32>>,
+    pub filter: Arc<Topic<FilterConfig>>,
+    pub statistics: Arc<Topic<Statistics>>,
+    pub statistics_window: Arc<Topic<f32>>,
+    history: Arc<Mutex<History>>,
+}
+
+impl AdcChannel {
+    fn new(
+        bb: &mut BrokerBuilder,
+        iio_thread: &Arc<IioThread>,
+        name: &str,
+        path: &str,
+        unit: &str,
+        range: (f32, f32),
+        default_rate: f32,
+    ) -> Result<Self> {
+        let fast = iio_thread.clone().get_channel(name)?;
+
+        let info = ChannelInfo {
+            name: name.to_string(),
+            topic: path.to_string(),
+            unit: unit.to_string(),
+            scale: fast.scale(),
+            min: range.0,
+            max: range.1,
+        };
+
+        let this = Self {
+            fast,
+            topic: bb.topic(path, true, false, false, None, HISTORY_LENGTH),
+            block: bb.topic_ro(&format!("/v1/tac/adc/{name}/block"), None),
+            info,
+            sample_rate: bb.topic(
+                &format!("/v1/tac/adc/{name}/sample_rate"),
+                true,
+                true,
+                true,
+                Some(default_rate),
+                1,
+            ),
+            filter: bb.topic(
+                &format!("/v1/tac/adc/{name}/filter"),
+                true,
+                true,
+                true,
+                Some(FilterConfig::None),
+                1,
+            ),
+            statistics: bb.topic_ro(&format!("/v1/tac/adc/{name}/statistics"), None),
+            statistics_window: bb.topic(
+                &format!("/v1/tac/adc/{name}/statistics/window"),
+                true,
+                true,
+                true,
+                Some(DEFAULT_STATISTICS_WINDOW),
+                1,
+            ),
+            history: Arc::new(Mutex::new(History::new())),
+        };
+
+        this.publish();
+        this.publish_blocks();
+
+        Ok(this)
+    }
+
+    /// Spawn an async task that publishes every sample of the channel in
+    /// the blocks they are read from the ADC in.
+    fn publish_blocks(&self) {
+        let blocks = CalibratedChannel::subscribe(&[self.fast.clone()]);
+        let topic = self.block.clone();
+
+        spawn(async move {
+            while let Ok(mut block) = blocks.recv().await {
+                if let (Some(values), Some(validity)) = (block.values.pop(), block.validity.pop()) {
+                    topic.set(BlockSamples {
+                        ts: block.start,
+                        period: block.period.as_secs_f64(),
+                        values,
+                        validity,
+                    });
+                }
+            }
+        });
+    }
+
+    /// Spawn an async task to average the samples read from the ADC over
+    /// intervals of the configured rate and publish them to the broker
+    /// based "slow" interface.
+    fn publish(&self) {
+        let channel = self.clone();
+        let blocks = CalibratedChannel::subscribe(&[self.fast.clone()]);
+
+        spawn(async move {
+            let mut rate = None;
+            let mut average = Average::new();
+            let mut filter = Filter::new();
+            let mut window = Window::new();
+            let mut statistics_published = Instant::now();
+
+            while let Ok(block) = blocks.recv().await {
+                // Changes to the rate take effect with the next block
+                let new_rate = channel
+                    .sample_rate
+                    .try_get()
+                    .filter(|rate| !rate.is_nan())
+                    .unwrap_or(DEFAULT_SAMPLE_RATE)
+                    .clamp(MIN_SAMPLE_RATE, MAX_SAMPLE_RATE);
+
+                if rate != Some(new_rate) {
+                    channel.fast.set_rate(new_rate);
+                    rate = Some(new_rate);
+                }
+
+                let interval = Duration::from_secs_f32(1.0 / new_rate);
+
+                let window_length = channel
+                    .statistics_window
+                    .try_get()
+                    .filter(|len| !len.is_nan())
+                    .unwrap_or(DEFAULT_STATISTICS_WINDOW)
+                    .clamp(MIN_STATISTICS_WINDOW, MAX_STATISTICS_WINDOW);
+                let window_length = Duration::from_secs_f32(window_length);
+
+                for (i, value) in block.values[0].iter().enumerate() {
+                    // The statistics cover every sample read from the ADC,
+                    // not just the averages that are published.
+                    window.add(block.ts(i).as_instant(), *value, window_length);
+
+                    let mut meas =
+                        match average.add(block.ts(i), *value, block.validity[0], interval) {
+                            Some(meas) => meas,
+                            None => continue,
+                        };
+
+                    // Filter before publishing, so that all consumers (e.g.
+                    // alarms, history and the web interface) see the same
+                    // values.
+                    let config = channel.filter.try_get().unwrap_or(FilterConfig::None);
+                    meas.value = filter.apply(config, meas.ts.as_instant(), meas.value);
+
+                    channel.history.lock().unwrap().add(&meas);
+                    channel.topic.set(meas);
+                }
+
+                // Do not flood the statistics topic at high sample rates
+                if statistics_published.elapsed() >= STATISTICS_INTERVAL {
+                    if let Some(statistics) = window.statistics() {
+                        channel.statistics.set(statistics);
+                    }
+
+                    statistics_published = Instant::now();
+                }
+            }
+        });
+    }
+}
+
+/// The average of the samples of a channel over consecutive intervals
+struct Average {
+    start: Option<Instant>,
+    last: Option<Timestamp>,
+    sum: f64,
+    count: u32,
+    validity: Validity,
+}
+
+impl Average {
+    fn new() -> Self {
+        Self {
+            start: None,
+            last: None,
+            sum: 0.0,
+            count: 0,
+            validity: Validity::Valid,
+        }
+    }
+
+    /// Add a sample and return the average of the previous interval if the
+    /// sample is more than `interval` newer than the first one in it.
+    ///
+    /// Intervals without samples are skipped, so that rates above what
+    /// the ADC provides do not result in duplicate values.
+    fn add(
+        &mut self,
+        ts: Timestamp,
+        value: f32,
+        validity: Validity,
+        interval: Duration,
+    ) -> Option<Measurement> {
+        let done = self
+            .start
+            .map(|start| ts.saturating_duration_since(start) >= interval)
+            .unwrap_or(false);
+
+        let finished = match (done, self.last) {
+            (true, Some(last)) => {
+                let meas = Measurement {
+                    ts: last,
+                    value: (self.sum / (self.count as f64)) as f32,
+                    validity: self.validity,
+                };
+
+                *self = Self::new();
+
+                Some(meas)
+            }
+            _ => None,
+        };
+
+        self.start.get_or_insert(ts.as_instant());
+        self.last = Some(ts);
+        self.sum += value as f64;
+        self.count += 1;
+
+        if validity != Validity::Valid {
+            self.validity = validity;
+        }
+
+        finished
+    }
+}
+
+/// Voltage and current of a supply rail that were sampled at the same time,
+/// and the power calculated from them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct RailMeasurement {
+    pub ts: Timestamp,
+    pub voltage: f32,
+    pub current: f32,
+    pub power: f32,
+}
+
+/// Publish the voltage and current of a supply rail in a single topic.
+///
+/// Multiplying the values of two independently published topics gives
+/// garbage during transients, as they are sampled at different points in
+/// time. The values in the rail topic are instead read from the ADC thread
+/// in one go and share a single timestamp.
+/// The rate follows the `sample_rate` topic of the `current` channel.
+fn publish_rail(bb: &mut BrokerBuilder, path: &str, voltage: &AdcChannel, current: &AdcChannel) {
+    let topic = bb.topic(path, true, false, false, None, HISTORY_LENGTH);
+    let voltage = voltage.clone();
+    let current = current.clone();
+
+    spawn(async move {
+        let mut prev_ts = None;
+
+        loop {
+            let rate = current
+                .sample_rate
+                .try_get()
+                .filter(|rate| !rate.is_nan())
+                .unwrap_or(DEFAULT_SAMPLE_RATE)
+                .clamp(MIN_SAMPLE_RATE, MAX_SAMPLE_RATE);
+
+            sleep(Duration::from_secs_f32(1.0 / rate)).await;
+
+            // The values are only returned if the ADC thread did not update
+            // them while they were read, which should almost never happen.
+            // Just skip this tick if it did.
+            let [volt, curr] = match voltage
+                .fast
+                .try_get_multiple([&voltage.fast, &current.fast])
+            {
+                Some(values) => values,
+                None => continue,
+            };
+
+            let ts = Some(volt.ts.as_instant());
+
+            if ts != prev_ts {
+                topic.set(RailMeasurement {
+                    ts: volt.ts,
+                    voltage: volt.value,
+                    current: curr.value,
+                    power: volt.value * curr.value,
+                });
+
+                prev_ts = ts;
+            }
+        }
+    });
+}
+
+/// Query parameters for the history endpoint.
+/// `from` and `to` are given in seconds since the unix epoch.
+#[derive(Deserialize)]
+struct HistoryQuery {
+    resolution: Option<Resolution>,
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+/// Query parameters for the export endpoint.
+/// `channels` is a comma separated list of channel names and defaults to
+/// all channels.
+#[derive(Deserialize)]
+struct ExportQuery {
+    channels: Option<String>,
+    format: Option<ExportFormat>,
+    resolution: Option<Resolution>,
+    from: Option<u64>,
+    to: Option<u64>,
 }
 
 #[derive(Clone)]
@@ -73,174 +495,324 @@ pub struct Adc {
     pub iobus_volt: AdcChannel,
     pub pwr_volt: AdcChannel,
     pub pwr_curr: AdcChannel,
+    /// Additional channels declared in the external sensor config file
+    pub sensors: Vec<Sensor>,
     pub time: Arc<Topic<Timestamp>>,
+    pub status: Arc<Topic<SubsystemStatus>>,
 }
 
 impl Adc {
     pub async fn new(bb: &mut BrokerBuilder) -> Result<Self> {
         let iio_thread = IioThread::new().await?;
 
-        let adc = Self {
-            usb_host_curr: AdcChannel {
-                fast: iio_thread.clone().get_channel("usb-host-curr").unwrap(),
-                topic: bb.topic(
-                    "/v1/usb/host/total/feedback/current",
-                    true,
-                    false,
-                    false,
-                    None,
-                    HISTORY_LENGTH,
-                ),
-            },
-            usb_host1_curr: AdcChannel {
-                fast: iio_thread.clone().get_channel("usb-host1-curr").unwrap(),
-                topic: bb.topic(
-                    "/v1/usb/host/port1/feedback/current",
-                    true,
-                    false,
-                    false,
-                    None,
-                    HISTORY_LENGTH,
-                ),
-            },
-            usb_host2_curr: AdcChannel {
-                fast: iio_thread.clone().get_channel("usb-host2-curr").unwrap(),
-                topic: bb.topic(
-                    "/v1/usb/host/port2/feedback/current",
-                    true,
-                    false,
-                    false,
-                    None,
-                    HISTORY_LENGTH,
-                ),
-            },
-            usb_host3_curr: AdcChannel {
-                fast: iio_thread.clone().get_channel("usb-host3-curr").unwrap(),
-                topic: bb.topic(
-                    "/v1/usb/host/port3/feedback/current",
-                    true,
-                    false,
-                    false,
-                    None,
-                    HISTORY_LENGTH,
-                ),
-            },
-            out0_volt: AdcChannel {
-                fast: iio_thread.clone().get_channel("out0-volt").unwrap(),
-                topic: bb.topic(
-                    "/v1/output/out_0/feedback/voltage",
-                    true,
-                    false,
-                    false,
-                    None,
-                    HISTORY_LENGTH,
-                ),
-            },
-            out1_volt: AdcChannel {
-                fast: iio_thread.clone().get_channel("out1-volt").unwrap(),
-                topic: bb.topic(
-                    "/v1/output/out_1/feedback/voltage",
-                    true,
-                    false,
-                    false,
-                    None,
-                    HISTORY_LENGTH,
-                ),
-            },
-            iobus_curr: AdcChannel {
-                fast: iio_thread.clone().get_channel("iobus-curr").unwrap(),
-                topic: bb.topic(
-                    "/v1/iobus/feedback/current",
-                    true,
-                    false,
-                    false,
-                    None,
-                    HISTORY_LENGTH,
-                ),
-            },
-            iobus_volt: AdcChannel {
-                fast: iio_thread.clone().get_channel("iobus-volt").unwrap(),
-                topic: bb.topic(
-                    "/v1/iobus/feedback/voltage",
-                    true,
-                    false,
-                    false,
-                    None,
-                    HISTORY_LENGTH,
-                ),
-            },
-            pwr_volt: AdcChannel {
-                fast: iio_thread.clone().get_channel("pwr-volt").unwrap(),
-                topic: bb.topic(
-                    "/v1/dut/feedback/voltage",
-                    true,
-                    false,
-                    false,
-                    None,
-                    HISTORY_LENGTH,
-                ),
-            },
-            pwr_curr: AdcChannel {
-                fast: iio_thread.get_channel("pwr-curr").unwrap(),
-                topic: bb.topic(
-                    "/v1/dut/feedback/current",
-                    true,
-                    false,
-                    false,
-                    None,
-                    HISTORY_LENGTH,
-                ),
-            },
+        let mut adc = Self {
+            usb_host_curr: AdcChannel::new(
+                bb,
+                &iio_thread,
+                "usb-host-curr",
+                "/v1/usb/host/total/feedback/current",
+                "A",
+                (0.0, 0.7),
+                DEFAULT_SAMPLE_RATE,
+            )?,
+            usb_host1_curr: AdcChannel::new(
+                bb,
+                &iio_thread,
+                "usb-host1-curr",
+                "/v1/usb/host/port1/feedback/current",
+                "A",
+                (0.0, 0.5),
+                DEFAULT_SAMPLE_RATE,
+            )?,
+            usb_host2_curr: AdcChannel::new(
+                bb,
+                &iio_thread,
+                "usb-host2-curr",
+                "/v1/usb/host/port2/feedback/current",
+                "A",
+                (0.0, 0.5),
+                DEFAULT_SAMPLE_RATE,
+            )?,
+            usb_host3_curr: AdcChannel::new(
+                bb,
+                &iio_thread,
+                "usb-host3-curr",
+                "/v1/usb/host/port3/feedback/current",
+                "A",
+                (0.0, 0.5),
+                DEFAULT_SAMPLE_RATE,
+            )?,
+            out0_volt: AdcChannel::new(
+                bb,
+                &iio_thread,
+                "out0-volt",
+                "/v1/output/out_0/feedback/voltage",
+                "V",
+                (-5.0, 5.0),
+                DEFAULT_SAMPLE_RATE,
+            )?,
+            out1_volt: AdcChannel::new(
+                bb,
+                &iio_thread,
+                "out1-volt",
+                "/v1/output/out_1/feedback/voltage",
+                "V",
+                (-5.0, 5.0),
+                DEFAULT_SAMPLE_RATE,
+            )?,
+            iobus_curr: AdcChannel::new(
+                bb,
+                &iio_thread,
+                "iobus-curr",
+                "/v1/iobus/feedback/current",
+                "A",
+                (0.0, 0.2),
+                SLOW_SAMPLE_RATE,
+            )?,
+            iobus_volt: AdcChannel::new(
+                bb,
+                &iio_thread,
+                "iobus-volt",
+                "/v1/iobus/feedback/voltage",
+                "V",
+                (0.0, 15.0),
+                SLOW_SAMPLE_RATE,
+            )?,
+            pwr_volt: AdcChannel::new(
+                bb,
+                &iio_thread,
+                "pwr-volt",
+                "/v1/dut/feedback/voltage",
+                "V",
+                (0.0, 48.0),
+                DEFAULT_SAMPLE_RATE,
+            )?,
+            pwr_curr: AdcChannel::new(
+                bb,
+                &iio_thread,
+                "pwr-curr",
+                "/v1/dut/feedback/current",
+                "A",
+                (0.0, 5.0),
+                DEFAULT_SAMPLE_RATE,
+            )?,
+            sensors: Vec::new(),
             time: bb.topic_ro("/v1/tac/time/now", None),
+            status: bb.topic_ro("/v1/tac/subsystems/adc", None),
         };
 
+        publish_rail(bb, "/v1/dut/feedback/power", &adc.pwr_volt, &adc.pwr_curr);
+        publish_rail(
+            bb,
+            "/v1/iobus/feedback/power",
+            &adc.iobus_volt,
+            &adc.iobus_curr,
+        );
+
+        // Allow clients to discover the available channels and their units
+        let infos: Vec<ChannelInfo> = adc
+            .channels()
+            .iter()
+            .map(|(_, channel)| channel.info.clone())
+            .collect();
+        bb.topic_ro("/v1/tac/adc/channels", Some(infos));
+
+        // Analyze the power quality on channels selected via the API
+        ripple::setup(
+            bb,
+            adc.channels()
+                .iter()
+                .map(|(name, channel)| (*name, channel.fast.clone()))
+                .collect(),
+        );
+
+        let reserved_names: Vec<&str> = adc.channels().iter().map(|(name, _)| *name).collect();
+        adc.sensors = sensors::setup(bb, &reserved_names);
+
         let adc_clone = adc.clone();
 
-        // Spawn an async task to transfer values from the Atomic value based
-        // "fast" interface to the broker based "slow" interface.
+        // Spawn an async task that keeps the time topic current and checks
+        // that the ADC thread is still providing new values.
         spawn(async move {
             loop {
                 sleep(SLOW_INTERVAL).await;
 
-                adc_clone
-                    .usb_host_curr
-                    .topic
-                    .set(adc_clone.usb_host_curr.fast.get());
-                adc_clone
-                    .usb_host1_curr
-                    .topic
-                    .set(adc_clone.usb_host1_curr.fast.get());
-                adc_clone
-                    .usb_host2_curr
-                    .topic
-                    .set(adc_clone.usb_host2_curr.fast.get());
-                adc_clone
-                    .usb_host3_curr
-                    .topic
-                    .set(adc_clone.usb_host3_curr.fast.get());
-                adc_clone
-                    .out0_volt
-                    .topic
-                    .set(adc_clone.out0_volt.fast.get());
-                adc_clone
-                    .out1_volt
-                    .topic
-                    .set(adc_clone.out1_volt.fast.get());
-                adc_clone
-                    .iobus_curr
-                    .topic
-                    .set(adc_clone.iobus_curr.fast.get());
-                adc_clone
-                    .iobus_volt
-                    .topic
-                    .set(adc_clone.iobus_volt.fast.get());
-                adc_clone.pwr_volt.topic.set(adc_clone.pwr_volt.fast.get());
-                adc_clone.pwr_curr.topic.set(adc_clone.pwr_curr.fast.get());
-
                 adc_clone.time.set(Timestamp::now());
+
+                // The values keep their timestamp if the ADC thread
+                // is no longer updating them.
+                let status = match adc_clone.pwr_volt.fast.get().ts.elapsed() > MAX_AGE {
+                    true => SubsystemStatus::failed("No new ADC values"),
+                    false => SubsystemStatus::Ok,
+                };
+
+                report(&adc_clone.status, status);
             }
         });
 
         Ok(adc)
     }
+
+    pub fn channels(&self) -> [(&'static str, &AdcChannel); 10] {
+        [
+            ("usb-host-curr", &self.usb_host_curr),
+            ("usb-host1-curr", &self.usb_host1_curr),
+            ("usb-host2-curr", &self.usb_host2_curr),
+            ("usb-host3-curr", &self.usb_host3_curr),
+            ("out0-volt", &self.out0_volt),
+            ("out1-volt", &self.out1_volt),
+            ("iobus-curr", &self.iobus_curr),
+            ("iobus-volt", &self.iobus_volt),
+            ("pwr-volt", &self.pwr_volt),
+            ("pwr-curr", &self.pwr_curr),
+        ]
+    }
+
+    /// The histories of the ADC channels and external sensors by name and
+    /// with the path of the endpoint to query them
+    pub fn histories(&self) -> Vec<(String, String, Arc<Mutex<History>>)> {
+        let mut histories: Vec<_> = self
+            .channels()
+            .iter()
+            .map(|(name, channel)| {
+                (
+                    name.to_string(),
+                    format!("/v1/tac/adc/{name}/history"),
+                    channel.history.clone(),
+                )
+            })
+            .collect();
+
+        histories.extend(self.sensors.iter().map(|sensor| {
+            (
+                sensor.name.clone(),
+                format!("/v1/tac/sensors/{}/history", sensor.name),
+                sensor.history.clone(),
+            )
+        }));
+
+        histories
+    }
+
+    /// Allow querying the recorded history of the channels, so that the web
+    /// interface can plot values from before the page was opened.
+    ///
+    /// `extra` are the histories of measurements from outside of the ADC,
+    /// like temperatures, that should be served the same way.
+    /// `aliases` are additional paths to serve histories from, that are not
+    /// part of the export.
+    pub fn serve_history(
+        &self,
+        server: &mut Server<()>,
+        extra: Vec<(String, String, Arc<Mutex<History>>)>,
+        aliases: Vec<(String, Arc<Mutex<History>>)>,
+    ) {
+        let mut histories = self.histories();
+        histories.extend(extra);
+
+        let endpoints = histories
+            .iter()
+            .map(|(_, path, history)| (path.clone(), history.clone()))
+            .chain(aliases);
+
+        for (path, history) in endpoints {
+            server.at(&path).get(move |req: Request<()>| {
+                let history = history.clone();
+
+                async move {
+                    let query: HistoryQuery = match req.query() {
+                        Ok(query) => query,
+                        Err(e) => {
+                            return Ok(Response::builder(400)
+                                .body(format!("Failed to parse query parameters: {e}"))
+                                .build())
+                        }
+                    };
+
+                    let buckets = history.lock().unwrap().query(
+                        query.resolution.unwrap_or(Resolution::Second),
+                        query.from.unwrap_or(0),
+                        query.to.unwrap_or(u64::MAX),
+                    );
+
+                    Ok(Response::builder(200)
+                        .body(Body::from_json(&buckets)?)
+                        .build())
+                }
+            });
+        }
+
+        // Allow downloading the history of multiple channels at once as a
+        // file that can be opened in e.g. a spreadsheet.
+        let histories: Vec<(String, Arc<Mutex<History>>)> = histories
+            .into_iter()
+            .map(|(name, _, history)| (name, history))
+            .collect();
+
+        server
+            .at("/v1/tac/adc/export")
+            .get(move |req: Request<()>| {
+                let histories = histories.clone();
+
+                async move {
+                    let query: ExportQuery = match req.query() {
+                        Ok(query) => query,
+                        Err(e) => {
+                            return Ok(Response::builder(400)
+                                .body(format!("Failed to parse query parameters: {e}"))
+                                .build())
+                        }
+                    };
+
+                    let names: Vec<&str> = match &query.channels {
+                        Some(channels) => channels.split(',').map(str::trim).collect(),
+                        None => histories.iter().map(|(name, _)| name.as_str()).collect(),
+                    };
+
+                    let mut series = Vec::new();
+
+                    for name in names {
+                        let history = match histories.iter().find(|(n, _)| *n == name) {
+                            Some((_, history)) => history,
+                            None => {
+                                return Ok(Response::builder(400)
+                                    .body(format!("Unknown channel \"{name}\""))
+                                    .build())
+                            }
+                        };
+
+                        let buckets = history.lock().unwrap().query(
+                            query.resolution.unwrap_or(Resolution::Second),
+                            query.from.unwrap_or(0),
+                            query.to.unwrap_or(u64::MAX),
+                        );
+
+                        series.push((name, buckets));
+                    }
+
+                    let format = query.format.unwrap_or(ExportFormat::Csv);
+                    let disposition = format!(
+                        "attachment; filename=\"tac-measurements.{}\"",
+                        format.extension()
+                    );
+
+                    Ok(Response::builder(200)
+                        .body(export(&series, format))
+                        .content_type(format.content_type())
+                        .header("Content-Disposition", disposition)
+                        .build())
+                }
+            });
+    }
+
+    /// Allow streaming the raw samples of the channels via a websocket
+    pub fn serve_samples(&self, server: &mut Server<()>) {
+        let channels = self
+            .channels()
+            .iter()
+            .map(|(name, channel)| (*name, channel.fast.clone()))
+            .collect();
+
+        samples::serve(server, channels);
+    }
 }
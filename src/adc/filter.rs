@@ -0,0 +1,138 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+pub const MAX_AVERAGE_SAMPLES: u32 = 1000;
+pub const MIN_CUTOFF: f32 = 0.001;
+
+/// How the values of an ADC channel are filtered before they are published
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum FilterConfig {
+    None,
+    /// The mean of the last `samples` values
+    MovingAverage {
+        samples: u32,
+    },
+    /// A single pole low-pass filter with a `cutoff` frequency in Hz
+    LowPass {
+        cutoff: f32,
+    },
+}
+
+/// The state of the filter of a channel.
+///
+/// The state is reset whenever the filter config changes, so that values
+/// filtered with the old config do not leak into the new one.
+pub struct Filter {
+    config: FilterConfig,
+    samples: VecDeque<f32>,
+    prev: Option<(Instant, f32)>,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self {
+            config: FilterConfig::None,
+            samples: VecDeque::new(),
+            prev: None,
+        }
+    }
+
+    pub fn apply(&mut self, config: FilterConfig, ts: Instant, value: f32) -> f32 {
+        if config != self.config {
+            self.config = config;
+            self.samples.clear();
+            self.prev = None;
+        }
+
+        match config {
+            FilterConfig::None => value,
+            FilterConfig::MovingAverage { samples } => {
+                let len = samples.clamp(1, MAX_AVERAGE_SAMPLES) as usize;
+
+                self.samples.push_back(value);
+
+                while self.samples.len() > len {
+                    self.samples.pop_front();
+                }
+
+                self.samples.iter().sum::<f32>() / (self.samples.len() as f32)
+            }
+            FilterConfig::LowPass { cutoff } => {
+                let filtered = match self.prev {
+                    Some((prev_ts, prev_value)) => {
+                        // The samples do not arrive at a fixed rate, so the
+                        // filter coefficient is calculated for every sample.
+                        let dt = ts.saturating_duration_since(prev_ts).as_secs_f32();
+                        let alpha = 1.0 - (-2.0 * PI * cutoff.max(MIN_CUTOFF) * dt).exp();
+
+                        prev_value + alpha * (value - prev_value)
+                    }
+                    None => value,
+                };
+
+                self.prev = Some((ts, filtered));
+
+                filtered
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Filter, FilterConfig};
+
+    #[test]
+    fn moving_average() {
+        let config = FilterConfig::MovingAverage { samples: 2 };
+        let ts = Instant::now();
+        let mut filter = Filter::new();
+
+        assert_eq!(filter.apply(config, ts, 1.0), 1.0);
+        assert_eq!(filter.apply(config, ts, 3.0), 2.0);
+        assert_eq!(filter.apply(config, ts, 5.0), 4.0);
+
+        // Changing the config starts over
+        assert_eq!(filter.apply(FilterConfig::None, ts, 7.0), 7.0);
+        assert_eq!(filter.apply(config, ts, 1.0), 1.0);
+    }
+
+    #[test]
+    fn low_pass() {
+        let config = FilterConfig::LowPass { cutoff: 1.0 };
+        let start = Instant::now();
+        let mut filter = Filter::new();
+
+        assert_eq!(filter.apply(config, start, 0.0), 0.0);
+
+        // A step is followed slowly ...
+        let first = filter.apply(config, start + Duration::from_millis(10), 1.0);
+        assert!(first > 0.0 && first < 0.1);
+
+        // ... but eventually reached
+        let settled = filter.apply(config, start + Duration::from_secs(10), 1.0);
+        assert!((settled - 1.0).abs() < 1e-3);
+    }
+}
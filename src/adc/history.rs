@@ -0,0 +1,419 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use chrono::{SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::measurement::Measurement;
+
+/// The bucket sizes the history of an ADC channel is kept in
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Resolution {
+    Second,
+    Minute,
+    Hour,
+}
+
+impl Resolution {
+    const ALL: [Self; 3] = [Self::Second, Self::Minute, Self::Hour];
+
+    /// The time span covered by a single bucket
+    fn secs(&self) -> u64 {
+        match self {
+            Self::Second => 1,
+            Self::Minute => 60,
+            Self::Hour => 60 * 60,
+        }
+    }
+
+    /// The number of buckets to keep.
+    /// This amounts to an hour of seconds, a day of minutes and
+    /// a month of hours.
+    fn capacity(&self) -> usize {
+        match self {
+            Self::Second => 60 * 60,
+            Self::Minute => 24 * 60,
+            Self::Hour => 30 * 24,
+        }
+    }
+}
+
+/// Summary of the values of an ADC channel in a time span
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Bucket {
+    /// Start of the time span in seconds since the unix epoch
+    pub start: u64,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub count: u32,
+}
+
+impl Bucket {
+    fn new(start: u64, value: f32) -> Self {
+        Self {
+            start,
+            min: value,
+            max: value,
+            mean: value,
+            count: 1,
+        }
+    }
+
+    fn add(&mut self, value: f32) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.mean += (value - self.mean) / (self.count as f32);
+    }
+
+    fn merge(&mut self, other: &Bucket) {
+        let count = self.count + other.count;
+
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.mean =
+            (self.mean * self.count as f32 + other.mean * other.count as f32) / (count as f32);
+        self.count = count;
+    }
+}
+
+/// File formats the history can be exported in
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv",
+            Self::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportRow<'a> {
+    time: String,
+    channel: &'a str,
+    min: f32,
+    max: f32,
+    mean: f32,
+    count: u32,
+}
+
+/// Export the buckets of multiple channels sorted by time, with one row per
+/// bucket and channel and RFC 3339 timestamps.
+pub fn export(series: &[(&str, Vec<Bucket>)], format: ExportFormat) -> String {
+    let mut rows: Vec<(&str, &Bucket)> = series
+        .iter()
+        .flat_map(|(channel, buckets)| buckets.iter().map(move |bucket| (*channel, bucket)))
+        .collect();
+
+    // The sort is stable, so the channels stay in the requested order
+    rows.sort_by_key(|(_, bucket)| bucket.start);
+
+    let mut content = match format {
+        ExportFormat::Csv => "time,channel,min,max,mean,count\n".to_string(),
+        ExportFormat::Ndjson => String::new(),
+    };
+
+    for (channel, bucket) in rows {
+        let time = Utc
+            .timestamp_opt(bucket.start as i64, 0)
+            .single()
+            .map(|ts| ts.to_rfc3339_opts(SecondsFormat::Secs, true))
+            .unwrap_or_default();
+
+        let row = ExportRow {
+            time,
+            channel,
+            min: bucket.min,
+            max: bucket.max,
+            mean: bucket.mean,
+            count: bucket.count,
+        };
+
+        let line = match format {
+            ExportFormat::Csv => format!(
+                "{},{},{},{},{},{}",
+                row.time, row.channel, row.min, row.max, row.mean, row.count
+            ),
+            ExportFormat::Ndjson => serde_json::to_string(&row).unwrap(),
+        };
+
+        content.push_str(&line);
+        content.push('\n');
+    }
+
+    content
+}
+
+struct Ring {
+    resolution: Resolution,
+    buckets: VecDeque<Bucket>,
+}
+
+impl Ring {
+    fn new(resolution: Resolution) -> Self {
+        Self {
+            resolution,
+            buckets: VecDeque::new(),
+        }
+    }
+
+    fn add(&mut self, secs: u64, value: f32) {
+        let start = secs - secs % self.resolution.secs();
+
+        match self.buckets.back_mut() {
+            Some(bucket) if bucket.start == start => bucket.add(value),
+            _ => {
+                // The system time went backwards, e.g. because it was set via
+                // NTP. Start over instead of dropping all values until the
+                // time has caught up again.
+                if matches!(self.buckets.back(), Some(bucket) if bucket.start > start) {
+                    self.buckets.clear();
+                }
+
+                self.buckets.push_back(Bucket::new(start, value));
+
+                if self.buckets.len() > self.resolution.capacity() {
+                    self.buckets.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Merge a bucket of the same or a finer resolution into the ring.
+    /// Unlike `add()` this also works for buckets from the past.
+    fn insert(&mut self, bucket: &Bucket) {
+        let start = bucket.start - bucket.start % self.resolution.secs();
+
+        match self.buckets.binary_search_by_key(&start, |b| b.start) {
+            Ok(idx) => self.buckets[idx].merge(bucket),
+            Err(idx) => {
+                let mut bucket = *bucket;
+                bucket.start = start;
+                self.buckets.insert(idx, bucket);
+
+                if self.buckets.len() > self.resolution.capacity() {
+                    self.buckets.pop_front();
+                }
+            }
+        }
+    }
+}
+
+/// The history of an ADC channel at multiple resolutions, so that long
+/// time spans can be queried without having to keep every single value.
+pub struct History {
+    rings: [Ring; 3],
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            rings: Resolution::ALL.map(Ring::new),
+        }
+    }
+
+    fn add_at(&mut self, secs: u64, value: f32) {
+        for ring in self.rings.iter_mut() {
+            ring.add(secs, value);
+        }
+    }
+
+    pub fn add(&mut self, meas: &Measurement) {
+        if let Ok(ts) = meas
+            .ts
+            .in_system_time()
+            .duration_since(SystemTime::UNIX_EPOCH)
+        {
+            self.add_at(ts.as_secs(), meas.value);
+        }
+    }
+
+    /// Re-add minute buckets that were e.g. loaded from disk after a restart
+    /// to the minute and hour resolution histories.
+    pub fn restore(&mut self, bucket: &Bucket) {
+        for ring in self.rings.iter_mut() {
+            if ring.resolution.secs() >= Resolution::Minute.secs() {
+                ring.insert(bucket);
+            }
+        }
+    }
+
+    /// Get the buckets that overlap the time span from `from` to `to`
+    /// (in seconds since the unix epoch)
+    pub fn query(&self, resolution: Resolution, from: u64, to: u64) -> Vec<Bucket> {
+        let ring = self
+            .rings
+            .iter()
+            .find(|ring| ring.resolution == resolution)
+            .unwrap();
+
+        ring.buckets
+            .iter()
+            .filter(|bucket| bucket.start + resolution.secs() > from && bucket.start <= to)
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export, Bucket, ExportFormat, History, Resolution};
+
+    #[test]
+    fn buckets() {
+        let mut history = History::new();
+
+        history.add_at(119, 1.0);
+        history.add_at(120, 2.0);
+        history.add_at(120, 4.0);
+        history.add_at(185, 6.0);
+
+        let seconds = history.query(Resolution::Second, 0, u64::MAX);
+        assert_eq!(seconds.len(), 3);
+        assert_eq!(seconds[1].start, 120);
+        assert_eq!(seconds[1].count, 2);
+        assert_eq!(seconds[1].mean, 3.0);
+
+        let minutes = history.query(Resolution::Minute, 0, u64::MAX);
+        assert_eq!(minutes.len(), 3);
+        assert_eq!(minutes[0].start, 60);
+        assert_eq!(minutes[1].start, 120);
+        assert_eq!(minutes[1].min, 2.0);
+        assert_eq!(minutes[1].max, 4.0);
+
+        let hours = history.query(Resolution::Hour, 0, u64::MAX);
+        assert_eq!(hours.len(), 1);
+        assert_eq!(hours[0].count, 4);
+        assert!((hours[0].mean - 3.25).abs() < 1e-6);
+
+        // Buckets that only partially overlap the range are included
+        let range = history.query(Resolution::Minute, 150, 170);
+        assert_eq!(range.len(), 1);
+        assert_eq!(range[0].start, 120);
+
+        // Values from the past start the history over instead of being
+        // mixed in
+        history.add_at(10, 100.0);
+        let seconds = history.query(Resolution::Second, 0, u64::MAX);
+        assert_eq!(seconds.len(), 1);
+        assert_eq!(seconds[0].start, 10);
+
+        history.add_at(11, 101.0);
+        assert_eq!(history.query(Resolution::Second, 0, u64::MAX).len(), 2);
+        assert_eq!(history.query(Resolution::Hour, 0, u64::MAX).len(), 1);
+    }
+
+    #[test]
+    fn restore() {
+        let mut history = History::new();
+
+        // Values that were added since the start are kept when restoring
+        // older buckets
+        history.add_at(3630, 4.0);
+
+        let bucket = |start, mean, count| Bucket {
+            start,
+            min: mean,
+            max: mean,
+            mean,
+            count,
+        };
+
+        history.restore(&bucket(3540, 1.0, 1));
+        history.restore(&bucket(3600, 2.0, 3));
+
+        assert_eq!(history.query(Resolution::Second, 0, u64::MAX).len(), 1);
+
+        let minutes = history.query(Resolution::Minute, 0, u64::MAX);
+        assert_eq!(minutes.len(), 2);
+        assert_eq!(minutes[0].start, 3540);
+        assert_eq!(minutes[1].start, 3600);
+        assert_eq!(minutes[1].count, 4);
+        assert_eq!(minutes[1].min, 2.0);
+        assert_eq!(minutes[1].max, 4.0);
+        assert_eq!(minutes[1].mean, 2.5);
+
+        let hours = history.query(Resolution::Hour, 0, u64::MAX);
+        assert_eq!(hours.len(), 2);
+        assert_eq!(hours[0].start, 0);
+        assert_eq!(hours[1].start, 3600);
+    }
+
+    #[test]
+    fn capacity() {
+        let mut history = History::new();
+
+        for secs in 0..(2 * 60 * 60) {
+            history.add_at(secs, 0.0);
+        }
+
+        let seconds = history.query(Resolution::Second, 0, u64::MAX);
+        assert_eq!(seconds.len(), 60 * 60);
+        assert_eq!(seconds[0].start, 60 * 60);
+        assert_eq!(history.query(Resolution::Minute, 0, u64::MAX).len(), 120);
+    }
+
+    #[test]
+    fn export_formats() {
+        let bucket = |start, mean| Bucket {
+            start,
+            min: mean,
+            max: mean,
+            mean,
+            count: 1,
+        };
+
+        let series = [
+            ("pwr-volt", vec![bucket(60, 12.0), bucket(120, 11.5)]),
+            ("pwr-curr", vec![bucket(60, 0.5)]),
+        ];
+
+        assert_eq!(
+            export(&series, ExportFormat::Csv),
+            "time,channel,min,max,mean,count\n\
+             1970-01-01T00:01:00Z,pwr-volt,12,12,12,1\n\
+             1970-01-01T00:01:00Z,pwr-curr,0.5,0.5,0.5,1\n\
+             1970-01-01T00:02:00Z,pwr-volt,11.5,11.5,11.5,1\n"
+        );
+
+        let ndjson = export(&series, ExportFormat::Ndjson);
+        let first = ndjson.lines().next().unwrap();
+
+        assert_eq!(ndjson.lines().count(), 3);
+        assert_eq!(
+            first,
+            r#"{"time":"1970-01-01T00:01:00Z","channel":"pwr-volt","min":12.0,"max":12.0,"mean":12.0,"count":1}"#
+        );
+    }
+}
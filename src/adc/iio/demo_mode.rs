@@ -15,27 +15,161 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::ErrorKind;
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
-use std::time::Instant;
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
+use async_std::channel::{bounded, Receiver};
 use async_std::sync::{Arc, Mutex};
-use async_std::task::block_on;
+use async_std::task::{block_on, sleep, spawn};
+use log::warn;
 use rand::{thread_rng, Rng};
+use serde::Deserialize;
 
-use crate::measurement::{Measurement, Timestamp};
+use crate::adc::SampleBlock;
+use crate::measurement::{Measurement, Timestamp, Validity};
+
+const WAVEFORMS_PATH: &str = "demo_files/etc/tacd/demo_waveforms.json";
+
+/// The simulated ADC provides blocks of this many samples ...
+const BLOCK_SAMPLES: usize = 10;
+
+/// ... taken this far apart
+const SAMPLE_PERIOD: Duration = Duration::from_millis(10);
 
 // We need to somehow get the output states from digital_io/gpio/demo_mode.rs
 // to here. We could clobber the actual business code even more, or do dirty
 // mutable globals stuff.
 pub static DEMO_MAGIC: Mutex<Option<Arc<IioThread>>> = Mutex::new(None);
 
+fn default_duty() -> f32 {
+    0.5
+}
+
+/// A waveform that is added to the simulated value of a channel.
+/// All times are given in seconds.
+#[derive(Deserialize, Clone, Debug)]
+enum Waveform {
+    /// Rise linearly from `from` to `to` within `period`, then start over
+    Ramp {
+        from: f32,
+        to: f32,
+        period: f32,
+    },
+    Sine {
+        amplitude: f32,
+        period: f32,
+    },
+    /// Be `high` for the `duty` fraction of each `period` and `low` otherwise
+    Square {
+        low: f32,
+        high: f32,
+        period: f32,
+        #[serde(default = "default_duty")]
+        duty: f32,
+    },
+    /// Uniformly distributed noise between `-amplitude` and `amplitude`
+    Noise {
+        amplitude: f32,
+    },
+    /// Interpolate linearly between `(time, value)` points, counting from
+    /// the moment the channel was switched on, e.g. to model the current
+    /// drawn by a booting DUT.
+    /// The profile starts over every `period` if one is given and holds its
+    /// last value otherwise. It is zero while the channel is switched off.
+    Profile {
+        points: Vec<(f32, f32)>,
+        period: Option<f32>,
+    },
+}
+
+/// Where in the current period `t` is, from 0.0 to 1.0
+fn phase(t: f32, period: f32) -> f32 {
+    match period > 0.0 {
+        true => (t % period) / period,
+        false => 0.0,
+    }
+}
+
+fn interpolate(points: &[(f32, f32)], t: f32) -> f32 {
+    match points.iter().position(|(pt, _)| *pt > t) {
+        Some(0) => points[0].1,
+        Some(i) => {
+            let (t0, v0) = points[i - 1];
+            let (t1, v1) = points[i];
+
+            v0 + (v1 - v0) * (t - t0) / (t1 - t0)
+        }
+        None => points.last().map(|(_, v)| *v).unwrap_or(0.0),
+    }
+}
+
+impl Waveform {
+    fn value(&self, runtime: f32, on_time: Option<f32>) -> f32 {
+        match self {
+            Self::Ramp { from, to, period } => from + (to - from) * phase(runtime, *period),
+            Self::Sine { amplitude, period } => {
+                amplitude * (2.0 * PI * phase(runtime, *period)).sin()
+            }
+            Self::Square {
+                low,
+                high,
+                period,
+                duty,
+            } => match phase(runtime, *period) < *duty {
+                true => *high,
+                false => *low,
+            },
+            Self::Noise { amplitude } => (2.0 * thread_rng().gen::<f32>() - 1.0) * amplitude,
+            Self::Profile { points, period } => match (on_time, period) {
+                (None, _) => 0.0,
+                (Some(t), Some(period)) if *period > 0.0 => interpolate(points, t % period),
+                (Some(t), _) => interpolate(points, t),
+            },
+        }
+    }
+}
+
+/// The waveforms a channel should follow, as configured in the
+/// `demo_waveforms.json` file.
+#[derive(Deserialize, Clone, Debug, Default)]
+struct ChannelScript {
+    /// Ignore the built-in model of the channel and only output the
+    /// sum of the waveforms
+    #[serde(default)]
+    replace: bool,
+    waveforms: Vec<Waveform>,
+}
+
+fn load_scripts() -> HashMap<String, ChannelScript> {
+    let file = match File::open(WAVEFORMS_PATH) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return HashMap::new(),
+        Err(e) => {
+            warn!("Failed to open demo waveforms at \"{WAVEFORMS_PATH}\": {e}");
+            return HashMap::new();
+        }
+    };
+
+    serde_json::from_reader(file).unwrap_or_else(|e| {
+        warn!("Failed to parse demo waveforms at \"{WAVEFORMS_PATH}\": {e}");
+        HashMap::new()
+    })
+}
+
 pub struct CalibratedChannelInner {
     name: &'static str,
     timebase: Instant,
     state: AtomicBool,
     last_poll_ms: AtomicU64,
+    switched_on_ms: AtomicU64,
+    script: StdMutex<ChannelScript>,
     value: AtomicU32,
     nominal_value_on: f32,
     nominal_value_off: f32,
@@ -65,6 +199,8 @@ impl CalibratedChannel {
                 timebase: Instant::now(),
                 state: AtomicBool::new(false),
                 last_poll_ms: AtomicU64::new(0),
+                switched_on_ms: AtomicU64::new(0),
+                script: StdMutex::new(ChannelScript::default()),
                 value: AtomicU32::new(nominal_value_off.to_bits()),
                 nominal_value_on,
                 nominal_value_off,
@@ -83,6 +219,8 @@ impl CalibratedChannel {
                 timebase: Instant::now(),
                 state: AtomicBool::new(false),
                 last_poll_ms: AtomicU64::new(0),
+                switched_on_ms: AtomicU64::new(0),
+                script: StdMutex::new(ChannelScript::default()),
                 value: AtomicU32::new(0),
                 nominal_value_on: 0.0,
                 nominal_value_off: 0.0,
@@ -99,7 +237,11 @@ impl CalibratedChannel {
         channels: [&Self; N],
     ) -> Option<[Measurement; N]> {
         let ts = Timestamp::now();
-        let mut results = [Measurement { ts, value: 0.0 }; N];
+        let mut results = [Measurement {
+            ts,
+            value: 0.0,
+            validity: Validity::Valid,
+        }; N];
 
         for i in 0..N {
             results[i].value = channels[i].get().value;
@@ -108,19 +250,30 @@ impl CalibratedChannel {
         Some(results)
     }
 
+    fn runtime_ms(&self, ts: Timestamp) -> u64 {
+        let runtime = ts.as_instant().duration_since(self.inner.timebase);
+
+        u64::try_from(runtime.as_millis()).unwrap()
+    }
+
+    /// The physical value of one step of the raw ADC value (if known)
+    pub fn scale(&self) -> Option<f32> {
+        None
+    }
+
     pub fn get(&self) -> Measurement {
         let ts = Timestamp::now();
+        let runtime_ms = self.runtime_ms(ts);
 
         let dt = {
-            let runtime = ts.as_instant().duration_since(self.inner.timebase);
-
-            let runtime_ms = u64::try_from(runtime.as_millis()).unwrap();
             let last_poll_ms = self.inner.last_poll_ms.swap(runtime_ms, Ordering::Relaxed);
 
             (runtime_ms - last_poll_ms) as f32 / 1000.0
         };
 
-        let (nominal, time_constant) = match self.inner.state.load(Ordering::Relaxed) {
+        let state = self.inner.state.load(Ordering::Relaxed);
+
+        let (nominal, time_constant) = match state {
             true => (self.inner.nominal_value_on, self.inner.time_constant_on),
             false => (self.inner.nominal_value_off, self.inner.time_constant_off),
         };
@@ -140,11 +293,89 @@ impl CalibratedChannel {
 
         self.inner.value.store(value.to_bits(), Ordering::Relaxed);
 
-        Measurement { ts, value }
+        // The scripted waveforms are added on top of the model and do not
+        // influence its state.
+        let script = self.inner.script.lock().unwrap();
+
+        if !script.waveforms.is_empty() {
+            let runtime = runtime_ms as f32 / 1000.0;
+            let on_time = state.then(|| {
+                let switched_on_ms = self.inner.switched_on_ms.load(Ordering::Relaxed);
+                runtime_ms.saturating_sub(switched_on_ms) as f32 / 1000.0
+            });
+
+            let scripted: f32 = script
+                .waveforms
+                .iter()
+                .map(|waveform| waveform.value(runtime, on_time))
+                .sum();
+
+            value = match script.replace {
+                true => scripted,
+                false => value + scripted,
+            };
+        }
+
+        Measurement {
+            ts,
+            value,
+            validity: Validity::Valid,
+        }
+    }
+
+    /// The simulated channels are always sampled at the same rate
+    pub fn set_rate(&self, _rate: f32) {}
+
+    /// Get every sample of `channels` in blocks, like the hardware ADC
+    /// provides them.
+    /// The queue is closed if no channels are given.
+    pub fn subscribe(channels: &[Self]) -> Receiver<SampleBlock> {
+        let (tx, rx) = bounded(1);
+
+        if channels.is_empty() {
+            return rx;
+        }
+
+        let channels = channels.to_vec();
+
+        spawn(async move {
+            loop {
+                let start = Timestamp::now();
+                let mut values = vec![Vec::with_capacity(BLOCK_SAMPLES); channels.len()];
+
+                for _ in 0..BLOCK_SAMPLES {
+                    for (values, ch) in values.iter_mut().zip(channels.iter()) {
+                        values.push(ch.get().value);
+                    }
+
+                    sleep(SAMPLE_PERIOD).await;
+                }
+
+                let block = SampleBlock {
+                    start,
+                    period: SAMPLE_PERIOD,
+                    values,
+                    validity: vec![Validity::Valid; channels.len()],
+                };
+
+                if tx.send(block).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        rx
     }
 
     pub fn set(&self, state: bool) {
-        self.inner.state.store(state, Ordering::Relaxed);
+        let was_on = self.inner.state.swap(state, Ordering::Relaxed);
+
+        if state && !was_on {
+            let runtime_ms = self.runtime_ms(Timestamp::now());
+            self.inner
+                .switched_on_ms
+                .store(runtime_ms, Ordering::Relaxed);
+        }
     }
 }
 
@@ -183,6 +414,15 @@ impl IioThread {
             CalibratedChannel::with_exponential("pwr-curr", 1.2, 0.0, 0.002, 0.2, 0.01),
         ];
 
+        // Let channels follow the waveforms configured in the demo files
+        // instead of (or in addition to) their mostly static defaults.
+        for (name, script) in load_scripts() {
+            match channels.iter().find(|chan| chan.inner.name == name) {
+                Some(chan) => *chan.inner.script.lock().unwrap() = script,
+                None => warn!("Demo waveforms given for unknown channel {name}"),
+            }
+        }
+
         let this = Arc::new(Self { channels });
 
         *demo_magic = Some(this.clone());
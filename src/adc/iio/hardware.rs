@@ -17,23 +17,26 @@
 
 use std::convert::{TryFrom, TryInto};
 use std::io::Read;
-use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use std::sync::atomic::{fence, AtomicU16, AtomicU32, AtomicU64, Ordering};
 use std::sync::Mutex;
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Result};
-use async_std::channel::bounded;
+use async_std::channel::{bounded, Receiver, Sender};
 use async_std::stream::StreamExt;
 use async_std::sync::Arc;
+use async_std::task::spawn;
 
-use industrial_io::{Buffer, Channel};
+use industrial_io::{Buffer, Channel, Device};
+use nix::poll::{poll, PollFd, PollFlags};
 
 use log::{debug, warn};
 use thread_priority::*;
 
-use crate::measurement::{Measurement, Timestamp};
+use crate::adc::SampleBlock;
+use crate::measurement::{Measurement, Timestamp, Validity};
 
 // Hard coded list of channels using the internal STM32MP1 ADC.
 // Consists of the IIO channel name, the location of the calibration data
@@ -80,6 +83,285 @@ const CHANNELS_PWR: &[(&str, &str, &str)] = &[
     ("current", "powerboard-factory-data/pwr-curr", "pwr-curr"),
 ];
 
+/// The ADCs are sampled at this rate (in Hz), unless a higher rate is
+/// requested for one of their channels via `CalibratedChannel::set_rate()`
+const BASE_FREQUENCY: u32 = 1024;
+const MAX_FREQUENCY: u32 = 10_000;
+
+/// The STM32 ADC values are averaged over long blocks to reduce the noise
+/// and the CPU time spent per sample.
+const STM32_BLOCK_DURATION: Duration = Duration::from_millis(125);
+
+/// The power board ADC values are used for the over-current and
+/// over-voltage protection of the DUT power switch, so they are averaged
+/// over much shorter blocks to keep the reaction time low.
+const PWR_BLOCK_DURATION: Duration = Duration::from_millis(16);
+
+/// Index of the ADCs in `IioThread::timestamps`
+const ADC_STM32: usize = 0;
+const ADC_PWR: usize = 1;
+
+/// Number of blocks that may queue up for a subscriber before new blocks
+/// are dropped for it
+const BLOCK_QUEUE_LENGTH: usize = 16;
+
+/// The power board ADC is triggered by a software (hrtimer) trigger that is
+/// created via configfs, as the STM32 timer triggers can only be used by the
+/// STM32 ADC.
+const PWR_TRIGGER_NAME: &str = "tacd-pwr";
+const PWR_TRIGGER_CONFIGFS: &str = "/sys/kernel/config/iio/triggers/hrtimer/tacd-pwr";
+
+/// The largest raw values the ADCs can output (16 and 12 bit resolution).
+/// Samples at the upper end of the range mean that the input was clipped.
+const STM32_MAX_RAW: u16 = u16::MAX;
+const PWR_MAX_RAW: u16 = 0x0fff;
+
+/// The ADC a channel (by index in the values array) belongs to
+fn adc_of(index: usize) -> usize {
+    match index < CHANNELS_STM32.len() {
+        true => ADC_STM32,
+        false => ADC_PWR,
+    }
+}
+
+/// The index of the first channel of `adc` in the values array.
+/// The power board values are located after the STM32 values.
+fn first_index(adc: usize) -> usize {
+    match adc {
+        ADC_STM32 => 0,
+        _ => CHANNELS_STM32.len(),
+    }
+}
+
+/// Consecutive raw samples of all channels of one ADC
+struct RawBlock {
+    adc: usize,
+    /// Time the first sample in the block was taken at
+    start: Instant,
+    /// Time between two samples
+    period: Duration,
+    /// The samples of each channel of the ADC
+    samples: Vec<Vec<u16>>,
+    max_raw: u16,
+}
+
+impl RawBlock {
+    /// The time the last sample in the block was taken at
+    fn end(&self) -> Instant {
+        let len = self.samples.first().map(Vec::len).unwrap_or(0);
+
+        self.start + self.period * (len.saturating_sub(1) as u32)
+    }
+}
+
+/// Get the average of `samples` and whether any of them was clipped.
+fn block_average(samples: &[u16], max_raw: u16) -> (u16, bool) {
+    let clipped = samples.iter().any(|v| *v >= max_raw);
+    let sum: u32 = samples.iter().map(|v| *v as u32).sum();
+    let len = samples.len().max(1) as u32;
+
+    ((sum / len) as u16, clipped)
+}
+
+/// An ADC that is read in blocks of samples via a triggered buffer
+struct BufferedAdc {
+    adc: usize,
+    dev: Device,
+    trigger: Device,
+    channels: Vec<Channel>,
+    /// Only one buffer can exist per device, so the old one has to be
+    /// dropped before a new one is created
+    buffer: Option<Buffer>,
+    frequency: u32,
+    block_duration: Duration,
+    max_raw: u16,
+}
+
+impl BufferedAdc {
+    fn new(
+        adc: usize,
+        dev: &Device,
+        trigger: &Device,
+        channels: &[Channel],
+        block_duration: Duration,
+        max_raw: u16,
+    ) -> Result<Self> {
+        dev.set_trigger(trigger)?;
+
+        for ch in channels {
+            ch.enable();
+        }
+
+        let mut this = Self {
+            adc,
+            dev: dev.clone(),
+            trigger: trigger.clone(),
+            channels: channels.to_vec(),
+            buffer: None,
+            frequency: BASE_FREQUENCY,
+            block_duration,
+            max_raw,
+        };
+
+        this.buffer = match this.open(BASE_FREQUENCY) {
+            Ok(buffer) => Some(buffer),
+            Err(e) => {
+                for ch in channels {
+                    ch.disable();
+                }
+
+                return Err(e);
+            }
+        };
+
+        Ok(this)
+    }
+
+    /// Create a buffer that holds a block of samples taken at `frequency`
+    fn open(&self, frequency: u32) -> Result<Buffer> {
+        self.trigger
+            .attr_write_int("sampling_frequency", frequency as i64)?;
+
+        let samples = (self.block_duration.as_secs_f64() * frequency as f64) as usize;
+
+        Ok(self.dev.create_buffer(samples.max(1), false)?)
+    }
+
+    /// Sample the ADC at a different `frequency`.
+    /// The blocks keep their duration and contain more or less samples.
+    fn set_frequency(&mut self, frequency: u32) {
+        if frequency == self.frequency {
+            return;
+        }
+
+        self.buffer = None;
+
+        match self.open(frequency) {
+            Ok(buffer) => {
+                self.buffer = Some(buffer);
+                self.frequency = frequency;
+            }
+            Err(e) => {
+                warn!("Failed to change ADC sampling frequency to {frequency}Hz: {e}");
+                self.buffer = Some(self.open(self.frequency).unwrap());
+            }
+        }
+    }
+
+    fn period(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.frequency as f64)
+    }
+
+    /// Check if a complete block can be read without blocking
+    fn ready(&self) -> bool {
+        let fd = match self.buffer.as_ref().map(Buffer::poll_fd) {
+            Some(Ok(fd)) => fd,
+            _ => return true,
+        };
+
+        let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+
+        matches!(poll(&mut fds, 0), Ok(n) if n > 0)
+    }
+
+    /// Wait for the next block of samples
+    fn read(&mut self) -> RawBlock {
+        let period = self.period();
+        let buffer = self.buffer.as_mut().unwrap();
+
+        buffer.refill().unwrap();
+
+        // The last sample was just taken when the block is complete
+        let end = Instant::now();
+
+        let samples: Vec<Vec<u16>> = self
+            .channels
+            .iter()
+            .map(|ch| buffer.channel_iter::<u16>(ch).collect())
+            .collect();
+
+        let len = samples.first().map(Vec::len).unwrap_or(0);
+        let start = end
+            .checked_sub(period * (len.saturating_sub(1) as u32))
+            .unwrap_or(end);
+
+        RawBlock {
+            adc: self.adc,
+            start,
+            period,
+            samples,
+            max_raw: self.max_raw,
+        }
+    }
+}
+
+/// The power board ADC is read via a triggered buffer if the kernel driver
+/// supports it.
+///
+/// Otherwise only single samples can be read via sysfs, which costs a lot
+/// of CPU time per sample. They are then read once per STM32 block.
+enum PwrAdc {
+    Buffered(BufferedAdc),
+    Sysfs(Vec<Channel>),
+}
+
+impl PwrAdc {
+    fn new(ctx: &industrial_io::Context, dev: &Device) -> Self {
+        let channels: Vec<Channel> = CHANNELS_PWR
+            .iter()
+            .map(|(iio_name, _, _)| {
+                dev.find_channel(iio_name, false)
+                    .unwrap_or_else(|| panic!("Failed to open iio channel {}", iio_name))
+            })
+            .collect();
+
+        let buffered = ctx
+            .find_device(PWR_TRIGGER_NAME)
+            .ok_or(anyhow!("Could not find Powerboard ADC trigger"))
+            .and_then(|trig| {
+                BufferedAdc::new(
+                    ADC_PWR,
+                    dev,
+                    &trig,
+                    &channels,
+                    PWR_BLOCK_DURATION,
+                    PWR_MAX_RAW,
+                )
+            });
+
+        match buffered {
+            Ok(adc) => Self::Buffered(adc),
+            Err(err) => {
+                warn!(
+                    "Failed to set up Powerboard ADC buffer, falling back to sysfs: {}",
+                    err
+                );
+                Self::Sysfs(channels)
+            }
+        }
+    }
+}
+
+/// Read a single sample of each channel via sysfs
+fn read_sysfs(channels: &[Channel], prev: &mut Instant) -> RawBlock {
+    let samples = channels
+        .iter()
+        .map(|ch| vec![ch.attr_read_int("raw").unwrap() as u16])
+        .collect();
+
+    let now = Instant::now();
+    let period = now.saturating_duration_since(*prev);
+    *prev = now;
+
+    RawBlock {
+        adc: ADC_PWR,
+        start: now,
+        period,
+        samples,
+        max_raw: PWR_MAX_RAW,
+    }
+}
+
 #[derive(Clone, Copy)]
 struct Calibration {
     scale: f32,
@@ -145,8 +427,8 @@ impl CalibratedChannel {
         })
     }
 
-    /// Get values for multiple channels of the same `iio_thread` that were
-    /// sampled at the same timestamp.
+    /// Get values for multiple channels of the same ADC of the same
+    /// `iio_thread` that were sampled at the same timestamp.
     ///
     /// Returns None if not all values could be read while the timestamp stayed
     /// constant or if no values were acquired yet.
@@ -158,18 +440,28 @@ impl CalibratedChannel {
         &self,
         channels: [&Self; N],
     ) -> Option<[Measurement; N]> {
-        let ts_before = self.iio_thread.timestamp.load(Ordering::Acquire);
+        // The ADCs are triggered independently of each other, so only the
+        // values of the same ADC share a timestamp.
+        let timestamp = &self.iio_thread.timestamps[adc_of(self.index)];
+        let ts_before = timestamp.load(Ordering::Acquire);
 
+        let clipped = self.iio_thread.clipped.load(Ordering::Relaxed);
         let mut values_raw = [0; N];
         for (d, ch) in values_raw.iter_mut().zip(channels.iter()) {
             assert!(
                 Arc::ptr_eq(&self.iio_thread, &ch.iio_thread),
                 "Can only get synchronized adc values for the same thread"
             );
+            assert!(
+                adc_of(self.index) == adc_of(ch.index),
+                "Can only get synchronized adc values for the same adc"
+            );
             *d = self.iio_thread.values[ch.index].load(Ordering::Relaxed);
         }
 
-        let ts_after = self.iio_thread.timestamp.load(Ordering::Acquire);
+        // Make sure the values are read before the timestamp is checked again
+        fence(Ordering::Acquire);
+        let ts_after = timestamp.load(Ordering::Relaxed);
 
         if (ts_before == ts_after) && (ts_before != 0) {
             let ts = self
@@ -179,9 +471,17 @@ impl CalibratedChannel {
                 .unwrap();
             let ts = Timestamp::new(ts);
 
-            let mut values = [Measurement { ts, value: 0.0 }; N];
+            let mut values = [Measurement {
+                ts,
+                value: 0.0,
+                validity: Validity::Valid,
+            }; N];
             for i in 0..N {
                 values[i].value = channels[i].calibration.apply(values_raw[i] as f32);
+
+                if clipped & (1 << channels[i].index) != 0 {
+                    values[i].validity = Validity::Overrange;
+                }
             }
 
             Some(values)
@@ -196,6 +496,11 @@ impl CalibratedChannel {
         self.try_get_multiple([self]).map(|res| res[0])
     }
 
+    /// The physical value of one step of the raw ADC value (if known)
+    pub fn scale(&self) -> Option<f32> {
+        Some(self.calibration.scale)
+    }
+
     // Get the current value of the channel
     pub fn get(&self) -> Measurement {
         loop {
@@ -204,17 +509,134 @@ impl CalibratedChannel {
             }
         }
     }
+
+    /// Request the ADC of this channel to be sampled at `rate` samples per
+    /// second or faster.
+    /// The ADC is sampled at the highest rate requested for any of its
+    /// channels, but at least at `BASE_FREQUENCY`.
+    pub fn set_rate(&self, rate: f32) {
+        self.iio_thread.rates[self.index].store(rate.ceil() as u32, Ordering::Relaxed)
+    }
+
+    /// The calibrated samples of this channel in `raw` and whether any of
+    /// them was clipped
+    fn convert(&self, raw: &RawBlock) -> (Vec<f32>, Validity) {
+        let samples = &raw.samples[self.index - first_index(raw.adc)];
+
+        let values = samples
+            .iter()
+            .map(|v| self.calibration.apply(*v as f32))
+            .collect();
+
+        let validity = match samples.iter().any(|v| *v >= raw.max_raw) {
+            true => Validity::Overrange,
+            false => Validity::Valid,
+        };
+
+        (values, validity)
+    }
+
+    /// Get every sample of `channels` instead of just the block averages,
+    /// in blocks as they are read from the ADCs.
+    ///
+    /// The blocks follow the ADC of the first channel. Channels on the other
+    /// ADC repeat their most recent value, as the two ADCs are not
+    /// triggered in sync.
+    /// The queue is closed if no channels are given.
+    pub fn subscribe(channels: &[Self]) -> Receiver<SampleBlock> {
+        let (tx, rx) = bounded(BLOCK_QUEUE_LENGTH);
+
+        let lead = match channels.first() {
+            Some(first) => first.clone(),
+            None => return rx,
+        };
+
+        let (raw_tx, raw_rx) = bounded::<Arc<RawBlock>>(BLOCK_QUEUE_LENGTH);
+        lead.iio_thread.subscribers.lock().unwrap().push(raw_tx);
+
+        let channels = channels.to_vec();
+        let lead_adc = adc_of(lead.index);
+
+        spawn(async move {
+            // The most recent value of the channels that are not on the
+            // leading ADC
+            let mut held: Vec<Option<(f32, Validity)>> = vec![None; channels.len()];
+
+            while let Ok(raw) = raw_rx.recv().await {
+                if raw.adc != lead_adc {
+                    for (ch, held) in channels.iter().zip(held.iter_mut()) {
+                        if adc_of(ch.index) == raw.adc {
+                            let (samples, validity) = ch.convert(&raw);
+
+                            if let Some(last) = samples.last() {
+                                *held = Some((*last, validity));
+                            }
+                        }
+                    }
+
+                    continue;
+                }
+
+                let len = raw.samples.first().map(Vec::len).unwrap_or(0);
+                let mut values = Vec::with_capacity(channels.len());
+                let mut validities = Vec::with_capacity(channels.len());
+
+                for (ch, held) in channels.iter().zip(held.iter()) {
+                    if adc_of(ch.index) == lead_adc {
+                        let (samples, validity) = ch.convert(&raw);
+                        values.push(samples);
+                        validities.push(validity);
+                    } else if let Some((value, validity)) = held {
+                        values.push(vec![*value; len]);
+                        validities.push(*validity);
+                    }
+                }
+
+                // Wait until the other ADC provided a value for all channels
+                if values.len() != channels.len() {
+                    continue;
+                }
+
+                let block = SampleBlock {
+                    start: Timestamp::new(raw.start),
+                    period: raw.period,
+                    values,
+                    validity: validities,
+                };
+
+                if tx.send(block).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        rx
+    }
 }
 
 pub struct IioThread {
     ref_instant: Instant,
-    timestamp: AtomicU64,
+    /// Time of the last update of the values of each ADC
+    timestamps: [AtomicU64; 2],
     values: [AtomicU16; 10],
+    /// Bit mask of the channels that were clipped in the last acquisition
+    clipped: AtomicU16,
+    /// The sample rate requested for each channel
+    rates: [AtomicU32; 10],
+    /// Queues of the tasks that want to see every sample
+    subscribers: Mutex<Vec<Sender<Arc<RawBlock>>>>,
     join: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl IioThread {
-    fn adc_setup() -> Result<(Vec<Channel>, Buffer, Vec<Channel>)> {
+    fn adc_setup() -> Result<(BufferedAdc, PwrAdc)> {
+        // The IIO context only contains the devices that existed when it was
+        // created, so the trigger for the power board ADC has to be set up
+        // first.
+        if let Err(err) = std::fs::create_dir_all(PWR_TRIGGER_CONFIGFS) {
+            warn!("Failed to create Powerboard ADC trigger: {}", err);
+        }
+
         let ctx = industrial_io::Context::new()?;
 
         debug!("IIO devices:");
@@ -236,19 +658,7 @@ impl IioThread {
         let stm32_channels: Vec<Channel> = CHANNELS_STM32
             .iter()
             .map(|(iio_name, _, _)| {
-                let ch = stm32_adc
-                    .find_channel(iio_name, false)
-                    .unwrap_or_else(|| panic!("Failed to open iio channel {}", iio_name));
-
-                ch.enable();
-                ch
-            })
-            .collect();
-
-        let pwr_channels: Vec<Channel> = CHANNELS_PWR
-            .iter()
-            .map(|(iio_name, _, _)| {
-                pwr_adc
+                stm32_adc
                     .find_channel(iio_name, false)
                     .unwrap_or_else(|| panic!("Failed to open iio channel {}", iio_name))
             })
@@ -257,12 +667,19 @@ impl IioThread {
         let trig = ctx
             .find_device("tim4_trgo")
             .ok_or(anyhow!("Could not find STM32 Timer 4 trigger"))?;
-        trig.attr_write_int("sampling_frequency", 1024)?;
 
-        stm32_adc.set_trigger(&trig)?;
         ctx.set_timeout_ms(1000)?;
 
-        let stm32_buf = stm32_adc.create_buffer(128, false)?;
+        let stm32_adc = BufferedAdc::new(
+            ADC_STM32,
+            &stm32_adc,
+            &trig,
+            &stm32_channels,
+            STM32_BLOCK_DURATION,
+            STM32_MAX_RAW,
+        )?;
+
+        let pwr_adc = PwrAdc::new(&ctx, &pwr_adc);
 
         set_thread_priority_and_policy(
             thread_native_id(),
@@ -271,7 +688,80 @@ impl IioThread {
         )
         .map_err(|e| anyhow!("Failed to set realtime thread priority: {e:?}"))?;
 
-        Ok((stm32_channels, stm32_buf, pwr_channels))
+        Ok((stm32_adc, pwr_adc))
+    }
+
+    /// The frequency `adc` should be sampled at to satisfy the rates
+    /// requested for its channels
+    fn frequency(&self, adc: usize) -> u32 {
+        self.rates
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| adc_of(*index) == adc)
+            .map(|(_, rate)| rate.load(Ordering::Relaxed))
+            .fold(BASE_FREQUENCY, u32::max)
+            .min(MAX_FREQUENCY)
+    }
+
+    /// Publish the averages of a new block of samples via the atomic values
+    /// and the block itself to the subscribers.
+    fn update(&self, block: RawBlock) {
+        let offset = first_index(block.adc);
+
+        // Calculate everything before starting to update the values, so
+        // that the update is as short as possible.
+        let averages: Vec<(u16, bool)> = block
+            .samples
+            .iter()
+            .map(|samples| block_average(samples, block.max_raw))
+            .collect();
+
+        let own = averages
+            .iter()
+            .enumerate()
+            .fold(0u16, |mask, (i, _)| mask | (1 << (offset + i)));
+
+        let clipped = averages
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, clipped))| *clipped)
+            .fold(0u16, |mask, (i, _)| mask | (1 << (offset + i)));
+
+        let ts: u64 = block
+            .end()
+            .checked_duration_since(self.ref_instant)
+            .unwrap_or_default()
+            .as_nanos()
+            .try_into()
+            .unwrap();
+
+        // Mark the values as being updated (a timestamp of zero is never
+        // valid), so that readers can not mix values of different
+        // acquisitions, e.g. voltage and current of the same rail.
+        let timestamp = &self.timestamps[block.adc];
+        timestamp.store(0, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        for (d, (s, _)) in self.values[offset..].iter().zip(averages) {
+            d.store(s, Ordering::Relaxed)
+        }
+
+        // Only this thread writes the clipped mask, so the bits of the
+        // other ADC can be kept as they are.
+        let prev = self.clipped.load(Ordering::Relaxed);
+        self.clipped
+            .store((prev & !own) | clipped, Ordering::Relaxed);
+
+        timestamp.store(ts.max(1), Ordering::Release);
+
+        // Never block the realtime thread on a subscriber that does not
+        // keep up. It just misses blocks instead.
+        let block = Arc::new(block);
+
+        self.subscribers.lock().unwrap().retain(|tx| {
+            let _ = tx.try_send(block.clone());
+            !tx.is_closed()
+        });
     }
 
     pub async fn new() -> Result<Arc<Self>> {
@@ -288,73 +778,65 @@ impl IioThread {
         let join = thread::Builder::new()
             .name("tacd iio".into())
             .spawn(move || {
-                let (thread_weak, stm32_channels, mut stm32_buf, pwr_channels) =
-                    match Self::adc_setup() {
-                        Ok((stm32_channels, stm32_buf, pwr_channels)) => {
-                            let thread = Arc::new(Self {
-                                ref_instant: Instant::now(),
-                                timestamp: AtomicU64::new(0),
-                                values: [
-                                    AtomicU16::new(0),
-                                    AtomicU16::new(0),
-                                    AtomicU16::new(0),
-                                    AtomicU16::new(0),
-                                    AtomicU16::new(0),
-                                    AtomicU16::new(0),
-                                    AtomicU16::new(0),
-                                    AtomicU16::new(0),
-                                    AtomicU16::new(0),
-                                    AtomicU16::new(0),
-                                ],
-                                join: Mutex::new(None),
-                            });
-                            let thread_weak = Arc::downgrade(&thread);
-
-                            thread_res_tx.try_send(Ok(thread)).unwrap();
-
-                            (thread_weak, stm32_channels, stm32_buf, pwr_channels)
-                        }
-                        Err(e) => {
-                            thread_res_tx.try_send(Err(e)).unwrap();
-                            panic!()
-                        }
-                    };
+                let (thread_weak, mut stm32_adc, mut pwr_adc) = match Self::adc_setup() {
+                    Ok((stm32_adc, pwr_adc)) => {
+                        let thread = Arc::new(Self {
+                            ref_instant: Instant::now(),
+                            timestamps: [AtomicU64::new(0), AtomicU64::new(0)],
+                            values: [
+                                AtomicU16::new(0),
+                                AtomicU16::new(0),
+                                AtomicU16::new(0),
+                                AtomicU16::new(0),
+                                AtomicU16::new(0),
+                                AtomicU16::new(0),
+                                AtomicU16::new(0),
+                                AtomicU16::new(0),
+                                AtomicU16::new(0),
+                                AtomicU16::new(0),
+                            ],
+                            clipped: AtomicU16::new(0),
+                            rates: Default::default(),
+                            subscribers: Mutex::new(Vec::new()),
+                            join: Mutex::new(None),
+                        });
+                        let thread_weak = Arc::downgrade(&thread);
+
+                        thread_res_tx.try_send(Ok(thread)).unwrap();
+
+                        (thread_weak, stm32_adc, pwr_adc)
+                    }
+                    Err(e) => {
+                        thread_res_tx.try_send(Err(e)).unwrap();
+                        panic!()
+                    }
+                };
 
                 // Stop running as soon as the last reference to this Arc<IioThread>
                 // is dropped (e.g. the weak reference can no longer be upgraded).
+                let mut last_sysfs_read = Instant::now();
+
                 while let Some(thread) = thread_weak.upgrade() {
-                    // Use the buffer interface to get STM32 ADC values at a high
-                    // sampling rate to perform averaging in software.
-                    stm32_buf.refill().unwrap();
-
-                    let stm32_values = stm32_channels.iter().map(|ch| {
-                        let buf_sum: u32 =
-                            stm32_buf.channel_iter::<u16>(ch).map(|v| v as u32).sum();
-                        (buf_sum / (stm32_buf.capacity() as u32)) as u16
-                    });
-
-                    // Use the sysfs based interface to get the values from the
-                    // power board ADC at a slow sampling rate.
-                    let pwr_values = pwr_channels
-                        .iter()
-                        .map(|ch| ch.attr_read_int("raw").unwrap() as u16);
-
-                    // The power board values are located after the stm32 values
-                    // in the thread.values array.
-                    let values = stm32_values.chain(pwr_values);
-
-                    for (d, s) in thread.values.iter().zip(values) {
-                        d.store(s, Ordering::Relaxed)
-                    }
+                    stm32_adc.set_frequency(thread.frequency(ADC_STM32));
 
-                    let ts: u64 = Instant::now()
-                        .checked_duration_since(thread.ref_instant)
-                        .unwrap()
-                        .as_nanos()
-                        .try_into()
-                        .unwrap();
+                    match &mut pwr_adc {
+                        PwrAdc::Buffered(pwr_adc) => {
+                            pwr_adc.set_frequency(thread.frequency(ADC_PWR));
 
-                    thread.timestamp.store(ts, Ordering::Release);
+                            // The short power board blocks pace the thread.
+                            // The STM32 blocks are picked up whenever one
+                            // is complete.
+                            thread.update(pwr_adc.read());
+
+                            if stm32_adc.ready() {
+                                thread.update(stm32_adc.read());
+                            }
+                        }
+                        PwrAdc::Sysfs(channels) => {
+                            thread.update(stm32_adc.read());
+                            thread.update(read_sysfs(channels, &mut last_sysfs_read));
+                        }
+                    }
                 }
             })?;
 
@@ -16,12 +16,15 @@
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
 use std::time::Duration;
 
 use anyhow::{anyhow, Result};
+use async_std::channel::{bounded, Receiver, Sender};
 use async_std::sync::Arc;
 
-use crate::measurement::{Measurement, Timestamp};
+use crate::adc::SampleBlock;
+use crate::measurement::{Measurement, Timestamp, Validity};
 
 const NO_TRANSIENT: u32 = u32::MAX;
 
@@ -43,6 +46,7 @@ pub struct CalibratedChannel {
     val: Arc<AtomicU32>,
     stall: Arc<AtomicBool>,
     transient: Arc<AtomicU32>,
+    subscribers: Arc<Mutex<Vec<Sender<SampleBlock>>>>,
 }
 
 impl CalibratedChannel {
@@ -51,6 +55,7 @@ impl CalibratedChannel {
             val: Arc::new(AtomicU32::new(0)),
             stall: Arc::new(AtomicBool::new(false)),
             transient: Arc::new(AtomicU32::new(NO_TRANSIENT)),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
@@ -64,7 +69,11 @@ impl CalibratedChannel {
             *ts -= Duration::from_millis(500)
         }
 
-        let mut results = [Measurement { ts, value: 0.0 }; N];
+        let mut results = [Measurement {
+            ts,
+            value: 0.0,
+            validity: Validity::Valid,
+        }; N];
 
         for i in 0..N {
             // If a transient is scheduled (channels[i].transient != NO_TRANSIENT)
@@ -85,6 +94,11 @@ impl CalibratedChannel {
         self.try_get_multiple([self]).map(|res| res[0])
     }
 
+    /// The physical value of one step of the raw ADC value (if known)
+    pub fn scale(&self) -> Option<f32> {
+        None
+    }
+
     pub fn get(&self) -> Measurement {
         loop {
             if let Some(r) = self.try_get() {
@@ -93,6 +107,21 @@ impl CalibratedChannel {
         }
     }
 
+    /// The test channels do not have a sample rate
+    pub fn set_rate(&self, _rate: f32) {}
+
+    /// The test channels do not provide blocks of samples.
+    /// The queue stays open, but never yields anything.
+    pub fn subscribe(channels: &[Self]) -> Receiver<SampleBlock> {
+        let (tx, rx) = bounded(1);
+
+        if let Some(first) = channels.first() {
+            first.subscribers.lock().unwrap().push(tx);
+        }
+
+        rx
+    }
+
     pub fn set(&self, val: f32) {
         self.val.store(val.to_bits(), Ordering::Relaxed)
     }
@@ -0,0 +1,265 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use serde::{Deserialize, Serialize};
+
+use super::CalibratedChannel;
+use crate::broker::{BrokerBuilder, Topic};
+
+const MIN_WINDOW_MS: u64 = 100;
+const MAX_WINDOW_MS: u64 = 60_000;
+
+/// Number of frequency bins between 0 Hz and the Nyquist frequency
+const SPECTRUM_BINS: usize = 32;
+
+/// The channels to analyze and the length of the window to analyze them
+/// over. The analysis is disabled if no channels are selected.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct RippleConfig {
+    pub channels: Vec<String>,
+    pub window_ms: u64,
+}
+
+impl Default for RippleConfig {
+    fn default() -> Self {
+        Self {
+            channels: Vec::new(),
+            window_ms: 1000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct SpectrumBin {
+    /// Center frequency of the bin in Hz
+    pub frequency: f32,
+    pub amplitude: f32,
+}
+
+/// The result of analyzing one channel over one window
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct RippleAnalysis {
+    pub channel: String,
+    pub mean: f32,
+    pub peak_to_peak: f32,
+    /// The rate the ADC was sampled at in the window (in Hz).
+    /// The spectrum reaches up to half of it.
+    pub sample_rate: f32,
+    pub spectrum: Vec<SpectrumBin>,
+}
+
+/// Calculate the amplitude spectrum of `samples` (with the mean removed)
+/// in `bins` bins from 0 Hz to half the `sample_rate`.
+///
+/// A Hann window is applied to reduce the leakage between bins.
+/// Only a few bins are needed, so calculating them one by one is cheap
+/// enough and does not require a full FFT.
+fn spectrum(samples: &[f32], sample_rate: f32, bins: usize) -> Vec<SpectrumBin> {
+    let len = samples.len();
+
+    if len < 2 || bins == 0 {
+        return Vec::new();
+    }
+
+    let mean = samples.iter().sum::<f32>() / (len as f32);
+
+    let windowed: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, val)| {
+            let hann = 0.5 - 0.5 * (2.0 * PI * (i as f32) / ((len - 1) as f32)).cos();
+            (val - mean) * hann
+        })
+        .collect();
+
+    // The Hann window halves the amplitude of a sine on average
+    let scale = 2.0 / (0.5 * len as f32);
+
+    (0..bins)
+        .map(|bin| {
+            let frequency = (bin as f32) * sample_rate / (2.0 * bins as f32);
+            let omega = 2.0 * PI * frequency / sample_rate;
+
+            let (re, im) = windowed
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (i, val)| {
+                    let phase = omega * (i as f32);
+                    (re + val * phase.cos(), im - val * phase.sin())
+                });
+
+            SpectrumBin {
+                frequency,
+                amplitude: (re * re + im * im).sqrt() * scale,
+            }
+        })
+        .collect()
+}
+
+fn analyze(channel: &str, samples: &[f32], sample_rate: f32) -> Option<RippleAnalysis> {
+    if samples.len() < 2 || !sample_rate.is_normal() {
+        return None;
+    }
+
+    let min = samples.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = samples.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let mean = samples.iter().sum::<f32>() / (samples.len() as f32);
+
+    Some(RippleAnalysis {
+        channel: channel.to_string(),
+        mean,
+        peak_to_peak: max - min,
+        sample_rate,
+        spectrum: spectrum(samples, sample_rate, SPECTRUM_BINS),
+    })
+}
+
+/// Periodically analyze the ripple on the channels selected in the config
+/// topic, e.g. to check the quality of the DUT power supply without an
+/// external instrument.
+pub(super) fn setup(bb: &mut BrokerBuilder, channels: Vec<(&'static str, CalibratedChannel)>) {
+    let config = bb.topic(
+        "/v1/tac/adc/ripple/config",
+        true,
+        true,
+        true,
+        Some(RippleConfig::default()),
+        1,
+    );
+    let results: Arc<Topic<Vec<RippleAnalysis>>> =
+        bb.topic_ro("/v1/tac/adc/ripple", Some(Vec::new()));
+
+    let (config_events, _) = config.clone().subscribe_unbounded();
+
+    spawn(async move {
+        loop {
+            // Only the most recent config is of interest
+            while config_events.try_recv().is_ok() {}
+
+            let config = config.try_get().unwrap_or_default();
+
+            let (names, selected): (Vec<&'static str>, Vec<CalibratedChannel>) = channels
+                .iter()
+                .filter(|(name, _)| config.channels.iter().any(|c| c == *name))
+                .cloned()
+                .unzip();
+
+            if selected.is_empty() {
+                results.modify(|prev| match prev {
+                    Some(prev) if prev.is_empty() => None,
+                    _ => Some(Vec::new()),
+                });
+
+                // Do nothing until the analysis is enabled
+                match config_events.recv().await {
+                    Ok(_) => continue,
+                    Err(_) => break,
+                }
+            }
+
+            let window =
+                Duration::from_millis(config.window_ms.clamp(MIN_WINDOW_MS, MAX_WINDOW_MS));
+
+            let blocks = CalibratedChannel::subscribe(&selected);
+            let mut samples: Vec<Vec<f32>> = vec![Vec::new(); selected.len()];
+            let mut first_ts: Option<Instant> = None;
+            let mut sample_rate = 0.0;
+            let mut complete = false;
+
+            while let Ok(block) = blocks.recv().await {
+                // Start over with the new config
+                if !config_events.is_empty() {
+                    break;
+                }
+
+                // An empty block has no last sample to measure the window by
+                if block.is_empty() {
+                    continue;
+                }
+
+                for (channel_samples, values) in samples.iter_mut().zip(block.values.iter()) {
+                    channel_samples.extend_from_slice(values);
+                }
+
+                sample_rate = 1.0 / block.period.as_secs_f32();
+
+                let first = *first_ts.get_or_insert(block.start.as_instant());
+                let last = block.ts(block.len() - 1).as_instant();
+
+                if last.saturating_duration_since(first) >= window {
+                    complete = true;
+                    break;
+                }
+            }
+
+            if !complete {
+                // Wait for a new config instead of spinning if the ADC
+                // stopped providing samples.
+                if config_events.is_empty() && config_events.recv().await.is_err() {
+                    break;
+                }
+
+                continue;
+            }
+
+            let analyses = names
+                .iter()
+                .zip(samples.iter())
+                .filter_map(|(name, samples)| analyze(name, samples, sample_rate))
+                .collect();
+
+            results.set(analyses);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::spectrum;
+
+    #[test]
+    fn sine_spectrum() {
+        // A sine with an amplitude of 2 at 25 Hz, sampled at 200 Hz
+        let samples: Vec<f32> = (0..400)
+            .map(|i| 5.0 + 2.0 * (2.0 * PI * 25.0 * (i as f32) / 200.0).sin())
+            .collect();
+
+        let bins = spectrum(&samples, 200.0, 8);
+
+        assert_eq!(bins.len(), 8);
+        assert_eq!(bins[2].frequency, 25.0);
+
+        // The peak is in the right bin and has roughly the right amplitude
+        let peak = bins
+            .iter()
+            .max_by(|a, b| a.amplitude.partial_cmp(&b.amplitude).unwrap())
+            .unwrap();
+
+        assert_eq!(peak.frequency, 25.0);
+        assert!((peak.amplitude - 2.0).abs() < 0.1);
+
+        // The offset is removed
+        assert!(bins[0].amplitude < 0.1);
+    }
+}
@@ -0,0 +1,160 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use async_tungstenite::tungstenite::Message;
+use futures_util::SinkExt;
+use serde::Deserialize;
+use tide::{Request, Response, Server};
+
+use super::CalibratedChannel;
+use crate::http_server::upgrade_websocket;
+use crate::measurement::Timestamp;
+
+/// Send a block once it contains this many samples ...
+const BLOCK_SAMPLES: usize = 256;
+
+/// ... or once its first sample is this old, whatever comes first
+const BLOCK_AGE: Duration = Duration::from_millis(100);
+
+#[derive(Deserialize)]
+struct SampleQuery {
+    /// Comma separated list of channel names, e.g. `pwr-volt,pwr-curr`
+    channels: String,
+    /// Only send every n-th sample
+    decimation: Option<u32>,
+}
+
+/// Append a sample of all selected channels to `block`.
+///
+/// Each sample consists of a timestamp in microseconds since the unix epoch
+/// (u64) followed by the value of each channel (f32), all in little endian.
+fn encode_sample(block: &mut Vec<u8>, ts: Timestamp, values: impl Iterator<Item = f32>) {
+    let ts = ts
+        .in_system_time()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|ts| ts.as_micros() as u64)
+        .unwrap_or(0);
+
+    block.extend_from_slice(&ts.to_le_bytes());
+
+    for value in values {
+        block.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Stream the raw samples of selected ADC channels in a compact binary
+/// format via a websocket, as JSON encoding every single sample does not
+/// keep up with the rate the ADC provides.
+pub(super) fn serve(server: &mut Server<()>, channels: Vec<(&'static str, CalibratedChannel)>) {
+    server
+        .at("/v1/tac/adc/samples")
+        .get(move |req: Request<()>| {
+            let channels = channels.clone();
+
+            async move {
+                let query: SampleQuery = match req.query() {
+                    Ok(query) => query,
+                    Err(e) => {
+                        return Ok(Response::builder(400)
+                            .body(format!("Failed to parse query parameters: {e}"))
+                            .build())
+                    }
+                };
+
+                let mut selected = Vec::new();
+
+                for name in query.channels.split(',').map(str::trim) {
+                    match channels.iter().find(|(n, _)| *n == name) {
+                        Some((_, channel)) => selected.push(channel.clone()),
+                        None => {
+                            return Ok(Response::builder(400)
+                                .body(format!("Unknown ADC channel \"{name}\""))
+                                .build())
+                        }
+                    }
+                }
+
+                let decimation = query.decimation.unwrap_or(1).max(1);
+                let sample_size = 8 + 4 * selected.len();
+
+                upgrade_websocket(&req, &[], move |mut ws| async move {
+                    let blocks = CalibratedChannel::subscribe(&selected);
+
+                    let mut block = Vec::new();
+                    let mut block_samples = 0;
+                    let mut block_start = Instant::now();
+                    let mut since_sent = 0;
+
+                    while let Ok(samples) = blocks.recv().await {
+                        for i in 0..samples.len() {
+                            since_sent += 1;
+
+                            if since_sent < decimation {
+                                continue;
+                            }
+
+                            since_sent = 0;
+
+                            if block_samples == 0 {
+                                block_start = Instant::now();
+                            }
+
+                            encode_sample(&mut block, samples.ts(i), samples.sample(i));
+                            block_samples += 1;
+                        }
+
+                        // Send full blocks right away and the rest once its
+                        // first sample is old enough.
+                        while block_samples >= BLOCK_SAMPLES
+                            || (block_samples > 0 && block_start.elapsed() >= BLOCK_AGE)
+                        {
+                            let len = block_samples.min(BLOCK_SAMPLES);
+                            let msg = Message::binary(
+                                block.drain(..len * sample_size).collect::<Vec<u8>>(),
+                            );
+
+                            if ws.send(msg).await.is_err() {
+                                return;
+                            }
+
+                            block_samples -= len;
+                        }
+                    }
+                })
+                .await
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode_sample;
+    use crate::measurement::Timestamp;
+
+    #[test]
+    fn sample_layout() {
+        let mut block = Vec::new();
+
+        encode_sample(&mut block, Timestamp::now(), [1.5, -2.0].iter().copied());
+
+        assert_eq!(block.len(), 8 + 2 * 4);
+        assert_eq!(block[8..12], 1.5f32.to_le_bytes());
+        assert_eq!(block[12..16], (-2.0f32).to_le_bytes());
+    }
+}
@@ -0,0 +1,274 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::fs::File;
+use std::io::ErrorKind;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_std::fs::{read_dir, read_to_string};
+use async_std::path::{Path, PathBuf};
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::{sleep, spawn};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::history::History;
+use crate::broker::{BrokerBuilder, Topic};
+use crate::measurement::Measurement;
+
+#[cfg(feature = "demo_mode")]
+const CONFIG_PATH: &str = "demo_files/etc/tacd/sensors.json";
+
+#[cfg(not(feature = "demo_mode"))]
+const CONFIG_PATH: &str = "/etc/tacd/sensors.json";
+
+#[cfg(feature = "demo_mode")]
+const IIO_DEVICES: &str = "demo_files/sys/bus/iio/devices";
+
+#[cfg(not(feature = "demo_mode"))]
+const IIO_DEVICES: &str = "/sys/bus/iio/devices";
+
+#[cfg(feature = "demo_mode")]
+const HWMON_DEVICES: &str = "demo_files/sys/class/hwmon";
+
+#[cfg(not(feature = "demo_mode"))]
+const HWMON_DEVICES: &str = "/sys/class/hwmon";
+
+const MIN_INTERVAL_MS: u64 = 10;
+
+/// Where to read the raw value of an external sensor from
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum SensorSource {
+    /// Any file that contains a single number, e.g. a sysfs attribute
+    File(String),
+    /// An IIO channel, e.g. `{"device": "ina219", "channel": "in_current0"}`.
+    /// The value is read from `<channel>_raw` of the IIO device with the
+    /// given name and converted to the unit of the channel using its
+    /// `<channel>_scale` and `<channel>_offset` (if present).
+    Iio { device: String, channel: String },
+    /// A hwmon attribute, e.g. `{"device": "tmp102", "attribute": "temp1_input"}`.
+    Hwmon { device: String, attribute: String },
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_interval_ms() -> u64 {
+    1000
+}
+
+/// An external sensor as declared in the sensor config file.
+///
+/// The published value is `raw * scale + offset`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SensorConfig {
+    pub name: String,
+    pub source: SensorSource,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    #[serde(default)]
+    pub offset: f32,
+    #[serde(default)]
+    pub unit: String,
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+}
+
+/// Find the device directory below `class` that has the given `name`.
+/// The numbering of devices (e.g. `hwmon3`) depends on the probe order,
+/// so the names are the only stable way to refer to them.
+async fn find_device(class: &str, name: &str) -> Option<PathBuf> {
+    let mut entries = read_dir(class).await.ok()?;
+
+    while let Some(Ok(entry)) = entries.next().await {
+        let path = entry.path();
+
+        if let Ok(dev_name) = read_to_string(path.join("name")).await {
+            if dev_name.trim() == name {
+                return Some(path);
+            }
+        }
+    }
+
+    None
+}
+
+/// The file to read a sensor value from and how to convert it
+struct ResolvedSource {
+    path: PathBuf,
+    scale: f32,
+    offset: f32,
+}
+
+impl ResolvedSource {
+    async fn read(&self) -> Option<f32> {
+        read_value(&self.path)
+            .await
+            .map(|raw| (raw + self.offset) * self.scale)
+    }
+}
+
+impl SensorSource {
+    async fn resolve(&self) -> Option<ResolvedSource> {
+        let (path, scale, offset) = match self {
+            Self::File(path) => (PathBuf::from(path), 1.0, 0.0),
+            Self::Iio { device, channel } => {
+                let dev = find_device(IIO_DEVICES, device).await?;
+
+                // Not every IIO channel has a scale and offset. Their values
+                // are the identity in that case.
+                let scale = read_value(&dev.join(format!("{channel}_scale")))
+                    .await
+                    .unwrap_or(1.0);
+                let offset = read_value(&dev.join(format!("{channel}_offset")))
+                    .await
+                    .unwrap_or(0.0);
+
+                (dev.join(format!("{channel}_raw")), scale, offset)
+            }
+            Self::Hwmon { device, attribute } => {
+                let dev = find_device(HWMON_DEVICES, device).await?;
+
+                (dev.join(attribute), 1.0, 0.0)
+            }
+        };
+
+        Some(ResolvedSource {
+            path,
+            scale,
+            offset,
+        })
+    }
+}
+
+async fn read_value(path: &Path) -> Option<f32> {
+    read_to_string(path).await.ok()?.trim().parse().ok()
+}
+
+/// A measurement channel provided by a sensor that is not part of the TAC
+/// itself, like an external shunt or temperature probe.
+#[derive(Clone)]
+pub struct Sensor {
+    pub name: String,
+    pub topic: Arc<Topic<Measurement>>,
+    pub(super) history: Arc<Mutex<History>>,
+}
+
+impl Sensor {
+    fn new(bb: &mut BrokerBuilder, config: SensorConfig) -> Self {
+        let this = Self {
+            name: config.name.clone(),
+            topic: bb.topic_ro(&format!("/v1/tac/sensors/{}", config.name), None),
+            history: Arc::new(Mutex::new(History::new())),
+        };
+
+        let sensor = this.clone();
+
+        spawn(async move {
+            let interval = Duration::from_millis(config.interval_ms.max(MIN_INTERVAL_MS));
+            let mut source = None;
+
+            loop {
+                sleep(interval).await;
+
+                // Sensors may be connected via USB and come and go, so the
+                // device is looked up again if reading from it fails.
+                if source.is_none() {
+                    source = config.source.resolve().await;
+                }
+
+                let raw = match &source {
+                    Some(source) => source.read().await,
+                    None => None,
+                };
+
+                match raw {
+                    Some(raw) => {
+                        let meas = Measurement::now(raw * config.scale + config.offset);
+
+                        sensor.history.lock().unwrap().add(&meas);
+                        sensor.topic.set(meas);
+                    }
+                    None => {
+                        // Let consumers know that the sensor is gone instead
+                        // of silently keeping the last value around.
+                        let last = sensor.topic.try_get().map(|meas| meas.value);
+                        sensor.topic.set(Measurement::read_error(last));
+
+                        source = None;
+                    }
+                }
+            }
+        });
+
+        this
+    }
+}
+
+fn load_config() -> Vec<SensorConfig> {
+    let file = match File::open(CONFIG_PATH) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            info!("Sensor config at \"{CONFIG_PATH}\" does not exist. Not adding external sensors");
+            return Vec::new();
+        }
+        Err(e) => {
+            warn!("Failed to open sensor config at \"{CONFIG_PATH}\": {e}");
+            return Vec::new();
+        }
+    };
+
+    serde_json::from_reader(file).unwrap_or_else(|e| {
+        warn!("Failed to parse sensor config at \"{CONFIG_PATH}\": {e}");
+        Vec::new()
+    })
+}
+
+/// Set up the external sensors declared in the sensor config file
+pub fn setup(bb: &mut BrokerBuilder, reserved_names: &[&str]) -> Vec<Sensor> {
+    let mut configs = load_config();
+
+    // The names are used in topic paths and to refer to the channels in e.g.
+    // alarm rules, so they have to be unique.
+    let mut seen: Vec<String> = reserved_names.iter().map(|n| n.to_string()).collect();
+
+    configs.retain(|config| {
+        let valid =
+            !config.name.is_empty() && !config.name.contains('/') && !seen.contains(&config.name);
+
+        if !valid {
+            warn!(
+                "Ignoring external sensor with invalid or duplicate name \"{}\"",
+                config.name
+            );
+        }
+
+        seen.push(config.name.clone());
+
+        valid
+    });
+
+    bb.topic_ro("/v1/tac/sensors", Some(configs.clone()));
+
+    configs
+        .into_iter()
+        .map(|config| Sensor::new(bb, config))
+        .collect()
+}
@@ -0,0 +1,264 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Summary of the values of an ADC channel in a rolling time window
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Statistics {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub rms: f32,
+    pub count: usize,
+}
+
+/// Number of chunks a window is divided into
+const CHUNKS: u32 = 100;
+
+/// The summary of consecutive samples in a window
+#[derive(Clone, Copy)]
+struct Chunk {
+    start: Instant,
+    end: Instant,
+    min: f32,
+    max: f32,
+    sum: f64,
+    sum_sq: f64,
+    count: usize,
+}
+
+impl Chunk {
+    fn new(ts: Instant, value: f32) -> Self {
+        Self {
+            start: ts,
+            end: ts,
+            min: value,
+            max: value,
+            sum: value as f64,
+            sum_sq: (value as f64) * (value as f64),
+            count: 1,
+        }
+    }
+
+    fn add(&mut self, ts: Instant, value: f32) {
+        self.end = ts;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value as f64;
+        self.sum_sq += (value as f64) * (value as f64);
+        self.count += 1;
+    }
+}
+
+/// The values of an ADC channel in a rolling time window.
+///
+/// The samples are summarized in chunks of a hundredth of the window
+/// length, so that long windows at high sample rates neither use up a lot
+/// of memory nor have to be iterated over for every new value.
+/// The sums and the candidates for minimum and maximum are updated as
+/// chunks enter and leave the window.
+pub struct Window {
+    chunks: VecDeque<Chunk>,
+    /// The chunk new samples are added to
+    current: Option<Chunk>,
+    /// Candidates for the minimum in increasing order of value
+    min: VecDeque<(Instant, f32)>,
+    /// Candidates for the maximum in decreasing order of value
+    max: VecDeque<(Instant, f32)>,
+    sum: f64,
+    sum_sq: f64,
+    count: usize,
+}
+
+impl Window {
+    pub fn new() -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            current: None,
+            min: VecDeque::new(),
+            max: VecDeque::new(),
+            sum: 0.0,
+            sum_sq: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Move a complete chunk into the window
+    fn push(&mut self, chunk: Chunk) {
+        self.sum += chunk.sum;
+        self.sum_sq += chunk.sum_sq;
+        self.count += chunk.count;
+
+        while self
+            .min
+            .back()
+            .map(|(_, v)| *v >= chunk.min)
+            .unwrap_or(false)
+        {
+            self.min.pop_back();
+        }
+
+        while self
+            .max
+            .back()
+            .map(|(_, v)| *v <= chunk.max)
+            .unwrap_or(false)
+        {
+            self.max.pop_back();
+        }
+
+        self.min.push_back((chunk.end, chunk.min));
+        self.max.push_back((chunk.end, chunk.max));
+        self.chunks.push_back(chunk);
+    }
+
+    /// Add a new value and drop the ones that are more than `length` older
+    /// than it.
+    pub fn add(&mut self, ts: Instant, value: f32, length: Duration) {
+        let chunk_length = length / CHUNKS;
+
+        match self.current.as_mut() {
+            Some(chunk) if ts.saturating_duration_since(chunk.start) < chunk_length => {
+                chunk.add(ts, value)
+            }
+            _ => {
+                if let Some(chunk) = self.current.replace(Chunk::new(ts, value)) {
+                    self.push(chunk);
+                }
+            }
+        }
+
+        // A chunk leaves the window once its newest sample does
+        let expired = |end: &Instant| ts.saturating_duration_since(*end) > length;
+
+        while let Some(old) = self.chunks.front().filter(|c| expired(&c.end)).copied() {
+            self.chunks.pop_front();
+            self.sum -= old.sum;
+            self.sum_sq -= old.sum_sq;
+            self.count -= old.count;
+        }
+
+        while self
+            .min
+            .front()
+            .map(|(end, _)| expired(end))
+            .unwrap_or(false)
+        {
+            self.min.pop_front();
+        }
+
+        while self
+            .max
+            .front()
+            .map(|(end, _)| expired(end))
+            .unwrap_or(false)
+        {
+            self.max.pop_front();
+        }
+    }
+
+    pub fn statistics(&self) -> Option<Statistics> {
+        let current = self.current.as_ref()?;
+
+        let min = self.min.front().map(|(_, v)| *v).unwrap_or(current.min);
+        let max = self.max.front().map(|(_, v)| *v).unwrap_or(current.max);
+        let sum = self.sum + current.sum;
+        let sum_sq = self.sum_sq + current.sum_sq;
+        let count = self.count + current.count;
+
+        Some(Statistics {
+            min: min.min(current.min),
+            max: max.max(current.max),
+            mean: (sum / count as f64) as f32,
+            rms: (sum_sq / count as f64).max(0.0).sqrt() as f32,
+            count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Window;
+
+    #[test]
+    fn rolling_window() {
+        let start = Instant::now();
+        let length = Duration::from_secs(3);
+        let mut window = Window::new();
+
+        assert!(window.statistics().is_none());
+
+        for (secs, value) in [(0, 5.0), (1, -1.0), (2, 3.0), (3, 1.0)] {
+            window.add(start + Duration::from_secs(secs), value, length);
+        }
+
+        let stats = window.statistics().unwrap();
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.min, -1.0);
+        assert_eq!(stats.max, 5.0);
+        assert_eq!(stats.mean, 2.0);
+        assert_eq!(stats.rms, 3.0);
+
+        // The maximum and then the minimum leave the window
+        window.add(start + Duration::from_secs(4), 2.0, length);
+
+        let stats = window.statistics().unwrap();
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.min, -1.0);
+        assert_eq!(stats.max, 3.0);
+
+        window.add(start + Duration::from_secs(5), 2.0, length);
+
+        let stats = window.statistics().unwrap();
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.max, 3.0);
+        assert_eq!(stats.mean, 2.0);
+    }
+
+    #[test]
+    fn chunked_window() {
+        let start = Instant::now();
+        let length = Duration::from_secs(1);
+        let mut window = Window::new();
+
+        // Ten samples per chunk of 10ms
+        for i in 0..10_000u64 {
+            let value = match i {
+                5_000 => 10.0,
+                _ => (i % 2) as f32,
+            };
+
+            window.add(start + Duration::from_millis(i), value, length);
+        }
+
+        // The window does not grow with the number of samples
+        assert!(window.chunks.len() <= 101);
+
+        let stats = window.statistics().unwrap();
+        assert!(stats.count >= 1_000 && stats.count <= 1_010);
+        assert_eq!(stats.min, 0.0);
+        assert_eq!(stats.max, 1.0);
+        assert!((stats.mean - 0.5).abs() < 0.01);
+    }
+}
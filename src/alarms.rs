@@ -0,0 +1,288 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::{Duration, Instant};
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use futures::stream::{select_all, BoxStream};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::adc::Adc;
+use crate::broker::{BrokerBuilder, Topic};
+use crate::dut_power::OutputRequest;
+use crate::measurement::{Measurement, Validity};
+
+/// Which side of the limit is considered bad
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Condition {
+    Over,
+    Under,
+}
+
+/// What to do (in addition to showing the alarm) once an alarm triggers
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum AlarmAction {
+    DutPowerOff,
+}
+
+/// A user defined limit for an ADC channel or external sensor
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct AlarmRule {
+    /// The name of the ADC channel or external sensor, e.g. `pwr-curr`,
+    /// or `soc-temperature`
+    pub channel: String,
+    pub condition: Condition,
+    pub limit: f32,
+    /// How far the value has to get back from the limit for the alarm to
+    /// be cleared again
+    #[serde(default)]
+    pub hysteresis: f32,
+    /// How long (in milliseconds) the limit has to be violated continuously
+    /// before the alarm triggers
+    #[serde(default)]
+    pub debounce_ms: u64,
+    /// How long (in milliseconds) the value has to stay back from the limit
+    /// (including the hysteresis) before the alarm is cleared again
+    #[serde(default)]
+    pub clear_debounce_ms: u64,
+    #[serde(default)]
+    pub action: Option<AlarmAction>,
+}
+
+impl AlarmRule {
+    fn violated(&self, value: f32) -> bool {
+        match self.condition {
+            Condition::Over => value > self.limit,
+            Condition::Under => value < self.limit,
+        }
+    }
+
+    fn cleared(&self, value: f32) -> bool {
+        match self.condition {
+            Condition::Over => value < self.limit - self.hysteresis,
+            Condition::Under => value > self.limit + self.hysteresis,
+        }
+    }
+}
+
+/// An alarm that is currently active and the value that triggered it
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TriggeredAlarm {
+    pub rule: AlarmRule,
+    pub value: f32,
+}
+
+#[derive(Default)]
+struct RuleState {
+    violated_since: Option<Instant>,
+    cleared_since: Option<Instant>,
+    triggered_by: Option<f32>,
+}
+
+impl RuleState {
+    /// Feed a new value into the state machine of a rule.
+    ///
+    /// Returns whether the alarm was triggered or cleared by this value.
+    fn update(&mut self, rule: &AlarmRule, ts: Instant, value: f32) -> bool {
+        if self.triggered_by.is_some() {
+            if !rule.cleared(value) {
+                self.cleared_since = None;
+                return false;
+            }
+
+            let since = *self.cleared_since.get_or_insert(ts);
+
+            if ts.duration_since(since) >= Duration::from_millis(rule.clear_debounce_ms) {
+                self.triggered_by = None;
+                self.violated_since = None;
+                self.cleared_since = None;
+                return true;
+            }
+
+            return false;
+        }
+
+        if !rule.violated(value) {
+            self.violated_since = None;
+            return false;
+        }
+
+        let since = *self.violated_since.get_or_insert(ts);
+
+        if ts.duration_since(since) >= Duration::from_millis(rule.debounce_ms) {
+            self.triggered_by = Some(value);
+            return true;
+        }
+
+        false
+    }
+}
+
+/// Check the ADC channels against user defined limits, so that not every
+/// user of the TAC has to implement this on their own.
+pub struct Alarms {
+    pub triggered: Arc<Topic<Vec<TriggeredAlarm>>>,
+}
+
+impl Alarms {
+    pub fn new(
+        bb: &mut BrokerBuilder,
+        adc: &Adc,
+        soc_temperature: Arc<Topic<Measurement>>,
+        dut_pwr_request: Arc<Topic<OutputRequest>>,
+    ) -> Self {
+        let rules: Arc<Topic<Vec<AlarmRule>>> = bb.topic(
+            "/v1/tac/alarms/rules",
+            true,
+            true,
+            true,
+            Some(Vec::new()),
+            1,
+        );
+        let triggered = bb.topic_ro("/v1/tac/alarms/triggered", Some(Vec::new()));
+
+        let mut streams: Vec<BoxStream<'static, (String, Measurement)>> = Vec::new();
+
+        for (name, channel) in adc.channels() {
+            let (events, _) = channel.topic.clone().subscribe_unbounded();
+            streams.push(Box::pin(events.map(move |meas| (name.to_string(), meas))));
+        }
+
+        for sensor in adc.sensors.iter() {
+            let name = sensor.name.clone();
+            let (events, _) = sensor.topic.clone().subscribe_unbounded();
+            streams.push(Box::pin(events.map(move |meas| (name.clone(), meas))));
+        }
+
+        let (events, _) = soc_temperature.subscribe_unbounded();
+        streams.push(Box::pin(
+            events.map(|meas| ("soc-temperature".to_string(), meas)),
+        ));
+
+        let triggered_task = triggered.clone();
+
+        spawn(async move {
+            let mut events = select_all(streams);
+            let mut active_rules: Vec<AlarmRule> = Vec::new();
+            let mut states: Vec<RuleState> = Vec::new();
+
+            while let Some((name, meas)) = events.next().await {
+                // A sensor that can not be read neither violates nor clears
+                // a limit
+                if meas.validity == Validity::ReadError {
+                    continue;
+                }
+
+                // Start over with a clean state once the rules are changed
+                let current_rules = rules.try_get().unwrap_or_default();
+
+                if current_rules != active_rules {
+                    states = current_rules.iter().map(|_| RuleState::default()).collect();
+                    active_rules = current_rules;
+                    triggered_task.set(Vec::new());
+                }
+
+                let mut changed = false;
+
+                for (rule, state) in active_rules.iter().zip(states.iter_mut()) {
+                    if rule.channel != name || !state.update(rule, meas.ts.as_instant(), meas.value)
+                    {
+                        continue;
+                    }
+
+                    changed = true;
+
+                    if state.triggered_by.is_none() {
+                        continue;
+                    }
+
+                    warn!(
+                        "Alarm for {} triggered at {}, limit is {}",
+                        rule.channel, meas.value, rule.limit
+                    );
+
+                    if rule.action == Some(AlarmAction::DutPowerOff) {
+                        dut_pwr_request.set(OutputRequest::Off);
+                    }
+                }
+
+                if changed {
+                    let alarms: Vec<TriggeredAlarm> = active_rules
+                        .iter()
+                        .zip(states.iter())
+                        .filter_map(|(rule, state)| {
+                            state.triggered_by.map(|value| TriggeredAlarm {
+                                rule: rule.clone(),
+                                value,
+                            })
+                        })
+                        .collect();
+
+                    triggered_task.set(alarms);
+                }
+            }
+        });
+
+        Self { triggered }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{AlarmRule, Condition, RuleState};
+
+    #[test]
+    fn hysteresis_and_debounce() {
+        let rule = AlarmRule {
+            channel: "pwr-curr".to_string(),
+            condition: Condition::Over,
+            limit: 2.0,
+            hysteresis: 0.5,
+            debounce_ms: 100,
+            clear_debounce_ms: 100,
+            action: None,
+        };
+
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut state = RuleState::default();
+
+        // Short spikes do not trigger the alarm
+        assert!(!state.update(&rule, at(0), 2.5));
+        assert!(!state.update(&rule, at(50), 1.0));
+        assert!(!state.update(&rule, at(100), 2.5));
+        assert!(!state.update(&rule, at(150), 2.5));
+        assert!(state.update(&rule, at(200), 3.0));
+        assert_eq!(state.triggered_by, Some(3.0));
+
+        // Going back below the limit is not enough to clear it
+        assert!(!state.update(&rule, at(250), 1.8));
+
+        // Neither is hovering around the hysteresis
+        assert!(!state.update(&rule, at(300), 1.4));
+        assert!(!state.update(&rule, at(350), 1.6));
+        assert!(!state.update(&rule, at(400), 1.4));
+        assert!(!state.update(&rule, at(450), 1.4));
+        assert!(state.update(&rule, at(500), 1.2));
+        assert_eq!(state.triggered_by, None);
+    }
+}
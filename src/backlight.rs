@@ -0,0 +1,356 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use async_std::future::timeout;
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::{spawn, spawn_blocking};
+use futures::stream::select;
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::broker::{BrokerBuilder, Topic};
+use crate::measurement::Measurement;
+
+#[cfg(feature = "demo_mode")]
+mod hw {
+    use std::io::Result;
+
+    pub struct Backlight;
+
+    impl Backlight {
+        pub fn new(_: &str) -> Result<Self> {
+            Ok(Self)
+        }
+
+        pub fn max_brightness(&self) -> Result<u64> {
+            Ok(8)
+        }
+
+        pub fn set_brightness(&self, val: u64) -> Result<()> {
+            log::info!("Backlight: Set brightness to {}", val);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod hw {
+    pub use sysfs_class::Backlight;
+}
+
+#[cfg(not(feature = "demo_mode"))]
+use sysfs_class::{Brightness, SysClass};
+
+#[cfg(feature = "demo_mode")]
+const IIO_DEVICES_PATH: &str = "demo_files/sys/bus/iio/devices";
+
+#[cfg(not(feature = "demo_mode"))]
+const IIO_DEVICES_PATH: &str = "/sys/bus/iio/devices";
+
+/// Brightness of the dimmed display relative to the configured brightness
+const DIMMED_BRIGHTNESS: f32 = 0.25;
+
+/// Turn the backlight off and power the display down completely after the
+/// screensaver was shown for this long.
+const DEEP_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// How often to read the ambient light sensor
+const AMBIENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Backlight brightness to use above a given ambient illuminance (in lux)
+const AMBIENT_STEPS: &[(f32, f32)] = &[
+    (0.0, 0.1),
+    (5.0, 0.25),
+    (50.0, 0.5),
+    (500.0, 0.75),
+    (2000.0, 1.0),
+];
+
+/// Factor the illuminance has to be above (or below) the threshold of a
+/// step before switching to it, so that the brightness does not flicker
+/// back and forth at the border between two steps.
+const AMBIENT_HYSTERESIS: f32 = 1.25;
+
+/// Get the index into `AMBIENT_STEPS` to use for the illuminance `lux`,
+/// given that `current` is in use right now.
+fn ambient_step(current: Option<usize>, lux: f32) -> usize {
+    let target = AMBIENT_STEPS
+        .iter()
+        .rposition(|(threshold, _)| lux >= *threshold)
+        .unwrap_or(0);
+
+    let mut step = match current {
+        Some(step) => step,
+        None => return target,
+    };
+
+    while step < target && lux >= AMBIENT_STEPS[step + 1].0 * AMBIENT_HYSTERESIS {
+        step += 1;
+    }
+
+    while step > target && lux < AMBIENT_STEPS[step].0 / AMBIENT_HYSTERESIS {
+        step -= 1;
+    }
+
+    step
+}
+
+/// Find an IIO device with an illuminance channel and return the path to
+/// the sysfs file to read it from.
+fn find_light_sensor() -> Option<PathBuf> {
+    Path::new(IIO_DEVICES_PATH)
+        .read_dir()
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().join("in_illuminance_input"))
+        .find(|path| path.is_file())
+}
+
+/// Periodically read the ambient light sensor at `path` (in lux)
+fn read_light_sensor(path: PathBuf, illuminance: Arc<Topic<Measurement>>) {
+    spawn_blocking(move || loop {
+        match std::fs::read_to_string(&path) {
+            Ok(val) => match val.trim().parse::<f32>() {
+                Ok(lux) => illuminance.set(Measurement::now(lux)),
+                Err(e) => warn!("Failed to parse ambient light sensor value: {e}"),
+            },
+            Err(e) => {
+                warn!("Failed to read ambient light sensor: {e}");
+                break;
+            }
+        }
+
+        std::thread::sleep(AMBIENT_INTERVAL);
+    });
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum IdleLevel {
+    Active,
+    Dimmed,
+    Off,
+}
+
+impl IdleLevel {
+    fn factor(&self) -> f32 {
+        match self {
+            Self::Active => 1.0,
+            Self::Dimmed => DIMMED_BRIGHTNESS,
+            Self::Off => 0.0,
+        }
+    }
+
+    /// Dim the display when three quarters of the screensaver timeout have
+    /// passed and turn it off completely after being idle for even longer.
+    fn from_idle_time(idle: Duration, screensaver_timeout: Option<u64>) -> Self {
+        let screensaver_timeout = match screensaver_timeout {
+            Some(secs) => Duration::from_secs(secs),
+            None => return Self::Active,
+        };
+
+        if idle >= screensaver_timeout + DEEP_IDLE_TIMEOUT {
+            Self::Off
+        } else if idle >= screensaver_timeout * 3 / 4 {
+            Self::Dimmed
+        } else {
+            Self::Active
+        }
+    }
+}
+
+pub struct Backlight {
+    pub brightness: Arc<Topic<f32>>,
+    pub auto_brightness: Arc<Topic<bool>>,
+    pub illuminance: Arc<Topic<Measurement>>,
+    pub idle: Arc<Topic<IdleLevel>>,
+}
+
+impl Backlight {
+    pub fn new(bb: &mut BrokerBuilder) -> Self {
+        let brightness = bb.topic(
+            "/v1/tac/display/backlight/brightness",
+            true,
+            true,
+            true,
+            Some(1.0),
+            1,
+        );
+        let idle = bb.topic_ro("/v1/tac/display/backlight/idle", Some(IdleLevel::Active));
+
+        // Follow the ambient light instead of using the configured brightness
+        // (if there is an ambient light sensor).
+        // This is opt-in, so that a brightness that was set by hand is not
+        // overridden after an update.
+        let auto_brightness = bb.topic(
+            "/v1/tac/display/backlight/auto",
+            true,
+            true,
+            true,
+            Some(false),
+            1,
+        );
+        let illuminance = bb.topic_ro("/v1/tac/display/backlight/illuminance", None);
+
+        match find_light_sensor() {
+            Some(path) => read_light_sensor(path, illuminance.clone()),
+            None => info!("No ambient light sensor found. Not adjusting brightness"),
+        }
+
+        let this = Self {
+            brightness,
+            auto_brightness,
+            illuminance,
+            idle,
+        };
+
+        let backlight = match hw::Backlight::new("backlight") {
+            Ok(bl) => bl,
+            Err(e) => {
+                info!("Can not control the display backlight: {e}");
+                return this;
+            }
+        };
+
+        let max_brightness = backlight.max_brightness().unwrap_or(1) as f32;
+
+        let (brightness_events, _) = this.brightness.clone().subscribe_unbounded();
+        let (auto_events, _) = this.auto_brightness.clone().subscribe_unbounded();
+        let (illuminance_events, _) = this.illuminance.clone().subscribe_unbounded();
+        let (idle_events, _) = this.idle.clone().subscribe_unbounded();
+
+        let brightness_task = this.brightness.clone();
+        let auto_task = this.auto_brightness.clone();
+        let illuminance_task = this.illuminance.clone();
+        let idle_task = this.idle.clone();
+
+        spawn(async move {
+            let mut events = select(
+                select(brightness_events.map(|_| ()), auto_events.map(|_| ())),
+                select(illuminance_events.map(|_| ()), idle_events.map(|_| ())),
+            );
+
+            let mut step = None;
+            let mut prev_val = None;
+
+            while events.next().await.is_some() {
+                let auto = auto_task.try_get().unwrap_or(true);
+
+                let brightness = match illuminance_task.try_get() {
+                    Some(meas) if auto => {
+                        let next = ambient_step(step, meas.value);
+                        step = Some(next);
+                        AMBIENT_STEPS[next].1
+                    }
+                    _ => brightness_task.try_get().unwrap_or(1.0).clamp(0.0, 1.0),
+                };
+
+                let factor = idle_task.try_get().unwrap_or(IdleLevel::Active).factor();
+
+                let val = (brightness * factor * max_brightness).round() as u64;
+
+                // The ambient light is sampled continuously, but the
+                // brightness only changes every now and then.
+                if prev_val == Some(val) {
+                    continue;
+                }
+
+                prev_val = Some(val);
+
+                if let Err(e) = backlight.set_brightness(val) {
+                    warn!("Failed to set backlight brightness: {e}");
+                }
+            }
+        });
+
+        this
+    }
+
+    /// Track the time since the last button press and dim the backlight
+    /// before the screensaver is activated.
+    pub fn handle_idle<B>(
+        &self,
+        buttons: &Arc<Topic<B>>,
+        screensaver_timeout: &Arc<Topic<Option<u64>>>,
+    ) where
+        B: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    {
+        enum Event {
+            Button,
+            Timeout(Option<u64>),
+        }
+
+        let (buttons_events, _) = buttons.clone().subscribe_unbounded();
+        let (timeout_events, _) = screensaver_timeout.clone().subscribe_unbounded();
+        let mut events = select(
+            buttons_events.map(|_| Event::Button),
+            timeout_events.map(Event::Timeout),
+        );
+
+        let idle = self.idle.clone();
+
+        spawn(async move {
+            let mut last_activity = Instant::now();
+            let mut screensaver_timeout = None;
+
+            loop {
+                match timeout(Duration::from_secs(1), events.next()).await {
+                    Ok(None) => break,
+                    Ok(Some(Event::Button)) => last_activity = Instant::now(),
+                    Ok(Some(Event::Timeout(t))) => {
+                        screensaver_timeout = t;
+                        last_activity = Instant::now();
+                    }
+                    Err(_) => {}
+                }
+
+                let level = IdleLevel::from_idle_time(last_activity.elapsed(), screensaver_timeout);
+
+                idle.modify(|prev| match prev {
+                    Some(prev) if prev == level => None,
+                    _ => Some(level),
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ambient_step;
+
+    #[test]
+    fn ambient_hysteresis() {
+        // Without a previous step the matching one is used right away
+        assert_eq!(ambient_step(None, 0.0), 0);
+        assert_eq!(ambient_step(None, 60.0), 2);
+        assert_eq!(ambient_step(None, 10000.0), 4);
+
+        // Small changes around a threshold do not change the step
+        assert_eq!(ambient_step(Some(2), 45.0), 2);
+        assert_eq!(ambient_step(Some(1), 55.0), 1);
+
+        // Larger ones do
+        assert_eq!(ambient_step(Some(2), 30.0), 1);
+        assert_eq!(ambient_step(Some(1), 70.0), 2);
+        assert_eq!(ambient_step(Some(0), 700.0), 3);
+        assert_eq!(ambient_step(Some(4), 1.0), 0);
+    }
+}
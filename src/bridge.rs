@@ -0,0 +1,219 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::path::Path;
+use std::time::Duration;
+
+use async_std::task::{sleep, spawn};
+use serde::{Deserialize, Serialize};
+
+use crate::broker::BrokerBuilder;
+use crate::dbus::networkmanager::InterfaceNames;
+
+mod mirror;
+
+pub use mirror::MIRROR_INTERFACE;
+
+#[cfg(feature = "demo_mode")]
+mod rw {
+    use std::io::{Error, ErrorKind, Result};
+    use std::path::Path;
+
+    const FILES: &[(&str, &str)] = &[
+        ("/brif/dut/state", "3"),
+        ("/brif/dut/port_no", "0x1"),
+        ("/brif/uplink/state", "3"),
+        ("/brif/uplink/port_no", "0x2"),
+    ];
+
+    // Two entries in the binary format used by the brforward file.
+    // One learned on port 1 (dut) and one on port 2 (uplink).
+    const FDB: &[u8] = &[
+        0x00, 0x50, 0x56, 0x3c, 0xaa, 0x01, 0x01, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, // dut
+        0x00, 0x50, 0x56, 0x3c, 0xbb, 0x02, 0x02, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, // uplink
+    ];
+
+    pub fn list_dir<P: AsRef<Path>>(_: P) -> Result<Vec<String>> {
+        Ok(vec!["dut".to_string(), "uplink".to_string()])
+    }
+
+    pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String> {
+        let path = path.as_ref().to_str().unwrap();
+
+        FILES
+            .iter()
+            .find(|(tail, _)| path.ends_with(tail))
+            .map(|(_, content)| content.to_string())
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "No such file"))
+    }
+
+    pub fn read<P: AsRef<Path>>(_: P) -> Result<Vec<u8>> {
+        Ok(FDB.to_vec())
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod rw {
+    use std::io::Result;
+    use std::path::Path;
+
+    pub use std::fs::{read, read_to_string};
+
+    pub fn list_dir<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+        std::fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.file_name().to_string_lossy().to_string()))
+            .collect()
+    }
+}
+
+use rw::{list_dir, read, read_to_string};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const NET_CLASS_PATH: &str = "/sys/class/net";
+
+/// Size of a `struct __fdb_entry` as read from the `brforward` sysfs file
+const FDB_ENTRY_LEN: usize = 16;
+
+/// The STP state of a bridge port as reported by the kernel
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PortState {
+    Disabled,
+    Listening,
+    Learning,
+    Forwarding,
+    Blocking,
+    Unknown,
+}
+
+impl From<u32> for PortState {
+    fn from(state: u32) -> Self {
+        match state {
+            0 => Self::Disabled,
+            1 => Self::Listening,
+            2 => Self::Learning,
+            3 => Self::Forwarding,
+            4 => Self::Blocking,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct BridgePort {
+    pub name: String,
+    pub state: PortState,
+    /// MAC addresses the bridge has learned on this port
+    pub addresses: Vec<String>,
+}
+
+/// Parse the forwarding database as read from the `brforward` sysfs file
+/// into a list of (port number, MAC address) tuples.
+/// Addresses that belong to the bridge itself are skipped.
+fn parse_fdb(fdb: &[u8]) -> Vec<(u16, String)> {
+    fdb.chunks_exact(FDB_ENTRY_LEN)
+        .filter(|entry| entry[7] == 0)
+        .map(|entry| {
+            let mac = entry[..6]
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<String>>()
+                .join(":");
+
+            let port_no = u16::from(entry[6]) | (u16::from(entry[12]) << 8);
+
+            (port_no, mac)
+        })
+        .collect()
+}
+
+fn read_ports(bridge_path: &Path) -> Vec<BridgePort> {
+    let brif = bridge_path.join("brif");
+
+    let fdb = read(bridge_path.join("brforward"))
+        .map(|fdb| parse_fdb(&fdb))
+        .unwrap_or_default();
+
+    let mut names = list_dir(&brif).unwrap_or_default();
+    names.sort();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let port_path = brif.join(&name);
+
+            let state = read_to_string(port_path.join("state"))
+                .ok()
+                .and_then(|s| s.trim().parse::<u32>().ok())
+                .map(PortState::from)
+                .unwrap_or(PortState::Unknown);
+
+            let port_no = read_to_string(port_path.join("port_no"))
+                .ok()
+                .and_then(|s| u16::from_str_radix(s.trim().trim_start_matches("0x"), 16).ok());
+
+            let addresses = fdb
+                .iter()
+                .filter(|(no, _)| Some(*no) == port_no)
+                .map(|(_, mac)| mac.clone())
+                .collect();
+
+            BridgePort {
+                name,
+                state,
+                addresses,
+            }
+        })
+        .collect()
+}
+
+/// Expose the port membership and STP state of the bridge and allow
+/// mirroring the DUT traffic
+pub fn setup(bb: &mut BrokerBuilder) {
+    let ports = bb.topic_ro("/v1/tac/network/bridge/ports", None);
+    let mirror = bb.topic(
+        "/v1/tac/network/bridge/mirror",
+        true,
+        true,
+        true,
+        Some(None),
+        1,
+    );
+
+    let names = InterfaceNames::load();
+    let bridge_path = Path::new(NET_CLASS_PATH).join(&names.bridge);
+
+    let mirror_bridge_path = bridge_path.clone();
+
+    // Poll the bridge state from sysfs and update the topic on changes,
+    // e.g. when a port is removed from the bridge or changes STP state.
+    spawn(async move {
+        loop {
+            let current = read_ports(&bridge_path);
+
+            ports.modify(|prev| match prev {
+                Some(prev) if prev == current => None,
+                _ => Some(current),
+            });
+
+            sleep(POLL_INTERVAL).await;
+        }
+    });
+
+    // Only the traffic of the DUT can be mirrored, so that this can not
+    // be used to snoop on the lab network.
+    mirror::handle_mirror(names, mirror_bridge_path, mirror);
+}
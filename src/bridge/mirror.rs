@@ -0,0 +1,151 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::list_dir;
+use crate::broker::Topic;
+use crate::dbus::networkmanager::InterfaceNames;
+
+#[cfg(feature = "demo_mode")]
+mod cmd {
+    use anyhow::Result;
+
+    pub async fn run(cmd: &str, args: &[&str]) -> Result<()> {
+        println!(
+            "Asked to run \"{cmd} {}\" but don't feel like it",
+            args.join(" ")
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod cmd {
+    use anyhow::{anyhow, Result};
+    use async_std::process::Command;
+
+    pub async fn run(cmd: &str, args: &[&str]) -> Result<()> {
+        let status = Command::new(cmd).args(args).status().await?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("\"{cmd} {}\" failed: {status}", args.join(" ")))
+        }
+    }
+}
+
+use cmd::run;
+
+/// Dummy interface the DUT traffic is mirrored to when it should be
+/// observed via the capture subsystem.
+pub const MIRROR_INTERFACE: &str = "tac-mirror";
+
+/// Where to send copies of all frames sent and received on the DUT interface
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum MirrorTarget {
+    /// Another network interface, e.g. a USB ethernet adapter
+    Port(String),
+    /// The `tac-mirror` interface, which can be captured via the API
+    Capture,
+}
+
+/// Remove all mirroring rules from the source interface
+async fn clear_mirror(source: &str) {
+    // This fails if there was no mirroring set up before, which is fine
+    let _ = run("tc", &["qdisc", "del", "dev", source, "clsact"]).await;
+}
+
+async fn set_up_mirror(
+    names: &InterfaceNames,
+    bridge_path: &Path,
+    target: &MirrorTarget,
+) -> Result<()> {
+    let source = names.dut.as_str();
+
+    let target = match target {
+        MirrorTarget::Port(name) => {
+            // Mirroring the DUT traffic to the uplink would leak it into the
+            // lab network and mirroring it to itself would loop.
+            if *name == names.dut || *name == names.uplink {
+                bail!("Can not mirror the DUT traffic to {name}");
+            }
+
+            let ports = list_dir(bridge_path.join("brif")).unwrap_or_default();
+
+            if !ports.contains(name) {
+                bail!("{name} is not a port of {}", names.bridge);
+            }
+
+            name.as_str()
+        }
+        MirrorTarget::Capture => {
+            // The interface may already exist from a previous run
+            let _ = run("ip", &["link", "add", MIRROR_INTERFACE, "type", "dummy"]).await;
+            run("ip", &["link", "set", MIRROR_INTERFACE, "up"]).await?;
+
+            MIRROR_INTERFACE
+        }
+    };
+
+    run("tc", &["qdisc", "add", "dev", source, "clsact"]).await?;
+
+    for direction in ["ingress", "egress"] {
+        run(
+            "tc",
+            &[
+                "filter", "add", "dev", source, direction, "matchall", "action", "mirred",
+                "egress", "mirror", "dev", target,
+            ],
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Mirror the traffic on the DUT interface to the target set in the
+/// `mirror` topic using traffic control mirred actions.
+pub(super) fn handle_mirror(
+    names: InterfaceNames,
+    bridge_path: PathBuf,
+    mirror: Arc<Topic<Option<MirrorTarget>>>,
+) {
+    let (mut mirror_stream, _) = mirror.subscribe_unbounded();
+
+    spawn(async move {
+        while let Some(target) = mirror_stream.next().await {
+            clear_mirror(&names.dut).await;
+
+            if let Some(target) = target {
+                if let Err(e) = set_up_mirror(&names, &bridge_path, &target).await {
+                    warn!("Failed to set up port mirroring: {e}");
+                    clear_mirror(&names.dut).await;
+                }
+            }
+        }
+    });
+}
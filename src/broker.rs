@@ -21,18 +21,40 @@ use serde::{de::DeserializeOwned, Serialize};
 mod mqtt_conn;
 mod persistence;
 mod rest;
+mod sessions;
 mod topic;
 
 pub use mqtt_conn::TopicName;
+pub use sessions::WebSession;
 pub use topic::{AnySubscriptionHandle, AnyTopic, Native, SubscriptionHandle, Topic};
 
 pub struct BrokerBuilder {
     topics: Vec<Arc<dyn AnyTopic>>,
+    web_sessions: Arc<Topic<Vec<WebSession>>>,
 }
 
 impl BrokerBuilder {
     pub fn new() -> Self {
-        Self { topics: Vec::new() }
+        // The clients currently connected via MQTT, so that users can see
+        // if someone else is controlling the TAC right now.
+        let web_sessions = Arc::new(Topic::new(
+            "/v1/tac/web/sessions",
+            true,
+            false,
+            false,
+            Some(Vec::new()),
+            1,
+        ));
+
+        Self {
+            topics: vec![web_sessions.clone()],
+            web_sessions,
+        }
+    }
+
+    /// Get the topic listing the currently connected web clients
+    pub fn web_sessions(&self) -> Arc<Topic<Vec<WebSession>>> {
+        self.web_sessions.clone()
     }
 
     /// Register a new topic
@@ -110,6 +132,12 @@ impl BrokerBuilder {
         self.topic(path, false, true, false, initial, 1)
     }
 
+    /// Get the persisted value of a topic before it is loaded during
+    /// `build()`, e.g. to set up hardware in the right state from the start.
+    pub fn persisted_value<E: DeserializeOwned>(&self, path: &str) -> Option<E> {
+        persistence::load_value(path)
+    }
+
     /// Finish building the broker
     ///
     /// This consumes the builder so that no new topics can be registered
@@ -118,6 +146,6 @@ impl BrokerBuilder {
 
         persistence::register(topics.clone());
         rest::register(server, topics.clone());
-        mqtt_conn::register(server, topics);
+        mqtt_conn::register(server, topics, self.web_sessions);
     }
 }
@@ -25,16 +25,11 @@ use async_std::sync::{Arc, Mutex};
 use async_std::task::spawn;
 
 use async_tungstenite::tungstenite::{
-    protocol::{
-        frame::{coding::CloseCode, CloseFrame},
-        Role,
-    },
+    protocol::frame::{coding::CloseCode, CloseFrame},
     Message,
 };
 use async_tungstenite::WebSocketStream;
 
-use base64::Engine;
-
 use futures_lite::future::race;
 use futures_util::future::Either;
 use futures_util::{FutureExt, SinkExt, StreamExt};
@@ -45,15 +40,14 @@ use mqtt::packet::suback::SubscribeReturnCode;
 use mqtt::TopicFilter;
 use mqtt::{packet::*, Decodable, Encodable};
 
-use sha1::{digest::Update, Digest, Sha1};
-use tide::http::format_err;
-use tide::http::headers::{HeaderName, CONNECTION, UPGRADE};
 use tide::http::upgrade::Connection;
-use tide::{Request, Response, StatusCode};
+use tide::Request;
 
 pub use mqtt::TopicName;
 
-use super::{AnySubscriptionHandle, AnyTopic};
+use super::sessions::{SessionGuard, WebSession};
+use super::{AnySubscriptionHandle, AnyTopic, Topic};
+use crate::http_server::upgrade_websocket;
 
 /// Limit the number of elements in the queue leading to the websocket
 /// connection. This assumes that the websocket connection will provide
@@ -66,9 +60,6 @@ const MAX_QUEUE_LENGTH: usize = 4096;
 /// the backpressure mechanism mentioned above actually does something.
 const MAX_PENDING_BYTES: usize = 256 * 1024;
 
-/// This is used in the WebSocket handshake
-const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
-
 // The mqtt crate provides the Decodable and Encodable traits that can decode/
 // encode packets from/to Readers/Writers.
 // This is nice, but we use WebSocket Messages instead of Readers/Writers.
@@ -103,6 +94,8 @@ impl<E> EncodableExt for E where E: Encodable {}
 /// from protocol handshake to teardown.
 async fn handle_connection(
     topics: Arc<Vec<Arc<dyn AnyTopic>>>,
+    sessions: Arc<Topic<Vec<WebSession>>>,
+    address: Option<String>,
     mut stream: WebSocketStream<Connection>,
 ) {
     // The MQTT connection starts with a CONNECT packet.
@@ -152,6 +145,9 @@ async fn handle_connection(
         return;
     }
 
+    // List the connection in the sessions topic until it is closed
+    let session = SessionGuard::new(sessions, address);
+
     let (stream_tx, mut stream_rx) = stream.split();
 
     // Wrap the tx side of a stream in an Option so that we can later .take()
@@ -341,6 +337,8 @@ async fn handle_connection(
                         res = Err(e.into());
                         break 'connection;
                     }
+
+                    session.touch();
                 }
             }
             VariablePacket::PingreqPacket(_) => {
@@ -398,69 +396,21 @@ async fn handle_connection(
     let _ = ws.close(Some(close_frame)).await;
 }
 
-fn header_contains_ignore_case(req: &Request<()>, header_name: HeaderName, value: &str) -> bool {
-    req.header(header_name)
-        .map(|h| {
-            h.as_str()
-                .split(',')
-                .any(|s| s.trim().eq_ignore_ascii_case(value.trim()))
-        })
-        .unwrap_or(false)
-}
-
-pub(super) fn register(server: &mut tide::Server<()>, topics: Arc<Vec<Arc<dyn AnyTopic>>>) {
+pub(super) fn register(
+    server: &mut tide::Server<()>,
+    topics: Arc<Vec<Arc<dyn AnyTopic>>>,
+    sessions: Arc<Topic<Vec<WebSession>>>,
+) {
     server.at("/v1/mqtt").get(move |req: Request<()>| {
         let topics = topics.clone();
+        let sessions = sessions.clone();
+        let address = req.remote().map(|a| a.to_string());
 
         async move {
-            // These are the good parts from tide-websockets without the bad
-            // WebSocketConnection wrapper.
-
-            let connection_upgrade = header_contains_ignore_case(&req, CONNECTION, "upgrade");
-            let upgrade_to_websocket = header_contains_ignore_case(&req, UPGRADE, "websocket");
-            let upgrade_requested = connection_upgrade && upgrade_to_websocket;
-
-            if !upgrade_requested {
-                return Ok(Response::new(StatusCode::UpgradeRequired));
-            }
-
-            let header = match req.header("Sec-Websocket-Key") {
-                Some(h) => h.as_str(),
-                None => return Err(format_err!("expected sec-websocket-key")),
-            };
-
-            let protocol = req.header("Sec-Websocket-Protocol").and_then(|value| {
-                value
-                    .as_str()
-                    .split(',')
-                    .map(str::trim)
-                    .find(|req_p| req_p == &"mqttv3.1" || req_p == &"mqtt")
-            });
-
-            let mut response = Response::new(StatusCode::SwitchingProtocols);
-
-            response.insert_header(UPGRADE, "websocket");
-            response.insert_header(CONNECTION, "Upgrade");
-            let hash = Sha1::new().chain(header).chain(WEBSOCKET_GUID).finalize();
-            let hash = base64::engine::general_purpose::STANDARD.encode(&hash[..]);
-            response.insert_header("Sec-Websocket-Accept", hash);
-            response.insert_header("Sec-Websocket-Version", "13");
-
-            if let Some(protocol) = protocol {
-                response.insert_header("Sec-Websocket-Protocol", protocol);
-            }
-
-            let http_res: &mut tide::http::Response = response.as_mut();
-            let upgrade_receiver = http_res.recv_upgrade().await;
-
-            spawn(async move {
-                if let Some(stream) = upgrade_receiver.await {
-                    let ws = WebSocketStream::from_raw_socket(stream, Role::Server, None).await;
-                    handle_connection(topics, ws).await;
-                }
-            });
-
-            Ok(response)
+            upgrade_websocket(&req, &["mqttv3.1", "mqtt"], move |ws| {
+                handle_connection(topics, sessions, address, ws)
+            })
+            .await
         }
     });
 }
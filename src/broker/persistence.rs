@@ -17,6 +17,7 @@
 
 use std::fs::{create_dir, rename, File};
 use std::path::Path;
+use std::sync::Mutex;
 
 use anyhow::{bail, Result};
 use async_std::channel::{unbounded, Receiver};
@@ -24,10 +25,11 @@ use async_std::prelude::*;
 use async_std::sync::Arc;
 use async_std::task::spawn;
 use log::{error, info};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{from_reader, to_writer_pretty, Map, Value};
 
 use super::{AnyTopic, TopicName};
+use crate::safe_state;
 
 #[cfg(feature = "demo_mode")]
 const PERSISTENCE_PATH: &str = "demo_files/srv/tacd/state.json";
@@ -35,6 +37,10 @@ const PERSISTENCE_PATH: &str = "demo_files/srv/tacd/state.json";
 #[cfg(not(feature = "demo_mode"))]
 const PERSISTENCE_PATH: &str = "/srv/tacd/state.json";
 
+/// Saving on a change and on exit may happen at the same time, but both
+/// write to the same temporary file.
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
 #[derive(Serialize, Deserialize)]
 struct PersistenceFile {
     format_version: u64,
@@ -80,7 +86,23 @@ fn load(topics: &[Arc<dyn AnyTopic>]) -> Result<()> {
     Ok(())
 }
 
+/// Get the value a persistent topic had when the state file was last written,
+/// without waiting for the broker to be built
+pub fn load_value<E: DeserializeOwned>(path: &str) -> Option<E> {
+    let file: PersistenceFile = from_reader(File::open(PERSISTENCE_PATH).ok()?).ok()?;
+
+    if file.format_version != 1 {
+        return None;
+    }
+
+    let value = file.persistent_topics.get(path)?.clone();
+
+    serde_json::from_value(value).ok()
+}
+
 fn save(topics: &Arc<Vec<Arc<dyn AnyTopic>>>) -> Result<()> {
+    let _guard = SAVE_LOCK.lock().unwrap();
+
     let persistent_topics = {
         let mut map = Map::new();
 
@@ -156,5 +178,14 @@ pub fn register(topics: Arc<Vec<Arc<dyn AnyTopic>>>) {
         topic.subscribe_as_bytes(tx.clone(), false);
     }
 
+    // Topics that are changed by other exit hooks (which are registered
+    // before the broker is built) are saved as well.
+    let topics_exit = topics.clone();
+    safe_state::on_exit(move || {
+        if let Err(e) = save(&topics_exit) {
+            error!("Failed to save persistent topics on exit: {e}");
+        }
+    });
+
     spawn(async move { save_on_change(topics, rx).await.unwrap() });
 }
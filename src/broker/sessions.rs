@@ -0,0 +1,114 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use async_std::sync::Arc;
+use serde::{Deserialize, Serialize};
+
+use super::Topic;
+
+/// Only update the activity timestamp of a session if it changed by at
+/// least this much, so that a busy web interface does not cause a flood
+/// of updates on the sessions topic.
+const ACTIVITY_RESOLUTION: Duration = Duration::from_secs(5);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A web interface (or other MQTT client) connected to the tacd
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct WebSession {
+    pub id: u64,
+    pub address: Option<String>,
+    /// When the client connected, in seconds since the unix epoch
+    pub connected_since: u64,
+    /// When the client last changed something on the TAC, in seconds since
+    /// the unix epoch. Periodic pings and subscriptions do not count as
+    /// activity.
+    pub last_activity: Option<u64>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Keeps a session listed in the sessions topic for as long as it exists
+pub(super) struct SessionGuard {
+    sessions: Arc<Topic<Vec<WebSession>>>,
+    id: u64,
+}
+
+impl SessionGuard {
+    pub(super) fn new(sessions: Arc<Topic<Vec<WebSession>>>, address: Option<String>) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+        let session = WebSession {
+            id,
+            address,
+            connected_since: now(),
+            last_activity: None,
+        };
+
+        sessions.modify(|prev| {
+            let mut list = prev.unwrap_or_default();
+            list.push(session);
+            Some(list)
+        });
+
+        Self { sessions, id }
+    }
+
+    /// Mark the session as active, e.g. because the client set a topic
+    pub(super) fn touch(&self) {
+        let now = now();
+        let id = self.id;
+
+        self.sessions.modify(|prev| {
+            let mut list = prev?;
+            let session = list.iter_mut().find(|s| s.id == id)?;
+
+            let recent = session
+                .last_activity
+                .map(|ts| now.saturating_sub(ts) < ACTIVITY_RESOLUTION.as_secs())
+                .unwrap_or(false);
+
+            if recent {
+                return None;
+            }
+
+            session.last_activity = Some(now);
+
+            Some(list)
+        });
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let id = self.id;
+
+        self.sessions.modify(|prev| {
+            let mut list = prev?;
+            list.retain(|s| s.id != id);
+            Some(list)
+        });
+    }
+}
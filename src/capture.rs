@@ -0,0 +1,553 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use async_std::channel::{bounded, Sender};
+use async_std::prelude::*;
+use async_std::task::{block_on, spawn_blocking};
+use async_tungstenite::tungstenite::protocol::frame::{coding::CloseCode, CloseFrame};
+use async_tungstenite::tungstenite::Message;
+use futures_lite::future::race;
+use futures_util::SinkExt;
+use log::warn;
+use serde::Deserialize;
+use tide::{http::mime, Request, Response, Server};
+
+use crate::bridge::MIRROR_INTERFACE;
+use crate::dbus::networkmanager::InterfaceNames;
+use crate::http_server::upgrade_websocket;
+use crate::setup_mode::is_admin;
+
+/// Captures stop after this amount of pcap data if the client did not ask
+/// for less.
+const MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Captures stop after this time if the client did not ask for less.
+const MAX_DURATION: Duration = Duration::from_secs(10 * 60);
+
+/// Every capture takes up memory and CPU time on the TAC,
+/// so only a few of them may run at the same time.
+const MAX_CAPTURES: usize = 2;
+
+/// The number of captures that are currently running
+static ACTIVE_CAPTURES: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of packets to buffer between the capture thread and the websocket.
+/// If the websocket can not keep up the kernel will drop packets instead.
+const QUEUE_LENGTH: usize = 256;
+
+/// Longest frame we expect on the wire (jumbo frames included)
+const SNAPLEN: usize = 9216;
+
+/// Ethernet link type as defined in the pcap file format
+const LINKTYPE_ETHERNET: u32 = 1;
+
+#[cfg(feature = "demo_mode")]
+mod raw {
+    use std::io::Result;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    // An ARP request for 192.168.1.1 sent by 192.168.1.100
+    const DEMO_FRAME: [u8; 42] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x08, 0x06, 0x00,
+        0x01, 0x08, 0x00, 0x06, 0x04, 0x00, 0x01, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 192, 168, 1,
+        100, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 192, 168, 1, 1,
+    ];
+
+    pub struct PacketSocket;
+
+    impl PacketSocket {
+        pub fn open(_interface: &str) -> Result<Self> {
+            Ok(Self)
+        }
+
+        pub fn recv(&self, buf: &mut [u8]) -> Result<Option<usize>> {
+            sleep(Duration::from_secs(1));
+
+            buf[..DEMO_FRAME.len()].copy_from_slice(&DEMO_FRAME);
+
+            Ok(Some(DEMO_FRAME.len()))
+        }
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod raw {
+    use std::io::{Error, Result};
+    use std::mem::size_of;
+    use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+
+    use nix::errno::Errno;
+    use nix::libc;
+    use nix::net::if_::if_nametoindex;
+    use nix::sys::socket::{recv, setsockopt, sockopt::ReceiveTimeout, MsgFlags};
+    use nix::sys::time::{TimeVal, TimeValLike};
+
+    /// An AF_PACKET socket receiving all frames seen on an interface
+    pub struct PacketSocket {
+        fd: OwnedFd,
+    }
+
+    impl PacketSocket {
+        pub fn open(interface: &str) -> Result<Self> {
+            let protocol = (libc::ETH_P_ALL as u16).to_be();
+            let index = if_nametoindex(interface)?;
+
+            let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, protocol as i32) };
+
+            if fd < 0 {
+                return Err(Error::last_os_error());
+            }
+
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+            // Only receive frames from the requested interface
+            let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+            addr.sll_family = libc::AF_PACKET as u16;
+            addr.sll_protocol = protocol;
+            addr.sll_ifindex = index as i32;
+
+            let res = unsafe {
+                libc::bind(
+                    fd.as_raw_fd(),
+                    &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                    size_of::<libc::sockaddr_ll>() as u32,
+                )
+            };
+
+            if res < 0 {
+                return Err(Error::last_os_error());
+            }
+
+            // Wake up regularly so that the time limit can be enforced even
+            // if no traffic is seen.
+            setsockopt(fd.as_raw_fd(), ReceiveTimeout, &TimeVal::milliseconds(500))?;
+
+            Ok(Self { fd })
+        }
+
+        /// Receive a single frame into `buf`.
+        /// Returns the length of the frame on the wire, which may be longer
+        /// than `buf`, or `None` if no frame was received in time.
+        pub fn recv(&self, buf: &mut [u8]) -> Result<Option<usize>> {
+            match recv(self.fd.as_raw_fd(), buf, MsgFlags::MSG_TRUNC) {
+                Ok(len) => Ok(Some(len)),
+                Err(Errno::EAGAIN) | Err(Errno::EINTR) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+    }
+}
+
+use raw::PacketSocket;
+
+#[derive(Deserialize)]
+struct QueryParams {
+    interface: String,
+    filter: Option<String>,
+    max_bytes: Option<u64>,
+    max_seconds: Option<u64>,
+    token: Option<String>,
+}
+
+/// One of the `MAX_CAPTURES` slots for running captures.
+/// The slot is given back once this is dropped.
+struct CaptureSlot {}
+
+impl CaptureSlot {
+    fn take() -> Option<Self> {
+        ACTIVE_CAPTURES
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+                (active < MAX_CAPTURES).then_some(active + 1)
+            })
+            .ok()
+            .map(|_| Self {})
+    }
+}
+
+impl Drop for CaptureSlot {
+    fn drop(&mut self) {
+        ACTIVE_CAPTURES.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A simple capture filter in the form of comma separated `key=value` pairs
+/// that all have to match for a frame to be captured.
+/// E.g. `proto=udp,port=67` to capture DHCP traffic.
+#[derive(Default, PartialEq, Debug)]
+struct CaptureFilter {
+    ether: Option<[u8; 6]>,
+    ethertype: Option<u16>,
+    host: Option<Ipv4Addr>,
+    proto: Option<u8>,
+    port: Option<u16>,
+}
+
+fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let mut res = [0; 6];
+    let mut octets = mac.split(':');
+
+    for byte in res.iter_mut() {
+        *byte = u8::from_str_radix(octets.next()?, 16).ok()?;
+    }
+
+    match octets.next() {
+        Some(_) => None,
+        None => Some(res),
+    }
+}
+
+impl CaptureFilter {
+    fn parse(filter: &str) -> Result<Self, String> {
+        let mut res = Self::default();
+
+        for term in filter.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let (key, value) = term
+                .split_once('=')
+                .ok_or_else(|| format!("Filter term \"{term}\" is not of the form key=value"))?;
+
+            let invalid = || format!("Invalid value \"{value}\" for filter \"{key}\"");
+
+            match key {
+                "ether" => res.ether = Some(parse_mac(value).ok_or_else(invalid)?),
+                "ethertype" => {
+                    let value = value.trim_start_matches("0x");
+                    res.ethertype = Some(u16::from_str_radix(value, 16).map_err(|_| invalid())?)
+                }
+                "host" => res.host = Some(value.parse().map_err(|_| invalid())?),
+                "proto" => {
+                    res.proto = Some(match value {
+                        "icmp" => 1,
+                        "tcp" => 6,
+                        "udp" => 17,
+                        _ => return Err(invalid()),
+                    })
+                }
+                "port" => res.port = Some(value.parse().map_err(|_| invalid())?),
+                _ => return Err(format!("Unknown filter \"{key}\"")),
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn matches(&self, frame: &[u8]) -> bool {
+        if frame.len() < 14 {
+            return false;
+        }
+
+        if let Some(ether) = self.ether {
+            if frame[0..6] != ether && frame[6..12] != ether {
+                return false;
+            }
+        }
+
+        // Look through a single VLAN tag
+        let (ethertype, payload) = match u16::from_be_bytes([frame[12], frame[13]]) {
+            0x8100 if frame.len() >= 18 => {
+                (u16::from_be_bytes([frame[16], frame[17]]), &frame[18..])
+            }
+            ethertype => (ethertype, &frame[14..]),
+        };
+
+        if self.ethertype.map(|t| t != ethertype).unwrap_or(false) {
+            return false;
+        }
+
+        let needs_ip = self.host.is_some() || self.proto.is_some() || self.port.is_some();
+
+        if !needs_ip {
+            return true;
+        }
+
+        // The IP based filters only support IPv4
+        if ethertype != 0x0800 || payload.len() < 20 {
+            return false;
+        }
+
+        if let Some(host) = self.host {
+            let host = host.octets();
+
+            if payload[12..16] != host && payload[16..20] != host {
+                return false;
+            }
+        }
+
+        let proto = payload[9];
+
+        if self.proto.map(|p| p != proto).unwrap_or(false) {
+            return false;
+        }
+
+        if let Some(port) = self.port {
+            let header_len = ((payload[0] & 0x0f) as usize) * 4;
+
+            // Only TCP and UDP have ports
+            if (proto != 6 && proto != 17) || payload.len() < header_len + 4 {
+                return false;
+            }
+
+            let ports = &payload[header_len..];
+            let src = u16::from_be_bytes([ports[0], ports[1]]);
+            let dst = u16::from_be_bytes([ports[2], ports[3]]);
+
+            if src != port && dst != port {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+fn pcap_header() -> Vec<u8> {
+    let mut header = Vec::with_capacity(24);
+
+    header.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+    header.extend_from_slice(&2u16.to_le_bytes());
+    header.extend_from_slice(&4u16.to_le_bytes());
+    header.extend_from_slice(&0i32.to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&(SNAPLEN as u32).to_le_bytes());
+    header.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+
+    header
+}
+
+fn pcap_record(frame: &[u8], orig_len: usize) -> Vec<u8> {
+    let ts = SystemTime::UNIX_EPOCH.elapsed().unwrap_or_default();
+    let mut record = Vec::with_capacity(16 + frame.len());
+
+    record.extend_from_slice(&(ts.as_secs() as u32).to_le_bytes());
+    record.extend_from_slice(&ts.subsec_micros().to_le_bytes());
+    record.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+    record.extend_from_slice(&(orig_len as u32).to_le_bytes());
+    record.extend_from_slice(frame);
+
+    record
+}
+
+/// Read frames from the socket and send them as pcap records via `tx`
+/// until one of the limits is reached or the receiving end goes away.
+fn capture(
+    socket: PacketSocket,
+    filter: CaptureFilter,
+    max_bytes: u64,
+    max_duration: Duration,
+    tx: Sender<Vec<u8>>,
+) {
+    let deadline = Instant::now() + max_duration;
+    let mut buf = vec![0; SNAPLEN];
+
+    let header = pcap_header();
+    let mut bytes_sent = header.len() as u64;
+
+    if block_on(tx.send(header)).is_err() {
+        return;
+    }
+
+    while Instant::now() < deadline {
+        // The receive timeout of the socket makes sure that this is also
+        // checked regularly while no frames are seen.
+        if tx.is_closed() {
+            return;
+        }
+
+        let orig_len = match socket.recv(&mut buf) {
+            Ok(Some(len)) => len,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Failed to capture packets: {e}");
+                return;
+            }
+        };
+
+        let frame = &buf[..orig_len.min(SNAPLEN)];
+
+        if !filter.matches(frame) {
+            continue;
+        }
+
+        let record = pcap_record(frame, orig_len);
+
+        bytes_sent += record.len() as u64;
+
+        if bytes_sent > max_bytes {
+            return;
+        }
+
+        if block_on(tx.send(record)).is_err() {
+            return;
+        }
+    }
+}
+
+pub fn serve(server: &mut Server<()>) {
+    server
+        .at("/v1/tac/network/capture")
+        .get(|req: Request<()>| async move {
+            let params: QueryParams = match req.query() {
+                Ok(params) => params,
+                Err(e) => {
+                    return Ok(Response::builder(400)
+                        .body(format!("Failed to parse query parameters: {e}"))
+                        .build())
+                }
+            };
+
+            // The captured traffic may contain confidential data of the DUT
+            if !is_admin(params.token.as_deref().unwrap_or("")).await {
+                return Ok(Response::builder(403)
+                    .body("Capturing traffic requires the admin role")
+                    .content_type(mime::PLAIN)
+                    .build());
+            }
+
+            // Only the interfaces on the DUT side may be captured, so that
+            // this can not be used to snoop on the lab network.
+            let names = InterfaceNames::load();
+            let interface = match params.interface.as_str() {
+                "dut" => names.dut,
+                "tac-bridge" => names.bridge,
+                "mirror" => MIRROR_INTERFACE.to_string(),
+                other => {
+                    return Ok(Response::builder(400)
+                        .body(format!("Capturing on \"{other}\" is not supported"))
+                        .build())
+                }
+            };
+
+            let filter = match CaptureFilter::parse(params.filter.as_deref().unwrap_or("")) {
+                Ok(filter) => filter,
+                Err(e) => return Ok(Response::builder(400).body(e).build()),
+            };
+
+            let max_bytes = params.max_bytes.unwrap_or(MAX_BYTES).min(MAX_BYTES);
+            let max_duration = params
+                .max_seconds
+                .map(Duration::from_secs)
+                .unwrap_or(MAX_DURATION)
+                .min(MAX_DURATION);
+
+            // Tell the client why it did not get a capture using the close
+            // reason, as websocket clients usually do not see the HTTP status.
+            let slot = match CaptureSlot::take() {
+                Some(slot) => slot,
+                None => {
+                    return upgrade_websocket(&req, &[], |mut ws| async move {
+                        let close_frame = CloseFrame {
+                            code: CloseCode::Again,
+                            reason: format!("At most {MAX_CAPTURES} captures may run at once")
+                                .into(),
+                        };
+
+                        let _ = ws.close(Some(close_frame)).await;
+                    })
+                    .await
+                }
+            };
+
+            // Open the socket before upgrading the connection so that errors
+            // can be reported via the HTTP status code.
+            let socket = match PacketSocket::open(&interface) {
+                Ok(socket) => socket,
+                Err(e) => {
+                    return Ok(Response::builder(500)
+                        .body(format!("Failed to open capture socket: {e}"))
+                        .build())
+                }
+            };
+
+            upgrade_websocket(&req, &[], move |ws| async move {
+                let (tx, mut rx) = bounded(QUEUE_LENGTH);
+
+                // The slot is only given back once the capture thread is done
+                spawn_blocking(move || {
+                    capture(socket, filter, max_bytes, max_duration, tx);
+                    drop(slot);
+                });
+
+                let (mut ws_tx, mut ws_rx) = futures_util::StreamExt::split(ws);
+
+                let forward = async {
+                    while let Some(chunk) = rx.next().await {
+                        if ws_tx.send(Message::binary(chunk)).await.is_err() {
+                            return false;
+                        }
+                    }
+
+                    true
+                };
+
+                // The client does not send anything, but reading from the
+                // websocket is the only way to notice it going away while
+                // no frames are captured.
+                let client_gone = async {
+                    while let Some(Ok(msg)) = ws_rx.next().await {
+                        if msg.is_close() {
+                            break;
+                        }
+                    }
+
+                    false
+                };
+
+                let capture_done = race(forward, client_gone).await;
+
+                // Dropping rx makes the capture thread stop as well
+                drop(rx);
+
+                if capture_done {
+                    let _ = ws_tx.close().await;
+                }
+            })
+            .await
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CaptureFilter;
+
+    #[test]
+    fn filter() {
+        // A DHCP discover from 00:11:22:33:44:55
+        let mut frame = vec![0xff; 6];
+        frame.extend_from_slice(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x08, 0x00]);
+        frame.extend_from_slice(&[0x45, 0, 0, 0, 0, 0, 0, 0, 64, 17, 0, 0]);
+        frame.extend_from_slice(&[0, 0, 0, 0, 255, 255, 255, 255]);
+        frame.extend_from_slice(&[0, 68, 0, 67, 0, 0, 0, 0]);
+
+        let matches = |f: &str| CaptureFilter::parse(f).unwrap().matches(&frame);
+
+        assert!(matches(""));
+        assert!(matches("proto=udp,port=67"));
+        assert!(matches("ether=00:11:22:33:44:55"));
+        assert!(matches("ethertype=0x0800,host=255.255.255.255"));
+        assert!(!matches("proto=tcp"));
+        assert!(!matches("port=80"));
+        assert!(!matches("host=192.168.1.1"));
+
+        assert!(CaptureFilter::parse("proto=sctp").is_err());
+        assert!(CaptureFilter::parse("vlan=5").is_err());
+        assert!(CaptureFilter::parse("ether=00:11:22").is_err());
+    }
+}
@@ -53,22 +53,32 @@ mod zb {
 
 use zb::{Connection, ConnectionBuilder, Result};
 
+pub mod avahi;
+pub mod logind;
+pub mod modemmanager;
 pub mod networkmanager;
 pub mod rauc;
 pub mod systemd;
 pub mod tacd;
+pub mod timedate;
 
 pub use self::systemd::Systemd;
+pub use logind::Logind;
+pub use modemmanager::Cellular;
 pub use networkmanager::Network;
 pub use rauc::Rauc;
 pub use tacd::Tacd;
+pub use timedate::TimeDate;
 
 /// Bunch together everything that uses a DBus system connection here, even
 /// though it is conceptionally independent
 pub struct DbusSession {
+    pub cellular: Cellular,
+    pub logind: Logind,
     pub network: Network,
     pub rauc: Rauc,
     pub systemd: Systemd,
+    pub timedate: TimeDate,
 }
 
 impl DbusSession {
@@ -86,10 +96,15 @@ impl DbusSession {
 
         let conn = Arc::new(tacd.serve(conn_builder).build().await.unwrap());
 
+        avahi::setup(bb, &conn);
+
         Self {
+            cellular: Cellular::new(bb, &conn),
+            logind: Logind::new(bb, &conn),
             network: Network::new(bb, &conn, led_dut, led_uplink),
             rauc: Rauc::new(bb, &conn),
             systemd: Systemd::new(bb, &conn).await,
+            timedate: TimeDate::new(bb, &conn),
         }
     }
 }
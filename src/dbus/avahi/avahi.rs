@@ -0,0 +1,122 @@
+//! # DBus interface proxies for: `org.freedesktop.Avahi.Server`, `org.freedesktop.Avahi.EntryGroup`, `org.freedesktop.Avahi.ServiceBrowser`
+//!
+//! This code was generated by `zbus-xmlgen` `2.0.1` from DBus introspection data
+//! and trimmed down to the members used by the tacd.
+//! Source: `Interface '/' from service 'org.freedesktop.Avahi' on system bus`.
+
+use zbus::dbus_proxy;
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.Avahi",
+    interface = "org.freedesktop.Avahi.Server",
+    default_path = "/"
+)]
+trait Server {
+    /// EntryGroupNew method
+    fn entry_group_new(&self) -> zbus::Result<zbus::zvariant::OwnedObjectPath>;
+
+    /// GetAlternativeServiceName method
+    fn get_alternative_service_name(&self, name: &str) -> zbus::Result<String>;
+
+    /// GetHostName method
+    fn get_host_name(&self) -> zbus::Result<String>;
+
+    /// ResolveService method
+    #[allow(clippy::too_many_arguments)]
+    fn resolve_service(
+        &self,
+        interface: i32,
+        protocol: i32,
+        name: &str,
+        type_: &str,
+        domain: &str,
+        aprotocol: i32,
+        flags: u32,
+    ) -> zbus::Result<(
+        i32,
+        i32,
+        String,
+        String,
+        String,
+        String,
+        i32,
+        String,
+        u16,
+        Vec<Vec<u8>>,
+        u32,
+    )>;
+
+    /// ServiceBrowserPrepare method
+    fn service_browser_prepare(
+        &self,
+        interface: i32,
+        protocol: i32,
+        type_: &str,
+        domain: &str,
+        flags: u32,
+    ) -> zbus::Result<zbus::zvariant::OwnedObjectPath>;
+}
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.Avahi",
+    interface = "org.freedesktop.Avahi.EntryGroup"
+)]
+trait EntryGroup {
+    /// AddService method
+    #[allow(clippy::too_many_arguments)]
+    fn add_service(
+        &self,
+        interface: i32,
+        protocol: i32,
+        flags: u32,
+        name: &str,
+        type_: &str,
+        domain: &str,
+        host: &str,
+        port: u16,
+        txt: &[&[u8]],
+    ) -> zbus::Result<()>;
+
+    /// Commit method
+    fn commit(&self) -> zbus::Result<()>;
+
+    /// Reset method
+    fn reset(&self) -> zbus::Result<()>;
+
+    /// StateChanged signal
+    #[dbus_proxy(signal)]
+    fn state_changed(&self, state: i32, error: &str) -> zbus::Result<()>;
+}
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.Avahi",
+    interface = "org.freedesktop.Avahi.ServiceBrowser"
+)]
+trait ServiceBrowser {
+    /// Start method
+    fn start(&self) -> zbus::Result<()>;
+
+    /// ItemNew signal
+    #[dbus_proxy(signal)]
+    fn item_new(
+        &self,
+        interface: i32,
+        protocol: i32,
+        name: &str,
+        type_: &str,
+        domain: &str,
+        flags: u32,
+    ) -> zbus::Result<()>;
+
+    /// ItemRemove signal
+    #[dbus_proxy(signal)]
+    fn item_remove(
+        &self,
+        interface: i32,
+        protocol: i32,
+        name: &str,
+        type_: &str,
+        domain: &str,
+        flags: u32,
+    ) -> zbus::Result<()>;
+}
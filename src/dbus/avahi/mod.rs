@@ -0,0 +1,263 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::sync::Arc;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "demo_mode"))]
+use std::collections::HashMap;
+
+#[cfg(not(feature = "demo_mode"))]
+use async_std::prelude::*;
+
+#[cfg(not(feature = "demo_mode"))]
+use async_std::task::spawn;
+
+#[cfg(not(feature = "demo_mode"))]
+use log::warn;
+
+use super::Connection;
+use crate::broker::{BrokerBuilder, Topic};
+
+#[cfg(not(feature = "demo_mode"))]
+mod avahi;
+
+/// The mDNS service type used to announce and find tacd instances
+#[cfg(not(feature = "demo_mode"))]
+const SERVICE_TYPE: &str = "_lxatac._tcp";
+
+// Constants from avahi-common/defs.h
+#[cfg(not(feature = "demo_mode"))]
+const IF_UNSPEC: i32 = -1;
+#[cfg(not(feature = "demo_mode"))]
+const PROTO_UNSPEC: i32 = -1;
+#[cfg(not(feature = "demo_mode"))]
+const PROTO_INET: i32 = 0;
+#[cfg(not(feature = "demo_mode"))]
+const LOOKUP_RESULT_LOCAL: u32 = 8;
+#[cfg(not(feature = "demo_mode"))]
+const ENTRY_GROUP_COLLISION: i32 = 3;
+
+/// Another TAC found on the network
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DiscoveredTac {
+    pub hostname: String,
+    pub address: String,
+    pub version: Option<String>,
+}
+
+/// Extract the tacd version from the TXT record of an announced service
+#[cfg(not(feature = "demo_mode"))]
+fn version_from_txt(txt: &[Vec<u8>]) -> Option<String> {
+    txt.iter()
+        .filter_map(|entry| entry.strip_prefix(b"version="))
+        .map(|v| String::from_utf8_lossy(v).into_owned())
+        .next()
+}
+
+#[cfg(not(feature = "demo_mode"))]
+async fn add_service(group: &avahi::EntryGroupProxy<'_>, name: &str) -> zbus::Result<()> {
+    let version = format!("version={}", env!("VERSION_STRING"));
+
+    group
+        .add_service(
+            IF_UNSPEC,
+            PROTO_UNSPEC,
+            0,
+            name,
+            SERVICE_TYPE,
+            "",
+            "",
+            80,
+            &[version.as_bytes()],
+        )
+        .await?;
+
+    group.commit().await
+}
+
+/// Announce this tacd via mDNS so that other TACs can find it.
+/// Pick another name whenever the current one collides with a service
+/// announced by someone else.
+#[cfg(not(feature = "demo_mode"))]
+async fn announce(conn: &Connection) -> zbus::Result<()> {
+    let server = avahi::ServerProxy::new(conn).await?;
+    let mut name = server.get_host_name().await?;
+
+    let group = avahi::EntryGroupProxy::builder(conn)
+        .path(server.entry_group_new().await?)?
+        .build()
+        .await?;
+
+    let mut states = group.receive_state_changed().await?;
+
+    add_service(&group, &name).await?;
+
+    while let Some(sig) = states.next().await {
+        if sig.args()?.state != ENTRY_GROUP_COLLISION {
+            continue;
+        }
+
+        let alternative = server.get_alternative_service_name(&name).await?;
+        warn!("mDNS service name {name} is already taken. Using {alternative} instead");
+        name = alternative;
+
+        group.reset().await?;
+        add_service(&group, &name).await?;
+    }
+
+    Ok(())
+}
+
+/// Keep the list of TACs in the `tacs` topic in sync with the services
+/// reported by avahi
+#[cfg(not(feature = "demo_mode"))]
+async fn browse(conn: &Connection, tacs: Arc<Topic<Vec<DiscoveredTac>>>) -> zbus::Result<()> {
+    /// A service is identified by (interface, protocol, name, type, domain).
+    /// The same name may e.g. show up on multiple interfaces.
+    type Key = (i32, i32, String, String, String);
+
+    enum Event {
+        New(Key, u32),
+        Remove(Key),
+    }
+
+    let server = avahi::ServerProxy::new(conn).await?;
+
+    // Use the ServiceBrowserPrepare + Start dance so that we can subscribe
+    // to the signals before avahi starts emitting them.
+    let browser = avahi::ServiceBrowserProxy::builder(conn)
+        .path(
+            server
+                .service_browser_prepare(IF_UNSPEC, PROTO_INET, SERVICE_TYPE, "", 0)
+                .await?,
+        )?
+        .build()
+        .await?;
+
+    let new_stream = browser.receive_item_new().await?.filter_map(|sig| {
+        sig.args().ok().map(|a| {
+            let key = (
+                a.interface,
+                a.protocol,
+                a.name.to_string(),
+                a.type_.to_string(),
+                a.domain.to_string(),
+            );
+
+            Event::New(key, a.flags)
+        })
+    });
+
+    let remove_stream = browser.receive_item_remove().await?.filter_map(|sig| {
+        sig.args().ok().map(|a| {
+            Event::Remove((
+                a.interface,
+                a.protocol,
+                a.name.to_string(),
+                a.type_.to_string(),
+                a.domain.to_string(),
+            ))
+        })
+    });
+
+    let mut events = futures::stream::select(new_stream, remove_stream);
+
+    browser.start().await?;
+
+    let mut found = HashMap::new();
+
+    while let Some(ev) = events.next().await {
+        match ev {
+            Event::New(key, flags) => {
+                // Do not list ourselves
+                if flags & LOOKUP_RESULT_LOCAL != 0 {
+                    continue;
+                }
+
+                let (interface, protocol, name, type_, domain) = &key;
+
+                match server
+                    .resolve_service(*interface, *protocol, name, type_, domain, PROTO_INET, 0)
+                    .await
+                {
+                    Ok((_, _, _, _, _, hostname, _, address, _, txt, _)) => {
+                        let tac = DiscoveredTac {
+                            hostname,
+                            address,
+                            version: version_from_txt(&txt),
+                        };
+
+                        found.insert(key, tac);
+                    }
+                    Err(e) => warn!("Failed to resolve mDNS service {name}: {e}"),
+                }
+            }
+            Event::Remove(key) => {
+                found.remove(&key);
+            }
+        }
+
+        let mut list: Vec<DiscoveredTac> = found.values().cloned().collect();
+        list.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+
+        tacs.set(list);
+    }
+
+    Ok(())
+}
+
+fn setup_topic(bb: &mut BrokerBuilder) -> Arc<Topic<Vec<DiscoveredTac>>> {
+    bb.topic_ro("/v1/tac/discovery/tacs", Some(Vec::new()))
+}
+
+/// Announce this tacd and list the other TACs found via mDNS
+#[cfg(feature = "demo_mode")]
+pub fn setup(bb: &mut BrokerBuilder, _conn: &Arc<Connection>) {
+    setup_topic(bb).set(vec![
+        DiscoveredTac {
+            hostname: "lxatac-00011.local".to_string(),
+            address: "192.168.1.11".to_string(),
+            version: Some("4.0-0-20230428214619".to_string()),
+        },
+        DiscoveredTac {
+            hostname: "lxatac-00042.local".to_string(),
+            address: "192.168.1.42".to_string(),
+            version: Some("4.1-0-20230301120000".to_string()),
+        },
+    ]);
+}
+
+/// Announce this tacd and list the other TACs found via mDNS
+#[cfg(not(feature = "demo_mode"))]
+pub fn setup(bb: &mut BrokerBuilder, conn: &Arc<Connection>) {
+    let tacs = setup_topic(bb);
+
+    let conn_task = conn.clone();
+    spawn(async move {
+        if let Err(e) = announce(&conn_task).await {
+            warn!("Failed to announce tacd via mDNS: {e}");
+        }
+    });
+
+    let conn_task = conn.clone();
+    spawn(async move {
+        if let Err(e) = browse(&conn_task, tacs).await {
+            warn!("Failed to browse for other TACs via mDNS: {e}");
+        }
+    });
+}
@@ -0,0 +1,20 @@
+//! # DBus interface proxy for: `org.freedesktop.login1.Manager`
+//!
+//! This code was generated by `zbus-xmlgen` `2.0.1` from DBus introspection data
+//! and trimmed down to the members used by the tacd.
+//! Source: `Interface '/org/freedesktop/login1' from service 'org.freedesktop.login1' on system bus`.
+
+use zbus::dbus_proxy;
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.login1",
+    interface = "org.freedesktop.login1.Manager",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    /// PowerOff method
+    fn power_off(&self, interactive: bool) -> zbus::Result<()>;
+
+    /// Reboot method
+    fn reboot(&self, interactive: bool) -> zbus::Result<()>;
+}
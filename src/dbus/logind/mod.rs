@@ -0,0 +1,275 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::{Duration, Instant};
+
+use async_std::fs::File;
+use async_std::future::timeout;
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use futures::stream::select;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::Connection;
+use crate::broker::{BrokerBuilder, Topic};
+use crate::setup_mode::is_admin;
+
+#[cfg(not(feature = "demo_mode"))]
+mod manager;
+
+/// Time a client has to confirm a reboot/power off request
+const CONFIRM_TIMEOUT: u32 = 30;
+
+/// Time between the confirmation and actually performing the action.
+/// The action can still be cancelled in this time, e.g. via the buttons.
+const COUNTDOWN: u32 = 10;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PowerAction {
+    Reboot,
+    PowerOff,
+}
+
+/// Only clients with the admin role may request a reboot/power off
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PowerRequest {
+    pub action: PowerAction,
+    /// The admin token set via /v1/tac/admin_token
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PowerConfirmation {
+    /// Confirm a request using the code shown on the display of the TAC
+    Confirm {
+        code: u32,
+    },
+    Cancel,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PowerState {
+    Idle,
+    AwaitingConfirmation { action: PowerAction, remaining: u32 },
+    CountingDown { action: PowerAction, remaining: u32 },
+}
+
+enum Event {
+    Request(PowerRequest),
+    Confirm(PowerConfirmation),
+}
+
+pub struct Logind {
+    pub request: Arc<Topic<PowerRequest>>,
+    pub confirm: Arc<Topic<PowerConfirmation>>,
+    pub state: Arc<Topic<PowerState>>,
+    /// The code that confirms the pending request. Only shown on the display
+    /// and never handed out via the API.
+    pub code: Arc<Topic<Option<u32>>>,
+}
+
+/// Get a random six digit confirmation code
+async fn confirmation_code() -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+
+    File::open("/dev/urandom")
+        .await?
+        .read_exact(&mut buf)
+        .await?;
+
+    Ok(u32::from_ne_bytes(buf) % 1_000_000)
+}
+
+#[cfg(feature = "demo_mode")]
+async fn perform(_conn: &Connection, action: PowerAction) {
+    println!("Asked to perform {:?} but don't feel like it", action);
+}
+
+#[cfg(not(feature = "demo_mode"))]
+async fn perform(conn: &Connection, action: PowerAction) {
+    let manager = match manager::ManagerProxy::new(conn).await {
+        Ok(m) => m,
+        Err(e) => {
+            warn!("Failed to connect to logind: {}", e);
+            return;
+        }
+    };
+
+    let res = match action {
+        PowerAction::Reboot => manager.reboot(false).await,
+        PowerAction::PowerOff => manager.power_off(false).await,
+    };
+
+    if let Err(e) = res {
+        warn!("Failed to {:?} via logind: {}", action, e);
+    }
+}
+
+impl PowerState {
+    /// Advance the countdowns by one second. Returns the action to perform
+    /// if a countdown has run out, i.e. its last second has passed.
+    fn tick(self) -> (Self, Option<PowerAction>) {
+        match self {
+            Self::Idle => (Self::Idle, None),
+            Self::AwaitingConfirmation {
+                remaining: 0..=1, ..
+            } => (Self::Idle, None),
+            Self::AwaitingConfirmation { action, remaining } => (
+                Self::AwaitingConfirmation {
+                    action,
+                    remaining: remaining - 1,
+                },
+                None,
+            ),
+            Self::CountingDown {
+                action,
+                remaining: 0..=1,
+            } => (Self::Idle, Some(action)),
+            Self::CountingDown { action, remaining } => (
+                Self::CountingDown {
+                    action,
+                    remaining: remaining - 1,
+                },
+                None,
+            ),
+        }
+    }
+}
+
+impl Logind {
+    pub fn new(bb: &mut BrokerBuilder, conn: &Arc<Connection>) -> Self {
+        let this = Self {
+            request: bb.topic_wo("/v1/tac/power/request", None),
+            confirm: bb.topic_wo("/v1/tac/power/confirm", None),
+            state: bb.topic_ro("/v1/tac/power/state", Some(PowerState::Idle)),
+            code: Topic::anonymous(Some(None)),
+        };
+
+        let conn = conn.clone();
+        let state = this.state.clone();
+        let code = this.code.clone();
+        let (request_stream, _) = this.request.clone().subscribe_unbounded();
+        let (confirm_stream, _) = this.confirm.clone().subscribe_unbounded();
+
+        // Rebooting or powering off the TAC is a two step process:
+        // A client with the admin role requests the action, which has to be
+        // confirmed within CONFIRM_TIMEOUT seconds using the code shown on
+        // the display. This makes sure that a single client can not reboot
+        // the TAC on its own by accident.
+        // After the confirmation there is another COUNTDOWN seconds in which
+        // the action can be cancelled (e.g. by pressing a button on the TAC).
+        spawn(async move {
+            let mut events = select(
+                request_stream.map(Event::Request),
+                confirm_stream.map(Event::Confirm),
+            );
+
+            let mut next_tick = Instant::now();
+
+            loop {
+                let current = state.try_get().unwrap_or(PowerState::Idle);
+
+                let ev = if current == PowerState::Idle {
+                    match events.next().await {
+                        Some(ev) => Some(ev),
+                        None => break,
+                    }
+                } else {
+                    let remaining = next_tick.saturating_duration_since(Instant::now());
+
+                    match timeout(remaining, events.next()).await {
+                        Ok(Some(ev)) => Some(ev),
+                        Ok(None) => break,
+                        Err(_) => None,
+                    }
+                };
+
+                let next = match (ev, current) {
+                    (Some(Event::Request(req)), PowerState::Idle) => {
+                        if !is_admin(&req.token).await {
+                            warn!("Rejected {:?} request without admin role", req.action);
+                            continue;
+                        }
+
+                        match confirmation_code().await {
+                            Ok(c) => {
+                                info!("{:?} requested. Waiting for confirmation", req.action);
+
+                                code.set(Some(c));
+                                next_tick = Instant::now() + Duration::from_secs(1);
+
+                                PowerState::AwaitingConfirmation {
+                                    action: req.action,
+                                    remaining: CONFIRM_TIMEOUT,
+                                }
+                            }
+                            Err(e) => {
+                                warn!("Failed to generate a confirmation code: {}", e);
+                                continue;
+                            }
+                        }
+                    }
+                    (
+                        Some(Event::Confirm(PowerConfirmation::Confirm { code: c })),
+                        PowerState::AwaitingConfirmation { action, .. },
+                    ) => {
+                        if code.try_get().flatten() == Some(c) {
+                            info!("{:?} confirmed", action);
+
+                            next_tick = Instant::now() + Duration::from_secs(1);
+
+                            PowerState::CountingDown {
+                                action,
+                                remaining: COUNTDOWN,
+                            }
+                        } else {
+                            // Do not allow guessing the code
+                            warn!("Wrong confirmation code for {:?}. Cancelled", action);
+                            PowerState::Idle
+                        }
+                    }
+                    (Some(Event::Confirm(PowerConfirmation::Cancel)), _) => PowerState::Idle,
+                    (Some(_), current) => current,
+                    (None, current) => {
+                        next_tick += Duration::from_secs(1);
+
+                        let (next, action) = current.tick();
+
+                        if let Some(action) = action {
+                            perform(&conn, action).await;
+                        }
+
+                        next
+                    }
+                };
+
+                // The code is only good for a single confirmation
+                if !matches!(next, PowerState::AwaitingConfirmation { .. }) {
+                    code.modify(|prev| prev.flatten().map(|_| None));
+                }
+
+                if next != current {
+                    state.set(next);
+                }
+            }
+        });
+
+        this
+    }
+}
@@ -0,0 +1,354 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::stream::StreamExt;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use serde::{Deserialize, Serialize};
+
+use super::Connection;
+use crate::broker::{BrokerBuilder, Topic};
+
+// All of the following includes are not used in demo_mode.
+// Put them inside a mod so we do not have to decorate each one with
+// a #[cfg(not(feature = "demo_mode"))].
+#[cfg(not(feature = "demo_mode"))]
+mod optional_includes {
+    pub use std::collections::HashMap;
+    pub use std::convert::TryFrom;
+    pub use std::time::Duration;
+
+    pub use anyhow::{anyhow, Result};
+    pub use async_std::channel::unbounded;
+    pub use async_std::task::sleep;
+    pub use futures::stream::select;
+    pub use log::warn;
+    pub use serde::de::DeserializeOwned;
+    pub use zvariant::Value;
+
+    pub(crate) use crate::dbus::networkmanager::{add_connection, remove_connection};
+}
+
+#[cfg(not(feature = "demo_mode"))]
+use optional_includes::*;
+
+#[cfg(not(feature = "demo_mode"))]
+mod modem;
+
+#[cfg(not(feature = "demo_mode"))]
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[cfg(not(feature = "demo_mode"))]
+const CONNECTION_UUID: &str = "7acd0000-0000-4000-8002-000000000000";
+
+/// Use a route metric worse than the one of the wired uplink, so that the
+/// modem is only used if the wired uplink is not available.
+#[cfg(not(feature = "demo_mode"))]
+const ROUTE_METRIC: i64 = 1000;
+
+/// The modem state as reported by ModemManager (`MMModemState`) or `Absent`
+/// if no modem was found.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum CellularState {
+    Absent,
+    Failed,
+    Unknown,
+    Initializing,
+    Locked,
+    Disabled,
+    Disabling,
+    Enabling,
+    Enabled,
+    Searching,
+    Registered,
+    Disconnecting,
+    Connecting,
+    Connected,
+}
+
+impl From<i32> for CellularState {
+    fn from(state: i32) -> Self {
+        match state {
+            -1 => Self::Failed,
+            1 => Self::Initializing,
+            2 => Self::Locked,
+            3 => Self::Disabled,
+            4 => Self::Disabling,
+            5 => Self::Enabling,
+            6 => Self::Enabled,
+            7 => Self::Searching,
+            8 => Self::Registered,
+            9 => Self::Disconnecting,
+            10 => Self::Connecting,
+            11 => Self::Connected,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct DataUsage {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+#[cfg(not(feature = "demo_mode"))]
+struct ModemStatus {
+    state: CellularState,
+    signal_quality: u32,
+    data_usage: DataUsage,
+    /// The port NetworkManager knows the modem by, e.g. `cdc-wdm0`
+    port: String,
+}
+
+/// Things the cellular connection profile depends on
+#[cfg(not(feature = "demo_mode"))]
+enum ProfileChange {
+    Apn(String),
+    Port(Option<String>),
+}
+
+pub struct Cellular {
+    pub apn: Arc<Topic<String>>,
+    pub state: Arc<Topic<CellularState>>,
+    pub signal_quality: Arc<Topic<u32>>,
+    pub data_usage: Arc<Topic<DataUsage>>,
+}
+
+impl Cellular {
+    fn setup_topics(bb: &mut BrokerBuilder) -> Self {
+        Self {
+            apn: bb.topic(
+                "/v1/tac/network/cellular/apn",
+                true,
+                true,
+                true,
+                Some(String::new()),
+                1,
+            ),
+            state: bb.topic_ro(
+                "/v1/tac/network/cellular/state",
+                Some(CellularState::Absent),
+            ),
+            signal_quality: bb.topic_ro("/v1/tac/network/cellular/signal_quality", Some(0)),
+            data_usage: bb.topic_ro(
+                "/v1/tac/network/cellular/data_usage",
+                Some(DataUsage {
+                    rx_bytes: 0,
+                    tx_bytes: 0,
+                }),
+            ),
+        }
+    }
+
+    #[cfg(feature = "demo_mode")]
+    pub fn new(bb: &mut BrokerBuilder, _conn: &Arc<Connection>) -> Self {
+        let this = Self::setup_topics(bb);
+
+        this.signal_quality.set(62);
+
+        // Pretend that the modem connects as soon as an APN is configured
+        let (mut apn_stream, _) = this.apn.clone().subscribe_unbounded();
+        let state = this.state.clone();
+        let data_usage = this.data_usage.clone();
+
+        spawn(async move {
+            while let Some(apn) = apn_stream.next().await {
+                if apn.is_empty() {
+                    state.set(CellularState::Registered);
+                } else {
+                    state.set(CellularState::Connected);
+                    data_usage.set(DataUsage {
+                        rx_bytes: 1_843_712,
+                        tx_bytes: 402_944,
+                    });
+                }
+            }
+        });
+
+        this
+    }
+
+    #[cfg(not(feature = "demo_mode"))]
+    pub fn new(bb: &mut BrokerBuilder, conn: &Arc<Connection>) -> Self {
+        let this = Self::setup_topics(bb);
+
+        let conn_task = conn.clone();
+        let (apn_stream, _) = this.apn.clone().subscribe_unbounded();
+        let (port_tx, port_rx) = unbounded();
+
+        // Keep the NetworkManager connection profile for the modem in sync
+        // with the configured APN and bind it to the modem we monitor.
+        // An empty APN disables the cellular uplink.
+        spawn(async move {
+            let mut changes = select(
+                apn_stream.map(ProfileChange::Apn),
+                port_rx.map(ProfileChange::Port),
+            );
+
+            let mut apn = String::new();
+            let mut port = None;
+
+            while let Some(change) = changes.next().await {
+                match change {
+                    ProfileChange::Apn(a) => apn = a,
+                    ProfileChange::Port(p) => port = p,
+                }
+
+                if let Err(e) = remove_connection(&conn_task, CONNECTION_UUID).await {
+                    warn!("Failed to remove cellular connection: {}", e);
+                }
+
+                let port = match (apn.is_empty(), &port) {
+                    (false, Some(port)) => port,
+                    _ => continue,
+                };
+
+                let settings = connection_settings(&apn, port);
+
+                if let Err(e) = add_connection(&conn_task, settings).await {
+                    warn!("Failed to set up cellular connection: {}", e);
+                }
+            }
+        });
+
+        let conn_task = conn.clone();
+        let state = this.state.clone();
+        let signal_quality = this.signal_quality.clone();
+        let data_usage = this.data_usage.clone();
+
+        // Modems may come and go (e.g. when they are reset) so we just poll
+        // ModemManager for the first modem it knows about.
+        spawn(async move {
+            let mut prev_port = None;
+
+            loop {
+                let port = match modem_status(&conn_task).await {
+                    Ok(status) => {
+                        set_if_changed(&state, status.state);
+                        set_if_changed(&signal_quality, status.signal_quality);
+                        set_if_changed(&data_usage, status.data_usage);
+                        Some(status.port)
+                    }
+                    Err(_) => {
+                        set_if_changed(&state, CellularState::Absent);
+                        None
+                    }
+                };
+
+                if port != prev_port {
+                    prev_port = port.clone();
+                    let _ = port_tx.send(port).await;
+                }
+
+                sleep(POLL_INTERVAL).await;
+            }
+        });
+
+        this
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+fn set_if_changed<E: Serialize + DeserializeOwned + Clone + PartialEq>(topic: &Topic<E>, value: E) {
+    topic.modify(|prev| match prev {
+        Some(prev) if prev == value => None,
+        _ => Some(value),
+    });
+}
+
+#[cfg(not(feature = "demo_mode"))]
+fn connection_settings<'a>(
+    apn: &'a str,
+    port: &'a str,
+) -> HashMap<&'static str, HashMap<&'static str, Value<'a>>> {
+    let mut connection = HashMap::new();
+    connection.insert("id", Value::from("tacd-cellular"));
+    connection.insert("uuid", Value::from(CONNECTION_UUID));
+    connection.insert("type", Value::from("gsm"));
+    connection.insert("interface-name", Value::from(port));
+    connection.insert("autoconnect", Value::from(true));
+
+    let mut gsm = HashMap::new();
+    gsm.insert("apn", Value::from(apn));
+
+    let mut ipv4 = HashMap::new();
+    ipv4.insert("method", Value::from("auto"));
+    ipv4.insert("route-metric", Value::from(ROUTE_METRIC));
+
+    let mut ipv6 = HashMap::new();
+    ipv6.insert("method", Value::from("auto"));
+    ipv6.insert("route-metric", Value::from(ROUTE_METRIC));
+
+    let mut settings = HashMap::new();
+    settings.insert("connection", connection);
+    settings.insert("gsm", gsm);
+    settings.insert("ipv4", ipv4);
+    settings.insert("ipv6", ipv6);
+
+    settings
+}
+
+#[cfg(not(feature = "demo_mode"))]
+async fn modem_status(conn: &Connection) -> Result<ModemStatus> {
+    let objects = modem::ObjectManagerProxy::new(conn)
+        .await?
+        .get_managed_objects()
+        .await?;
+
+    let path = objects
+        .into_keys()
+        .min_by(|a, b| a.as_str().cmp(b.as_str()))
+        .ok_or_else(|| anyhow!("No modem found"))?;
+
+    let modem = modem::ModemProxy::builder(conn).path(path)?.build().await?;
+
+    let state = CellularState::from(modem.state().await?);
+    let (signal_quality, _recent) = modem.signal_quality().await?;
+    let port = modem.primary_port().await?;
+
+    let mut usage = DataUsage {
+        rx_bytes: 0,
+        tx_bytes: 0,
+    };
+
+    for bearer_path in modem.bearers().await? {
+        let bearer = modem::BearerProxy::builder(conn)
+            .path(bearer_path)?
+            .build()
+            .await?;
+
+        let stats = bearer.stats().await?;
+
+        let get = |key: &str| {
+            stats
+                .get(key)
+                .and_then(|v| u64::try_from(v.clone()).ok())
+                .unwrap_or(0)
+        };
+
+        usage.rx_bytes += get("rx-bytes");
+        usage.tx_bytes += get("tx-bytes");
+    }
+
+    Ok(ModemStatus {
+        state,
+        signal_quality,
+        data_usage: usage,
+        port,
+    })
+}
@@ -0,0 +1,60 @@
+//! # DBus interface proxies for: `org.freedesktop.ModemManager1.Modem`, `org.freedesktop.ModemManager1.Bearer`
+//!
+//! This code was generated by `zbus-xmlgen` `2.0.0` from DBus introspection data
+//! and trimmed down to the members used by the tacd.
+//! Source: `Interface '/org/freedesktop/ModemManager1/Modem/0' from service 'org.freedesktop.ModemManager1' on system bus`.
+//!
+
+use zbus::dbus_proxy;
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.ModemManager1",
+    interface = "org.freedesktop.DBus.ObjectManager",
+    default_path = "/org/freedesktop/ModemManager1"
+)]
+trait ObjectManager {
+    /// GetManagedObjects method
+    fn get_managed_objects(
+        &self,
+    ) -> zbus::Result<
+        std::collections::HashMap<
+            zbus::zvariant::OwnedObjectPath,
+            std::collections::HashMap<
+                String,
+                std::collections::HashMap<String, zbus::zvariant::OwnedValue>,
+            >,
+        >,
+    >;
+}
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.ModemManager1",
+    interface = "org.freedesktop.ModemManager1.Modem"
+)]
+trait Modem {
+    /// Bearers property
+    #[dbus_proxy(property)]
+    fn bearers(&self) -> zbus::Result<Vec<zbus::zvariant::OwnedObjectPath>>;
+
+    /// PrimaryPort property
+    #[dbus_proxy(property)]
+    fn primary_port(&self) -> zbus::Result<String>;
+
+    /// SignalQuality property
+    #[dbus_proxy(property)]
+    fn signal_quality(&self) -> zbus::Result<(u32, bool)>;
+
+    /// State property
+    #[dbus_proxy(property)]
+    fn state(&self) -> zbus::Result<i32>;
+}
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.ModemManager1",
+    interface = "org.freedesktop.ModemManager1.Bearer"
+)]
+trait Bearer {
+    /// Stats property
+    #[dbus_proxy(property)]
+    fn stats(&self) -> zbus::Result<std::collections::HashMap<String, zbus::zvariant::OwnedValue>>;
+}
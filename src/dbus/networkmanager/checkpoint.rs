@@ -0,0 +1,312 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use async_std::future::timeout;
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use futures::stream::select;
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::broker::{BrokerBuilder, Topic};
+
+#[cfg(feature = "demo_mode")]
+mod nm {
+    use anyhow::Result;
+
+    pub struct Connection;
+    pub struct Checkpoint;
+
+    pub async fn create(_: &Connection, _: u32) -> Result<Checkpoint> {
+        Ok(Checkpoint)
+    }
+
+    pub async fn destroy(_: &Connection, _: &Checkpoint) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod nm {
+    use anyhow::Result;
+    use zvariant::{ObjectPath, OwnedObjectPath};
+
+    use super::super::networkmanager::NetworkManagerProxy;
+
+    pub use zbus::Connection;
+
+    pub type Checkpoint = OwnedObjectPath;
+
+    pub async fn create(conn: &Connection, rollback_timeout: u32) -> Result<Checkpoint> {
+        let proxy = NetworkManagerProxy::new(conn).await?;
+
+        // An empty list of devices means "all devices"
+        let devices: &[ObjectPath<'_>] = &[];
+
+        Ok(proxy
+            .checkpoint_create(devices, rollback_timeout, 0)
+            .await?)
+    }
+
+    pub async fn destroy(conn: &Connection, checkpoint: &Checkpoint) -> Result<()> {
+        let proxy = NetworkManagerProxy::new(conn).await?;
+
+        Ok(proxy.checkpoint_destroy(checkpoint).await?)
+    }
+}
+
+pub(super) use nm::Connection;
+
+/// A network topic that is saved when a checkpoint is created and reset
+/// when NetworkManager rolls back to it.
+pub(super) struct Tracked<T> {
+    topic: Arc<Topic<T>>,
+    restored: Option<Arc<Mutex<Vec<T>>>>,
+}
+
+impl<T> Clone for Tracked<T> {
+    fn clone(&self) -> Self {
+        Self {
+            topic: self.topic.clone(),
+            restored: self.restored.clone(),
+        }
+    }
+}
+
+impl<T> Tracked<T>
+where
+    T: Serialize + DeserializeOwned + Clone + PartialEq + Send + Sync + 'static,
+{
+    #[cfg(not(feature = "demo_mode"))]
+    pub(super) fn new(topic: Arc<Topic<T>>) -> Self {
+        Self {
+            topic,
+            restored: Some(Arc::new(Mutex::new(Vec::new()))),
+        }
+    }
+
+    /// Track a setting NetworkManager does not roll back itself, e.g. because
+    /// it is only applied at runtime. The handler of the topic applies
+    /// restored values like any other.
+    pub(super) fn runtime(topic: Arc<Topic<T>>) -> Self {
+        Self {
+            topic,
+            restored: None,
+        }
+    }
+
+    #[cfg(not(feature = "demo_mode"))]
+    pub(super) fn topic(&self) -> Arc<Topic<T>> {
+        self.topic.clone()
+    }
+
+    #[cfg(not(feature = "demo_mode"))]
+    /// Check if a value received from the topic was set by a rollback.
+    ///
+    /// NetworkManager has already restored the matching network configuration
+    /// in that case, so the handler of the topic must not apply it again.
+    pub(super) fn is_restored(&self, value: &T) -> bool {
+        let mut restored = match &self.restored {
+            Some(restored) => restored.lock().unwrap(),
+            None => return false,
+        };
+
+        match restored.iter().position(|r| r == value) {
+            Some(pos) => {
+                restored.remove(pos);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Restores a topic to the value it had when the checkpoint was created
+type Restore = Box<dyn FnOnce() + Send>;
+
+pub(super) trait Checkpointed: Send + Sync {
+    fn save(&self) -> Option<Restore>;
+}
+
+impl<T> Checkpointed for Tracked<T>
+where
+    T: Serialize + DeserializeOwned + Clone + PartialEq + Send + Sync + 'static,
+{
+    fn save(&self) -> Option<Restore> {
+        let value = self.topic.try_get()?;
+        let this = self.clone();
+
+        Some(Box::new(move || {
+            // Only an actual change reaches the handler and has to be
+            // marked as restored.
+            this.topic.modify(|prev| match prev {
+                Some(prev) if prev == value => None,
+                _ => {
+                    if let Some(restored) = &this.restored {
+                        restored.lock().unwrap().push(value.clone());
+                    }
+
+                    Some(value)
+                }
+            })
+        }))
+    }
+}
+
+/// The network configuration as seen by the tacd when a checkpoint was
+/// created. Restored when NetworkManager rolls back to the checkpoint.
+struct Snapshot(Vec<Restore>);
+
+impl Snapshot {
+    fn take(topics: &[Box<dyn Checkpointed>]) -> Self {
+        Self(topics.iter().filter_map(|t| t.save()).collect())
+    }
+
+    fn restore(self) {
+        for restore in self.0 {
+            restore();
+        }
+    }
+}
+
+enum Event {
+    Begin(u32),
+    Confirm,
+}
+
+pub struct Checkpoints {
+    pub begin: Arc<Topic<u32>>,
+    pub confirm: Arc<Topic<bool>>,
+    pub pending: Arc<Topic<bool>>,
+}
+
+impl Checkpoints {
+    pub(super) fn setup_topics(bb: &mut BrokerBuilder) -> Self {
+        Self {
+            begin: bb.topic_wo("/v1/tac/network/checkpoint/begin", None),
+            confirm: bb.topic_wo("/v1/tac/network/checkpoint/confirm", None),
+            pending: bb.topic_ro("/v1/tac/network/checkpoint/pending", Some(false)),
+        }
+    }
+
+    /// Handle checkpoint requests from the web client.
+    ///
+    /// A client writes the number of seconds it needs to make and test its
+    /// changes to the `begin` topic, makes the changes and then confirms them
+    /// via the `confirm` topic.
+    /// If the confirmation does not arrive in time NetworkManager restores the
+    /// network configuration from the checkpoint and we restore the
+    /// corresponding `topics`.
+    pub(super) fn handle(&self, conn: Arc<Connection>, topics: Vec<Box<dyn Checkpointed>>) {
+        let (begin_stream, _) = self.begin.clone().subscribe_unbounded();
+        let (confirm_stream, _) = self.confirm.clone().subscribe_unbounded();
+        let pending = self.pending.clone();
+
+        spawn(async move {
+            let mut events = select(
+                begin_stream.map(Event::Begin),
+                confirm_stream.filter(|c| *c).map(|_| Event::Confirm),
+            );
+
+            let mut current: Option<(nm::Checkpoint, Instant, Snapshot)> = None;
+
+            loop {
+                let deadline = current.as_ref().map(|(_, deadline, _)| *deadline);
+
+                let ev = match deadline {
+                    Some(deadline) => {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+
+                        match timeout(remaining, events.next()).await {
+                            Ok(ev) => ev,
+                            Err(_) => {
+                                // NetworkManager has rolled back the network
+                                // configuration by now. Do the same for our topics.
+                                warn!(
+                                    "Network configuration was not confirmed in time. Rolled back"
+                                );
+
+                                if let Some((_, _, snapshot)) = current.take() {
+                                    snapshot.restore();
+                                }
+
+                                pending.set(false);
+                                continue;
+                            }
+                        }
+                    }
+                    None => events.next().await,
+                };
+
+                let ev = match ev {
+                    Some(ev) => ev,
+                    None => break,
+                };
+
+                // A new checkpoint replaces the old one, a confirmation
+                // removes it. In both cases the old one has to go.
+                if let Some((checkpoint, _, _)) = current.take() {
+                    if let Err(e) = nm::destroy(&conn, &checkpoint).await {
+                        warn!("Failed to destroy network checkpoint: {}", e);
+                    }
+                }
+
+                match ev {
+                    Event::Begin(rollback_timeout) => {
+                        match begin(&conn, rollback_timeout, &topics).await {
+                            Ok(checkpoint) => {
+                                current = Some(checkpoint);
+                                pending.set(true);
+                            }
+                            Err(e) => {
+                                warn!("Failed to create network checkpoint: {}", e);
+                                pending.set(false);
+                            }
+                        }
+                    }
+                    Event::Confirm => {
+                        info!("Network configuration confirmed");
+                        pending.set(false);
+                    }
+                }
+            }
+        });
+    }
+}
+
+async fn begin(
+    conn: &Connection,
+    rollback_timeout: u32,
+    topics: &[Box<dyn Checkpointed>],
+) -> Result<(nm::Checkpoint, Instant, Snapshot)> {
+    // NetworkManager interprets a timeout of zero as "never roll back",
+    // which defeats the purpose.
+    if rollback_timeout == 0 {
+        bail!("A rollback timeout of zero seconds is not supported");
+    }
+
+    let snapshot = Snapshot::take(topics);
+    let checkpoint = nm::create(conn, rollback_timeout).await?;
+    let deadline = Instant::now() + Duration::from_secs(rollback_timeout.into());
+
+    Ok((checkpoint, deadline, snapshot))
+}
@@ -0,0 +1,269 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use serde::{Deserialize, Serialize};
+
+use super::checkpoint::{Checkpointed, Tracked};
+use super::InterfaceNames;
+use crate::broker::{BrokerBuilder, Topic};
+
+#[cfg(not(feature = "demo_mode"))]
+mod optional_includes {
+    pub use std::collections::HashMap;
+    pub use std::convert::TryFrom;
+    pub use std::net::IpAddr;
+    pub use std::time::Duration;
+
+    pub use async_std::task::sleep;
+
+    pub use anyhow::{anyhow, Result};
+    pub use futures::stream::select;
+    pub use log::warn;
+    pub use zbus::Connection;
+    pub use zvariant::{OwnedValue, Value};
+
+    pub use super::super::devices::DeviceProxy;
+    pub use super::super::dnsmanager::DnsManagerProxy;
+    pub(super) use super::super::path_from_interface;
+}
+
+#[cfg(not(feature = "demo_mode"))]
+use optional_includes::*;
+
+/// The DNS configuration NetworkManager currently uses for an interface
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DnsConfig {
+    pub interface: String,
+    pub servers: Vec<String>,
+    pub domains: Vec<String>,
+}
+
+/// DNS servers and search domains to use instead of the ones provided
+/// via DHCP or the connection profile.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DnsOverride {
+    pub servers: Vec<String>,
+    pub domains: Vec<String>,
+}
+
+pub struct Dns {
+    pub active: Arc<Topic<Vec<DnsConfig>>>,
+    pub override_config: Arc<Topic<Option<DnsOverride>>>,
+}
+
+impl Dns {
+    pub(super) fn setup_topics(bb: &mut BrokerBuilder) -> Self {
+        Self {
+            active: bb.topic_ro("/v1/tac/network/dns/active", Some(Vec::new())),
+            // `None` uses whatever the uplink connection provides
+            override_config: bb.topic(
+                "/v1/tac/network/dns/override",
+                true,
+                true,
+                true,
+                Some(None),
+                1,
+            ),
+        }
+    }
+
+    #[cfg(feature = "demo_mode")]
+    pub(super) fn handle(&self, names: &InterfaceNames) -> Vec<Box<dyn Checkpointed>> {
+        let dhcp = DnsConfig {
+            interface: names.bridge.clone(),
+            servers: vec!["192.168.1.1".to_string()],
+            domains: vec!["lab.example.com".to_string()],
+        };
+
+        let active = self.active.clone();
+        let (mut override_stream, _) = self.override_config.clone().subscribe_unbounded();
+
+        spawn(async move {
+            while let Some(ovr) = override_stream.next().await {
+                let config = match ovr {
+                    Some(ovr) => DnsConfig {
+                        interface: dhcp.interface.clone(),
+                        servers: ovr.servers,
+                        domains: ovr.domains,
+                    },
+                    None => dhcp.clone(),
+                };
+
+                active.set(vec![config]);
+            }
+        });
+
+        vec![Box::new(Tracked::runtime(self.override_config.clone()))]
+    }
+
+    #[cfg(not(feature = "demo_mode"))]
+    pub(super) fn handle(
+        &self,
+        conn: &Arc<Connection>,
+        names: &InterfaceNames,
+    ) -> Vec<Box<dyn Checkpointed>> {
+        let conn_task = conn.clone();
+        let active = self.active.clone();
+
+        // Forward the DNS configuration NetworkManager hands to the resolver
+        // to the broker framework.
+        spawn(async move {
+            let proxy = loop {
+                if let Ok(proxy) = DnsManagerProxy::new(&conn_task).await {
+                    break proxy;
+                }
+
+                sleep(Duration::from_secs(1)).await;
+            };
+
+            let mut stream = proxy.receive_configuration_changed().await;
+
+            if let Ok(c) = proxy.configuration().await {
+                active.set(parse_configuration(c));
+            }
+
+            while let Some(v) = stream.next().await {
+                if let Ok(c) = v.get().await {
+                    active.set(parse_configuration(c));
+                }
+            }
+        });
+
+        let conn_task = conn.clone();
+        let bridge = names.bridge.clone();
+        let override_config = self.override_config.clone();
+        let (override_stream, _) = self.override_config.clone().subscribe_unbounded();
+
+        // The overrides are applied to the bridge device at runtime instead
+        // of being written to the connection profile. This means they are
+        // lost whenever the connection is re-activated, so they have to be
+        // re-applied whenever that happens.
+        spawn(async move {
+            // NetworkManager may not know about the bridge yet
+            let device = loop {
+                if let Ok(device) = device_from_interface(&conn_task, &bridge).await {
+                    break device;
+                }
+
+                sleep(Duration::from_secs(1)).await;
+            };
+
+            let active_conn_stream = device.receive_active_connection_changed().await;
+
+            let mut changes = select(override_stream.map(|_| ()), active_conn_stream.map(|_| ()));
+
+            while changes.next().await.is_some() {
+                let ovr = override_config.try_get().flatten();
+
+                if let Err(e) = apply_override(&device, ovr.as_ref()).await {
+                    warn!("Failed to apply DNS override: {}", e);
+                }
+            }
+        });
+
+        // The override is not part of the connection profile, so
+        // NetworkManager can not roll it back and we have to apply the
+        // restored value ourselves.
+        vec![Box::new(Tracked::runtime(self.override_config.clone()))]
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+async fn device_from_interface(conn: &Connection, interface: &str) -> Result<DeviceProxy<'static>> {
+    let path = path_from_interface(conn, interface).await?;
+    let device = DeviceProxy::builder(conn).path(path)?.build().await?;
+
+    Ok(device)
+}
+
+#[cfg(not(feature = "demo_mode"))]
+fn string_list(entry: &HashMap<String, OwnedValue>, key: &str) -> Vec<String> {
+    entry
+        .get(key)
+        .and_then(|v| Vec::<String>::try_from(Value::from(v.clone())).ok())
+        .unwrap_or_default()
+}
+
+/// Turn the `Configuration` property of the NetworkManager DnsManager into
+/// a list of per-interface DNS configurations.
+#[cfg(not(feature = "demo_mode"))]
+fn parse_configuration(configuration: Vec<HashMap<String, OwnedValue>>) -> Vec<DnsConfig> {
+    configuration
+        .iter()
+        .map(|entry| DnsConfig {
+            interface: entry
+                .get("interface")
+                .and_then(|v| v.downcast_ref::<zvariant::Str>())
+                .map(|s| s.as_str().to_string())
+                .unwrap_or_default(),
+            servers: string_list(entry, "nameservers"),
+            domains: string_list(entry, "domains"),
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "demo_mode"))]
+async fn apply_override(device: &DeviceProxy<'_>, ovr: Option<&DnsOverride>) -> Result<()> {
+    // An empty set of settings re-applies the connection profile as is,
+    // which removes a previous override.
+    let ovr = match ovr {
+        Some(ovr) => ovr,
+        None => return Ok(device.reapply(HashMap::new(), 0, 0).await?),
+    };
+
+    let mut dns4 = Vec::new();
+    let mut dns6 = Vec::new();
+
+    for server in ovr.servers.iter() {
+        match server.parse::<IpAddr>() {
+            // NetworkManager expects IPv4 addresses in network byte order
+            Ok(IpAddr::V4(addr)) => dns4.push(u32::from_ne_bytes(addr.octets())),
+            Ok(IpAddr::V6(addr)) => dns6.push(addr.octets().to_vec()),
+            Err(_) => return Err(anyhow!("Invalid DNS server address \"{}\"", server)),
+        }
+    }
+
+    let (applied, version_id) = device.get_applied_connection(0).await?;
+
+    let mut settings: HashMap<&str, HashMap<&str, Value>> = applied
+        .iter()
+        .map(|(group, values)| {
+            let values = values
+                .iter()
+                .map(|(k, v)| (k.as_str(), Value::from(v.clone())))
+                .collect();
+
+            (group.as_str(), values)
+        })
+        .collect();
+
+    let ipv4 = settings.entry("ipv4").or_default();
+    ipv4.insert("dns", Value::from(dns4));
+    ipv4.insert("dns-search", Value::from(ovr.domains.clone()));
+    ipv4.insert("ignore-auto-dns", Value::from(true));
+
+    let ipv6 = settings.entry("ipv6").or_default();
+    ipv6.insert("dns", Value::from(dns6));
+    ipv6.insert("ignore-auto-dns", Value::from(true));
+
+    device.reapply(settings, version_id, 0).await?;
+
+    Ok(())
+}
@@ -0,0 +1,21 @@
+//! # DBus interface proxy for: `org.freedesktop.NetworkManager.DnsManager`
+//!
+//! This code was generated by `zbus-xmlgen` `2.0.0` from DBus introspection data
+//! and trimmed down to the members used by the tacd.
+//! Source: `Interface '/org/freedesktop/NetworkManager/DnsManager' from service 'org.freedesktop.NetworkManager' on system bus`.
+//!
+
+use zbus::dbus_proxy;
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.NetworkManager",
+    interface = "org.freedesktop.NetworkManager.DnsManager",
+    default_path = "/org/freedesktop/NetworkManager/DnsManager"
+)]
+trait DnsManager {
+    /// Configuration property
+    #[dbus_proxy(property)]
+    fn configuration(
+        &self,
+    ) -> zbus::Result<Vec<std::collections::HashMap<String, zbus::zvariant::OwnedValue>>>;
+}
@@ -0,0 +1,311 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use serde::{Deserialize, Serialize};
+
+use super::checkpoint::{Checkpointed, Tracked};
+use crate::broker::{BrokerBuilder, Topic};
+
+#[cfg(not(feature = "demo_mode"))]
+mod optional_includes {
+    pub use std::collections::HashMap;
+    pub use std::convert::TryFrom;
+    pub use std::time::Duration;
+
+    pub use anyhow::{anyhow, Result};
+    pub use async_std::task::sleep;
+    pub use futures::stream::select;
+    pub use log::warn;
+    pub use zbus::Connection;
+    pub use zvariant::{ObjectPath, Value};
+
+    pub use super::super::devices::{DeviceProxy, NMDeviceState};
+    pub use super::super::networkmanager::NetworkManagerProxy;
+}
+
+#[cfg(not(feature = "demo_mode"))]
+use optional_includes::*;
+
+#[cfg(not(feature = "demo_mode"))]
+use super::{path_from_interface, remove_connection, InterfaceNames};
+
+#[cfg(feature = "demo_mode")]
+mod paths {
+    pub const CA_CERT_PATH: &str = "demo_files/etc/tacd/8021x/ca.pem";
+    pub const CLIENT_CERT_PATH: &str = "demo_files/etc/tacd/8021x/client.pem";
+    pub const PRIVATE_KEY_PATH: &str = "demo_files/etc/tacd/8021x/client.key";
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod paths {
+    pub const CA_CERT_PATH: &str = "/etc/tacd/8021x/ca.pem";
+    pub const CLIENT_CERT_PATH: &str = "/etc/tacd/8021x/client.pem";
+    pub const PRIVATE_KEY_PATH: &str = "/etc/tacd/8021x/client.key";
+}
+
+pub use paths::*;
+
+#[cfg(not(feature = "demo_mode"))]
+const CONNECTION_UUID: &str = "7acd0000-0000-4000-8003-000000000000";
+
+#[cfg(not(feature = "demo_mode"))]
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Dot1xMethod {
+    /// Username/password authentication via PEAP and MSCHAPv2
+    Peap,
+    /// Certificate based authentication
+    Tls,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Dot1xConfig {
+    pub method: Dot1xMethod,
+    pub identity: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Dot1xStatus {
+    Disabled,
+    Authenticating,
+    Authenticated,
+    Failed,
+}
+
+pub struct Dot1x {
+    pub config: Arc<Topic<Option<Dot1xConfig>>>,
+    pub password: Arc<Topic<String>>,
+    pub status: Arc<Topic<Dot1xStatus>>,
+}
+
+impl Dot1x {
+    pub(super) fn setup_topics(bb: &mut BrokerBuilder) -> Self {
+        Self {
+            // `None` disables port authentication on the uplink
+            config: bb.topic(
+                "/v1/tac/network/uplink/8021x/config",
+                true,
+                true,
+                true,
+                Some(None),
+                1,
+            ),
+            // The password (PEAP) or private key password (TLS) can be set via
+            // the API but is never handed out.
+            password: bb.topic(
+                "/v1/tac/network/uplink/8021x/password",
+                false,
+                true,
+                true,
+                Some(String::new()),
+                1,
+            ),
+            status: bb.topic_ro(
+                "/v1/tac/network/uplink/8021x/status",
+                Some(Dot1xStatus::Disabled),
+            ),
+        }
+    }
+
+    #[cfg(feature = "demo_mode")]
+    pub(super) fn handle(&self) -> Vec<Box<dyn Checkpointed>> {
+        let status = self.status.clone();
+        let (mut config_stream, _) = self.config.clone().subscribe_unbounded();
+
+        spawn(async move {
+            while let Some(config) = config_stream.next().await {
+                status.set(match config {
+                    Some(_) => Dot1xStatus::Authenticated,
+                    None => Dot1xStatus::Disabled,
+                });
+            }
+        });
+
+        vec![
+            Box::new(Tracked::runtime(self.config.clone())),
+            Box::new(Tracked::runtime(self.password.clone())),
+        ]
+    }
+
+    #[cfg(not(feature = "demo_mode"))]
+    pub(super) fn handle(
+        &self,
+        conn: &Arc<Connection>,
+        names: &InterfaceNames,
+    ) -> Vec<Box<dyn Checkpointed>> {
+        let tracked_config = Tracked::new(self.config.clone());
+        let tracked_password = Tracked::new(self.password.clone());
+
+        let conn_task = conn.clone();
+        let names_task = names.clone();
+        let config = self.config.clone();
+        let password = self.password.clone();
+        let status = self.status.clone();
+
+        let (config_stream, _) = self.config.clone().subscribe_unbounded();
+        let (password_stream, _) = self.password.clone().subscribe_unbounded();
+
+        let restored_config = tracked_config.clone();
+        let restored_password = tracked_password.clone();
+
+        // Re-create the uplink connection whenever the configuration changes
+        spawn(async move {
+            let mut changes = select(
+                config_stream.map(move |c| restored_config.is_restored(&c)),
+                password_stream.map(move |p| restored_password.is_restored(&p)),
+            );
+
+            while let Some(restored) = changes.next().await {
+                // NetworkManager has already rolled back the uplink connection,
+                // only the status has to follow.
+                if restored {
+                    if config.try_get().flatten().is_none() {
+                        status.set(Dot1xStatus::Disabled);
+                    }
+
+                    continue;
+                }
+
+                if let Err(e) = remove_connection(&conn_task, CONNECTION_UUID).await {
+                    warn!("Failed to remove 802.1X uplink connection: {}", e);
+                }
+
+                let config = match config.try_get().flatten() {
+                    Some(config) => config,
+                    None => {
+                        status.set(Dot1xStatus::Disabled);
+                        continue;
+                    }
+                };
+
+                let password = password.try_get().unwrap_or_default();
+
+                status.set(Dot1xStatus::Authenticating);
+
+                if let Err(e) = add_connection(&conn_task, &names_task, &config, &password).await {
+                    warn!("Failed to set up 802.1X uplink connection: {}", e);
+                    status.set(Dot1xStatus::Failed);
+                }
+            }
+        });
+
+        let conn_task = conn.clone();
+        let uplink = names.uplink.clone();
+        let config = self.config.clone();
+        let status = self.status.clone();
+
+        // Keep track of the authentication state via the state of the uplink
+        spawn(async move {
+            loop {
+                sleep(STATUS_POLL_INTERVAL).await;
+
+                if config.try_get().flatten().is_none() {
+                    continue;
+                }
+
+                let new_status = match uplink_state(&conn_task, &uplink).await {
+                    Ok(NMDeviceState::Activted) => Dot1xStatus::Authenticated,
+                    Ok(NMDeviceState::Failed) => Dot1xStatus::Failed,
+                    Ok(_) => Dot1xStatus::Authenticating,
+                    Err(_) => continue,
+                };
+
+                status.modify(|prev| match prev {
+                    Some(prev) if prev == new_status => None,
+                    _ => Some(new_status),
+                });
+            }
+        });
+
+        vec![Box::new(tracked_config), Box::new(tracked_password)]
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+async fn uplink_state(conn: &Connection, uplink: &str) -> Result<NMDeviceState> {
+    let path = path_from_interface(conn, uplink).await?;
+
+    let proxy = DeviceProxy::builder(conn).path(path)?.build().await?;
+
+    Ok(proxy.get_state().await?)
+}
+
+/// NetworkManager expects certificate paths as NUL terminated byte arrays
+/// using the `file://` scheme.
+#[cfg(not(feature = "demo_mode"))]
+fn cert_path(path: &str) -> Value<'static> {
+    let mut bytes = format!("file://{path}").into_bytes();
+    bytes.push(0);
+
+    Value::from(bytes)
+}
+
+#[cfg(not(feature = "demo_mode"))]
+async fn add_connection(
+    conn: &Connection,
+    names: &InterfaceNames,
+    config: &Dot1xConfig,
+    password: &str,
+) -> Result<()> {
+    if config.identity.is_empty() {
+        return Err(anyhow!("No identity set"));
+    }
+
+    // The uplink is a port of the bridge, so the connection has to be one as well
+    let mut connection = HashMap::new();
+    connection.insert("id", Value::from("tacd-uplink-8021x"));
+    connection.insert("uuid", Value::from(CONNECTION_UUID));
+    connection.insert("type", Value::from("802-3-ethernet"));
+    connection.insert("interface-name", Value::from(names.uplink.as_str()));
+    connection.insert("master", Value::from(names.bridge.as_str()));
+    connection.insert("slave-type", Value::from("bridge"));
+
+    let mut dot1x = HashMap::new();
+    dot1x.insert("identity", Value::from(config.identity.as_str()));
+    dot1x.insert("ca-cert", cert_path(CA_CERT_PATH));
+
+    match config.method {
+        Dot1xMethod::Peap => {
+            dot1x.insert("eap", Value::from(vec!["peap"]));
+            dot1x.insert("phase2-auth", Value::from("mschapv2"));
+            dot1x.insert("password", Value::from(password));
+        }
+        Dot1xMethod::Tls => {
+            dot1x.insert("eap", Value::from(vec!["tls"]));
+            dot1x.insert("client-cert", cert_path(CLIENT_CERT_PATH));
+            dot1x.insert("private-key", cert_path(PRIVATE_KEY_PATH));
+            dot1x.insert("private-key-password", Value::from(password));
+        }
+    }
+
+    let mut settings = HashMap::new();
+    settings.insert("connection", connection);
+    settings.insert("802-1x", dot1x);
+
+    let root = ObjectPath::try_from("/")?;
+
+    NetworkManagerProxy::new(conn)
+        .await?
+        .add_and_activate_connection(settings, &root, &root)
+        .await?;
+
+    Ok(())
+}
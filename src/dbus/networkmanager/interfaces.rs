@@ -0,0 +1,130 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::fs::File;
+use std::io::ErrorKind;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "demo_mode")]
+const CONFIG_PATH: &str = "demo_files/etc/tacd/network.json";
+
+#[cfg(not(feature = "demo_mode"))]
+const CONFIG_PATH: &str = "/etc/tacd/network.json";
+
+/// Mapping from the roles of network interfaces on the TAC to the actual
+/// interface names used by the system.
+///
+/// Custom images may rename interfaces, so the names can be overridden in
+/// a configuration file.
+/// Fields that are missing in the configuration file keep their default.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct InterfaceNames {
+    pub dut: String,
+    pub uplink: String,
+    pub bridge: String,
+    /// Additional interfaces to publish the link status of
+    pub monitored: Vec<String>,
+}
+
+impl Default for InterfaceNames {
+    fn default() -> Self {
+        Self {
+            dut: "dut".to_string(),
+            uplink: "uplink".to_string(),
+            bridge: "tac-bridge".to_string(),
+            monitored: Vec::new(),
+        }
+    }
+}
+
+impl InterfaceNames {
+    /// Drop monitored interfaces whose names would result in topic paths
+    /// that collide with the ones of the fixed interfaces or each other.
+    fn without_invalid_monitored(mut self) -> Self {
+        let mut seen: Vec<String> = Vec::new();
+
+        self.monitored.retain(|name| {
+            let reserved = ["dut", "uplink", "tac-bridge"].contains(&name.as_str());
+            let malformed = name.is_empty() || name.contains('/');
+            let duplicate = seen.contains(name);
+
+            if reserved || malformed || duplicate {
+                warn!("Ignoring monitored interface \"{name}\" as it collides with another topic");
+                return false;
+            }
+
+            seen.push(name.clone());
+            true
+        });
+
+        self
+    }
+
+    pub fn load() -> Self {
+        let file = match File::open(CONFIG_PATH) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                info!(
+                    "Network config at \"{}\" does not exist. Using default interface names",
+                    CONFIG_PATH
+                );
+                return Self::default();
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to open network config at \"{}\": {}",
+                    CONFIG_PATH, e
+                );
+                return Self::default();
+            }
+        };
+
+        let names: Self = serde_json::from_reader(file).unwrap_or_else(|e| {
+            warn!(
+                "Failed to parse network config at \"{}\": {}",
+                CONFIG_PATH, e
+            );
+            Self::default()
+        });
+
+        names.without_invalid_monitored()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InterfaceNames;
+
+    #[test]
+    fn monitored_collisions() {
+        let names = InterfaceNames {
+            monitored: ["wlan0", "dut", "", "a/b", "wlan0", "tac-bridge", "eth2"]
+                .iter()
+                .map(|n| n.to_string())
+                .collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            names.without_invalid_monitored().monitored,
+            ["wlan0", "eth2"]
+        );
+    }
+}
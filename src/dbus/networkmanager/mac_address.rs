@@ -0,0 +1,195 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_std::future::timeout;
+use async_std::stream::StreamExt;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use log::warn;
+use zbus::Connection;
+use zvariant::{ObjectPath, OwnedValue, Value};
+
+use super::checkpoint::Tracked;
+use super::devices::DeviceProxy;
+use super::networkmanager::NetworkManagerProxy;
+use super::settings::{SettingsConnectionProxy, SettingsProxy};
+use super::{path_from_interface, InterfaceNames};
+
+/// Only change the connection profile in memory and leave the one on disk
+/// untouched, so that the TAC falls back to its own MAC address if the tacd
+/// is not running.
+const UPDATE2_FLAG_IN_MEMORY: u32 = 0x2;
+
+/// Time between two attempts to set the MAC address, e.g. while the DUT
+/// interface is not yet known to NetworkManager after startup.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+fn get_str<'a>(
+    settings: &'a HashMap<String, HashMap<String, OwnedValue>>,
+    group: &str,
+    key: &str,
+) -> Option<&'a str> {
+    settings
+        .get(group)
+        .and_then(|g| g.get(key))
+        .and_then(|v| v.downcast_ref::<zvariant::Str>())
+        .map(|v| v.as_str())
+}
+
+/// Check that a MAC address is given in the `aa:bb:cc:dd:ee:ff` notation
+fn is_valid_mac(mac: &str) -> bool {
+    let octets: Vec<&str> = mac.split(':').collect();
+
+    octets.len() == 6
+        && octets
+            .iter()
+            .all(|o| o.len() == 2 && u8::from_str_radix(o, 16).is_ok())
+}
+
+/// Compare two (optional) MAC addresses regardless of the case of the hex
+/// digits, as NetworkManager reports them in upper case.
+fn same_mac(a: Option<&str>, b: Option<&str>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+async fn set_mac_address(conn: &Connection, dut: &str, mac: Option<&str>) -> Result<()> {
+    let device_path = path_from_interface(conn, dut).await?;
+    let device = DeviceProxy::builder(conn)
+        .path(device_path.clone())?
+        .build()
+        .await?;
+
+    let (applied, _) = device.get_applied_connection(0).await?;
+
+    // Re-activating the connection interrupts the DUT link,
+    // so only do it if something actually changed.
+    if same_mac(
+        get_str(&applied, "802-3-ethernet", "assigned-mac-address"),
+        mac,
+    ) {
+        return Ok(());
+    }
+
+    let uuid = get_str(&applied, "connection", "uuid")
+        .ok_or_else(|| anyhow!("The DUT connection has no UUID"))?;
+
+    let path = SettingsProxy::new(conn)
+        .await?
+        .get_connection_by_uuid(uuid)
+        .await?;
+
+    let connection = SettingsConnectionProxy::builder(conn)
+        .path(path.clone())?
+        .build()
+        .await?;
+
+    let stored = connection.get_settings().await?;
+
+    let mut settings: HashMap<&str, HashMap<&str, Value>> = stored
+        .iter()
+        .map(|(group, values)| {
+            let values = values
+                .iter()
+                .map(|(k, v)| (k.as_str(), Value::from(v.clone())))
+                .collect();
+
+            (group.as_str(), values)
+        })
+        .collect();
+
+    let ethernet = settings.entry("802-3-ethernet").or_default();
+
+    // The deprecated cloned-mac-address would take precedence
+    ethernet.remove("cloned-mac-address");
+
+    match mac {
+        Some(mac) => ethernet.insert("assigned-mac-address", Value::from(mac)),
+        None => ethernet.remove("assigned-mac-address"),
+    };
+
+    connection
+        .update2(settings, UPDATE2_FLAG_IN_MEMORY, HashMap::new())
+        .await?;
+
+    // NetworkManager can not change the MAC address of an active connection
+    // via reapply, so the connection has to be activated again.
+    let root = ObjectPath::try_from("/")?;
+
+    NetworkManagerProxy::new(conn)
+        .await?
+        .activate_connection(&path, &device_path, &root)
+        .await?;
+
+    Ok(())
+}
+
+/// Set the MAC address of the DUT interface to the one in the `mac_address`
+/// topic, or back to the default if it is `None`.
+pub(super) fn handle_mac_address(
+    conn: &Arc<Connection>,
+    mac_address: Tracked<Option<String>>,
+    names: &InterfaceNames,
+) {
+    let conn = conn.clone();
+    let dut = names.dut.clone();
+
+    spawn(async move {
+        let (mut mac_stream, _) = mac_address.topic().subscribe_unbounded();
+        let mut pending = None;
+
+        loop {
+            let mac = match pending.take() {
+                Some(mac) => mac,
+                None => match mac_stream.next().await {
+                    Some(mac) => mac,
+                    None => break,
+                },
+            };
+
+            if mac_address.is_restored(&mac) {
+                continue;
+            }
+
+            if let Some(mac) = &mac {
+                if !is_valid_mac(mac) {
+                    warn!("Not setting invalid DUT MAC address \"{}\"", mac);
+                    continue;
+                }
+            }
+
+            // Keep trying until the address could be set or a different
+            // one is requested in the meantime.
+            while let Err(e) = set_mac_address(&conn, &dut, mac.as_deref()).await {
+                warn!("Failed to set the DUT MAC address: {}. Retrying", e);
+
+                if let Ok(newer) = timeout(RETRY_INTERVAL, mac_stream.next()).await {
+                    pending = newer;
+                    break;
+                }
+            }
+        }
+    });
+}
@@ -15,6 +15,9 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
+#[cfg(not(feature = "demo_mode"))]
+use std::collections::HashMap;
+
 use async_std;
 use async_std::sync::Arc;
 
@@ -23,8 +26,26 @@ use serde::{Deserialize, Serialize};
 use crate::broker::{BrokerBuilder, Topic};
 use crate::led::BlinkPattern;
 
+mod checkpoint;
 mod devices;
+mod dns;
+mod dnsmanager;
+pub mod dot1x;
 mod hostname;
+mod interfaces;
+#[cfg(not(feature = "demo_mode"))]
+mod mac_address;
+mod settings;
+mod vlan;
+mod wireguard;
+
+pub use checkpoint::Checkpoints;
+use checkpoint::{Checkpointed, Tracked};
+pub use dns::Dns;
+pub use dot1x::Dot1x;
+pub use interfaces::InterfaceNames;
+pub use vlan::Vlan;
+pub use wireguard::WireGuard;
 
 // All of the following includes are not used in demo_mode.
 // Put them inside a mod so we do not have to decorate each one with
@@ -38,7 +59,7 @@ mod optional_includes {
     pub use std::convert::TryInto;
     pub use std::time::Duration;
     pub use zbus::{Connection, PropertyStream};
-    pub use zvariant::{ObjectPath, OwnedObjectPath};
+    pub use zvariant::{ObjectPath, OwnedObjectPath, Value};
 }
 
 #[cfg(not(feature = "demo_mode"))]
@@ -74,6 +95,39 @@ async fn path_from_interface(con: &Connection, interface: &str) -> Result<OwnedO
     Err(anyhow!("No interface found: {}", interface))
 }
 
+/// Add a connection profile to NetworkManager without activating it
+#[cfg(not(feature = "demo_mode"))]
+pub(crate) async fn add_connection(
+    con: &Connection,
+    connection: HashMap<&str, HashMap<&str, Value<'_>>>,
+) -> Result<()> {
+    settings::SettingsProxy::new(con)
+        .await?
+        .add_connection(connection)
+        .await?;
+
+    Ok(())
+}
+
+/// Delete the connection profile with the given UUID from NetworkManager
+/// (if it exists).
+#[cfg(not(feature = "demo_mode"))]
+pub(crate) async fn remove_connection(con: &Connection, uuid: &str) -> Result<()> {
+    let settings = settings::SettingsProxy::new(con).await?;
+
+    // There is nothing to remove if NetworkManager does not know the connection
+    if let Ok(path) = settings.get_connection_by_uuid(uuid).await {
+        settings::SettingsConnectionProxy::builder(con)
+            .path(path)?
+            .build()
+            .await?
+            .delete()
+            .await?;
+    }
+
+    Ok(())
+}
+
 #[cfg(not(feature = "demo_mode"))]
 async fn get_link_info(con: &Connection, path: &str) -> Result<LinkInfo> {
     let eth_proxy = devices::WiredProxy::builder(con)
@@ -241,15 +295,55 @@ pub struct Network {
     pub bridge_interface: Arc<Topic<Vec<String>>>,
     pub dut_interface: Arc<Topic<LinkInfo>>,
     pub uplink_interface: Arc<Topic<LinkInfo>>,
+    pub monitored_interfaces: Vec<(String, Arc<Topic<LinkInfo>>)>,
+    pub vlans: Arc<Topic<Vec<Vlan>>>,
+    pub dut_mac_address: Arc<Topic<Option<String>>>,
+    pub wireguard: WireGuard,
+    pub checkpoints: Checkpoints,
+    pub dot1x: Dot1x,
+    pub dns: Dns,
 }
 
 impl Network {
-    fn setup_topics(bb: &mut BrokerBuilder) -> Self {
+    fn setup_topics(bb: &mut BrokerBuilder, names: &InterfaceNames) -> Self {
+        // The topic names of the dut, uplink and bridge interfaces stay the
+        // same, no matter how the interfaces are actually called.
+        let monitored_interfaces = names
+            .monitored
+            .iter()
+            .map(|name| {
+                let path = format!("/v1/tac/network/interface/{name}");
+                (name.clone(), bb.topic_ro(&path, None))
+            })
+            .collect();
+
         Self {
             hostname: bb.topic_ro("/v1/tac/network/hostname", None),
             bridge_interface: bb.topic_ro("/v1/tac/network/interface/tac-bridge", None),
             dut_interface: bb.topic_ro("/v1/tac/network/interface/dut", None),
             uplink_interface: bb.topic_ro("/v1/tac/network/interface/uplink", None),
+            monitored_interfaces,
+            vlans: bb.topic(
+                "/v1/tac/network/dut/vlans",
+                true,
+                true,
+                true,
+                Some(Vec::new()),
+                1,
+            ),
+            // `None` uses the default MAC address of the interface
+            dut_mac_address: bb.topic(
+                "/v1/tac/network/dut/mac_address",
+                true,
+                true,
+                true,
+                Some(None),
+                1,
+            ),
+            wireguard: WireGuard::setup_topics(bb),
+            checkpoints: Checkpoints::setup_topics(bb),
+            dot1x: Dot1x::setup_topics(bb),
+            dns: Dns::setup_topics(bb),
         }
     }
 
@@ -260,7 +354,8 @@ impl Network {
         _led_dut: Arc<Topic<BlinkPattern>>,
         _led_uplink: Arc<Topic<BlinkPattern>>,
     ) -> Self {
-        let this = Self::setup_topics(bb);
+        let names = InterfaceNames::load();
+        let this = Self::setup_topics(bb, &names);
 
         this.hostname.set("lxatac".to_string());
         this.bridge_interface.set(vec![String::from("192.168.1.1")]);
@@ -273,6 +368,29 @@ impl Network {
             carrier: true,
         });
 
+        for (_, topic) in this.monitored_interfaces.iter() {
+            topic.set(LinkInfo {
+                speed: 0,
+                carrier: false,
+            });
+        }
+
+        vlan::reject_invalid_vlans(this.vlans.clone());
+
+        // There is no NetworkManager to roll back to a checkpoint in demo
+        // mode, so the restored values are handled like any other.
+        let mut tracked: Vec<Box<dyn Checkpointed>> = vec![
+            Box::new(Tracked::runtime(this.vlans.clone())),
+            Box::new(Tracked::runtime(this.dut_mac_address.clone())),
+        ];
+
+        tracked.extend(this.wireguard.handle());
+        tracked.extend(this.dot1x.handle());
+        tracked.extend(this.dns.handle(&names));
+
+        this.checkpoints
+            .handle(Arc::new(checkpoint::Connection), tracked);
+
         this
     }
 
@@ -283,7 +401,8 @@ impl Network {
         led_dut: Arc<Topic<BlinkPattern>>,
         led_uplink: Arc<Topic<BlinkPattern>>,
     ) -> Self {
-        let this = Self::setup_topics(bb);
+        let names = InterfaceNames::load();
+        let this = Self::setup_topics(bb, &names);
 
         {
             let conn = conn.clone();
@@ -307,10 +426,11 @@ impl Network {
 
         {
             let conn = conn.clone();
+            let name = names.dut.clone();
             let dut_interface = this.dut_interface.clone();
             async_std::task::spawn(async move {
                 let mut link_stream = loop {
-                    if let Ok(ls) = LinkStream::new(conn.clone(), "dut").await {
+                    if let Ok(ls) = LinkStream::new(conn.clone(), &name).await {
                         break ls;
                     }
 
@@ -335,10 +455,11 @@ impl Network {
 
         {
             let conn = conn.clone();
+            let name = names.uplink.clone();
             let uplink_interface = this.uplink_interface.clone();
             async_std::task::spawn(async move {
                 let mut link_stream = loop {
-                    if let Ok(ls) = LinkStream::new(conn.clone(), "uplink").await {
+                    if let Ok(ls) = LinkStream::new(conn.clone(), &name).await {
                         break ls;
                     }
 
@@ -360,10 +481,11 @@ impl Network {
 
         {
             let conn = conn.clone();
+            let name = names.bridge.clone();
             let bridge_interface = this.bridge_interface.clone();
             async_std::task::spawn(async move {
                 let mut ip_stream = loop {
-                    if let Ok(ips) = IpStream::new(conn.clone(), "tac-bridge").await {
+                    if let Ok(ips) = IpStream::new(conn.clone(), &name).await {
                         break ips;
                     }
 
@@ -378,6 +500,44 @@ impl Network {
             });
         }
 
+        for (name, topic) in this.monitored_interfaces.iter() {
+            let conn = conn.clone();
+            let name = name.clone();
+            let topic = topic.clone();
+            async_std::task::spawn(async move {
+                let mut link_stream = loop {
+                    if let Ok(ls) = LinkStream::new(conn.clone(), &name).await {
+                        break ls;
+                    }
+
+                    sleep(Duration::from_secs(1)).await;
+                };
+
+                topic.set(link_stream.now());
+
+                while let Ok(info) = link_stream.next().await {
+                    topic.set(info);
+                }
+            });
+        }
+
+        vlan::reject_invalid_vlans(this.vlans.clone());
+
+        let vlans = Tracked::new(this.vlans.clone());
+        let dut_mac_address = Tracked::new(this.dut_mac_address.clone());
+
+        vlan::handle_vlans(conn, vlans.clone(), &names);
+        mac_address::handle_mac_address(conn, dut_mac_address.clone(), &names);
+
+        let mut tracked: Vec<Box<dyn Checkpointed>> =
+            vec![Box::new(vlans), Box::new(dut_mac_address)];
+
+        tracked.extend(this.wireguard.handle(conn));
+        tracked.extend(this.dot1x.handle(conn, &names));
+        tracked.extend(this.dns.handle(conn, &names));
+
+        this.checkpoints.handle(conn.clone(), tracked);
+
         this
     }
 }
@@ -0,0 +1,57 @@
+//! # DBus interface proxies for: `org.freedesktop.NetworkManager.Settings`, `org.freedesktop.NetworkManager.Settings.Connection`
+//!
+//! This code was generated by `zbus-xmlgen` `2.0.0` from DBus introspection data
+//! and trimmed down to the members used by the tacd.
+//! Source: `Interface '/org/freedesktop/NetworkManager/Settings' from service 'org.freedesktop.NetworkManager' on system bus`.
+//!
+
+use zbus::dbus_proxy;
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.NetworkManager",
+    interface = "org.freedesktop.NetworkManager.Settings",
+    default_path = "/org/freedesktop/NetworkManager/Settings"
+)]
+trait Settings {
+    /// AddConnection method
+    fn add_connection(
+        &self,
+        connection: std::collections::HashMap<
+            &str,
+            std::collections::HashMap<&str, zbus::zvariant::Value<'_>>,
+        >,
+    ) -> zbus::Result<zbus::zvariant::OwnedObjectPath>;
+
+    /// GetConnectionByUuid method
+    fn get_connection_by_uuid(&self, uuid: &str) -> zbus::Result<zbus::zvariant::OwnedObjectPath>;
+}
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.NetworkManager",
+    interface = "org.freedesktop.NetworkManager.Settings.Connection"
+)]
+trait SettingsConnection {
+    /// Delete method
+    fn delete(&self) -> zbus::Result<()>;
+
+    /// GetSettings method
+    fn get_settings(
+        &self,
+    ) -> zbus::Result<
+        std::collections::HashMap<
+            String,
+            std::collections::HashMap<String, zbus::zvariant::OwnedValue>,
+        >,
+    >;
+
+    /// Update2 method
+    fn update2(
+        &self,
+        settings: std::collections::HashMap<
+            &str,
+            std::collections::HashMap<&str, zbus::zvariant::Value<'_>>,
+        >,
+        flags: u32,
+        args: std::collections::HashMap<&str, zbus::zvariant::Value<'_>>,
+    ) -> zbus::Result<std::collections::HashMap<String, zbus::zvariant::OwnedValue>>;
+}
@@ -0,0 +1,208 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::broker::Topic;
+
+#[cfg(not(feature = "demo_mode"))]
+mod optional_includes {
+    pub use std::collections::HashMap;
+    pub use std::convert::TryFrom;
+
+    pub use anyhow::Result;
+    pub use zbus::Connection;
+    pub use zvariant::{ObjectPath, Value};
+
+    pub(super) use super::super::checkpoint::Tracked;
+    pub use super::super::networkmanager::NetworkManagerProxy;
+}
+
+#[cfg(not(feature = "demo_mode"))]
+use optional_includes::*;
+
+#[cfg(not(feature = "demo_mode"))]
+use super::{remove_connection, InterfaceNames};
+
+/// A tagged VLAN sub-interface on top of the DUT interface
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Vlan {
+    /// The VLAN tag in the range 1 to 4094
+    pub id: u16,
+    /// Add the sub-interface as port to `tac-bridge` (`true`) or keep it
+    /// isolated from the rest of the network (`false`).
+    pub bridged: bool,
+}
+
+/// Check that all VLAN tags are in the valid range and that there is only a
+/// single sub-interface per tag.
+fn vlans_valid(vlans: &[Vlan]) -> bool {
+    vlans.iter().enumerate().all(|(i, vlan)| {
+        let in_range = (1..=4094).contains(&vlan.id);
+        let unique = vlans[..i].iter().all(|other| other.id != vlan.id);
+
+        in_range && unique
+    })
+}
+
+/// Reject invalid lists of VLANs by going back to the last valid one
+pub(super) fn reject_invalid_vlans(vlans: Arc<Topic<Vec<Vlan>>>) {
+    let (mut vlans_stream, _) = vlans.clone().subscribe_unbounded();
+
+    spawn(async move {
+        let mut prev_valid = Vec::new();
+
+        while let Some(new) = vlans_stream.next().await {
+            if vlans_valid(&new) {
+                prev_valid = new;
+            } else {
+                warn!("Rejecting VLANs with invalid or duplicate tags: {new:?}");
+                vlans.set(prev_valid.clone());
+            }
+        }
+    });
+}
+
+#[cfg(not(feature = "demo_mode"))]
+impl Vlan {
+    fn interface_name(&self, parent: &str) -> String {
+        format!("{}.{}", parent, self.id)
+    }
+
+    /// Derive a stable connection UUID from the VLAN tag, so that we can
+    /// find connections we have created in a previous run of the tacd.
+    fn uuid(&self) -> String {
+        format!("7acd0000-0000-4000-8000-{:012x}", self.id)
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+async fn add_vlan(conn: &Connection, vlan: &Vlan, names: &InterfaceNames) -> Result<()> {
+    let interface_name = vlan.interface_name(&names.dut);
+    let uuid = vlan.uuid();
+
+    let mut connection = HashMap::new();
+    connection.insert("id", Value::from(interface_name.as_str()));
+    connection.insert("uuid", Value::from(uuid.as_str()));
+    connection.insert("type", Value::from("vlan"));
+    connection.insert("interface-name", Value::from(interface_name.as_str()));
+
+    let mut vlan_setting = HashMap::new();
+    vlan_setting.insert("parent", Value::from(names.dut.as_str()));
+    vlan_setting.insert("id", Value::from(u32::from(vlan.id)));
+
+    let mut settings = HashMap::new();
+
+    if vlan.bridged {
+        connection.insert("master", Value::from(names.bridge.as_str()));
+        connection.insert("slave-type", Value::from("bridge"));
+    } else {
+        let mut ipv4 = HashMap::new();
+        ipv4.insert("method", Value::from("link-local"));
+
+        let mut ipv6 = HashMap::new();
+        ipv6.insert("method", Value::from("link-local"));
+
+        settings.insert("ipv4", ipv4);
+        settings.insert("ipv6", ipv6);
+    }
+
+    settings.insert("connection", connection);
+    settings.insert("vlan", vlan_setting);
+
+    let root = ObjectPath::try_from("/")?;
+
+    NetworkManagerProxy::new(conn)
+        .await?
+        .add_and_activate_connection(settings, &root, &root)
+        .await?;
+
+    Ok(())
+}
+
+/// Create, update and remove VLAN sub-interfaces on the DUT interface so
+/// that they match the content of the `vlans` topic.
+#[cfg(not(feature = "demo_mode"))]
+pub(super) fn handle_vlans(
+    conn: &Arc<Connection>,
+    vlans: Tracked<Vec<Vlan>>,
+    names: &InterfaceNames,
+) {
+    let conn = conn.clone();
+    let names = names.clone();
+
+    spawn(async move {
+        let (mut vlans_stream, _) = vlans.topic().subscribe_unbounded();
+
+        // Start with an empty list so that the connections of the wanted VLANs
+        // are re-created below, replacing ones left over from a previous run.
+        let mut applied: Vec<Vlan> = Vec::new();
+
+        while let Some(wanted) = vlans_stream.next().await {
+            // Invalid lists are replaced by reject_invalid_vlans()
+            if !vlans_valid(&wanted) {
+                continue;
+            }
+
+            // NetworkManager has already put the VLANs back in place
+            if vlans.is_restored(&wanted) {
+                applied = wanted;
+                continue;
+            }
+
+            let stale = applied.iter().chain(wanted.iter()).filter(|vlan| {
+                let unchanged = applied.contains(vlan) && wanted.contains(vlan);
+                !unchanged
+            });
+
+            for vlan in stale {
+                if let Err(e) = remove_connection(&conn, &vlan.uuid()).await {
+                    warn!("Failed to remove VLAN {}: {}", vlan.id, e);
+                }
+            }
+
+            for vlan in wanted.iter().filter(|vlan| !applied.contains(vlan)) {
+                if let Err(e) = add_vlan(&conn, vlan, &names).await {
+                    warn!("Failed to set up VLAN {}: {}", vlan.id, e);
+                }
+            }
+
+            applied = wanted;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{vlans_valid, Vlan};
+
+    #[test]
+    fn vlan_tags() {
+        let vlan = |id| Vlan { id, bridged: false };
+
+        assert!(vlans_valid(&[]));
+        assert!(vlans_valid(&[vlan(1), vlan(4094)]));
+
+        assert!(!vlans_valid(&[vlan(0)]));
+        assert!(!vlans_valid(&[vlan(4095)]));
+        assert!(!vlans_valid(&[vlan(10), vlan(20), vlan(10)]));
+    }
+}
@@ -0,0 +1,317 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use serde::{Deserialize, Serialize};
+
+use super::checkpoint::{Checkpointed, Tracked};
+use crate::broker::{BrokerBuilder, Topic};
+
+#[cfg(not(feature = "demo_mode"))]
+mod optional_includes {
+    pub use std::collections::HashMap;
+    pub use std::convert::TryFrom;
+    pub use std::time::Duration;
+
+    pub use anyhow::{anyhow, Result};
+    pub use async_std::task::sleep;
+    pub use futures::stream::select;
+    pub use log::warn;
+    pub use zbus::Connection;
+    pub use zvariant::{ObjectPath, Value};
+
+    pub use super::super::devices::{DeviceProxy, NMDeviceState};
+    pub use super::super::networkmanager::NetworkManagerProxy;
+}
+
+#[cfg(not(feature = "demo_mode"))]
+use optional_includes::*;
+
+#[cfg(not(feature = "demo_mode"))]
+use super::{path_from_interface, remove_connection};
+
+#[cfg(not(feature = "demo_mode"))]
+const INTERFACE: &str = "wg0";
+
+#[cfg(not(feature = "demo_mode"))]
+const CONNECTION_UUID: &str = "7acd0000-0000-4000-8001-000000000000";
+
+#[cfg(not(feature = "demo_mode"))]
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The remote end of the WireGuard tunnel and the local tunnel address
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct WireGuardConfig {
+    /// Local address of the TAC inside the tunnel in CIDR notation,
+    /// e.g. `10.0.0.2/24`.
+    pub address: String,
+    pub peer_public_key: String,
+    /// `<host>:<port>` of the peer
+    pub peer_endpoint: String,
+    pub allowed_ips: Vec<String>,
+    /// Keepalive interval in seconds to keep NAT mappings open (0 to disable)
+    pub persistent_keepalive: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum WireGuardStatus {
+    Disabled,
+    Connecting,
+    Connected,
+    Failed,
+}
+
+#[derive(Clone)]
+pub struct WireGuard {
+    pub enabled: Arc<Topic<bool>>,
+    pub config: Arc<Topic<Option<WireGuardConfig>>>,
+    pub private_key: Arc<Topic<String>>,
+    pub status: Arc<Topic<WireGuardStatus>>,
+}
+
+impl WireGuard {
+    pub(super) fn setup_topics(bb: &mut BrokerBuilder) -> Self {
+        Self {
+            // Setting this to false acts as a kill switch that immediately
+            // tears down the tunnel.
+            enabled: bb.topic(
+                "/v1/tac/network/wireguard/enabled",
+                true,
+                true,
+                true,
+                Some(false),
+                1,
+            ),
+            config: bb.topic(
+                "/v1/tac/network/wireguard/config",
+                true,
+                true,
+                true,
+                Some(None),
+                1,
+            ),
+            // The private key can be set via the API but is never handed out
+            private_key: bb.topic(
+                "/v1/tac/network/wireguard/private_key",
+                false,
+                true,
+                true,
+                Some(String::new()),
+                1,
+            ),
+            status: bb.topic_ro(
+                "/v1/tac/network/wireguard/status",
+                Some(WireGuardStatus::Disabled),
+            ),
+        }
+    }
+
+    #[cfg(feature = "demo_mode")]
+    pub(super) fn handle(&self) -> Vec<Box<dyn Checkpointed>> {
+        let status = self.status.clone();
+        let (mut enabled_stream, _) = self.enabled.clone().subscribe_unbounded();
+
+        spawn(async move {
+            while let Some(enabled) = enabled_stream.next().await {
+                status.set(match enabled {
+                    true => WireGuardStatus::Connected,
+                    false => WireGuardStatus::Disabled,
+                });
+            }
+        });
+
+        vec![
+            Box::new(Tracked::runtime(self.enabled.clone())),
+            Box::new(Tracked::runtime(self.config.clone())),
+            Box::new(Tracked::runtime(self.private_key.clone())),
+        ]
+    }
+
+    #[cfg(not(feature = "demo_mode"))]
+    pub(super) fn handle(&self, conn: &Arc<Connection>) -> Vec<Box<dyn Checkpointed>> {
+        let tracked_enabled = Tracked::new(self.enabled.clone());
+        let tracked_config = Tracked::new(self.config.clone());
+        let tracked_private_key = Tracked::new(self.private_key.clone());
+
+        let conn_task = conn.clone();
+        let enabled = self.enabled.clone();
+        let config = self.config.clone();
+        let private_key = self.private_key.clone();
+        let status = self.status.clone();
+
+        let (enabled_stream, _) = self.enabled.clone().subscribe_unbounded();
+        let (config_stream, _) = self.config.clone().subscribe_unbounded();
+        let (private_key_stream, _) = self.private_key.clone().subscribe_unbounded();
+
+        let restored_enabled = tracked_enabled.clone();
+        let restored_config = tracked_config.clone();
+        let restored_private_key = tracked_private_key.clone();
+
+        // Re-create the tunnel whenever any part of its configuration changes
+        spawn(async move {
+            let mut changes = select(
+                enabled_stream.map(move |e| restored_enabled.is_restored(&e)),
+                select(
+                    config_stream.map(move |c| restored_config.is_restored(&c)),
+                    private_key_stream.map(move |k| restored_private_key.is_restored(&k)),
+                ),
+            );
+
+            while let Some(restored) = changes.next().await {
+                // NetworkManager has already rolled back the tunnel,
+                // only the status has to follow.
+                if restored {
+                    if !enabled.try_get().unwrap_or(false) {
+                        status.set(WireGuardStatus::Disabled);
+                    }
+
+                    continue;
+                }
+
+                if let Err(e) = remove_connection(&conn_task, CONNECTION_UUID).await {
+                    warn!("Failed to remove WireGuard connection: {}", e);
+                }
+
+                if !enabled.try_get().unwrap_or(false) {
+                    status.set(WireGuardStatus::Disabled);
+                    continue;
+                }
+
+                let config = config.try_get().flatten();
+                let private_key = private_key.try_get().unwrap_or_default();
+
+                status.set(WireGuardStatus::Connecting);
+
+                if let Err(e) = add_tunnel(&conn_task, config, &private_key).await {
+                    warn!("Failed to set up WireGuard tunnel: {}", e);
+                    status.set(WireGuardStatus::Failed);
+                }
+            }
+        });
+
+        let conn_task = conn.clone();
+        let enabled = self.enabled.clone();
+        let status = self.status.clone();
+
+        // NetworkManager takes care of (re-)connecting the tunnel.
+        // Keep track of how that is going.
+        spawn(async move {
+            loop {
+                sleep(STATUS_POLL_INTERVAL).await;
+
+                if !enabled.try_get().unwrap_or(false) {
+                    continue;
+                }
+
+                let new_status = match tunnel_state(&conn_task).await {
+                    Ok(NMDeviceState::Activted) => WireGuardStatus::Connected,
+                    Ok(NMDeviceState::Failed) => WireGuardStatus::Failed,
+                    Ok(_) => WireGuardStatus::Connecting,
+                    // Keep the status set by the setup task (e.g. Failed)
+                    // if the interface does not exist (yet).
+                    Err(_) => continue,
+                };
+
+                status.modify(|prev| match prev {
+                    Some(prev) if prev == new_status => None,
+                    _ => Some(new_status),
+                });
+            }
+        });
+
+        vec![
+            Box::new(tracked_enabled),
+            Box::new(tracked_config),
+            Box::new(tracked_private_key),
+        ]
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+async fn tunnel_state(conn: &Connection) -> Result<NMDeviceState> {
+    let path = path_from_interface(conn, INTERFACE).await?;
+
+    let proxy = DeviceProxy::builder(conn).path(path)?.build().await?;
+
+    Ok(proxy.get_state().await?)
+}
+
+#[cfg(not(feature = "demo_mode"))]
+async fn add_tunnel(
+    conn: &Connection,
+    config: Option<WireGuardConfig>,
+    private_key: &str,
+) -> Result<()> {
+    let config = config.ok_or_else(|| anyhow!("No tunnel configuration set"))?;
+
+    if private_key.is_empty() {
+        return Err(anyhow!("No private key set"));
+    }
+
+    let (address, prefix) = config
+        .address
+        .split_once('/')
+        .and_then(|(addr, prefix)| prefix.parse::<u32>().ok().map(|p| (addr, p)))
+        .ok_or_else(|| anyhow!("Invalid tunnel address {}", config.address))?;
+
+    let mut connection = HashMap::new();
+    connection.insert("id", Value::from("tacd-wireguard"));
+    connection.insert("uuid", Value::from(CONNECTION_UUID));
+    connection.insert("type", Value::from("wireguard"));
+    connection.insert("interface-name", Value::from(INTERFACE));
+
+    let mut peer: HashMap<&str, Value> = HashMap::new();
+    peer.insert("public-key", Value::from(config.peer_public_key.as_str()));
+    peer.insert("endpoint", Value::from(config.peer_endpoint.as_str()));
+    peer.insert("allowed-ips", Value::from(config.allowed_ips.clone()));
+    peer.insert(
+        "persistent-keepalive",
+        Value::from(config.persistent_keepalive),
+    );
+
+    let mut wireguard = HashMap::new();
+    wireguard.insert("private-key", Value::from(private_key));
+    wireguard.insert("peers", Value::from(vec![peer]));
+
+    let mut address_data: HashMap<&str, Value> = HashMap::new();
+    address_data.insert("address", Value::from(address));
+    address_data.insert("prefix", Value::from(prefix));
+
+    let mut ipv4 = HashMap::new();
+    ipv4.insert("method", Value::from("manual"));
+    ipv4.insert("address-data", Value::from(vec![address_data]));
+
+    let mut ipv6 = HashMap::new();
+    ipv6.insert("method", Value::from("ignore"));
+
+    let mut settings = HashMap::new();
+    settings.insert("connection", connection);
+    settings.insert("wireguard", wireguard);
+    settings.insert("ipv4", ipv4);
+    settings.insert("ipv6", ipv6);
+
+    let root = ObjectPath::try_from("/")?;
+
+    NetworkManagerProxy::new(conn)
+        .await?
+        .add_and_activate_connection(settings, &root, &root)
+        .await?;
+
+    Ok(())
+}
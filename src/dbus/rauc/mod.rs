@@ -17,17 +17,34 @@
 
 use std::collections::HashMap;
 
+use async_std::fs::{create_dir_all, remove_file, File};
+use async_std::io::copy;
+use async_std::path::Path;
+use async_std::prelude::*;
 use async_std::sync::Arc;
+use async_std::task::spawn;
 use serde::{Deserialize, Serialize};
+use tide::{http::mime, Request, Response, Server};
 
 #[cfg(not(feature = "demo_mode"))]
-use async_std::prelude::*;
+use futures::stream::select;
 
 #[cfg(not(feature = "demo_mode"))]
-use async_std::task::spawn;
+use log::{info, warn};
 
 use super::Connection;
 use crate::broker::{BrokerBuilder, Topic};
+use crate::setup_mode::is_admin;
+
+#[cfg(feature = "demo_mode")]
+const UPLOAD_PATH: &str = "demo_files/srv/tacd/upload.raucb";
+
+#[cfg(not(feature = "demo_mode"))]
+const UPLOAD_PATH: &str = "/srv/tacd/upload.raucb";
+
+/// Uploads larger than this are rejected, so they can not fill up the
+/// data partition. Bundles for the TAC are a lot smaller than this.
+const MAX_UPLOAD_SIZE: u64 = 512 * 1024 * 1024;
 
 #[cfg(feature = "demo_mode")]
 mod demo_mode;
@@ -35,6 +52,10 @@ mod demo_mode;
 #[cfg(not(feature = "demo_mode"))]
 mod installer;
 
+mod update_channels;
+
+pub use update_channels::{MaintenanceWindow, UpdateChannel, UpstreamBundle};
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Progress {
     pub percentage: i32,
@@ -52,14 +73,128 @@ impl From<(i32, String, i32)> for Progress {
     }
 }
 
+/// The outcome of the most recent installation, as reported by RAUC's
+/// `Completed` signal.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum InstallResult {
+    Success,
+    Failure,
+}
+
+impl From<i32> for InstallResult {
+    fn from(result: i32) -> Self {
+        match result {
+            0 => Self::Success,
+            _ => Self::Failure,
+        }
+    }
+}
+
+/// Slot state changes that can be requested via the API
+///
+/// * `Good` - Mark the booted slot as good, e.g. after verifying that an
+///   update works as expected.
+/// * `Bad` - Mark the booted slot as bad, so that the bootloader will fall
+///   back to the other slot on the next boot.
+/// * `Rollback` - Mark the other slot as active, so that it is booted on
+///   the next boot.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum SlotMark {
+    Good,
+    Bad,
+    Rollback,
+}
+
+impl SlotMark {
+    /// The (state, slot_identifier) tuple to pass to RAUC's Mark method
+    #[cfg(not(feature = "demo_mode"))]
+    fn as_rauc_args(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::Good => ("good", "booted"),
+            Self::Bad => ("bad", "booted"),
+            Self::Rollback => ("active", "other"),
+        }
+    }
+}
+
 type SlotStatus = HashMap<String, HashMap<String, String>>;
 
+#[cfg(not(feature = "demo_mode"))]
+async fn refresh_slot_status(
+    proxy: &installer::InstallerProxy<'_>,
+    slot_status: &Topic<Arc<SlotStatus>>,
+    primary: &Topic<String>,
+) {
+    if let Ok(slots) = proxy.get_slot_status().await {
+        let slots = slots
+            .into_iter()
+            .map(|(slot_name, slot_info)| {
+                let mut info: HashMap<String, String> = slot_info
+                    .into_iter()
+                    .map(|(k, v)| {
+                        // Convert itegers to strings as raw zvariant values are
+                        // unusable when json serialized and I can not be bothered
+                        // to fiddle around with an enum that wraps strings and integers
+                        // or something like that
+                        let ss = v.downcast_ref::<str>().map(|s| s.to_string());
+                        let s32 = v.downcast_ref::<u32>().map(|i| format!("{i}"));
+                        let s64 = v.downcast_ref::<u64>().map(|i| format!("{i}"));
+
+                        // Some of the field names make defining a "RaucSlot" type
+                        // in Typescript difficult. Not matching the names defined
+                        // in RAUC's API is also not great, but the lesser evil in
+                        // this case.
+                        let k = k
+                            .replace("type", "fs_type")
+                            .replace("class", "slot_class")
+                            .replace(['.', '-'], "_");
+
+                        (k, ss.or(s32).or(s64).unwrap_or_default())
+                    })
+                    .collect();
+
+                // Include the (unmangled) slot name as a field in the slot
+                // dict, once again to make life in the Web Interface easier.
+                info.insert("name".to_string(), slot_name.clone());
+
+                // Remove "." from the dictionary key to make defining a typescript
+                // type easier ("rootfs.0" -> "rootfs_0").
+                (slot_name.replace('.', "_"), info)
+            })
+            .collect();
+
+        // In the RAUC API the slot status is a list of (name, info) tuples.
+        // It is once again easier in typescript to represent it as a dict with
+        // the names as keys, so that is what's exposed here.
+        slot_status.set(Arc::new(slots));
+    }
+
+    if let Ok(p) = proxy.get_primary().await {
+        primary.set(p);
+    }
+}
+
+#[derive(Deserialize)]
+struct UploadParams {
+    token: Option<String>,
+}
+
 pub struct Rauc {
     pub operation: Arc<Topic<String>>,
     pub progress: Arc<Topic<Progress>>,
     pub slot_status: Arc<Topic<Arc<SlotStatus>>>,
+    pub booted_slot: Arc<Topic<String>>,
+    pub primary: Arc<Topic<String>>,
+    pub mark: Arc<Topic<SlotMark>>,
     pub last_error: Arc<Topic<String>>,
+    pub result: Arc<Topic<InstallResult>>,
     pub install: Arc<Topic<String>>,
+    pub update_server: Arc<Topic<String>>,
+    pub update_channel: Arc<Topic<UpdateChannel>>,
+    pub update_available: Arc<Topic<Option<UpstreamBundle>>>,
+    pub auto_install: Arc<Topic<bool>>,
+    pub maintenance_window: Arc<Topic<MaintenanceWindow>>,
+    install_local: Arc<Topic<String>>,
 }
 
 impl Rauc {
@@ -68,19 +203,147 @@ impl Rauc {
             operation: bb.topic_ro("/v1/tac/update/operation", None),
             progress: bb.topic_ro("/v1/tac/update/progress", None),
             slot_status: bb.topic_ro("/v1/tac/update/slots", None),
+            booted_slot: bb.topic_ro("/v1/tac/update/booted_slot", None),
+            primary: bb.topic_ro("/v1/tac/update/primary", None),
+            mark: bb.topic_wo("/v1/tac/update/mark", None),
             last_error: bb.topic_ro("/v1/tac/update/last_error", None),
+            result: bb.topic_ro("/v1/tac/update/result", None),
             install: bb.topic_wo("/v1/tac/update/install", Some("".to_string())),
+            update_server: bb.topic(
+                "/v1/tac/update/server",
+                true,
+                true,
+                true,
+                Some("".to_string()),
+                1,
+            ),
+            update_channel: bb.topic(
+                "/v1/tac/update/channel",
+                true,
+                true,
+                true,
+                Some(UpdateChannel::Stable),
+                1,
+            ),
+            update_available: bb.topic_ro("/v1/tac/update/available", Some(None)),
+            auto_install: bb.topic(
+                "/v1/tac/update/auto_install",
+                true,
+                true,
+                true,
+                Some(false),
+                1,
+            ),
+            maintenance_window: bb.topic(
+                "/v1/tac/update/maintenance_window",
+                true,
+                true,
+                true,
+                Some(MaintenanceWindow {
+                    start_hour: 2,
+                    end_hour: 4,
+                }),
+                1,
+            ),
+            install_local: Topic::anonymous(None),
         }
     }
 
+    /// Accept RAUC bundles uploaded via HTTP PUT and install them
+    ///
+    /// The bundle is streamed to a file on the data partition instead of
+    /// being kept in memory (`/tmp` is a tmpfs on the TAC), as bundles can
+    /// easily be larger than the RAM of the TAC.
+    /// Installing a bundle is disruptive, so it requires the admin role.
+    pub fn serve_upload(&self, server: &mut Server<()>) {
+        let install_local = self.install_local.clone();
+
+        server
+            .at("/v1/tac/update/upload")
+            .put(move |req: Request<()>| {
+                let install_local = install_local.clone();
+
+                async move {
+                    let token = req
+                        .query::<UploadParams>()
+                        .ok()
+                        .and_then(|params| params.token)
+                        .unwrap_or_default();
+
+                    if !is_admin(&token).await {
+                        return Ok(Response::builder(403)
+                            .body("Uploading bundles requires the admin role")
+                            .content_type(mime::PLAIN)
+                            .build());
+                    }
+
+                    let path = Path::new(UPLOAD_PATH);
+
+                    if let Some(parent) = path.parent() {
+                        create_dir_all(parent).await?;
+                    }
+
+                    let mut file = File::create(path).await?;
+                    let len = copy(req.take(MAX_UPLOAD_SIZE + 1), &mut file).await?;
+
+                    if len > MAX_UPLOAD_SIZE {
+                        drop(file);
+                        remove_file(path).await?;
+
+                        return Ok(Response::builder(413)
+                            .body(format!("Bundles may be at most {MAX_UPLOAD_SIZE} bytes"))
+                            .content_type(mime::PLAIN)
+                            .build());
+                    }
+
+                    file.sync_all().await?;
+
+                    install_local.set(UPLOAD_PATH.to_string());
+
+                    Ok(Response::new(204))
+                }
+            });
+    }
+
     #[cfg(feature = "demo_mode")]
     pub fn new(bb: &mut BrokerBuilder, _conn: &Arc<Connection>) -> Self {
         let inst = Self::setup_topics(bb);
 
+        let slot_status = demo_mode::slot_status();
+
+        // Take the booted and primary slot from the demo slot status,
+        // so that they match what it says about the slots.
+        let booted = slot_status
+            .values()
+            .find(|slot| slot.get("state").map(|s| s == "booted").unwrap_or(false));
+
+        if let Some(booted) = booted {
+            inst.booted_slot.set(booted["bootname"].clone());
+            inst.primary.set(booted["name"].clone());
+        }
+
         inst.operation.set("idle".to_string());
-        inst.slot_status.set(Arc::new(demo_mode::slot_status()));
+        inst.slot_status.set(Arc::new(slot_status));
         inst.last_error.set("".to_string());
 
+        let (mut mark_stream, _) = inst.mark.clone().subscribe_unbounded();
+
+        spawn(async move {
+            while let Some(mark) = mark_stream.next().await {
+                println!("Asked to mark slot {mark:?} but don't feel like it");
+            }
+        });
+
+        let (mut install_stream, _) = inst.install_local.clone().subscribe_unbounded();
+
+        spawn(async move {
+            while let Some(path) = install_stream.next().await {
+                println!("Asked to install {path} but don't feel like it");
+            }
+        });
+
+        update_channels::poll_updates(&inst);
+
         inst
     }
 
@@ -91,6 +354,7 @@ impl Rauc {
         let conn_task = conn.clone();
         let operation = inst.operation.clone();
         let slot_status = inst.slot_status.clone();
+        let primary = inst.primary.clone();
 
         spawn(async move {
             let proxy = installer::InstallerProxy::new(&conn_task).await.unwrap();
@@ -105,49 +369,7 @@ impl Rauc {
                 // Referesh the slot status whenever the current operation changes
                 // This is mostly relevant for "installing" -> "idle" transitions
                 // but it can't hurt to do it on any transition.
-                if let Ok(slots) = proxy.get_slot_status().await {
-                    let slots = slots
-                        .into_iter()
-                        .map(|(slot_name, slot_info)| {
-                            let mut info: HashMap<String, String> = slot_info
-                                .into_iter()
-                                .map(|(k, v)| {
-                                    // Convert itegers to strings as raw zvariant values are
-                                    // unusable when json serialized and I can not be bothered
-                                    // to fiddle around with an enum that wraps strings and integers
-                                    // or something like that
-                                    let ss = v.downcast_ref::<str>().map(|s| s.to_string());
-                                    let s32 = v.downcast_ref::<u32>().map(|i| format!("{i}"));
-                                    let s64 = v.downcast_ref::<u64>().map(|i| format!("{i}"));
-
-                                    // Some of the field names make defining a "RaucSlot" type
-                                    // in Typescript difficult. Not matching the names defined
-                                    // in RAUC's API is also not great, but the lesser evil in
-                                    // this case.
-                                    let k = k
-                                        .replace("type", "fs_type")
-                                        .replace("class", "slot_class")
-                                        .replace(['.', '-'], "_");
-
-                                    (k, ss.or(s32).or(s64).unwrap_or_default())
-                                })
-                                .collect();
-
-                            // Include the (unmangled) slot name as a field in the slot
-                            // dict, once again to make life in the Web Interface easier.
-                            info.insert("name".to_string(), slot_name.clone());
-
-                            // Remove "." from the dictionary key to make defining a typescript
-                            // type easier ("rootfs.0" -> "rootfs_0").
-                            (slot_name.replace('.', "_"), info)
-                        })
-                        .collect();
-
-                    // In the RAUC API the slot status is a list of (name, info) tuples.
-                    // It is once again easier in typescript to represent it as a dict with
-                    // the names as keys, so that is what's exposed here.
-                    slot_status.set(Arc::new(slots));
-                }
+                refresh_slot_status(&proxy, &slot_status, &primary).await;
 
                 // Wait for the current operation to change
                 if let Some(v) = stream.next().await {
@@ -160,6 +382,37 @@ impl Rauc {
             }
         });
 
+        let conn_task = conn.clone();
+        let booted_slot = inst.booted_slot.clone();
+        let slot_status = inst.slot_status.clone();
+        let primary = inst.primary.clone();
+        let last_error = inst.last_error.clone();
+        let (mut mark_stream, _) = inst.mark.clone().subscribe_unbounded();
+
+        // Handle requests to mark slots as good/bad/active and update the slot
+        // status afterwards, so that the effect is visible right away.
+        spawn(async move {
+            let proxy = installer::InstallerProxy::new(&conn_task).await.unwrap();
+
+            if let Ok(slot) = proxy.boot_slot().await {
+                booted_slot.set(slot);
+            }
+
+            while let Some(mark) = mark_stream.next().await {
+                let (state, slot_identifier) = mark.as_rauc_args();
+
+                match proxy.mark(state, slot_identifier).await {
+                    Ok((slot_name, message)) => info!("Marked slot {slot_name}: {message}"),
+                    Err(e) => {
+                        warn!("Failed to mark slot {slot_identifier} as {state}: {e}");
+                        last_error.set(e.to_string());
+                    }
+                }
+
+                refresh_slot_status(&proxy, &slot_status, &primary).await;
+            }
+        });
+
         let conn_task = conn.clone();
         let progress = inst.progress.clone();
 
@@ -201,22 +454,49 @@ impl Rauc {
         });
 
         let conn_task = conn.clone();
-        let (mut install_stream, _) = inst.install.clone().subscribe_unbounded();
+        let result = inst.result.clone();
+
+        // Forward the "Completed" signal to the broker framework
+        spawn(async move {
+            let proxy = installer::InstallerProxy::new(&conn_task).await.unwrap();
+
+            let mut stream = proxy.receive_completed().await.unwrap();
+
+            while let Some(completed) = stream.next().await {
+                if let Ok(args) = completed.args() {
+                    result.set(args.result.into());
+                }
+            }
+        });
+
+        let conn_task = conn.clone();
+        let last_error = inst.last_error.clone();
+        let (install_stream, _) = inst.install.clone().subscribe_unbounded();
+        let (install_local_stream, _) = inst.install_local.clone().subscribe_unbounded();
 
         // Forward the "install" topic from the broker framework to RAUC
         spawn(async move {
             let proxy = installer::InstallerProxy::new(&conn_task).await.unwrap();
 
-            while let Some(url) = install_stream.next().await {
-                // Poor-mans validation. It feels wrong to let someone point to any
-                // file on the TAC from the web interface.
-                if url.starts_with("http://") || url.starts_with("https://") {
-                    // TODO: some kind of error handling
-                    let _ = proxy.install(&url).await;
+            // Poor-mans validation. It feels wrong to let someone point to any
+            // file on the TAC from the web interface.
+            // Uploaded bundles are placed at a fixed path by the tacd itself
+            // and take a different route that bypasses this check.
+            let install_stream = install_stream
+                .filter(|url| url.starts_with("http://") || url.starts_with("https://"));
+
+            let mut sources = select(install_stream, install_local_stream);
+
+            while let Some(source) = sources.next().await {
+                if let Err(e) = proxy.install_bundle(&source, HashMap::new()).await {
+                    warn!("Failed to start installation of {source}: {e}");
+                    last_error.set(e.to_string());
                 }
             }
         });
 
+        update_channels::poll_updates(&inst);
+
         inst
     }
 }
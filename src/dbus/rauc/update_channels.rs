@@ -0,0 +1,257 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::Duration;
+
+use async_std::future::timeout;
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use chrono::{Local, Timelike};
+use futures::stream::select;
+use futures::FutureExt as _;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{Rauc, SlotStatus};
+use crate::broker::Topic;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+#[cfg(feature = "demo_mode")]
+mod http {
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+
+    pub struct RequestDecoy {}
+
+    impl RequestDecoy {
+        pub async fn recv_json<T: DeserializeOwned>(&self) -> Result<T, String> {
+            let bundle = json!({
+                "version": "4.1-0-20230301120000",
+                "url": "http://example.com/stable/lxatac-core-bundle-base.raucb",
+            });
+
+            serde_json::from_value(bundle).map_err(|e| e.to_string())
+        }
+    }
+
+    pub fn get(_: &str) -> RequestDecoy {
+        RequestDecoy {}
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod http {
+    pub use surf::get;
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum UpdateChannel {
+    Stable,
+    Testing,
+}
+
+impl UpdateChannel {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Stable => "stable",
+            Self::Testing => "testing",
+        }
+    }
+}
+
+/// Description of the most recent bundle in an update channel as served
+/// by the update server at `<server>/<channel>.json`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct UpstreamBundle {
+    pub version: String,
+    pub url: String,
+}
+
+/// A daily time window (in local time) in which unattended updates may be
+/// installed. The window may wrap around midnight (e.g. from 22 to 4 o'clock).
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct MaintenanceWindow {
+    pub start_hour: u32,
+    pub end_hour: u32,
+}
+
+impl MaintenanceWindow {
+    fn contains(&self, hour: u32) -> bool {
+        if self.start_hour <= self.end_hour {
+            (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+}
+
+/// Get the bundle version of the slot we are currently booted from
+fn booted_version(slots: &SlotStatus) -> Option<&str> {
+    slots
+        .values()
+        .find(|slot| slot.get("state").map(|s| s == "booted").unwrap_or(false))
+        .and_then(|slot| slot.get("bundle_version"))
+        .map(|v| v.as_str())
+}
+
+/// The bundles are fetched via plain HTTP, as there is no TLS support
+/// in the HTTP client.
+fn server_supported(server: &str) -> bool {
+    server.is_empty() || server.starts_with("http://")
+}
+
+/// Reset the update server to the previous value if it is set to an URL
+/// we can not poll, e.g. one using https.
+fn reject_unsupported_servers(server: Arc<Topic<String>>) {
+    let (mut server_events, _) = server.clone().subscribe_unbounded();
+
+    spawn(async move {
+        let mut prev_valid = String::new();
+
+        while let Some(new) = server_events.next().await {
+            if server_supported(&new) {
+                prev_valid = new;
+            } else {
+                warn!("Rejecting update server \"{new}\". Only http:// URLs are supported");
+                server.set(prev_valid.clone());
+            }
+        }
+    });
+}
+
+/// Periodically ask the update server for the newest bundle in the selected
+/// channel and (if requested) install it during the maintenance window.
+/// The server is asked right away when the server or channel are changed.
+pub(super) fn poll_updates(rauc: &Rauc) {
+    let server = rauc.update_server.clone();
+    let channel = rauc.update_channel.clone();
+    let available = rauc.update_available.clone();
+    let auto_install = rauc.auto_install.clone();
+    let maintenance_window = rauc.maintenance_window.clone();
+    let slot_status = rauc.slot_status.clone();
+    let operation = rauc.operation.clone();
+    let install = rauc.install.clone();
+
+    reject_unsupported_servers(server.clone());
+
+    let (server_events, _) = server.clone().subscribe_unbounded();
+    let (channel_events, _) = channel.clone().subscribe_unbounded();
+    let mut changes = select(server_events.map(|_| ()), channel_events.map(|_| ()));
+
+    spawn(async move {
+        // Remember what we have already installed, as the booted version will
+        // only change after a reboot and we do not want to install the same
+        // bundle over and over again in the meantime.
+        let mut installed_url: Option<String> = None;
+
+        loop {
+            // Changes up to now are taken into account by this poll
+            while let Some(Some(())) = changes.next().now_or_never() {}
+
+            let server = server.try_get().unwrap_or_default();
+
+            // An empty server URL disables update polling altogether
+            if server.is_empty() {
+                available.set(None);
+                let _ = timeout(POLL_INTERVAL, changes.next()).await;
+                continue;
+            }
+
+            let channel = channel.try_get().unwrap_or(UpdateChannel::Stable);
+            let url = format!("{}/{}.json", server.trim_end_matches('/'), channel.name());
+
+            let upstream = match http::get(&url).recv_json::<UpstreamBundle>().await {
+                Ok(upstream) => upstream,
+                Err(e) => {
+                    warn!("Failed to query update server at {url}: {e}");
+                    let _ = timeout(POLL_INTERVAL, changes.next()).await;
+                    continue;
+                }
+            };
+
+            let is_new = slot_status
+                .try_get()
+                .and_then(|slots| booted_version(&slots).map(|v| v != upstream.version))
+                .unwrap_or(false);
+
+            if is_new {
+                let in_window = maintenance_window
+                    .try_get()
+                    .map(|w| w.contains(Local::now().hour()))
+                    .unwrap_or(false);
+
+                let is_idle = operation.try_get().map(|op| op == "idle").unwrap_or(false);
+                let already_installed = installed_url.as_ref() == Some(&upstream.url);
+
+                if auto_install.try_get().unwrap_or(false)
+                    && in_window
+                    && is_idle
+                    && !already_installed
+                {
+                    info!("Automatically installing update {}", upstream.version);
+                    install.set(upstream.url.clone());
+                    installed_url = Some(upstream.url.clone());
+                }
+
+                available.set(Some(upstream));
+            } else {
+                available.set(None);
+            }
+
+            let _ = timeout(POLL_INTERVAL, changes.next()).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{server_supported, MaintenanceWindow};
+
+    #[test]
+    fn maintenance_window() {
+        let day = MaintenanceWindow {
+            start_hour: 8,
+            end_hour: 17,
+        };
+
+        assert!(!day.contains(7));
+        assert!(day.contains(8));
+        assert!(day.contains(16));
+        assert!(!day.contains(17));
+
+        let night = MaintenanceWindow {
+            start_hour: 22,
+            end_hour: 4,
+        };
+
+        assert!(!night.contains(21));
+        assert!(night.contains(22));
+        assert!(night.contains(0));
+        assert!(night.contains(3));
+        assert!(!night.contains(4));
+    }
+
+    #[test]
+    fn supported_servers() {
+        assert!(server_supported(""));
+        assert!(server_supported("http://example.com/updates"));
+        assert!(!server_supported("https://example.com/updates"));
+        assert!(!server_supported("example.com"));
+    }
+}
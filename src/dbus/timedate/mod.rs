@@ -0,0 +1,175 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+
+#[cfg(not(feature = "demo_mode"))]
+use log::warn;
+
+use super::Connection;
+use crate::broker::{BrokerBuilder, Topic};
+
+#[cfg(not(feature = "demo_mode"))]
+mod timedated;
+
+pub struct TimeDate {
+    pub ntp_enabled: Arc<Topic<bool>>,
+    pub ntp_synchronized: Arc<Topic<bool>>,
+    pub ntp_server: Arc<Topic<String>>,
+    pub timezone: Arc<Topic<String>>,
+    pub set_timezone: Arc<Topic<String>>,
+}
+
+impl TimeDate {
+    fn setup_topics(bb: &mut BrokerBuilder) -> Self {
+        // Use the "register a read-only and a write-only topic with the same name"
+        // trick, so that the timezone topic always reflects what timedated
+        // reports and not what a client requested.
+        Self {
+            ntp_enabled: bb.topic_ro("/v1/tac/time/ntp/enabled", None),
+            ntp_synchronized: bb.topic_ro("/v1/tac/time/ntp/synchronized", None),
+            ntp_server: bb.topic_ro("/v1/tac/time/ntp/server", None),
+            timezone: bb.topic_ro("/v1/tac/time/timezone", None),
+            set_timezone: bb.topic_wo("/v1/tac/time/timezone", None),
+        }
+    }
+
+    #[cfg(feature = "demo_mode")]
+    pub fn new(bb: &mut BrokerBuilder, _conn: &Arc<Connection>) -> Self {
+        let this = Self::setup_topics(bb);
+
+        this.ntp_enabled.set(true);
+        this.ntp_synchronized.set(true);
+        this.ntp_server.set("0.pool.ntp.org".to_string());
+        this.timezone.set("Europe/Berlin".to_string());
+
+        let timezone = this.timezone.clone();
+        let (mut timezone_reqs, _) = this.set_timezone.clone().subscribe_unbounded();
+
+        spawn(async move {
+            while let Some(tz) = timezone_reqs.next().await {
+                timezone.set(tz);
+            }
+        });
+
+        this
+    }
+
+    #[cfg(not(feature = "demo_mode"))]
+    pub fn new(bb: &mut BrokerBuilder, conn: &Arc<Connection>) -> Self {
+        let this = Self::setup_topics(bb);
+
+        let conn_task = conn.clone();
+        let ntp_enabled = this.ntp_enabled.clone();
+
+        // Forward the "NTP" property to the broker framework
+        spawn(async move {
+            let proxy = timedated::TimedateProxy::new(&conn_task).await.unwrap();
+
+            let mut stream = proxy.receive_ntp_changed().await;
+
+            if let Ok(v) = proxy.ntp().await {
+                ntp_enabled.set(v);
+            }
+
+            while let Some(v) = stream.next().await {
+                if let Ok(v) = v.get().await {
+                    ntp_enabled.set(v);
+                }
+            }
+        });
+
+        let conn_task = conn.clone();
+        let ntp_synchronized = this.ntp_synchronized.clone();
+
+        // Forward the "NTPSynchronized" property to the broker framework
+        spawn(async move {
+            let proxy = timedated::TimedateProxy::new(&conn_task).await.unwrap();
+
+            let mut stream = proxy.receive_ntp_synchronized_changed().await;
+
+            if let Ok(v) = proxy.ntp_synchronized().await {
+                ntp_synchronized.set(v);
+            }
+
+            while let Some(v) = stream.next().await {
+                if let Ok(v) = v.get().await {
+                    ntp_synchronized.set(v);
+                }
+            }
+        });
+
+        let conn_task = conn.clone();
+        let ntp_server = this.ntp_server.clone();
+
+        // Forward the NTP server currently used by timesyncd to the broker
+        // framework
+        spawn(async move {
+            let proxy = timedated::TimesyncProxy::new(&conn_task).await.unwrap();
+
+            let mut stream = proxy.receive_server_name_changed().await;
+
+            if let Ok(v) = proxy.server_name().await {
+                ntp_server.set(v);
+            }
+
+            while let Some(v) = stream.next().await {
+                if let Ok(v) = v.get().await {
+                    ntp_server.set(v);
+                }
+            }
+        });
+
+        let conn_task = conn.clone();
+        let timezone = this.timezone.clone();
+
+        // Forward the "Timezone" property to the broker framework
+        spawn(async move {
+            let proxy = timedated::TimedateProxy::new(&conn_task).await.unwrap();
+
+            let mut stream = proxy.receive_timezone_changed().await;
+
+            if let Ok(v) = proxy.timezone().await {
+                timezone.set(v);
+            }
+
+            while let Some(v) = stream.next().await {
+                if let Ok(v) = v.get().await {
+                    timezone.set(v);
+                }
+            }
+        });
+
+        let conn_task = conn.clone();
+        let (mut timezone_reqs, _) = this.set_timezone.clone().subscribe_unbounded();
+
+        // Forward timezone change requests from the broker framework to timedated
+        spawn(async move {
+            let proxy = timedated::TimedateProxy::new(&conn_task).await.unwrap();
+
+            while let Some(tz) = timezone_reqs.next().await {
+                if let Err(e) = proxy.set_timezone(&tz, false).await {
+                    warn!("Failed to set timezone to {tz}: {e}");
+                }
+            }
+        });
+
+        this
+    }
+}
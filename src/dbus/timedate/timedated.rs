@@ -0,0 +1,51 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use zbus::dbus_proxy;
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.timedate1",
+    interface = "org.freedesktop.timedate1",
+    default_path = "/org/freedesktop/timedate1"
+)]
+trait Timedate {
+    /// SetTimezone method
+    fn set_timezone(&self, timezone: &str, interactive: bool) -> zbus::Result<()>;
+
+    /// NTP property
+    #[dbus_proxy(name = "NTP", property)]
+    fn ntp(&self) -> zbus::Result<bool>;
+
+    /// NTPSynchronized property
+    #[dbus_proxy(name = "NTPSynchronized", property)]
+    fn ntp_synchronized(&self) -> zbus::Result<bool>;
+
+    /// Timezone property
+    #[dbus_proxy(property)]
+    fn timezone(&self) -> zbus::Result<String>;
+}
+
+#[dbus_proxy(
+    default_service = "org.freedesktop.timesync1",
+    interface = "org.freedesktop.timesync1.Manager",
+    default_path = "/org/freedesktop/timesync1"
+)]
+trait Timesync {
+    /// ServerName property
+    #[dbus_proxy(property)]
+    fn server_name(&self) -> zbus::Result<String>;
+}
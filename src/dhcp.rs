@@ -0,0 +1,140 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::Duration;
+
+use async_std::sync::Arc;
+use async_std::task::{sleep, spawn};
+use serde::{Deserialize, Serialize};
+
+use crate::broker::{BrokerBuilder, Topic};
+
+#[cfg(feature = "demo_mode")]
+mod rw {
+    use std::io::Result;
+    use std::path::Path;
+
+    const LEASES: &str = "\
+1893456000 00:50:56:3c:aa:01 192.168.1.100 christmas-tree 01:00:50:56:3c:aa:01
+1893456000 00:50:56:3c:aa:02 192.168.1.101 * *
+";
+
+    pub async fn read_to_string<P: AsRef<Path>>(_: P) -> Result<String> {
+        Ok(LEASES.to_string())
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod rw {
+    pub use async_std::fs::read_to_string;
+}
+
+use rw::read_to_string;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const LEASES_PATH: &str = "/var/lib/misc/dnsmasq.leases";
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DhcpLease {
+    pub expires: u64,
+    pub mac: String,
+    pub ip: String,
+    pub hostname: Option<String>,
+}
+
+pub struct Dhcp {
+    pub leases: Arc<Topic<Vec<DhcpLease>>>,
+}
+
+/// Parse the content of a dnsmasq lease file.
+///
+/// Every line describes a single lease in the format
+/// `<expiry> <mac> <ip> <hostname> <client id>`, where unknown hostnames
+/// and client ids are represented by a `*`.
+/// Malformed lines are skipped.
+fn parse_leases(content: &str) -> Vec<DhcpLease> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+
+            let expires = fields.next()?.parse().ok()?;
+            let mac = fields.next()?.to_string();
+            let ip = fields.next()?.to_string();
+            let hostname = fields.next().filter(|h| *h != "*").map(|h| h.to_string());
+
+            Some(DhcpLease {
+                expires,
+                mac,
+                ip,
+                hostname,
+            })
+        })
+        .collect()
+}
+
+impl Dhcp {
+    pub fn new(bb: &mut BrokerBuilder) -> Self {
+        let leases = bb.topic_ro("/v1/tac/network/dut/leases", Some(Vec::new()));
+
+        let leases_task = leases.clone();
+
+        // dnsmasq does not provide change notifications for its lease file
+        // that we could easily hook into, so poll it instead and only update
+        // the topic if anything changed.
+        spawn(async move {
+            loop {
+                let current = read_to_string(LEASES_PATH)
+                    .await
+                    .map(|content| parse_leases(&content))
+                    .unwrap_or_default();
+
+                leases_task.modify(|prev| match prev {
+                    Some(prev) if prev == current => None,
+                    _ => Some(current),
+                });
+
+                sleep(POLL_INTERVAL).await;
+            }
+        });
+
+        Self { leases }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_leases;
+
+    #[test]
+    fn lease_file() {
+        let content = "\
+1680000000 aa:bb:cc:dd:ee:ff 192.168.1.10 dut-1 01:aa:bb:cc:dd:ee:ff
+1680000100 11:22:33:44:55:66 192.168.1.11 * *
+garbage
+";
+
+        let leases = parse_leases(content);
+
+        assert_eq!(leases.len(), 2);
+        assert_eq!(leases[0].expires, 1680000000);
+        assert_eq!(leases[0].mac, "aa:bb:cc:dd:ee:ff");
+        assert_eq!(leases[0].ip, "192.168.1.10");
+        assert_eq!(leases[0].hostname.as_deref(), Some("dut-1"));
+        assert_eq!(leases[1].hostname, None);
+    }
+}
@@ -15,12 +15,20 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_std::future::timeout;
 use async_std::prelude::*;
 use async_std::sync::Arc;
 use async_std::task::{spawn, spawn_blocking};
+use async_trait::async_trait;
+use nix::time::{clock_gettime, ClockId};
+use serde::{Deserialize, Serialize};
 
 use crate::broker::{BrokerBuilder, Topic};
 use crate::led::BlinkPattern;
+use crate::safe_state;
 
 #[cfg(test)]
 mod gpio {
@@ -40,46 +48,223 @@ mod gpio {
     pub use hardware::*;
 }
 
-pub use gpio::{find_line, EventRequestFlags, EventType, LineHandle, LineRequestFlags};
+pub use gpio::{find_line, request_events, LineHandle, LineRequestFlags};
+
+/// The current time of the clock the kernel uses to timestamp GPIO events
+fn monotonic_ns() -> u64 {
+    clock_gettime(ClockId::CLOCK_MONOTONIC)
+        .map(|ts| Duration::from(ts).as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// An edge on an input line
+#[derive(Clone, Copy, Debug)]
+pub struct Edge {
+    /// The level of the line after the edge
+    pub level: bool,
+    /// When the edge happened in nanoseconds of CLOCK_MONOTONIC
+    pub monotonic_ns: u64,
+}
+
+impl Edge {
+    /// An edge for GPIO implementations that do not provide timestamps
+    #[cfg(any(test, feature = "demo_mode"))]
+    fn now(level: bool) -> Self {
+        Self {
+            level,
+            monotonic_ns: monotonic_ns(),
+        }
+    }
+
+    /// Get the time since the edge
+    fn age(&self) -> Duration {
+        Duration::from_nanos(monotonic_ns().saturating_sub(self.monotonic_ns))
+    }
+
+    /// Express the time of the edge as Instant, which uses the same clock
+    fn instant(&self) -> Instant {
+        Instant::now() - self.age()
+    }
+}
+
+/// An edge on an input as published via the broker framework
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct InputEvent {
+    pub asserted: bool,
+    /// When the kernel saw the edge in nanoseconds of CLOCK_MONOTONIC
+    pub monotonic_ns: u64,
+    /// The time between the edge and the tacd handling it
+    pub latency_ns: u64,
+}
+
+/// The edges on an input line, provided by the real and the stub GPIO
+/// implementations alike.
+#[async_trait]
+pub trait LineEvents: Send {
+    /// Get the current level of the line
+    fn level(&self) -> Result<bool>;
+
+    /// Wait for the next edge on the line
+    async fn next_edge(&mut self) -> Result<Edge>;
+}
+
+mod pwm;
+pub use pwm::OutputMode;
+
+mod edges;
+mod expanders;
+mod readback;
+mod user_gpio;
+
+use readback::Readback;
+pub use readback::ReadbackMismatch;
 
 pub struct DigitalIo {
     pub out_0: Arc<Topic<bool>>,
     pub out_1: Arc<Topic<bool>>,
+    pub out_0_mode: Arc<Topic<OutputMode>>,
+    pub out_1_mode: Arc<Topic<OutputMode>>,
     pub uart_rx_en: Arc<Topic<bool>>,
     pub uart_tx_en: Arc<Topic<bool>>,
     pub iobus_flt_fb: Arc<Topic<bool>>,
+    pub readback_mismatches: Arc<Topic<Vec<ReadbackMismatch>>>,
+}
+
+/// A request to assert (or deassert) an output for some time and then
+/// return it to its previous state
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Pulse {
+    pub asserted: bool,
+    pub duration_ms: u64,
+}
+
+/// Provide a topic that generates pulses on an output, so that clients do
+/// not have to time two separate writes to e.g. reset a DUT.
+fn handle_pulse(bb: &mut BrokerBuilder, path: &str, asserted: Arc<Topic<bool>>) {
+    let pulse: Arc<Topic<Pulse>> = bb.topic_wo(path, None);
+    let (mut pulses, _) = pulse.subscribe_unbounded();
+
+    spawn(async move {
+        while let Some(mut pulse) = pulses.next().await {
+            let prev = asserted.try_get().unwrap_or(false);
+
+            loop {
+                asserted.set(pulse.asserted);
+
+                let duration = Duration::from_millis(pulse.duration_ms);
+
+                match timeout(duration, pulses.next()).await {
+                    // A new pulse cancels the current one. The output is
+                    // returned to the state from before the first pulse
+                    // once the new one is over.
+                    Ok(Some(next)) => pulse = next,
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+
+            // Do not undo changes made via the asserted topic in the meantime
+            if asserted.try_get() == Some(pulse.asserted) {
+                asserted.set(prev);
+            }
+        }
+    });
 }
 
 /// Handle a GPIO line whose state is completely defined by the broker framework
 /// writing to it. (e.g. whatever it is set to _is_ the line status).
 fn handle_line_wo(
     bb: &mut BrokerBuilder,
+    readback: &mut Readback,
     path: &str,
     line_name: &str,
     initial: bool,
     inverted: bool,
-    led_topic: Option<Arc<Topic<BlinkPattern>>>,
 ) -> Arc<Topic<bool>> {
     let topic = bb.topic_rw(path, Some(initial));
     let line = find_line(line_name).unwrap();
-    let dst = line
-        .request(LineRequestFlags::OUTPUT, (initial ^ inverted) as _, "tacd")
-        .unwrap();
+    let dst = Arc::new(
+        line.request(LineRequestFlags::OUTPUT, (initial ^ inverted) as _, "tacd")
+            .unwrap(),
+    );
+
+    readback.add(line_name, dst.clone(), topic.clone(), inverted, None);
 
     let (mut src, _) = topic.clone().subscribe_unbounded();
 
     spawn(async move {
         while let Some(ev) = src.next().await {
             dst.set_value((ev ^ inverted) as _).unwrap();
+        }
+    });
+
+    topic
+}
+
+/// Handle one of the user outputs, which can either be set to a level or
+/// generate a PWM signal.
+fn handle_output(
+    bb: &mut BrokerBuilder,
+    readback: &mut Readback,
+    name: &'static str,
+    line_name: &str,
+    led_topic: Arc<Topic<BlinkPattern>>,
+) -> (Arc<Topic<bool>>, Arc<Topic<OutputMode>>) {
+    let asserted = bb.topic_rw(&format!("/v1/output/{name}/asserted"), Some(false));
+    let mode = bb.topic_rw(&format!("/v1/output/{name}/mode"), Some(OutputMode::Level));
+    let frequency = bb.topic_rw(&format!("/v1/output/{name}/pwm/frequency"), Some(100.0));
+    let duty = bb.topic_rw(&format!("/v1/output/{name}/pwm/duty"), Some(0.5));
+
+    handle_pulse(bb, &format!("/v1/output/{name}/pulse"), asserted.clone());
 
-            if let Some(led) = &led_topic {
-                let pattern = BlinkPattern::solid(if ev { 1.0 } else { 0.0 });
-                led.set(pattern);
+    let line = find_line(line_name).unwrap();
+    let dst = Arc::new(line.request(LineRequestFlags::OUTPUT, 0, "tacd").unwrap());
+
+    readback.add(
+        line_name,
+        dst.clone(),
+        asserted.clone(),
+        false,
+        Some(mode.clone()),
+    );
+
+    let dst_safe = dst.clone();
+    safe_state::register(name, move |on| {
+        let _ = dst_safe.set_value(on as _);
+    });
+
+    let (mut src, _) = asserted.clone().subscribe_unbounded();
+    let dst_task = dst.clone();
+    let mode_task = mode.clone();
+    let led_task = led_topic.clone();
+
+    spawn(async move {
+        while let Some(ev) = src.next().await {
+            // The PWM thread restores the level once PWM mode is left
+            if mode_task.try_get() == Some(OutputMode::Pwm) {
+                continue;
             }
+
+            dst_task.set_value(ev as _).unwrap();
+            led_task.set(BlinkPattern::solid(if ev { 1.0 } else { 0.0 }));
         }
     });
 
-    topic
+    let asserted_thread = asserted.clone();
+    let mode_thread = mode.clone();
+
+    spawn_blocking(move || {
+        pwm::run(
+            dst,
+            asserted_thread,
+            mode_thread,
+            frequency,
+            duty,
+            led_topic,
+        )
+    });
+
+    (asserted, mode)
 }
 
 /// Handle a GPIO line whose state is completely defined by itself
@@ -88,26 +273,14 @@ fn handle_line_ro(bb: &mut BrokerBuilder, path: &str, line_name: &str) -> Arc<To
     let topic = bb.topic_ro(path, None);
     let line = find_line(line_name).unwrap();
 
-    let topic_thread = topic.clone();
-
-    let src = line
-        .events(
-            LineRequestFlags::INPUT,
-            EventRequestFlags::BOTH_EDGES,
-            "tacd",
-        )
-        .unwrap();
-
-    spawn_blocking(move || {
-        topic_thread.set(src.get_value().unwrap() != 0);
+    let topic_task = topic.clone();
+    let mut src = request_events(&line, "tacd").unwrap();
 
-        for ev in src {
-            let state = match ev.unwrap().event_type() {
-                EventType::RisingEdge => true,
-                EventType::FallingEdge => false,
-            };
+    spawn(async move {
+        topic_task.set(src.level().unwrap());
 
-            topic_thread.set(state);
+        loop {
+            topic_task.set(src.next_edge().await.unwrap().level);
         }
     });
 
@@ -120,34 +293,51 @@ impl DigitalIo {
         led_0: Arc<Topic<BlinkPattern>>,
         led_1: Arc<Topic<BlinkPattern>>,
     ) -> Self {
-        let out_0 = handle_line_wo(
+        // Compare the actual state of the output lines with the commanded
+        // one, e.g. to detect shorted output drivers.
+        let mut readback = Readback::default();
+
+        let (out_0, out_0_mode) = handle_output(bb, &mut readback, "out_0", "OUT_0", led_0);
+        let (out_1, out_1_mode) = handle_output(bb, &mut readback, "out_1", "OUT_1", led_1);
+
+        let uart_rx_en = handle_line_wo(
             bb,
-            "/v1/output/out_0/asserted",
-            "OUT_0",
-            false,
-            false,
-            Some(led_0),
+            &mut readback,
+            "/v1/uart/rx/enabled",
+            "UART_RX_EN",
+            true,
+            true,
         );
-
-        let out_1 = handle_line_wo(
+        let uart_tx_en = handle_line_wo(
             bb,
-            "/v1/output/out_1/asserted",
-            "OUT_1",
-            false,
-            false,
-            Some(led_1),
+            &mut readback,
+            "/v1/uart/tx/enabled",
+            "UART_TX_EN",
+            true,
+            true,
         );
-
-        let uart_rx_en = handle_line_wo(bb, "/v1/uart/rx/enabled", "UART_RX_EN", true, true, None);
-        let uart_tx_en = handle_line_wo(bb, "/v1/uart/tx/enabled", "UART_TX_EN", true, true, None);
         let iobus_flt_fb = handle_line_ro(bb, "/v1/iobus/feedback/fault", "IOBUS_FLT_FB");
 
+        // There are no real input lines in demo mode, so allow simulating
+        // edges on them instead.
+        #[cfg(feature = "demo_mode")]
+        gpio::setup_edge_injection(bb);
+
+        // Expose additional GPIOs, e.g. on custom breakout hardware, that
+        // are declared in the config file.
+        user_gpio::setup(bb, &mut readback);
+
+        let readback_mismatches = readback.setup(bb);
+
         Self {
             out_0,
             out_1,
+            out_0_mode,
+            out_1_mode,
             uart_rx_en,
             uart_tx_en,
             iobus_flt_fb,
+            readback_mismatches,
         }
     }
 }
@@ -0,0 +1,190 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_std::sync::Arc;
+use async_std::task::{sleep, spawn};
+use serde::{Deserialize, Serialize};
+
+use crate::broker::BrokerBuilder;
+
+/// Time between two updates of the edge count and frequency topics
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Signals with a longer period than this are considered stopped
+const MAX_PERIOD: Duration = Duration::from_secs(10);
+
+/// The number of edges seen on an input since the last reset
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct EdgeCount {
+    pub rising: u64,
+    pub falling: u64,
+}
+
+/// The frequency of the signal on an input, based on its rising edges
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct SignalFrequency {
+    pub frequency: f32,
+    /// `None` if there currently is no signal
+    pub period_ms: Option<f32>,
+}
+
+#[derive(Default)]
+pub(super) struct EdgeCounter {
+    count: EdgeCount,
+    /// The first and the most recent rising edge of the current
+    /// measurement and the number of periods in between
+    rising: Option<(Instant, Instant, u32)>,
+}
+
+impl EdgeCounter {
+    pub(super) fn edge(&mut self, rising: bool, ts: Instant) {
+        if !rising {
+            self.count.falling += 1;
+            return;
+        }
+
+        self.count.rising += 1;
+
+        self.rising = match self.rising {
+            Some((first, _, periods)) => Some((first, ts, periods + 1)),
+            None => Some((ts, ts, 0)),
+        };
+    }
+
+    /// Get the average frequency since the last call.
+    ///
+    /// Returns `None` if no full period was seen since then but the
+    /// signal may just be slow.
+    fn measure(&mut self, now: Instant) -> Option<SignalFrequency> {
+        let stopped = SignalFrequency {
+            frequency: 0.0,
+            period_ms: None,
+        };
+
+        let (first, last, periods) = match self.rising {
+            Some(rising) => rising,
+            None => return Some(stopped),
+        };
+
+        if now.saturating_duration_since(last) > MAX_PERIOD {
+            self.rising = None;
+            return Some(stopped);
+        }
+
+        if periods == 0 {
+            return None;
+        }
+
+        // Continue the next measurement at the most recent edge
+        self.rising = Some((last, last, 0));
+
+        let period = last.duration_since(first).as_secs_f32() / periods as f32;
+
+        Some(SignalFrequency {
+            frequency: 1.0 / period,
+            period_ms: Some(period * 1000.0),
+        })
+    }
+}
+
+/// Publish edge counts and the signal frequency of an input below `path`
+/// based on the edges fed into the returned counter.
+pub(super) fn setup_edge_counter(bb: &mut BrokerBuilder, path: &str) -> Arc<Mutex<EdgeCounter>> {
+    let edges = bb.topic_ro(&format!("{path}/edges"), Some(EdgeCount::default()));
+    let reset = bb.topic_wo::<bool>(&format!("{path}/edges/reset"), None);
+    let frequency = bb.topic_ro(&format!("{path}/frequency"), None);
+
+    let counter = Arc::new(Mutex::new(EdgeCounter::default()));
+    let counter_task = counter.clone();
+
+    let (reset_events, _) = reset.subscribe_unbounded();
+
+    spawn(async move {
+        loop {
+            sleep(PUBLISH_INTERVAL).await;
+
+            let mut reset_requested = false;
+
+            while let Ok(ev) = reset_events.try_recv() {
+                reset_requested |= ev;
+            }
+
+            let (count, freq) = {
+                let mut counter = counter_task.lock().unwrap();
+
+                if reset_requested {
+                    counter.count = EdgeCount::default();
+                }
+
+                (counter.count, counter.measure(Instant::now()))
+            };
+
+            if edges.try_get() != Some(count) {
+                edges.set(count);
+            }
+
+            if let Some(freq) = freq {
+                if frequency.try_get() != Some(freq) {
+                    frequency.set(freq);
+                }
+            }
+        }
+    });
+
+    counter
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::EdgeCounter;
+
+    #[test]
+    fn frequency() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut counter = EdgeCounter::default();
+
+        // 20Hz signal
+        for ms in (0..=1000).step_by(50) {
+            counter.edge(true, at(ms));
+            counter.edge(false, at(ms + 25));
+        }
+
+        assert_eq!(counter.count.rising, 21);
+        assert_eq!(counter.count.falling, 21);
+
+        let freq = counter.measure(at(1030)).unwrap();
+        assert!((freq.frequency - 20.0).abs() < 0.01);
+
+        // A slow signal is measured across multiple calls
+        assert!(counter.measure(at(2000)).is_none());
+        counter.edge(true, at(3000));
+
+        let freq = counter.measure(at(3030)).unwrap();
+        assert!((freq.frequency - 0.5).abs() < 0.01);
+
+        // A signal that stopped reports a frequency of zero
+        let freq = counter.measure(at(20_000)).unwrap();
+        assert_eq!(freq.frequency, 0.0);
+        assert_eq!(freq.period_ms, None);
+    }
+}
@@ -0,0 +1,232 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::broker::BrokerBuilder;
+
+#[cfg(feature = "demo_mode")]
+const CONFIG_PATH: &str = "demo_files/etc/tacd/gpio_expanders.json";
+
+#[cfg(not(feature = "demo_mode"))]
+const CONFIG_PATH: &str = "/etc/tacd/gpio_expanders.json";
+
+const I2C_DEVICES: &str = "/sys/bus/i2c/devices";
+
+/// The devices handled by the kernel's pca953x driver that make sense to
+/// attach to the TAC
+const SUPPORTED_MODELS: &[&str] = &[
+    "pca9505", "pca9506", "pca9534", "pca9535", "pca9536", "pca9537", "pca9538", "pca9539",
+    "pca9554", "pca9555", "pca9556", "pca9557", "pca9574", "pca9575", "pca9698",
+];
+
+/// How often and how long to wait for the driver to register the gpiochip
+/// after instantiating the device
+const PROBE_RETRIES: u32 = 20;
+const PROBE_INTERVAL: Duration = Duration::from_millis(50);
+
+#[cfg(feature = "demo_mode")]
+mod hw {
+    use std::io::Result;
+    use std::path::Path;
+
+    pub fn exists(_path: &Path) -> bool {
+        true
+    }
+
+    pub fn write(_path: &Path, _content: &str) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn list_dir(_path: &Path) -> Result<Vec<String>> {
+        Ok(vec![
+            "driver".to_string(),
+            "gpio".to_string(),
+            "gpiochip6".to_string(),
+            "name".to_string(),
+        ])
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod hw {
+    use std::io::Result;
+    use std::path::Path;
+
+    pub use std::fs::write;
+
+    pub fn exists(path: &Path) -> bool {
+        path.exists()
+    }
+
+    pub fn list_dir(path: &Path) -> Result<Vec<String>> {
+        std::fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect()
+    }
+}
+
+/// An I2C GPIO expander as declared in the expander config file
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ExpanderConfig {
+    /// The name used to reference the expander in the GPIO config file
+    pub name: String,
+    /// The number of the I2C bus, as in `/dev/i2c-<bus>`
+    pub bus: u32,
+    /// The 7-bit I2C address (in decimal, as JSON does not do hex)
+    pub address: u16,
+    /// The device type as known by the kernel driver, e.g. "pca9555"
+    pub model: String,
+}
+
+/// The name the kernel gives an I2C device, e.g. "1-0020"
+fn device_name(bus: u32, address: u16) -> String {
+    format!("{bus}-{address:04x}")
+}
+
+/// Find the character device of a GPIO controller in the list of entries
+/// in its parent device's sysfs directory.
+/// The legacy sysfs interface adds a "gpio" directory, which is not what
+/// we are looking for.
+fn find_gpiochip(entries: &[String]) -> Option<&str> {
+    entries.iter().map(|e| e.as_str()).find(|e| {
+        e.strip_prefix("gpiochip")
+            .map(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or(false)
+    })
+}
+
+/// Make sure the kernel knows about the expander and get the name of its
+/// gpiochip.
+fn instantiate(config: &ExpanderConfig) -> Result<String, String> {
+    if !SUPPORTED_MODELS.contains(&config.model.as_str()) {
+        return Err(format!("Unsupported model \"{}\"", config.model));
+    }
+
+    if config.address > 0x7f {
+        return Err(format!("Invalid I2C address {}", config.address));
+    }
+
+    let device = Path::new(I2C_DEVICES).join(device_name(config.bus, config.address));
+
+    // The device may already be declared in the device tree or have been
+    // instantiated by a previous tacd run.
+    if !hw::exists(&device) {
+        let new_device = Path::new(I2C_DEVICES)
+            .join(format!("i2c-{}", config.bus))
+            .join("new_device");
+
+        let desc = format!("{} 0x{:02x}", config.model, config.address);
+
+        hw::write(&new_device, &desc)
+            .map_err(|e| format!("Failed to instantiate device on bus {}: {e}", config.bus))?;
+    }
+
+    // Probing happens asynchronously and may fail, e.g. if nothing answers
+    // on the given address.
+    for _ in 0..PROBE_RETRIES {
+        if let Ok(entries) = hw::list_dir(&device) {
+            if let Some(chip) = find_gpiochip(&entries) {
+                return Ok(chip.to_string());
+            }
+        }
+
+        sleep(PROBE_INTERVAL);
+    }
+
+    Err("The driver did not register a gpiochip. Is the device connected?".to_string())
+}
+
+fn load_config() -> Vec<ExpanderConfig> {
+    let file = match File::open(CONFIG_PATH) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            info!("GPIO expander config at \"{CONFIG_PATH}\" does not exist. Not adding expanders");
+            return Vec::new();
+        }
+        Err(e) => {
+            warn!("Failed to open GPIO expander config at \"{CONFIG_PATH}\": {e}");
+            return Vec::new();
+        }
+    };
+
+    serde_json::from_reader(file).unwrap_or_else(|e| {
+        warn!("Failed to parse GPIO expander config at \"{CONFIG_PATH}\": {e}");
+        Vec::new()
+    })
+}
+
+/// Set up the expanders declared in the expander config file.
+/// Returns a map from expander name to the name of its gpiochip,
+/// so that their pins can be referenced in the GPIO config file.
+pub(super) fn setup(bb: &mut BrokerBuilder) -> HashMap<String, String> {
+    let mut configs = load_config();
+    let mut chips = HashMap::new();
+
+    configs.retain(|config| {
+        if config.name.is_empty() || chips.contains_key(&config.name) {
+            warn!(
+                "Ignoring GPIO expander with invalid or duplicate name \"{}\"",
+                config.name
+            );
+            return false;
+        }
+
+        match instantiate(config) {
+            Ok(chip) => {
+                info!("Using {chip} for GPIO expander \"{}\"", config.name);
+                chips.insert(config.name.clone(), chip);
+                true
+            }
+            Err(e) => {
+                warn!("Failed to set up GPIO expander \"{}\": {e}", config.name);
+                false
+            }
+        }
+    });
+
+    bb.topic_ro("/v1/gpio/expanders", Some(configs));
+
+    chips
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{device_name, find_gpiochip};
+
+    #[test]
+    fn names() {
+        assert_eq!(device_name(1, 0x20), "1-0020");
+        assert_eq!(device_name(0, 0x74), "0-0074");
+
+        let entries: Vec<String> = ["driver", "gpio", "gpiochip", "gpiochip7", "name"]
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+
+        assert_eq!(find_gpiochip(&entries), Some("gpiochip7"));
+        assert_eq!(find_gpiochip(&entries[..3]), None);
+    }
+}
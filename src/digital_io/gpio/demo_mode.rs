@@ -15,21 +15,46 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
-use std::iter::Iterator;
-use std::thread::sleep;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
 
-use anyhow::Result;
-use async_std::task::block_on;
+use anyhow::{anyhow, Result};
+use async_std::channel::{unbounded, Receiver, Sender};
+use async_std::prelude::*;
+use async_std::task::{block_on, spawn};
+use async_trait::async_trait;
+use log::warn;
+use serde::{Deserialize, Serialize};
 
 use crate::adc::IioThread;
+use crate::broker::BrokerBuilder;
+use crate::digital_io::{Edge, LineEvents};
+
+/// The input lines that were requested so far and where to send
+/// injected levels for them
+static INPUTS: Mutex<Vec<(String, Sender<bool>)>> = Mutex::new(Vec::new());
+
+/// A change of level on a simulated input line, as requested via the
+/// broker framework
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct InjectedEdge {
+    pub line: String,
+    pub level: bool,
+}
 
 pub struct LineHandle {
     name: String,
+    val: AtomicU8,
 }
 
 impl LineHandle {
+    pub fn get_value(&self) -> Result<u8, ()> {
+        Ok(self.val.load(Ordering::Relaxed))
+    }
+
     pub fn set_value(&self, val: u8) -> Result<(), ()> {
+        self.val.store(val, Ordering::Relaxed);
+
         // This does not actually set up any IIO things.
         // It is just a hack to let adc/iio/demo_mode.rs
         // communicate with this function so that toggling an output
@@ -62,49 +87,61 @@ impl LineHandle {
     }
 }
 
-pub struct LineEvent(u8);
-
-impl LineEvent {
-    pub fn event_type(&self) -> EventType {
-        match self.0 {
-            0 => EventType::FallingEdge,
-            _ => EventType::RisingEdge,
-        }
-    }
+/// The inputs only change when edges are injected via the
+/// /v1/demo/gpio/inject topic
+pub struct LineEventHandle {
+    level: bool,
+    injected: Receiver<bool>,
 }
 
-pub struct LineEventHandle {}
-
-impl LineEventHandle {
-    pub fn get_value(&self) -> Result<u8, ()> {
-        Ok(0)
+#[async_trait]
+impl LineEvents for LineEventHandle {
+    fn level(&self) -> Result<bool> {
+        Ok(self.level)
     }
-}
-
-impl Iterator for LineEventHandle {
-    type Item = Result<LineEvent, ()>;
 
-    fn next(&mut self) -> Option<Self::Item> {
+    async fn next_edge(&mut self) -> Result<Edge> {
         loop {
-            sleep(Duration::from_secs(1000));
+            let level = self
+                .injected
+                .recv()
+                .await
+                .map_err(|_| anyhow!("Edge injection stopped"))?;
+
+            // Setting a line to the level it already has is not an edge
+            if level != self.level {
+                self.level = level;
+                return Ok(Edge::now(level));
+            }
         }
     }
 }
 
-pub enum EventType {
-    RisingEdge,
-    FallingEdge,
-}
+/// Allow injecting edges on the simulated input lines to exercise the
+/// input handling without actual hardware
+pub fn setup_edge_injection(bb: &mut BrokerBuilder) {
+    let inject = bb.topic_wo::<InjectedEdge>("/v1/demo/gpio/inject", None);
+    let (mut src, _) = inject.subscribe_unbounded();
+
+    spawn(async move {
+        while let Some(edge) = src.next().await {
+            let inputs = INPUTS.lock().unwrap();
+            let mut found = false;
+
+            for (_, dst) in inputs.iter().filter(|(name, _)| name == &edge.line) {
+                found |= dst.try_send(edge.level).is_ok();
+            }
 
-#[allow(non_camel_case_types)]
-pub enum EventRequestFlags {
-    BOTH_EDGES,
+            if !found {
+                warn!("Can not inject edge on unknown input line {}", edge.line);
+            }
+        }
+    });
 }
 
 #[allow(clippy::upper_case_acronyms)]
 pub enum LineRequestFlags {
     OUTPUT,
-    INPUT,
 }
 
 pub struct FindDecoy {
@@ -115,25 +152,32 @@ impl FindDecoy {
     pub fn request(&self, _: LineRequestFlags, initial: u8, _: &str) -> Result<LineHandle> {
         let line_handle = LineHandle {
             name: self.name.clone(),
+            val: AtomicU8::new(initial),
         };
 
         line_handle.set_value(initial).unwrap();
 
         Ok(line_handle)
     }
+}
 
-    pub fn events(
-        &self,
-        _: LineRequestFlags,
-        _: EventRequestFlags,
-        _: &str,
-    ) -> Result<LineEventHandle, ()> {
-        Ok(LineEventHandle {})
-    }
+pub fn request_events(line: &FindDecoy, _: &str) -> Result<LineEventHandle> {
+    let (tx, rx) = unbounded();
+
+    INPUTS.lock().unwrap().push((line.name.clone(), tx));
+
+    Ok(LineEventHandle {
+        level: false,
+        injected: rx,
+    })
 }
 
-pub fn find_line(name: &str) -> Result<FindDecoy> {
-    Ok(FindDecoy {
+pub fn find_line(name: &str) -> Option<FindDecoy> {
+    Some(FindDecoy {
         name: name.to_string(),
     })
 }
+
+pub fn find_line_by_offset(chip: &str, offset: u32) -> Option<FindDecoy> {
+    find_line(&format!("{chip}:{offset}"))
+}
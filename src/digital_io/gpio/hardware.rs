@@ -17,9 +17,78 @@
 
 pub use gpio_cdev::*;
 
+use std::error::Error as _;
+
+use anyhow::Result;
+use async_io::Async;
+use async_trait::async_trait;
+
+use crate::digital_io::{Edge, LineEvents};
+
+/// The edges on an input line, delivered via the async executor instead
+/// of a blocking read in a thread of its own.
+pub struct AsyncLineEventHandle(Async<LineEventHandle>);
+
+#[async_trait]
+impl LineEvents for AsyncLineEventHandle {
+    fn level(&self) -> Result<bool> {
+        Ok(self.0.get_ref().get_value()? != 0)
+    }
+
+    async fn next_edge(&mut self) -> Result<Edge> {
+        // Another wakeup may have raced us to the event, in which case the
+        // read returns WouldBlock and read_with_mut waits for the next one.
+        let ev = self
+            .0
+            .read_with_mut(|handle| handle.get_event().map_err(into_io_error))
+            .await?;
+
+        let level = match ev.event_type() {
+            EventType::RisingEdge => true,
+            EventType::FallingEdge => false,
+        };
+
+        // The kernel uses CLOCK_MONOTONIC for the timestamps of events
+        // requested via the v1 character device ABI.
+        Ok(Edge {
+            level,
+            monotonic_ns: ev.timestamp(),
+        })
+    }
+}
+
+/// Keep the io::Error (and thus a WouldBlock) gpio_cdev wraps read errors in
+fn into_io_error(err: Error) -> std::io::Error {
+    match err
+        .source()
+        .and_then(|e| e.downcast_ref::<std::io::Error>())
+    {
+        Some(io) if io.kind() == std::io::ErrorKind::WouldBlock => io.kind().into(),
+        _ => std::io::Error::new(std::io::ErrorKind::Other, err),
+    }
+}
+
+/// Request the line as input and get notified about edges on it
+pub fn request_events(line: &Line, consumer: &str) -> Result<AsyncLineEventHandle> {
+    let handle = line.events(
+        LineRequestFlags::INPUT,
+        EventRequestFlags::BOTH_EDGES,
+        consumer,
+    )?;
+
+    Ok(AsyncLineEventHandle(Async::new(handle)?))
+}
+
 pub fn find_line(name: &str) -> Option<Line> {
     chips()
         .unwrap()
         .flat_map(|c| c.unwrap().lines())
         .find(|l| l.info().unwrap().name() == Some(name))
 }
+
+pub fn find_line_by_offset(chip: &str, offset: u32) -> Option<Line> {
+    Chip::new(format!("/dev/{chip}"))
+        .ok()?
+        .get_line(offset)
+        .ok()
+}
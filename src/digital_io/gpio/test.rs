@@ -15,14 +15,15 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
-use std::iter::Iterator;
 use std::sync::atomic::{AtomicU8, Ordering};
-use std::thread::sleep;
 use std::time::Duration;
 
 use anyhow::Result;
 use async_std::sync::{Arc, Mutex};
-use async_std::task::block_on;
+use async_std::task::{block_on, sleep};
+use async_trait::async_trait;
+
+use crate::digital_io::{Edge, LineEvents};
 
 static LINES: Mutex<Vec<(String, Arc<AtomicU8>)>> = Mutex::new(Vec::new());
 
@@ -32,6 +33,10 @@ pub struct LineHandle {
 }
 
 impl LineHandle {
+    pub fn get_value(&self) -> Result<u8, ()> {
+        Ok(self.val.load(Ordering::Relaxed))
+    }
+
     pub fn set_value(&self, val: u8) -> Result<(), ()> {
         println!("GPIO simulation set {} to {}", self.name, val);
         self.val.store(val, Ordering::Relaxed);
@@ -39,59 +44,34 @@ impl LineHandle {
     }
 }
 
-pub struct LineEvent(u8);
-
-impl LineEvent {
-    pub fn event_type(&self) -> EventType {
-        match self.0 {
-            0 => EventType::FallingEdge,
-            _ => EventType::RisingEdge,
-        }
-    }
-}
-
 pub struct LineEventHandle {
     val: Arc<AtomicU8>,
     prev_val: u8,
 }
 
-impl LineEventHandle {
-    pub fn get_value(&self) -> Result<u8, ()> {
-        Ok(self.val.load(Ordering::Relaxed))
+#[async_trait]
+impl LineEvents for LineEventHandle {
+    fn level(&self) -> Result<bool> {
+        Ok(self.val.load(Ordering::Relaxed) != 0)
     }
-}
-
-impl Iterator for LineEventHandle {
-    type Item = Result<LineEvent, ()>;
 
-    fn next(&mut self) -> Option<Self::Item> {
+    async fn next_edge(&mut self) -> Result<Edge> {
         loop {
             let val = self.val.load(Ordering::Relaxed);
 
             if val != self.prev_val {
                 self.prev_val = val;
-                return Some(Ok(LineEvent(val)));
+                return Ok(Edge::now(val != 0));
             }
 
-            sleep(Duration::from_millis(100));
+            sleep(Duration::from_millis(100)).await;
         }
     }
 }
 
-pub enum EventType {
-    RisingEdge,
-    FallingEdge,
-}
-
-#[allow(non_camel_case_types)]
-pub enum EventRequestFlags {
-    BOTH_EDGES,
-}
-
 #[allow(clippy::upper_case_acronyms)]
 pub enum LineRequestFlags {
     OUTPUT,
-    INPUT,
 }
 
 pub struct FindDecoy {
@@ -109,24 +89,12 @@ impl FindDecoy {
         })
     }
 
-    pub fn events(
-        &self,
-        _: LineRequestFlags,
-        _: EventRequestFlags,
-        _: &str,
-    ) -> Result<LineEventHandle> {
-        Ok(LineEventHandle {
-            val: self.val.clone(),
-            prev_val: self.val.load(Ordering::Relaxed),
-        })
-    }
-
     pub fn stub_get(&self) -> u8 {
         self.val.load(Ordering::Relaxed)
     }
 }
 
-pub fn find_line(name: &str) -> Result<FindDecoy> {
+pub fn find_line(name: &str) -> Option<FindDecoy> {
     let val = {
         let mut lines = block_on(LINES.lock());
 
@@ -139,8 +107,19 @@ pub fn find_line(name: &str) -> Result<FindDecoy> {
         }
     };
 
-    Ok(FindDecoy {
+    Some(FindDecoy {
         name: name.to_string(),
         val,
     })
 }
+
+pub fn request_events(line: &FindDecoy, _: &str) -> Result<LineEventHandle> {
+    Ok(LineEventHandle {
+        val: line.val.clone(),
+        prev_val: line.val.load(Ordering::Relaxed),
+    })
+}
+
+pub fn find_line_by_offset(chip: &str, offset: u32) -> Option<FindDecoy> {
+    find_line(&format!("{chip}:{offset}"))
+}
@@ -0,0 +1,124 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use async_std::sync::Arc;
+use serde::{Deserialize, Serialize};
+
+use super::LineHandle;
+use crate::broker::Topic;
+use crate::led::BlinkPattern;
+
+/// The PWM signal is generated by toggling the GPIO in software,
+/// which limits the usable frequency range.
+pub const MIN_FREQUENCY: f32 = 0.1;
+pub const MAX_FREQUENCY: f32 = 1000.0;
+
+/// How often to check if the output was switched to PWM mode
+const IDLE_POLL: Duration = Duration::from_millis(100);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum OutputMode {
+    /// The output follows the `asserted` topic
+    Level,
+    /// The output is toggled according to the `pwm/frequency` and
+    /// `pwm/duty` topics
+    Pwm,
+}
+
+/// Get the asserted and deasserted time of a PWM period
+fn timing(frequency: f32, duty: f32) -> (Duration, Duration) {
+    let period_us = (1_000_000.0 / frequency.clamp(MIN_FREQUENCY, MAX_FREQUENCY)).round();
+    let high_us = (period_us * duty.clamp(0.0, 1.0)).round();
+
+    (
+        Duration::from_micros(high_us as u64),
+        Duration::from_micros((period_us - high_us) as u64),
+    )
+}
+
+/// Generate a PWM signal on the line while the output is in PWM mode and
+/// restore the level from the `asserted` topic once it is not.
+///
+/// This function never returns and should be run in a thread of its own.
+pub(super) fn run(
+    line: Arc<LineHandle>,
+    asserted: Arc<Topic<bool>>,
+    mode: Arc<Topic<OutputMode>>,
+    frequency: Arc<Topic<f32>>,
+    duty: Arc<Topic<f32>>,
+    led: Arc<Topic<BlinkPattern>>,
+) {
+    // The brightness last shown on the LED while in PWM mode
+    let mut shown_duty: Option<f32> = None;
+
+    loop {
+        let is_pwm = mode.try_get() == Some(OutputMode::Pwm);
+
+        if !is_pwm {
+            if shown_duty.take().is_some() {
+                let level = asserted.try_get().unwrap_or(false);
+                line.set_value(level as _).unwrap();
+                led.set(BlinkPattern::solid(if level { 1.0 } else { 0.0 }));
+            }
+
+            sleep(IDLE_POLL);
+            continue;
+        }
+
+        let duty = duty.try_get().unwrap_or(0.5).clamp(0.0, 1.0);
+
+        // Show the average output level on the LED
+        if shown_duty != Some(duty) {
+            led.set(BlinkPattern::solid(duty));
+            shown_duty = Some(duty);
+        }
+
+        let (high, low) = timing(frequency.try_get().unwrap_or(100.0), duty);
+
+        if !high.is_zero() {
+            line.set_value(1).unwrap();
+            sleep(high);
+        }
+
+        if !low.is_zero() {
+            line.set_value(0).unwrap();
+            sleep(low);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::timing;
+
+    #[test]
+    fn pwm_timing() {
+        let ms = Duration::from_millis;
+
+        assert_eq!(timing(10.0, 0.25), (ms(25), ms(75)));
+        assert_eq!(timing(10.0, 0.0), (ms(0), ms(100)));
+        assert_eq!(timing(10.0, 2.0), (ms(100), ms(0)));
+
+        // The frequency is limited to what can be done in software
+        assert_eq!(timing(1_000_000.0, 1.0), (ms(1), ms(0)));
+    }
+}
@@ -0,0 +1,145 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::Duration;
+
+use async_std::sync::Arc;
+use async_std::task::{sleep, spawn};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{LineHandle, OutputMode};
+use crate::broker::{BrokerBuilder, Topic};
+
+/// Time between two reads of the output lines
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An output line whose actual state differs from the commanded one
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ReadbackMismatch {
+    pub output: String,
+    pub commanded: bool,
+    pub actual: bool,
+}
+
+struct Output {
+    name: String,
+    line: Arc<LineHandle>,
+    commanded: Arc<Topic<bool>>,
+    inverted: bool,
+    mode: Option<Arc<Topic<OutputMode>>>,
+    /// The line did not match the commanded state in the previous poll
+    suspect: bool,
+}
+
+/// Collects the output lines to read back
+#[derive(Default)]
+pub(super) struct Readback {
+    outputs: Vec<Output>,
+}
+
+impl Readback {
+    pub(super) fn add(
+        &mut self,
+        name: &str,
+        line: Arc<LineHandle>,
+        commanded: Arc<Topic<bool>>,
+        inverted: bool,
+        mode: Option<Arc<Topic<OutputMode>>>,
+    ) {
+        self.outputs.push(Output {
+            name: name.to_string(),
+            line,
+            commanded,
+            inverted,
+            mode,
+            suspect: false,
+        });
+    }
+
+    /// Periodically compare the state of the lines with the commanded one,
+    /// e.g. to find shorted output drivers.
+    pub(super) fn setup(self, bb: &mut BrokerBuilder) -> Arc<Topic<Vec<ReadbackMismatch>>> {
+        let mismatches = bb.topic_ro(
+            "/v1/tac/selfcheck/readback",
+            Some(Vec::<ReadbackMismatch>::new()),
+        );
+        let mismatches_task = mismatches.clone();
+        let mut outputs = self.outputs;
+
+        spawn(async move {
+            loop {
+                sleep(POLL_INTERVAL).await;
+
+                let mut found = Vec::new();
+
+                for output in outputs.iter_mut() {
+                    // An output in PWM mode toggles all the time
+                    let is_pwm = output
+                        .mode
+                        .as_ref()
+                        .map(|mode| mode.try_get() == Some(OutputMode::Pwm))
+                        .unwrap_or(false);
+
+                    let commanded = output.commanded.try_get();
+                    let actual = output
+                        .line
+                        .get_value()
+                        .ok()
+                        .map(|v| (v != 0) ^ output.inverted);
+
+                    let (commanded, actual) = match (is_pwm, commanded, actual) {
+                        (false, Some(commanded), Some(actual)) => (commanded, actual),
+                        _ => {
+                            output.suspect = false;
+                            continue;
+                        }
+                    };
+
+                    // The line is set asynchronously after the topic changes,
+                    // so only a mismatch in two polls in a row counts.
+                    if commanded != actual && output.suspect {
+                        found.push(ReadbackMismatch {
+                            output: output.name.clone(),
+                            commanded,
+                            actual,
+                        });
+                    }
+
+                    output.suspect = commanded != actual;
+                }
+
+                let prev = mismatches_task.try_get().unwrap_or_default();
+
+                for mismatch in found.iter() {
+                    if !prev.iter().any(|m| m.output == mismatch.output) {
+                        warn!(
+                            "Output line {} reads back as {} but should be {}",
+                            mismatch.output, mismatch.actual, mismatch.commanded
+                        );
+                    }
+                }
+
+                if found != prev {
+                    mismatches_task.set(found);
+                }
+            }
+        });
+
+        mismatches
+    }
+}
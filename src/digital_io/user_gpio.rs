@@ -0,0 +1,229 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::ErrorKind;
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::edges::setup_edge_counter;
+use super::expanders;
+use super::gpio::find_line_by_offset;
+use super::readback::Readback;
+use super::{find_line, handle_pulse, request_events, InputEvent, LineEvents, LineRequestFlags};
+use crate::broker::BrokerBuilder;
+
+#[cfg(feature = "demo_mode")]
+const CONFIG_PATH: &str = "demo_files/etc/tacd/gpios.json";
+
+#[cfg(not(feature = "demo_mode"))]
+const CONFIG_PATH: &str = "/etc/tacd/gpios.json";
+
+/// How to find the GPIO line
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum GpioLine {
+    /// A line identified by its name, as shown by `gpioinfo`
+    Name(String),
+    /// A line identified by its chip and offset,
+    /// e.g. `{"chip": "gpiochip5", "offset": 3}`
+    Offset { chip: String, offset: u32 },
+    /// A pin on an I2C GPIO expander declared in the expander config file,
+    /// e.g. `{"expander": "ext", "pin": 7}`
+    Expander { expander: String, pin: u32 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+/// A GPIO as declared in the GPIO config file
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct GpioConfig {
+    pub name: String,
+    pub line: GpioLine,
+    pub direction: Direction,
+    /// The state of an output before it is set via the API
+    #[serde(default)]
+    pub initial: bool,
+    #[serde(default)]
+    pub active_low: bool,
+}
+
+/// Request the line and expose it via a topic.
+/// Returns false if the line could not be set up.
+fn setup_gpio(
+    bb: &mut BrokerBuilder,
+    readback: &mut Readback,
+    expanders: &HashMap<String, String>,
+    config: &GpioConfig,
+) -> bool {
+    let line = match &config.line {
+        GpioLine::Name(name) => find_line(name),
+        GpioLine::Offset { chip, offset } => find_line_by_offset(chip, *offset),
+        GpioLine::Expander { expander, pin } => expanders
+            .get(expander)
+            .and_then(|chip| find_line_by_offset(chip, *pin)),
+    };
+
+    let line = match line {
+        Some(line) => line,
+        None => {
+            warn!(
+                "Could not find the line {:?} for GPIO \"{}\"",
+                config.line, config.name
+            );
+            return false;
+        }
+    };
+
+    let path = format!("/v1/gpio/{}", config.name);
+    let active_low = config.active_low;
+
+    match config.direction {
+        Direction::Output => {
+            let initial = config.initial;
+            let dst = match line.request(
+                LineRequestFlags::OUTPUT,
+                (initial ^ active_low) as _,
+                "tacd",
+            ) {
+                Ok(dst) => Arc::new(dst),
+                Err(_) => {
+                    warn!("Failed to request GPIO \"{}\" as output", config.name);
+                    return false;
+                }
+            };
+
+            let topic = bb.topic_rw(&path, Some(initial));
+
+            readback.add(&config.name, dst.clone(), topic.clone(), active_low, None);
+            let (mut src, _) = topic.clone().subscribe_unbounded();
+
+            handle_pulse(bb, &format!("{path}/pulse"), topic);
+            let name = config.name.clone();
+
+            spawn(async move {
+                while let Some(ev) = src.next().await {
+                    if dst.set_value((ev ^ active_low) as _).is_err() {
+                        warn!("Failed to set GPIO \"{name}\"");
+                    }
+                }
+            });
+        }
+        Direction::Input => {
+            let mut src = match request_events(&line, "tacd") {
+                Ok(src) => src,
+                Err(e) => {
+                    warn!("Failed to request GPIO \"{}\" as input: {e}", config.name);
+                    return false;
+                }
+            };
+
+            let topic = bb.topic_ro(&path, None);
+            let events = bb.topic_ro(&format!("{path}/events"), None);
+            let counter = setup_edge_counter(bb, &path);
+            let name = config.name.clone();
+
+            spawn(async move {
+                if let Ok(level) = src.level() {
+                    topic.set(level ^ active_low);
+                }
+
+                loop {
+                    let edge = match src.next_edge().await {
+                        Ok(edge) => edge,
+                        Err(e) => {
+                            warn!("Failed to read events of GPIO \"{name}\": {e}");
+                            break;
+                        }
+                    };
+
+                    let state = edge.level ^ active_low;
+
+                    events.set(InputEvent {
+                        asserted: state,
+                        monotonic_ns: edge.monotonic_ns,
+                        latency_ns: edge.age().as_nanos() as u64,
+                    });
+
+                    counter.lock().unwrap().edge(state, edge.instant());
+                    topic.set(state);
+                }
+            });
+        }
+    }
+
+    true
+}
+
+fn load_config() -> Vec<GpioConfig> {
+    let file = match File::open(CONFIG_PATH) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            info!("GPIO config at \"{CONFIG_PATH}\" does not exist. Not adding user GPIOs");
+            return Vec::new();
+        }
+        Err(e) => {
+            warn!("Failed to open GPIO config at \"{CONFIG_PATH}\": {e}");
+            return Vec::new();
+        }
+    };
+
+    serde_json::from_reader(file).unwrap_or_else(|e| {
+        warn!("Failed to parse GPIO config at \"{CONFIG_PATH}\": {e}");
+        Vec::new()
+    })
+}
+
+/// Set up the GPIOs declared in the GPIO config file
+pub(super) fn setup(bb: &mut BrokerBuilder, readback: &mut Readback) {
+    let expanders = expanders::setup(bb);
+
+    let mut configs = load_config();
+    let mut seen: Vec<String> = Vec::new();
+
+    // The names are used in topic paths, so they have to be unique and
+    // must not collide with the expander list.
+    configs.retain(|config| {
+        let valid = !config.name.is_empty()
+            && !config.name.contains('/')
+            && config.name != "expanders"
+            && !seen.contains(&config.name);
+
+        if !valid {
+            warn!(
+                "Ignoring GPIO with invalid or duplicate name \"{}\"",
+                config.name
+            );
+        }
+
+        seen.push(config.name.clone());
+
+        valid
+    });
+
+    configs.retain(|config| setup_gpio(bb, readback, &expanders, config));
+
+    bb.topic_ro("/v1/gpio", Some(configs));
+}
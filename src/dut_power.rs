@@ -15,7 +15,8 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
-use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use std::sync::Mutex;
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -24,12 +25,15 @@ use async_std::channel::bounded;
 use async_std::prelude::*;
 use async_std::sync::{Arc, Weak};
 use async_std::task;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
 use crate::adc::AdcChannel;
 use crate::broker::{BrokerBuilder, Topic};
 use crate::digital_io::{find_line, LineHandle, LineRequestFlags};
 use crate::led::{BlinkPattern, BlinkPatternBuilder};
+use crate::safe_state;
+use crate::subsystems::{report, SubsystemStatus};
 
 #[cfg(any(test, feature = "demo_mode"))]
 mod prio {
@@ -59,10 +63,19 @@ mod prio {
 
 use prio::realtime_priority;
 
+mod boot;
+mod energy;
+mod powercycle;
+mod soft_start;
+
+pub use soft_start::SoftStart;
+
 const MAX_AGE: Duration = Duration::from_millis(300);
 const THREAD_INTERVAL: Duration = Duration::from_millis(100);
 const TASK_INTERVAL: Duration = Duration::from_millis(200);
+const STALL_INTERVAL: Duration = Duration::from_secs(1);
 const MAX_CURRENT: f32 = 5.0;
+const MIN_CURRENT_LIMIT: f32 = 0.1;
 const MAX_VOLTAGE: f32 = 48.0;
 const MIN_VOLTAGE: f32 = -1.0;
 
@@ -149,6 +162,19 @@ impl From<u8> for OutputState {
     }
 }
 
+/// What to do if the current exceeds the user configurable limit.
+/// Exceeding what the hardware can handle always turns the output off.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum OverCurrentAction {
+    /// Turn the output off until it is turned on again
+    LatchOff,
+    /// Turn the output off and back on again after `delay_ms`.
+    /// Gives up after `attempts` tries until the output is turned on again.
+    Retry { attempts: u32, delay_ms: u64 },
+    /// Keep the output on and only raise a warning
+    WarnOnly,
+}
+
 pub struct TickReader {
     src: Weak<AtomicU32>,
     val: u32,
@@ -184,6 +210,9 @@ impl TickReader {
 pub struct DutPwrThread {
     pub request: Arc<Topic<OutputRequest>>,
     pub state: Arc<Topic<OutputState>>,
+    pub status: Arc<Topic<SubsystemStatus>>,
+    pub current_limit: Arc<Topic<f32>>,
+    pub current_limit_warning: Arc<Topic<bool>>,
     tick: Arc<AtomicU32>,
 }
 
@@ -285,18 +314,47 @@ impl DutPwrThread {
         pwr_volt: AdcChannel,
         pwr_curr: AdcChannel,
         pwr_led: Arc<Topic<BlinkPattern>>,
+        initially_on: bool,
     ) -> Result<Self> {
-        let pwr_line = find_line("DUT_PWR_EN").unwrap().request(
+        // The realtime thread takes ownership of the channels
+        let energy_volt = pwr_volt.clone();
+        let energy_curr = pwr_curr.clone();
+        let boot_curr = pwr_curr.clone();
+
+        // Always start with the output turned off. If it should be on the
+        // thread turns it on once it has valid measurements, so that the
+        // protection against over-voltage and over-current is in place.
+        let initial_request = match initially_on {
+            true => OutputRequest::On,
+            false => OutputRequest::Idle,
+        };
+
+        let pwr_line = Arc::new(find_line("DUT_PWR_EN").unwrap().request(
             LineRequestFlags::OUTPUT,
             1 - PWR_LINE_ASSERTED,
             "tacd",
-        )?;
+        )?);
 
-        let discharge_line = find_line("DUT_PWR_DISCH").unwrap().request(
+        let discharge_line = Arc::new(find_line("DUT_PWR_DISCH").unwrap().request(
             LineRequestFlags::OUTPUT,
             DISCHARGE_LINE_ASSERTED,
             "tacd",
-        )?;
+        )?);
+
+        // Bypass the power thread when entering the safe state, as it may
+        // be what stalled.
+        let pwr_line_safe = pwr_line.clone();
+        let discharge_line_safe = discharge_line.clone();
+
+        safe_state::register("dut_power", move |on| {
+            if on {
+                let _ = discharge_line_safe.set_value(1 - DISCHARGE_LINE_ASSERTED);
+                let _ = pwr_line_safe.set_value(PWR_LINE_ASSERTED);
+            } else {
+                let _ = pwr_line_safe.set_value(1 - PWR_LINE_ASSERTED);
+                let _ = discharge_line_safe.set_value(DISCHARGE_LINE_ASSERTED);
+            }
+        });
 
         // The realtime priority must be set up inside the tread, but
         // the operation may fail, in which case we want new() to fail
@@ -305,6 +363,23 @@ impl DutPwrThread {
         // succeeded.
         let (thread_res_tx, mut thread_res_rx) = bounded(1);
 
+        // The user configurable overcurrent threshold, as the bit pattern of
+        // an f32 so it can be read by the thread without locking.
+        let current_limit = Arc::new(AtomicU32::new(MAX_CURRENT.to_bits()));
+        let current_limit_thread = current_limit.clone();
+
+        // Whether exceeding the user configurable limit should only be
+        // reported instead of turning the output off, and if it is exceeded.
+        let warn_only = Arc::new(AtomicBool::new(false));
+        let warn_only_thread = warn_only.clone();
+        let over_limit = Arc::new(AtomicBool::new(false));
+        let over_limit_thread = over_limit.clone();
+
+        // How to turn the output on. Only read by the thread when handling
+        // an On request.
+        let soft_start_config = Arc::new(Mutex::new(SoftStart::Off));
+        let soft_start_config_thread = soft_start_config.clone();
+
         // Spawn a high priority thread that handles the power status
         // in a realtimey fashion.
         thread::Builder::new()
@@ -312,6 +387,9 @@ impl DutPwrThread {
             .spawn(move || {
                 let mut last_ts: Option<Instant> = None;
 
+                // The profile and start of a soft start that is in progress
+                let mut soft_start: Option<(SoftStart, Instant)> = None;
+
                 // There may be transients in the measured voltage/current, e.g. due to EMI or
                 // inrush currents.
                 // Nothing will break if they are sufficiently short, so the DUT can stay powered.
@@ -325,7 +403,7 @@ impl DutPwrThread {
                         let tick = Arc::new(AtomicU32::new(0));
                         let tick_weak = Arc::downgrade(&tick);
 
-                        let request = Arc::new(AtomicU8::new(OutputRequest::Idle as u8));
+                        let request = Arc::new(AtomicU8::new(initial_request as u8));
                         let state = Arc::new(AtomicU8::new(OutputState::Off as u8));
 
                         thread_res_tx
@@ -344,7 +422,20 @@ impl DutPwrThread {
                 // As tick is a private member of the struct this is equivalent
                 // to running as long as the DutPwrThread was not dropped.
                 while let Some(tick) = tick_weak.upgrade() {
-                    thread::sleep(THREAD_INTERVAL);
+                    // Spend the time until the next look at the measurements
+                    // ramping up the output, if it was just turned on.
+                    // Any change of the state (e.g. due to a fault) ends the
+                    // soft start.
+                    match soft_start.take() {
+                        Some((profile, start))
+                            if state.load(Ordering::Relaxed) == OutputState::On as u8 =>
+                        {
+                            if soft_start::step(&pwr_line, &profile, start, THREAD_INTERVAL) {
+                                soft_start = Some((profile, start));
+                            }
+                        }
+                        _ => thread::sleep(THREAD_INTERVAL),
+                    }
 
                     // Get new voltage and current readings while making sure
                     // that they are not stale
@@ -425,7 +516,16 @@ impl DutPwrThread {
 
                     // Don't even look at the requests if there is an ongoin
                     // overcurrent condition.
-                    if curr > MAX_CURRENT {
+                    // The user configurable limit may be set to only warn,
+                    // but the limit of the hardware is always enforced.
+                    let max_curr = f32::from_bits(current_limit_thread.load(Ordering::Relaxed));
+                    let is_over_limit = curr > max_curr;
+
+                    over_limit_thread.store(is_over_limit, Ordering::Relaxed);
+
+                    if curr > MAX_CURRENT
+                        || (is_over_limit && !warn_only_thread.load(Ordering::Relaxed))
+                    {
                         turn_off_with_reason(
                             OutputState::OverCurrent,
                             &pwr_line,
@@ -441,10 +541,19 @@ impl DutPwrThread {
                     match req {
                         OutputRequest::Idle => {}
                         OutputRequest::On => {
+                            let profile = *soft_start_config_thread.lock().unwrap();
+                            let was_on = state.load(Ordering::Relaxed) == OutputState::On as u8;
+
                             discharge_line
                                 .set_value(1 - DISCHARGE_LINE_ASSERTED)
                                 .unwrap();
-                            pwr_line.set_value(PWR_LINE_ASSERTED).unwrap();
+
+                            if profile == SoftStart::Off || was_on {
+                                pwr_line.set_value(PWR_LINE_ASSERTED).unwrap();
+                            } else {
+                                soft_start = Some((profile, Instant::now()));
+                            }
+
                             state.store(OutputState::On as u8, Ordering::Relaxed);
                         }
                         OutputRequest::Off => {
@@ -477,15 +586,32 @@ impl DutPwrThread {
         let state_topic = bb.topic_ro::<OutputState>("/v1/dut/powered", None);
 
         setup_labgrid_compat(bb, request_topic.clone(), state_topic.clone());
+        energy::setup_energy_counters(bb, energy_volt, energy_curr, state_topic.clone());
+        let powercycle =
+            powercycle::setup_powercycle(bb, request_topic.clone(), state_topic.clone());
+        boot::setup_boot_detection(
+            bb,
+            boot_curr,
+            state_topic.clone(),
+            request_topic.clone(),
+            powercycle,
+        );
+
+        // The number of times the output was turned back on after an
+        // overcurrent event since the last request from the user.
+        let retries = Arc::new(AtomicU32::new(0));
 
         // Requests come from the broker framework and are placed into an atomic
         // request variable read by the thread.
         let state_topic_task = state_topic.clone();
+        let request_task = request.clone();
+        let retries_task = retries.clone();
         let (mut request_stream, _) = request_topic.clone().subscribe_unbounded();
         task::spawn(async move {
             while let Some(req) = request_stream.next().await {
+                retries_task.store(0, Ordering::Relaxed);
                 state_topic_task.set(OutputState::Changing);
-                request.store(req as u8, Ordering::Relaxed);
+                request_task.store(req as u8, Ordering::Relaxed);
             }
         });
 
@@ -506,6 +632,152 @@ impl DutPwrThread {
             }
         });
 
+        // The power thread only makes progress as long as it is running and
+        // receives fresh ADC values. Let the rest of the tacd know if it
+        // does not.
+        let status_topic = bb.topic_ro("/v1/tac/subsystems/dut_power", None);
+        let status_topic_task = status_topic.clone();
+        let mut tick_reader = TickReader::new(&tick);
+        task::spawn(async move {
+            loop {
+                task::sleep(STALL_INTERVAL).await;
+
+                let status = match tick_reader.is_stale() {
+                    true => SubsystemStatus::failed("Power thread stalled"),
+                    false => SubsystemStatus::Ok,
+                };
+
+                report(&status_topic_task, status);
+            }
+        });
+
+        // The overcurrent threshold can be lowered (but never raised beyond
+        // what the hardware can handle) to protect sensitive DUTs.
+        let current_limit_topic = bb.topic(
+            "/v1/dut/current_limit",
+            true,
+            true,
+            true,
+            Some(MAX_CURRENT),
+            1,
+        );
+        let (mut current_limit_stream, _) = current_limit_topic.clone().subscribe_unbounded();
+        task::spawn(async move {
+            while let Some(limit) = current_limit_stream.next().await {
+                let limit = match limit.is_nan() {
+                    true => MAX_CURRENT,
+                    false => limit.clamp(MIN_CURRENT_LIMIT, MAX_CURRENT),
+                };
+
+                current_limit.store(limit.to_bits(), Ordering::Relaxed);
+            }
+        });
+
+        // What should happen if the limit set above is exceeded
+        let current_limit_action = bb.topic(
+            "/v1/dut/current_limit/action",
+            true,
+            true,
+            true,
+            Some(OverCurrentAction::LatchOff),
+            1,
+        );
+        let current_limit_warning = bb.topic_ro("/v1/dut/current_limit/warning", Some(false));
+
+        let (mut action_stream, _) = current_limit_action.clone().subscribe_unbounded();
+        task::spawn(async move {
+            while let Some(action) = action_stream.next().await {
+                warn_only.store(action == OverCurrentAction::WarnOnly, Ordering::Relaxed);
+            }
+        });
+
+        let current_limit_warning_task = current_limit_warning.clone();
+        task::spawn(async move {
+            loop {
+                task::sleep(TASK_INTERVAL).await;
+
+                let is_over_limit = over_limit.load(Ordering::Relaxed);
+
+                current_limit_warning_task.modify(|prev| match prev != Some(is_over_limit) {
+                    true => Some(is_over_limit),
+                    false => None,
+                });
+            }
+        });
+
+        // Turn the output back on after an overcurrent event if requested.
+        // This bypasses the request topic so that the retries are not reset.
+        let state_topic_task = state_topic.clone();
+        let (mut state_stream, _) = state_topic.clone().subscribe_unbounded();
+        task::spawn(async move {
+            while let Some(state) = state_stream.next().await {
+                if state != OutputState::OverCurrent {
+                    continue;
+                }
+
+                let (attempts, delay_ms) = match current_limit_action.try_get() {
+                    Some(OverCurrentAction::Retry { attempts, delay_ms }) => (attempts, delay_ms),
+                    _ => continue,
+                };
+
+                let attempt = retries.load(Ordering::Relaxed) + 1;
+
+                if attempt > attempts {
+                    warn!("Giving up on DUT power after {attempts} overcurrent retries");
+                    continue;
+                }
+
+                retries.store(attempt, Ordering::Relaxed);
+
+                info!("Turning DUT power back on after overcurrent ({attempt}/{attempts})");
+                task::sleep(Duration::from_millis(delay_ms)).await;
+
+                // The output may have been turned on or off in the meantime
+                if state_topic_task.try_get() == Some(OutputState::OverCurrent) {
+                    state_topic_task.set(OutputState::Changing);
+                    request.store(OutputRequest::On as u8, Ordering::Relaxed);
+                }
+            }
+        });
+
+        // Limit the inrush current when turning the output on
+        let soft_start = bb.topic(
+            "/v1/dut/soft_start",
+            true,
+            true,
+            true,
+            Some(SoftStart::Off),
+            1,
+        );
+        let soft_start_applied = bb.topic_ro("/v1/dut/soft_start/applied", Some(None));
+
+        let soft_start_config_task = soft_start_config.clone();
+        let (mut soft_start_stream, _) = soft_start.subscribe_unbounded();
+        task::spawn(async move {
+            while let Some(profile) = soft_start_stream.next().await {
+                let profile = match profile.validate() {
+                    Ok(()) => profile,
+                    Err(e) => {
+                        warn!("Invalid soft start profile {profile:?}: {e}. Not using soft start");
+                        SoftStart::Off
+                    }
+                };
+
+                *soft_start_config_task.lock().unwrap() = profile;
+            }
+        });
+
+        // Report the profile that was used the last time the output was turned on
+        let (mut state_stream, _) = state_topic.clone().subscribe_unbounded();
+        task::spawn(async move {
+            while let Some(state) = state_stream.next().await {
+                if state == OutputState::On {
+                    let profile = *soft_start_config.lock().unwrap();
+                    soft_start_applied.set(Some(profile));
+                }
+            }
+        });
+
         // Forward the state information to the DUT Power LED
         let (mut state_stream, _) = state_topic.clone().subscribe_unbounded();
         task::spawn(async move {
@@ -540,6 +812,9 @@ impl DutPwrThread {
         Ok(Self {
             request: request_topic,
             state: state_topic,
+            status: status_topic,
+            current_limit: current_limit_topic,
+            current_limit_warning,
             tick,
         })
     }
@@ -579,6 +854,7 @@ mod tests {
                 adc.pwr_volt.clone(),
                 adc.pwr_curr.clone(),
                 led.clone(),
+                false,
             ))
             .unwrap();
 
@@ -0,0 +1,267 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use async_std::sync::Arc;
+use async_std::task;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{OutputRequest, OutputState};
+use crate::adc::AdcChannel;
+use crate::broker::{BrokerBuilder, Topic};
+
+/// Time between two looks at the DUT current
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What the DUT is up to, judging by the current it draws
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum BootState {
+    Off,
+    Booting,
+    Booted,
+    Idle,
+    Hung,
+}
+
+/// What to do once a DUT is considered hung
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum HungAction {
+    PowerOff,
+    PowerCycle,
+}
+
+/// Thresholds for the boot detection. Their optimal values depend heavily
+/// on the DUT, so they are configurable via the API.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct BootDetectConfig {
+    /// The current may vary by this much (in A) in a settled DUT
+    pub settle_range: f32,
+    /// How long (in ms) the current has to stay within the settle range
+    /// for the DUT to be considered booted
+    pub settle_ms: u64,
+    /// Ignore settled currents for this long (in ms) after power on, e.g.
+    /// while the DUT waits in the bootloader
+    pub min_boot_ms: u64,
+    /// A booted DUT drawing less than this (in A) is considered idle
+    pub idle_current: f32,
+    /// A DUT that did not boot after this long (in ms) is considered hung
+    pub boot_timeout_ms: u64,
+    #[serde(default)]
+    pub hung_action: Option<HungAction>,
+}
+
+impl Default for BootDetectConfig {
+    fn default() -> Self {
+        Self {
+            settle_range: 0.02,
+            settle_ms: 5000,
+            min_boot_ms: 2000,
+            idle_current: 0.05,
+            boot_timeout_ms: 120_000,
+            hung_action: None,
+        }
+    }
+}
+
+/// Classify the boot progress of the DUT from its current profile.
+///
+/// A DUT is considered booted once its current settles after power on,
+/// as most devices draw an erratic current while loading and starting
+/// their software.
+#[derive(Default)]
+struct Detector {
+    powered_since: Option<Instant>,
+    window: VecDeque<(Instant, f32)>,
+    booted: bool,
+}
+
+impl Detector {
+    fn update(
+        &mut self,
+        config: &BootDetectConfig,
+        powered: bool,
+        ts: Instant,
+        current: f32,
+    ) -> BootState {
+        if !powered {
+            *self = Self::default();
+            return BootState::Off;
+        }
+
+        let since = *self.powered_since.get_or_insert(ts);
+        let settle = Duration::from_millis(config.settle_ms);
+
+        self.window.push_back((ts, current));
+
+        // Keep the newest sample from before the window, so that we know
+        // whether the samples cover all of it
+        while self.window.len() > 1 && self.window[1].0 + settle <= ts {
+            self.window.pop_front();
+        }
+
+        let (min, max, sum) = self.window.iter().fold(
+            (f32::INFINITY, f32::NEG_INFINITY, 0.0),
+            |(min, max, sum), (_, c)| (min.min(*c), max.max(*c), sum + c),
+        );
+
+        let covered = self.window[0].0 + settle <= ts;
+        let settled = covered && max - min <= config.settle_range;
+        let mean = sum / self.window.len() as f32;
+        let on_time = ts.saturating_duration_since(since);
+
+        if settled && on_time >= Duration::from_millis(config.min_boot_ms) {
+            self.booted = true;
+        }
+
+        if self.booted {
+            if settled && mean < config.idle_current {
+                BootState::Idle
+            } else {
+                BootState::Booted
+            }
+        } else if on_time > Duration::from_millis(config.boot_timeout_ms) {
+            BootState::Hung
+        } else {
+            BootState::Booting
+        }
+    }
+}
+
+/// Publish whether the DUT booted, is idle or hung based on the current it
+/// draws, so that e.g. CI pipelines can wait for it before the console is up.
+pub(super) fn setup_boot_detection(
+    bb: &mut BrokerBuilder,
+    pwr_curr: AdcChannel,
+    state: Arc<Topic<OutputState>>,
+    request: Arc<Topic<OutputRequest>>,
+    powercycle: Arc<Topic<bool>>,
+) {
+    let boot_state = bb.topic_ro("/v1/dut/boot/state", Some(BootState::Off));
+    let config = bb.topic(
+        "/v1/dut/boot/config",
+        true,
+        true,
+        true,
+        Some(BootDetectConfig::default()),
+        1,
+    );
+
+    task::spawn(async move {
+        let mut detector = Detector::default();
+
+        loop {
+            task::sleep(SAMPLE_INTERVAL).await;
+
+            let config = config.try_get().unwrap_or_default();
+            let powered = state.try_get() == Some(OutputState::On);
+            let meas = pwr_curr.fast.get();
+
+            let new = detector.update(&config, powered, meas.ts.as_instant(), meas.value);
+            let prev = boot_state.try_get();
+
+            if prev == Some(new) {
+                continue;
+            }
+
+            boot_state.set(new);
+
+            if new != BootState::Hung {
+                continue;
+            }
+
+            match config.hung_action {
+                Some(HungAction::PowerOff) => {
+                    warn!("DUT seems to be hung. Turning it off");
+                    request.set(OutputRequest::Off);
+                }
+                Some(HungAction::PowerCycle) => {
+                    warn!("DUT seems to be hung. Power cycling it");
+                    powercycle.set(true);
+                }
+                None => {}
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{BootDetectConfig, BootState, Detector};
+
+    #[test]
+    fn boot_states() {
+        let config = BootDetectConfig::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        let mut detector = Detector::default();
+
+        assert_eq!(detector.update(&config, false, at(0), 0.0), BootState::Off);
+
+        // An erratic current while booting
+        for ms in (0..10_000).step_by(50) {
+            let current = if ms % 500 < 250 { 0.3 } else { 0.5 };
+            let state = detector.update(&config, true, at(ms), current);
+            assert_eq!(state, BootState::Booting);
+        }
+
+        // The current settles, the DUT is booted once the settle time passed
+        assert_eq!(
+            detector.update(&config, true, at(10_000), 0.2),
+            BootState::Booting
+        );
+        assert_eq!(
+            detector.update(&config, true, at(15_000), 0.21),
+            BootState::Booted
+        );
+
+        // A DUT drawing little current in a steady state is idle
+        for ms in (15_050..21_000).step_by(50) {
+            detector.update(&config, true, at(ms), 0.01);
+        }
+        assert_eq!(
+            detector.update(&config, true, at(21_000), 0.01),
+            BootState::Idle
+        );
+
+        // Turning the DUT off resets the detection
+        assert_eq!(
+            detector.update(&config, false, at(22_000), 0.0),
+            BootState::Off
+        );
+
+        // A DUT that never settles is considered hung after the timeout
+        for ms in (0..=config.boot_timeout_ms).step_by(100) {
+            let current = if ms % 200 == 0 { 0.3 } else { 0.5 };
+            detector.update(&config, true, at(30_000 + ms), current);
+        }
+        assert_eq!(
+            detector.update(
+                &config,
+                true,
+                at(30_000 + config.boot_timeout_ms + 100),
+                0.3
+            ),
+            BootState::Hung
+        );
+    }
+}
@@ -0,0 +1,212 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_std::sync::Arc;
+use async_std::task;
+use serde::{Deserialize, Serialize};
+
+use super::OutputState;
+use crate::adc::AdcChannel;
+use crate::broker::{BrokerBuilder, Topic};
+use crate::safe_state;
+
+/// Time between two looks at the DUT voltage and current
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Time between two updates of the energy topic
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time between two writes of the counters to the persistent state file.
+/// Writing on every update would wear out the flash.
+/// The counters are saved on exit as well.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Samples further apart than this are not integrated, as we do not know
+/// what happened in between (e.g. because the ADC thread stalled).
+const MAX_GAP: Duration = Duration::from_secs(1);
+
+/// The energy and charge delivered to the DUT
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct Energy {
+    pub watt_hours: f64,
+    pub amp_hours: f64,
+}
+
+impl Energy {
+    fn add(&self, other: &Self) -> Self {
+        Self {
+            watt_hours: self.watt_hours + other.watt_hours,
+            amp_hours: self.amp_hours + other.amp_hours,
+        }
+    }
+}
+
+/// Integrates voltage and current samples using the trapezoidal rule
+#[derive(Default)]
+struct Integrator {
+    prev: Option<(Instant, f32, f32)>,
+    total: Energy,
+}
+
+impl Integrator {
+    fn add(&mut self, ts: Instant, volt: f32, curr: f32) {
+        if let Some((prev_ts, prev_volt, prev_curr)) = self.prev {
+            let dt = ts.saturating_duration_since(prev_ts);
+
+            if dt <= MAX_GAP {
+                let hours = dt.as_secs_f64() / 3600.0;
+                let power = (prev_volt * prev_curr + volt * curr) as f64 / 2.0;
+                let curr = (prev_curr + curr) as f64 / 2.0;
+
+                self.total.watt_hours += power * hours;
+                self.total.amp_hours += curr * hours;
+            }
+        }
+
+        self.prev = Some((ts, volt, curr));
+    }
+
+    /// Stop integrating until the next sample, e.g. because the output was
+    /// turned off.
+    fn pause(&mut self) {
+        self.prev = None;
+    }
+
+    /// Get the energy accumulated since the last call
+    fn take(&mut self) -> Energy {
+        std::mem::take(&mut self.total)
+    }
+}
+
+/// Accumulate the energy and charge delivered to the DUT while it is powered,
+/// e.g. for endurance tests of battery powered devices.
+pub(super) fn setup_energy_counters(
+    bb: &mut BrokerBuilder,
+    pwr_volt: AdcChannel,
+    pwr_curr: AdcChannel,
+    state: Arc<Topic<OutputState>>,
+) {
+    let energy = bb.topic_ro("/v1/dut/energy", Some(Energy::default()));
+    let reset = bb.topic_wo::<bool>("/v1/dut/energy/reset", None);
+
+    // The counters as of the last time they were saved, so that they
+    // survive a restart of the tacd.
+    let saved = bb.topic(
+        "/v1/dut/energy/saved",
+        false,
+        false,
+        true,
+        Some(Energy::default()),
+        1,
+    );
+
+    let (reset_events, _) = reset.subscribe_unbounded();
+
+    // The energy counted since the counters were last saved
+    let unsaved = Arc::new(Mutex::new(Energy::default()));
+
+    // Do not lose the unsaved energy when the tacd is stopped
+    let unsaved_exit = unsaved.clone();
+    let saved_exit = saved.clone();
+    safe_state::on_exit(move || {
+        let unsaved = std::mem::take(&mut *unsaved_exit.lock().unwrap());
+        saved_exit.modify(|prev| Some(prev.unwrap_or_default().add(&unsaved)));
+    });
+
+    let unsaved_task = unsaved.clone();
+    task::spawn(async move {
+        let mut integrator = Integrator::default();
+        let mut last_publish = Instant::now();
+        let mut last_save = Instant::now();
+
+        loop {
+            task::sleep(SAMPLE_INTERVAL).await;
+
+            let mut reset_requested = false;
+
+            while let Ok(ev) = reset_events.try_recv() {
+                reset_requested |= ev;
+            }
+
+            let mut unsaved = unsaved_task.lock().unwrap();
+
+            if reset_requested {
+                integrator.take();
+                *unsaved = Energy::default();
+                saved.set(Energy::default());
+                energy.set(Energy::default());
+            }
+
+            if state.try_get() == Some(OutputState::On) {
+                if let Some([volt, curr]) = pwr_volt
+                    .fast
+                    .try_get_multiple([&pwr_volt.fast, &pwr_curr.fast])
+                {
+                    integrator.add(volt.ts.as_instant(), volt.value, curr.value);
+                }
+            } else {
+                integrator.pause();
+            }
+
+            *unsaved = unsaved.add(&integrator.take());
+
+            if last_save.elapsed() >= SAVE_INTERVAL {
+                saved.modify(|prev| Some(prev.unwrap_or_default().add(&unsaved)));
+                *unsaved = Energy::default();
+                last_save = Instant::now();
+            }
+
+            if last_publish.elapsed() >= PUBLISH_INTERVAL {
+                let total = saved.try_get().unwrap_or_default().add(&unsaved);
+                energy.set(total);
+                last_publish = Instant::now();
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Integrator;
+
+    #[test]
+    fn integrate() {
+        let start = Instant::now();
+        let mut integrator = Integrator::default();
+
+        // Half an hour at 12V and 2A, sampled every second
+        for secs in 0..=1800 {
+            integrator.add(start + Duration::from_secs(secs), 12.0, 2.0);
+        }
+
+        let energy = integrator.take();
+        assert!((energy.watt_hours - 12.0).abs() < 1e-6);
+        assert!((energy.amp_hours - 1.0).abs() < 1e-6);
+
+        // Gaps in the samples and paused intervals are not accounted for
+        integrator.add(start + Duration::from_secs(1900), 12.0, 2.0);
+        integrator.pause();
+        integrator.add(start + Duration::from_secs(1901), 12.0, 2.0);
+
+        assert_eq!(integrator.take().watt_hours, 0.0);
+    }
+}
@@ -0,0 +1,121 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::{Duration, Instant};
+
+use async_std::channel::Receiver;
+use async_std::future::timeout;
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task;
+use log::{info, warn};
+
+use super::{OutputRequest, OutputState};
+use crate::broker::{BrokerBuilder, Topic};
+
+/// Time between two looks at the output state while waiting for it to turn off
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The power thread should take a few hundred milliseconds at most to
+/// turn the output off.
+const SWITCH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Turn the output off, wait for `off_time` and turn it back on.
+/// A request from anyone else in the meantime cancels the power cycle.
+async fn power_cycle(
+    request: &Topic<OutputRequest>,
+    request_events: &mut Receiver<OutputRequest>,
+    state: &Topic<OutputState>,
+    off_time: Duration,
+) {
+    // Forget about requests from before the power cycle and about our own
+    while request_events.try_recv().is_ok() {}
+    request.set(OutputRequest::Off);
+    let _ = request_events.next().await;
+
+    // The hold-off time starts once the output is actually off and the
+    // discharge resistor is connected.
+    let start = Instant::now();
+
+    loop {
+        task::sleep(POLL_INTERVAL).await;
+
+        match state.try_get() {
+            Some(OutputState::Off) => break,
+            Some(OutputState::Changing) | None if start.elapsed() < SWITCH_TIMEOUT => {}
+            other => {
+                warn!("Aborting power cycle. The output is {other:?} instead of Off");
+                return;
+            }
+        }
+    }
+
+    if request_events.try_recv().is_ok() {
+        info!("Power cycle cancelled by a new request");
+        return;
+    }
+
+    match timeout(off_time, request_events.next()).await {
+        Err(_) => request.set(OutputRequest::On),
+        Ok(_) => info!("Power cycle cancelled by a new request"),
+    }
+}
+
+/// Allow power cycling the DUT with a single request, instead of relying on
+/// clients to send an off and an on request with a sufficient delay in between.
+/// Returns the topic used to trigger a power cycle.
+pub(super) fn setup_powercycle(
+    bb: &mut BrokerBuilder,
+    request: Arc<Topic<OutputRequest>>,
+    state: Arc<Topic<OutputState>>,
+) -> Arc<Topic<bool>> {
+    // Like for /v1/dut/powered the request and the state of the power cycle
+    // share the same external path.
+    let trigger = bb.topic_wo::<bool>("/v1/dut/powercycle", None);
+    let active = bb.topic_ro("/v1/dut/powercycle", Some(false));
+    let off_time_ms = bb.topic(
+        "/v1/dut/powercycle/off_time_ms",
+        true,
+        true,
+        true,
+        Some(2000u64),
+        1,
+    );
+
+    let (mut trigger_events, _) = trigger.clone().subscribe_unbounded();
+    let (mut request_events, _) = request.clone().subscribe_unbounded();
+
+    task::spawn(async move {
+        while let Some(start) = trigger_events.next().await {
+            if !start {
+                continue;
+            }
+
+            let off_time = Duration::from_millis(off_time_ms.try_get().unwrap_or(2000));
+
+            active.set(true);
+            power_cycle(&request, &mut request_events, &state, off_time).await;
+            active.set(false);
+
+            // Power cycle requests that came in while one was running are
+            // already satisfied.
+            while trigger_events.try_recv().is_ok() {}
+        }
+    });
+
+    trigger
+}
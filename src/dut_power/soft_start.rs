@@ -0,0 +1,222 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::PWR_LINE_ASSERTED;
+use crate::digital_io::LineHandle;
+
+/// The time between two updates of the output during a soft start
+const RESOLUTION: Duration = Duration::from_millis(1);
+
+/// The longest a soft start may take. The DUT power is not really on or off
+/// in the meantime.
+const MAX_DURATION_MS: u64 = 5000;
+
+/// How to turn on the DUT power to limit the inrush current, e.g. for DUTs
+/// with large input capacitors that would otherwise brown out the TAC.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum SoftStart {
+    /// Switch the output on at once
+    Off,
+    /// Switch the output with a PWM signal whose duty cycle rises linearly
+    /// from 0% to 100% over `duration_ms`
+    Ramp { duration_ms: u32, period_ms: u32 },
+    /// Switch the output on for `on_ms` and off for `off_ms`, `pulses` times,
+    /// before turning it on for good
+    Staged {
+        pulses: u32,
+        on_ms: u32,
+        off_ms: u32,
+    },
+}
+
+impl SoftStart {
+    pub fn validate(&self) -> Result<(), String> {
+        let duration_ms = match *self {
+            Self::Off => 0,
+            Self::Ramp {
+                duration_ms,
+                period_ms,
+            } => {
+                if period_ms < 2 {
+                    return Err("The PWM period has to be at least 2ms".to_string());
+                }
+
+                duration_ms as u64
+            }
+            Self::Staged {
+                pulses,
+                on_ms,
+                off_ms,
+            } => {
+                if on_ms == 0 || off_ms == 0 {
+                    return Err("The on and off times must not be zero".to_string());
+                }
+
+                pulses as u64 * (on_ms as u64 + off_ms as u64)
+            }
+        };
+
+        if duration_ms > MAX_DURATION_MS {
+            return Err(format!(
+                "The soft start must not take longer than {MAX_DURATION_MS}ms"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The state of the output `elapsed` after the start or None if the
+    /// soft start is done.
+    fn level(&self, elapsed: Duration) -> Option<bool> {
+        let t = elapsed.as_millis() as u64;
+
+        match *self {
+            Self::Off => None,
+            Self::Ramp {
+                duration_ms,
+                period_ms,
+            } => {
+                let (duration, period) = (duration_ms as u64, period_ms as u64);
+
+                if t >= duration {
+                    return None;
+                }
+
+                // On for the first t/duration of every period
+                Some((t % period) * duration < t * period)
+            }
+            Self::Staged {
+                pulses,
+                on_ms,
+                off_ms,
+            } => {
+                let cycle = on_ms as u64 + off_ms as u64;
+
+                if t >= cycle * pulses as u64 {
+                    return None;
+                }
+
+                Some(t % cycle < on_ms as u64)
+            }
+        }
+    }
+}
+
+/// Switch the output according to the soft start `profile` for `interval`.
+/// Returns false once the soft start is done and the output is on for good.
+pub(super) fn step(
+    pwr_line: &LineHandle,
+    profile: &SoftStart,
+    start: Instant,
+    interval: Duration,
+) -> bool {
+    let end = Instant::now() + interval;
+    let mut prev = None;
+
+    while Instant::now() < end {
+        let level = match profile.level(start.elapsed()) {
+            Some(level) => level,
+            None => {
+                pwr_line.set_value(PWR_LINE_ASSERTED).unwrap();
+                thread::sleep(end.saturating_duration_since(Instant::now()));
+                return false;
+            }
+        };
+
+        if prev != Some(level) {
+            let val = match level {
+                true => PWR_LINE_ASSERTED,
+                false => 1 - PWR_LINE_ASSERTED,
+            };
+
+            pwr_line.set_value(val).unwrap();
+            prev = Some(level);
+        }
+
+        thread::sleep(RESOLUTION);
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SoftStart;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn profiles() {
+        assert_eq!(SoftStart::Off.level(ms(0)), None);
+
+        let ramp = SoftStart::Ramp {
+            duration_ms: 1000,
+            period_ms: 10,
+        };
+
+        assert!(ramp.validate().is_ok());
+        assert_eq!(ramp.level(ms(0)), Some(false));
+        assert_eq!(ramp.level(ms(100)), Some(true));
+        assert_eq!(ramp.level(ms(101)), Some(true));
+        assert_eq!(ramp.level(ms(102)), Some(false));
+        assert_eq!(ramp.level(ms(505)), Some(true));
+        assert_eq!(ramp.level(ms(506)), Some(false));
+        assert_eq!(ramp.level(ms(1000)), None);
+
+        let staged = SoftStart::Staged {
+            pulses: 3,
+            on_ms: 5,
+            off_ms: 20,
+        };
+
+        assert!(staged.validate().is_ok());
+        assert_eq!(staged.level(ms(0)), Some(true));
+        assert_eq!(staged.level(ms(5)), Some(false));
+        assert_eq!(staged.level(ms(25)), Some(true));
+        assert_eq!(staged.level(ms(74)), Some(false));
+        assert_eq!(staged.level(ms(75)), None);
+
+        assert!(SoftStart::Ramp {
+            duration_ms: 10_000,
+            period_ms: 10
+        }
+        .validate()
+        .is_err());
+        assert!(SoftStart::Ramp {
+            duration_ms: 100,
+            period_ms: 1
+        }
+        .validate()
+        .is_err());
+        assert!(SoftStart::Staged {
+            pulses: 3,
+            on_ms: 0,
+            off_ms: 20
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -24,6 +24,10 @@ use std::path::Path;
 use log::warn;
 use tide::{Body, Response, Server};
 
+mod websocket;
+
+pub use websocket::upgrade_websocket;
+
 #[cfg(feature = "demo_mode")]
 mod consts {
     pub const WEBUI_DIR: &str = "web/build";
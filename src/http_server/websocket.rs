@@ -0,0 +1,105 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::future::Future;
+
+use async_std::task::spawn;
+use async_tungstenite::tungstenite::protocol::Role;
+use async_tungstenite::WebSocketStream;
+use base64::Engine;
+use sha1::{digest::Update, Digest, Sha1};
+use tide::http::format_err;
+use tide::http::headers::{HeaderName, CONNECTION, UPGRADE};
+use tide::http::upgrade::Connection;
+use tide::{Request, Response, StatusCode};
+
+/// This is used in the WebSocket handshake
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+fn header_contains_ignore_case(req: &Request<()>, header_name: HeaderName, value: &str) -> bool {
+    req.header(header_name)
+        .map(|h| {
+            h.as_str()
+                .split(',')
+                .any(|s| s.trim().eq_ignore_ascii_case(value.trim()))
+        })
+        .unwrap_or(false)
+}
+
+/// Answer a request to upgrade a HTTP connection to a websocket and
+/// run `handler` on the websocket once the upgrade is complete.
+///
+/// If the client requests one or more subprotocols the first one that
+/// is also contained in `protocols` is selected.
+pub async fn upgrade_websocket<F, Fut>(
+    req: &Request<()>,
+    protocols: &[&str],
+    handler: F,
+) -> tide::Result
+where
+    F: FnOnce(WebSocketStream<Connection>) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    // These are the good parts from tide-websockets without the bad
+    // WebSocketConnection wrapper.
+
+    let connection_upgrade = header_contains_ignore_case(req, CONNECTION, "upgrade");
+    let upgrade_to_websocket = header_contains_ignore_case(req, UPGRADE, "websocket");
+    let upgrade_requested = connection_upgrade && upgrade_to_websocket;
+
+    if !upgrade_requested {
+        return Ok(Response::new(StatusCode::UpgradeRequired));
+    }
+
+    let header = match req.header("Sec-Websocket-Key") {
+        Some(h) => h.as_str(),
+        None => return Err(format_err!("expected sec-websocket-key")),
+    };
+
+    let protocol = req.header("Sec-Websocket-Protocol").and_then(|value| {
+        value
+            .as_str()
+            .split(',')
+            .map(str::trim)
+            .find(|req_p| protocols.contains(req_p))
+    });
+
+    let mut response = Response::new(StatusCode::SwitchingProtocols);
+
+    response.insert_header(UPGRADE, "websocket");
+    response.insert_header(CONNECTION, "Upgrade");
+    let hash = Sha1::new().chain(header).chain(WEBSOCKET_GUID).finalize();
+    let hash = base64::engine::general_purpose::STANDARD.encode(&hash[..]);
+    response.insert_header("Sec-Websocket-Accept", hash);
+    response.insert_header("Sec-Websocket-Version", "13");
+
+    if let Some(protocol) = protocol {
+        response.insert_header("Sec-Websocket-Protocol", protocol);
+    }
+
+    let http_res: &mut tide::http::Response = response.as_mut();
+    let upgrade_receiver = http_res.recv_upgrade().await;
+
+    spawn(async move {
+        if let Some(stream) = upgrade_receiver.await {
+            let ws = WebSocketStream::from_raw_socket(stream, Role::Server, None).await;
+            handler(ws).await;
+        }
+    });
+
+    Ok(response)
+}
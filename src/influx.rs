@@ -0,0 +1,351 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::{sleep, spawn};
+use futures::stream::{select_all, BoxStream};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::adc::Adc;
+use crate::broker::{BrokerBuilder, Topic};
+use crate::measurement::{Measurement, Validity};
+
+/// Lines that are kept in memory while the server can not be reached.
+/// Once this limit is reached the oldest lines are dropped.
+const MAX_BUFFERED: usize = 100_000;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Name of the measurement the values are stored as in the database
+const MEASUREMENT: &str = "tacd";
+
+fn default_batch_size() -> usize {
+    1000
+}
+
+fn default_interval_ms() -> u64 {
+    1000
+}
+
+/// Where and what to push to an InfluxDB compatible server
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct InfluxConfig {
+    /// The full write URL, e.g. `http://influx:8086/write?db=tac` or
+    /// `http://influx:8086/api/v2/write?org=lab&bucket=tac`.
+    /// An empty URL disables the exporter.
+    #[serde(default)]
+    pub url: String,
+    /// The ADC channels and external sensors to export
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// Additional tags to add to every line, e.g. the name of the TAC
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            channels: Vec::new(),
+            tags: BTreeMap::new(),
+            batch_size: default_batch_size(),
+            interval_ms: default_interval_ms(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct InfluxStatus {
+    /// Lines waiting to be sent
+    pub buffered: usize,
+    pub sent: u64,
+    /// Lines that were dropped because the buffer was full or the server
+    /// rejected them
+    pub dropped: u64,
+    pub last_error: Option<String>,
+}
+
+/// Escape commas, spaces and equal signs in tag keys and values
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        if matches!(c, ',' | ' ' | '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Format a measurement in the InfluxDB line protocol with a nanosecond
+/// timestamp.
+/// The line protocol has no representation for NaN and infinity, so there
+/// is no line for them.
+fn line(tags: &BTreeMap<String, String>, channel: &str, value: f32, ns: u128) -> Option<String> {
+    if !value.is_finite() {
+        return None;
+    }
+
+    let mut line = format!("{MEASUREMENT},channel={}", escape(channel));
+
+    for (key, val) in tags {
+        line.push_str(&format!(",{}={}", escape(key), escape(val)));
+    }
+
+    line.push_str(&format!(" value={value} {ns}"));
+    Some(line)
+}
+
+struct Buffer {
+    lines: VecDeque<String>,
+    status: InfluxStatus,
+}
+
+enum SendError {
+    /// The server did not accept the batch. Sending it again will not help.
+    Rejected(String),
+    /// The server could not be reached or had a temporary problem
+    Failed(String),
+}
+
+async fn send(config: &InfluxConfig, token: Option<&str>, body: String) -> Result<(), SendError> {
+    // surf is built without TLS support
+    if config.url.starts_with("https://") {
+        return Err(SendError::Failed(
+            "https URLs are not supported. Use http instead".to_string(),
+        ));
+    }
+
+    let mut req = surf::post(&config.url)
+        .body_string(body)
+        .content_type("text/plain");
+
+    if let Some(token) = token {
+        req = req.header("Authorization", format!("Token {token}"));
+    }
+
+    let res = req.await.map_err(|e| SendError::Failed(e.to_string()))?;
+    let status = res.status();
+
+    if status.is_success() {
+        Ok(())
+    } else if status.is_client_error() {
+        Err(SendError::Rejected(format!(
+            "Server rejected the batch with {status}"
+        )))
+    } else {
+        Err(SendError::Failed(format!("Server responded with {status}")))
+    }
+}
+
+/// Push the lines in the buffer to the server in batches and retry with
+/// an increasing delay if that fails
+async fn push(
+    config: Arc<Topic<InfluxConfig>>,
+    token: Arc<Topic<Option<String>>>,
+    buffer: Arc<Mutex<Buffer>>,
+    status: Arc<Topic<InfluxStatus>>,
+) {
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        let config = config.try_get().unwrap_or_default();
+
+        let batch: Vec<String> = {
+            let buffer = buffer.lock().unwrap();
+            let len = buffer.lines.len().min(config.batch_size.max(1));
+            buffer.lines.iter().take(len).cloned().collect()
+        };
+
+        if config.url.is_empty() || batch.is_empty() {
+            sleep(Duration::from_millis(config.interval_ms)).await;
+            continue;
+        }
+
+        let token = token.try_get().flatten();
+        let res = send(&config, token.as_deref(), batch.join("\n")).await;
+
+        let (full, current) = {
+            let mut buffer = buffer.lock().unwrap();
+
+            // Lines may have been dropped from the front while sending,
+            // so do not remove more than are left.
+            let done = batch.len().min(buffer.lines.len());
+
+            match &res {
+                Ok(()) => {
+                    buffer.lines.drain(..done);
+                    buffer.status.sent += done as u64;
+                    buffer.status.last_error = None;
+                }
+                Err(SendError::Rejected(e)) => {
+                    // Retrying would block everything behind this batch
+                    buffer.lines.drain(..done);
+                    buffer.status.dropped += done as u64;
+                    buffer.status.last_error = Some(e.clone());
+                }
+                Err(SendError::Failed(e)) => buffer.status.last_error = Some(e.clone()),
+            }
+
+            buffer.status.buffered = buffer.lines.len();
+
+            (
+                buffer.lines.len() >= config.batch_size,
+                buffer.status.clone(),
+            )
+        };
+
+        status.set(current);
+
+        match res {
+            Ok(()) => {
+                backoff = MIN_BACKOFF;
+
+                // Catch up without waiting if there is a backlog
+                if !full {
+                    sleep(Duration::from_millis(config.interval_ms)).await;
+                }
+            }
+            Err(SendError::Rejected(e)) => {
+                warn!("Dropping measurements rejected by {}: {e}", config.url);
+                backoff = MIN_BACKOFF;
+            }
+            Err(SendError::Failed(e)) => {
+                warn!("Failed to push measurements to {}: {e}", config.url);
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Push the values of selected ADC channels and sensors to an InfluxDB or
+/// VictoriaMetrics server using the line protocol.
+pub fn setup(bb: &mut BrokerBuilder, adc: &Adc) {
+    let config: Arc<Topic<InfluxConfig>> = bb.topic(
+        "/v1/tac/influx/config",
+        true,
+        true,
+        true,
+        Some(InfluxConfig::default()),
+        1,
+    );
+    let status = bb.topic_ro("/v1/tac/influx/status", Some(InfluxStatus::default()));
+
+    // Sent as `Authorization: Token <token>` header if set.
+    // Kept separate from the config so that it can not be read back.
+    let token = bb.topic("/v1/tac/influx/token", false, true, true, Some(None), 1);
+
+    let mut streams: Vec<BoxStream<'static, (String, Measurement)>> = Vec::new();
+
+    for (name, channel) in adc.channels() {
+        let (events, _) = channel.topic.clone().subscribe_unbounded();
+        streams.push(Box::pin(events.map(move |meas| (name.to_string(), meas))));
+    }
+
+    for sensor in adc.sensors.iter() {
+        let name = sensor.name.clone();
+        let (events, _) = sensor.topic.clone().subscribe_unbounded();
+        streams.push(Box::pin(events.map(move |meas| (name.clone(), meas))));
+    }
+
+    let buffer = Arc::new(Mutex::new(Buffer {
+        lines: VecDeque::new(),
+        status: InfluxStatus::default(),
+    }));
+
+    spawn(push(config.clone(), token, buffer.clone(), status));
+
+    spawn(async move {
+        let mut events = select_all(streams);
+
+        while let Some((name, meas)) = events.next().await {
+            if meas.validity == Validity::ReadError {
+                continue;
+            }
+
+            let config = match config.try_get() {
+                Some(config) if !config.url.is_empty() && config.channels.contains(&name) => config,
+                _ => continue,
+            };
+
+            let ns = match meas
+                .ts
+                .in_system_time()
+                .duration_since(SystemTime::UNIX_EPOCH)
+            {
+                Ok(ts) => ts.as_nanos(),
+                Err(_) => continue,
+            };
+
+            let line = match line(&config.tags, &name, meas.value, ns) {
+                Some(line) => line,
+                None => continue,
+            };
+
+            let mut buffer = buffer.lock().unwrap();
+
+            buffer.lines.push_back(line);
+
+            if buffer.lines.len() > MAX_BUFFERED {
+                buffer.lines.pop_front();
+                buffer.status.dropped += 1;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::line;
+
+    #[test]
+    fn line_protocol() {
+        let mut tags = BTreeMap::new();
+
+        assert_eq!(
+            line(&tags, "pwr-volt", 12.5, 1_000_000_000).unwrap(),
+            "tacd,channel=pwr-volt value=12.5 1000000000"
+        );
+
+        assert_eq!(line(&tags, "pwr-volt", f32::NAN, 5), None);
+        assert_eq!(line(&tags, "pwr-volt", f32::INFINITY, 5), None);
+
+        tags.insert("host".to_string(), "lab tac,1".to_string());
+
+        assert_eq!(
+            line(&tags, "pwr-curr", 0.25, 5).unwrap(),
+            "tacd,channel=pwr-curr,host=lab\\ tac\\,1 value=0.25 5"
+        );
+    }
+}
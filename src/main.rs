@@ -18,36 +18,55 @@
 use futures::{select, FutureExt};
 
 mod adc;
+mod alarms;
+mod backlight;
+mod bridge;
 mod broker;
+mod capture;
 mod dbus;
+mod dhcp;
 mod digital_io;
 mod dut_power;
 mod http_server;
+mod influx;
 mod iobus;
 mod journal;
 mod led;
 mod measurement;
+mod power_restore;
 mod regulators;
+mod safe_state;
+mod scheduler;
+mod scope;
+mod selfcheck;
+mod sequences;
 mod setup_mode;
+mod subsystems;
 mod system;
 mod temperatures;
+mod tsdb;
 mod ui;
 mod usb_hub;
 mod watchdog;
 
 use adc::Adc;
+use alarms::Alarms;
+use backlight::Backlight;
 use broker::BrokerBuilder;
 use dbus::DbusSession;
+use dhcp::Dhcp;
 use digital_io::DigitalIo;
 use dut_power::DutPwrThread;
 use http_server::HttpServer;
 use iobus::IoBus;
 use led::Led;
 use regulators::Regulators;
+use scheduler::Scheduler;
+use selfcheck::SelfCheck;
 use setup_mode::SetupMode;
 use system::System;
 use temperatures::Temperatures;
-use ui::{Ui, UiResources};
+use ui::{Splash, Ui, UiResources};
 use usb_hub::UsbHub;
 use watchdog::Watchdog;
 
@@ -60,35 +79,92 @@ async fn main() -> Result<(), std::io::Error> {
     // The topics are also used to pass around data inside the tacd.
     let mut bb = BrokerBuilder::new();
 
+    // Put the outputs into user defined states when the tacd exits.
+    safe_state::setup(&mut bb);
+
+    // Decide which outputs to turn on, based on the user defined policy
+    // and the states they were in when the tacd stopped.
+    let initial_states = power_restore::initial_states(&bb);
+
+    // Show the startup progress on the display until the user interface
+    // takes over.
+    let mut splash = Splash::new().await;
+
     // Expose hardware on the TAC via the broker framework.
     let led = Led::new(&mut bb);
     let adc = Adc::new(&mut bb).await.unwrap();
+    splash.step_done("ADC started").await;
+
     let dut_pwr = DutPwrThread::new(
         &mut bb,
         adc.pwr_volt.clone(),
         adc.pwr_curr.clone(),
         led.dut_pwr.clone(),
+        initial_states.dut_power,
     )
     .await
     .unwrap();
-    let dig_io = DigitalIo::new(&mut bb, led.out_0.clone(), led.out_1.clone());
-    let regulators = Regulators::new(&mut bb);
+    splash.step_done("DUT power ready").await;
+
     let temperatures = Temperatures::new(&mut bb);
+
+    // Check the ADC channels and the SoC temperature against user defined
+    // limits.
+    let alarms = Alarms::new(
+        &mut bb,
+        &adc,
+        temperatures.soc_temperature.clone(),
+        dut_pwr.request.clone(),
+    );
+
+    // Push selected measurements to a central InfluxDB compatible server.
+    influx::setup(&mut bb, &adc);
+
+    let dig_io = DigitalIo::new(&mut bb, led.out_0.clone(), led.out_1.clone());
+    let regulators = Regulators::new(&mut bb, initial_states.iobus);
+
+    // Remember the output states, so they can be restored after a restart.
+    power_restore::setup(&mut bb, &dut_pwr, &regulators);
+
+    // Check that the outputs actually do what they were told to.
+    let selfcheck = SelfCheck::new(&mut bb, &adc, &dut_pwr, &dig_io, &regulators);
+
+    // Run user defined power and IO sequences, e.g. to bring up a DUT.
+    sequences::setup(&mut bb, &adc, &dut_pwr, &dig_io, &regulators);
+
+    // Switch the DUT and IOBus power at user defined times.
+    let scheduler = Scheduler::new(&mut bb, &dut_pwr, &regulators);
+
     let usb_hub = UsbHub::new(&mut bb);
+    let backlight = Backlight::new(&mut bb);
 
     // Expose other software on the TAC via the broker framework by connecting
     // to them via HTTP / DBus APIs.
     let iobus = IoBus::new(&mut bb);
-    let (network, rauc, systemd) = {
+    let (cellular, logind, network, rauc, systemd, timedate) = {
         let dbus = DbusSession::new(&mut bb, led.eth_dut.clone(), led.eth_lab.clone()).await;
 
-        (dbus.network, dbus.rauc, dbus.systemd)
+        (
+            dbus.cellular,
+            dbus.logind,
+            dbus.network,
+            dbus.rauc,
+            dbus.systemd,
+            dbus.timedate,
+        )
     };
+    splash.step_done("DBus connected").await;
 
     // Expose information about the system provided by the kernel via the
     // broker framework.
     let system = System::new(&mut bb);
 
+    // Expose the DHCP leases handed out to devices on the DUT side.
+    let dhcp = Dhcp::new(&mut bb);
+
+    // Expose the port membership and STP state of the tac-bridge.
+    bridge::setup(&mut bb);
+
     // Make sure the ADC and power switching threads of the tacd are not
     // stalled for too long by providing watchdog events to systemd
     // (if requested on start).
@@ -98,6 +174,35 @@ async fn main() -> Result<(), std::io::Error> {
     // interface and config files that may be edited inside the web ui.
     let mut http_server = HttpServer::new();
 
+    // Allow plotting the history of the ADC channels and temperatures in
+    // the web interface.
+    adc.serve_history(
+        &mut http_server.server,
+        temperatures.histories(),
+        temperatures.history_aliases(),
+    );
+
+    // Stream raw ADC samples to clients that need more than the broker
+    // can provide.
+    adc.serve_samples(&mut http_server.server);
+
+    // Record the ADC channels around trigger events like the DUT being
+    // powered on.
+    scope::serve(&mut bb, &mut http_server.server, &adc, &dut_pwr, &dig_io);
+
+    // Keep measurements and events on disk for long running tests and make
+    // the histories survive restarts.
+    tsdb::setup(
+        &mut http_server.server,
+        &adc,
+        &temperatures,
+        &alarms,
+        &dut_pwr,
+    );
+
+    // Allow uploading RAUC bundles via the web interface.
+    rauc.serve_upload(&mut http_server.server);
+
     // Allow editing some aspects of the TAC configuration when in "setup mode".
     let setup_mode = SetupMode::new(&mut bb, &mut http_server.server);
 
@@ -105,27 +210,40 @@ async fn main() -> Result<(), std::io::Error> {
     // in the web interface.
     journal::serve(&mut http_server.server);
 
+    // Allow capturing the network traffic on the DUT side and streaming
+    // it to the web interface in pcap format.
+    capture::serve(&mut http_server.server);
+    splash.step_done("Web server ready").await;
+
     // Set up the user interface for the hardware display on the TAC.
     // The different screens receive updates via the topics provided in
     // the UiResources struct.
     let ui = {
         let resources = UiResources {
             adc,
+            alarms,
+            backlight,
+            cellular,
+            dhcp,
             dig_io,
             dut_pwr,
             iobus,
             led,
+            logind,
             network,
             rauc,
             regulators,
+            scheduler,
+            selfcheck,
             setup_mode,
             system,
             systemd,
             temperatures,
+            timedate,
             usb_hub,
         };
 
-        Ui::new(&mut bb, resources, &mut http_server.server)
+        Ui::new(&mut bb, resources, &mut http_server.server, splash)
     };
 
     // Consume the BrokerBuilder (no further topics can be added or removed)
@@ -136,7 +254,7 @@ async fn main() -> Result<(), std::io::Error> {
 
     // Run until the user interface, http server or (if selected) the watchdog
     // exits (with an error).
-    if let Some(watchdog) = watchdog {
+    let res = if let Some(watchdog) = watchdog {
         select! {
             ui_err = ui.run().fuse() => ui_err,
             wi_err = http_server.serve().fuse() => wi_err,
@@ -147,5 +265,11 @@ async fn main() -> Result<(), std::io::Error> {
             ui_err = ui.run().fuse() => ui_err,
             wi_err = http_server.serve().fuse() => wi_err,
         }
-    }
+    };
+
+    // Whatever the reason for exiting is, do not leave the outputs in
+    // their last commanded state if the user does not want us to.
+    safe_state::enter();
+
+    res
 }
@@ -23,10 +23,25 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 #[derive(Debug, Clone, Copy)]
 pub struct Timestamp(Instant);
 
+/// Whether the value of a measurement can be trusted
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Validity {
+    #[default]
+    Valid,
+    /// The input was at the limit of the ADC's range. The actual value may
+    /// be larger in magnitude than the published one.
+    Overrange,
+    /// The value could not be read. The published value is the last one
+    /// that could.
+    ReadError,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct Measurement {
     pub ts: Timestamp,
     pub value: f32,
+    #[serde(default)]
+    pub validity: Validity,
 }
 
 impl Measurement {
@@ -34,6 +49,17 @@ impl Measurement {
         Self {
             ts: Timestamp::now(),
             value,
+            validity: Validity::Valid,
+        }
+    }
+
+    /// A measurement for a sensor that could not be read, repeating the
+    /// last known value (if any)
+    pub fn read_error(last: Option<f32>) -> Self {
+        Self {
+            ts: Timestamp::now(),
+            value: last.unwrap_or(0.0),
+            validity: Validity::ReadError,
         }
     }
 }
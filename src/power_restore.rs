@@ -0,0 +1,140 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::Duration;
+
+use async_std::task::{sleep, spawn};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::broker::BrokerBuilder;
+use crate::dut_power::{DutPwrThread, OutputState};
+use crate::regulators::Regulators;
+
+const POLICIES_PATH: &str = "/v1/tac/power_restore";
+const LAST_STATES_PATH: &str = "/v1/tac/power_restore/last";
+
+/// Time between two looks at the output states
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What to do with an output when the tacd starts
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum RestorePolicy {
+    AlwaysOff,
+    AlwaysOn,
+    /// Put the output into the state it was in when the tacd stopped
+    RestoreLast,
+}
+
+impl RestorePolicy {
+    fn apply(&self, last: bool) -> bool {
+        match self {
+            Self::AlwaysOff => false,
+            Self::AlwaysOn => true,
+            Self::RestoreLast => last,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct RestorePolicies {
+    pub dut_power: RestorePolicy,
+    pub iobus: RestorePolicy,
+}
+
+impl Default for RestorePolicies {
+    /// The behaviour of the tacd before the policies were configurable
+    fn default() -> Self {
+        Self {
+            dut_power: RestorePolicy::AlwaysOff,
+            iobus: RestorePolicy::AlwaysOn,
+        }
+    }
+}
+
+/// Whether the outputs are (or should be) on
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct PowerStates {
+    pub dut_power: bool,
+    pub iobus: bool,
+}
+
+/// Get the states the outputs should be in when the tacd starts.
+/// This has to happen before the outputs are set up, so that they are turned
+/// on as soon as possible.
+pub fn initial_states(bb: &BrokerBuilder) -> PowerStates {
+    let policies: RestorePolicies = bb.persisted_value(POLICIES_PATH).unwrap_or_default();
+    let last = bb.persisted_value(LAST_STATES_PATH);
+
+    let states = PowerStates {
+        dut_power: policies
+            .dut_power
+            .apply(last.map(|l: PowerStates| l.dut_power).unwrap_or(false)),
+        iobus: policies
+            .iobus
+            .apply(last.map(|l: PowerStates| l.iobus).unwrap_or(true)),
+    };
+
+    info!("Restoring power states {states:?} using policies {policies:?}");
+
+    states
+}
+
+/// Expose the restore policies and keep track of the output states so they
+/// can be restored after a restart.
+pub fn setup(bb: &mut BrokerBuilder, dut_pwr: &DutPwrThread, regulators: &Regulators) {
+    bb.topic(
+        POLICIES_PATH,
+        true,
+        true,
+        true,
+        Some(RestorePolicies::default()),
+        1,
+    );
+
+    let last = bb.topic(LAST_STATES_PATH, true, false, true, None, 1);
+
+    let dut_state = dut_pwr.state.clone();
+    let iobus_pwr_en = regulators.iobus_pwr_en.clone();
+
+    // Poll instead of reacting to changes, as the last states are only
+    // loaded from disk once the broker is built and would overwrite what
+    // was recorded before.
+    spawn(async move {
+        loop {
+            sleep(POLL_INTERVAL).await;
+
+            // Faults turn the output off, so they count as off
+            let dut_power = match dut_state.try_get() {
+                None | Some(OutputState::Changing) => continue,
+                Some(state) => state == OutputState::On,
+            };
+
+            let iobus = match iobus_pwr_en.try_get() {
+                Some(iobus) => iobus,
+                None => continue,
+            };
+
+            let states = PowerStates { dut_power, iobus };
+
+            last.modify(|prev| match prev != Some(states) {
+                true => Some(states),
+                false => None,
+            });
+        }
+    });
+}
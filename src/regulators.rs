@@ -20,6 +20,7 @@ use async_std::sync::Arc;
 use async_std::task::spawn;
 
 use crate::broker::{BrokerBuilder, Topic};
+use crate::safe_state;
 
 #[cfg(feature = "demo_mode")]
 mod reg {
@@ -73,9 +74,18 @@ fn handle_regulator(
 }
 
 impl Regulators {
-    pub fn new(bb: &mut BrokerBuilder) -> Self {
+    pub fn new(bb: &mut BrokerBuilder, iobus_initial: bool) -> Self {
+        safe_state::register("iobus", |on| {
+            let _ = regulator_set("output_iobus_12v", on);
+        });
+
         Self {
-            iobus_pwr_en: handle_regulator(bb, "/v1/iobus/powered", "output_iobus_12v", true),
+            iobus_pwr_en: handle_regulator(
+                bb,
+                "/v1/iobus/powered",
+                "output_iobus_12v",
+                iobus_initial,
+            ),
             uart_pwr_en: handle_regulator(bb, "/v1/uart/powered", "output_vuart", true),
         }
     }
@@ -0,0 +1,253 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use async_std::prelude::*;
+use async_std::task::spawn;
+use log::{error, info, warn};
+use nix::libc::c_int;
+use nix::sys::signal::{signal, SigHandler, Signal};
+use serde::{Deserialize, Serialize};
+
+use crate::broker::BrokerBuilder;
+
+/// Time between two looks at the signal flag
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Threads that keep the outputs and the protection against over-current and
+/// over-voltage going. A panic in any of these leaves the outputs unattended.
+const CRITICAL_THREADS: &[&str] = &["tacd power", "tacd iio"];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum SafeState {
+    /// Leave the output as it was last commanded
+    Keep,
+    Off,
+    /// Not allowed for the DUT power, as nothing protects it against
+    /// over-current once the tacd is gone.
+    On,
+}
+
+/// The states to put the outputs into when the tacd exits or panics
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct SafeStates {
+    pub dut_power: SafeState,
+    pub iobus: SafeState,
+    pub out_0: SafeState,
+    pub out_1: SafeState,
+}
+
+impl SafeStates {
+    const fn keep_all() -> Self {
+        Self {
+            dut_power: SafeState::Keep,
+            iobus: SafeState::Keep,
+            out_0: SafeState::Keep,
+            out_1: SafeState::Keep,
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        self.dut_power != SafeState::On
+    }
+
+    fn get(&self, output: &str) -> SafeState {
+        match output {
+            "dut_power" => self.dut_power,
+            "iobus" => self.iobus,
+            "out_0" => self.out_0,
+            "out_1" => self.out_1,
+            _ => SafeState::Keep,
+        }
+    }
+}
+
+impl Default for SafeStates {
+    fn default() -> Self {
+        Self::keep_all()
+    }
+}
+
+type SwitchFn = Box<dyn Fn(bool) + Send>;
+
+/// Functions that switch an output directly, without going through the
+/// broker framework and async runtime, as these may no longer work once
+/// the safe states are entered.
+static OUTPUTS: Mutex<Vec<(&'static str, SwitchFn)>> = Mutex::new(Vec::new());
+
+/// Functions that save state that would otherwise be lost when the tacd is
+/// stopped. They are called after the safe states were entered.
+static EXIT_HOOKS: Mutex<Vec<Box<dyn Fn() + Send>>> = Mutex::new(Vec::new());
+
+/// A copy of the safe state topic that can be accessed from a panic hook
+static CONFIG: Mutex<SafeStates> = Mutex::new(SafeStates::keep_all());
+
+/// When the systemd watchdog was last fed and how long to wait for the
+/// next time before giving up on the async runtime.
+static WATCHDOG_FED: Mutex<Option<(Instant, Duration)>> = Mutex::new(None);
+
+static SIGNALED: AtomicBool = AtomicBool::new(false);
+
+/// Lock a mutex without waiting on a thread that may have panicked while
+/// holding it.
+fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// Register a function that switches `output` on or off
+pub fn register(output: &'static str, switch: impl Fn(bool) + Send + 'static) {
+    OUTPUTS.lock().unwrap().push((output, Box::new(switch)));
+}
+
+/// Register a function that is called when the tacd is stopped via a signal.
+/// The functions are called in the order they were registered.
+pub fn on_exit(hook: impl Fn() + Send + 'static) {
+    EXIT_HOOKS.lock().unwrap().push(Box::new(hook));
+}
+
+/// Note that the watchdog was fed and expect it to be fed again within
+/// `deadline`, otherwise the safe states are entered.
+pub fn watchdog_fed(deadline: Duration) {
+    *WATCHDOG_FED.lock().unwrap() = Some((Instant::now(), deadline));
+}
+
+fn watchdog_missed() -> bool {
+    try_lock(&WATCHDOG_FED)
+        .and_then(|fed| *fed)
+        .map(|(fed_at, deadline)| fed_at.elapsed() > deadline)
+        .unwrap_or(false)
+}
+
+/// Put all outputs into their configured safe states
+pub fn enter() {
+    let config = match try_lock(&CONFIG) {
+        Some(config) => *config,
+        None => return,
+    };
+
+    let outputs = match try_lock(&OUTPUTS) {
+        Some(outputs) => outputs,
+        None => return,
+    };
+
+    for (output, switch) in outputs.iter() {
+        match config.get(output) {
+            SafeState::Keep => {}
+            SafeState::Off => switch(false),
+            SafeState::On => switch(true),
+        }
+    }
+}
+
+extern "C" fn on_signal(_: c_int) {
+    SIGNALED.store(true, Ordering::Relaxed);
+}
+
+/// Make sure the outputs are put into their safe states when the tacd is
+/// stopped, one of its critical threads panics or the async runtime stops
+/// feeding the watchdog, instead of keeping whatever state they were in.
+pub fn setup(bb: &mut BrokerBuilder) {
+    let safe_states = bb.topic(
+        "/v1/tac/safe_states",
+        true,
+        true,
+        true,
+        Some(SafeStates::default()),
+        1,
+    );
+
+    let (mut safe_states_events, _) = safe_states.clone().subscribe_unbounded();
+
+    spawn(async move {
+        let mut prev_valid = SafeStates::default();
+
+        while let Some(states) = safe_states_events.next().await {
+            if states.is_valid() {
+                *CONFIG.lock().unwrap() = states;
+                prev_valid = states;
+            } else {
+                warn!("Rejecting safe states that switch the DUT power on");
+                safe_states.set(prev_valid);
+            }
+        }
+    });
+
+    let prev_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        prev_hook(info);
+
+        // A panic anywhere else only ends the task it happened in,
+        // the way it always did.
+        let critical = thread::current()
+            .name()
+            .map(|name| CRITICAL_THREADS.contains(&name))
+            .unwrap_or(false);
+
+        if !critical {
+            return;
+        }
+
+        error!("Entering safe states after a panic in a critical thread");
+        enter();
+
+        // The outputs may no longer match what the topics say, so there is
+        // no point in carrying on. systemd will restart us.
+        std::process::exit(1);
+    }));
+
+    for sig in [Signal::SIGTERM, Signal::SIGINT].iter() {
+        // The handler only sets an atomic flag, which is async signal safe
+        unsafe { signal(*sig, SigHandler::Handler(on_signal)) }.unwrap();
+    }
+
+    thread::Builder::new()
+        .name("tacd safe state".into())
+        .spawn(|| loop {
+            thread::sleep(SIGNAL_POLL_INTERVAL);
+
+            // systemd would kill us without giving us a chance to enter the
+            // safe states, so do it before the watchdog runs out.
+            if watchdog_missed() {
+                error!("The watchdog was not fed in time. Entering safe states");
+                enter();
+                std::process::exit(1);
+            }
+
+            if SIGNALED.load(Ordering::Relaxed) {
+                info!("Received signal. Entering safe states and exiting");
+                enter();
+
+                if let Some(hooks) = try_lock(&EXIT_HOOKS) {
+                    for hook in hooks.iter() {
+                        hook();
+                    }
+                }
+
+                std::process::exit(0);
+            }
+        })
+        .unwrap();
+}
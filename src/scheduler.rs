@@ -0,0 +1,382 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::Duration;
+
+use async_std::sync::Arc;
+use async_std::task::{sleep, spawn};
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::broker::{BrokerBuilder, Topic};
+use crate::dut_power::{DutPwrThread, OutputRequest};
+use crate::regulators::Regulators;
+
+/// The rules have a resolution of one minute, so looking a few times per
+/// minute is plenty.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How far into the future to look for the next action.
+/// Rules like "on February 29th if it is a monday" may take a while.
+const MAX_DAYS_AHEAD: u32 = 8 * 366;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Target {
+    DutPower,
+    IoBus,
+}
+
+/// Switch `target` on or off whenever the local time matches `cron`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ScheduleRule {
+    /// A cron expression with the five fields minute, hour, day of month,
+    /// month and day of week, e.g. "0 22 * * 1-5".
+    /// Supports `*`, lists (`1,3`), ranges (`1-5`) and steps (`*/15`).
+    pub cron: String,
+    pub target: Target,
+    pub on: bool,
+}
+
+/// The next action the scheduler is going to take
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ScheduledAction {
+    pub target: Target,
+    pub on: bool,
+    /// The local time of the action in RFC 3339 format
+    pub at: String,
+}
+
+fn contains(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+fn parse_number(num: &str, field: &str) -> Result<u32, String> {
+    num.parse()
+        .map_err(|_| format!("Invalid number \"{num}\" in \"{field}\""))
+}
+
+/// Parse a single field of a cron expression into a bit mask of the values
+/// it matches
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, parse_number(step, part)?),
+            None => (part, 1),
+        };
+
+        if step == 0 {
+            return Err(format!("Invalid step in \"{part}\""));
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_number(start, part)?, parse_number(end, part)?)
+        } else {
+            // "5/15" is short for "5-<max>/15"
+            let start = parse_number(range, part)?;
+            (start, if step > 1 { max } else { start })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!("\"{part}\" is outside of {min}-{max}"));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+
+    Ok(mask)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+
+        let (minute, hour, day, month, weekday) = match fields[..] {
+            [minute, hour, day, month, weekday] => (minute, hour, day, month, weekday),
+            _ => return Err(format!("Expected five fields in \"{expr}\"")),
+        };
+
+        // Both 0 and 7 mean sunday
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+
+        if contains(weekdays, 7) {
+            weekdays |= 1;
+        }
+
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if !contains(self.months, date.month()) {
+            return false;
+        }
+
+        let day = contains(self.days, date.day());
+        let weekday = contains(self.weekdays, date.weekday().num_days_from_sunday());
+
+        // Like in cron a date matches if either the day of month or the day
+        // of week matches, unless one of them is unrestricted.
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+
+    fn matches(&self, time: NaiveDateTime) -> bool {
+        self.matches_date(time.date())
+            && contains(self.hours, time.hour())
+            && contains(self.minutes, time.minute())
+    }
+
+    /// The first full minute after `time` that matches the schedule
+    fn next_after(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut date = time.date();
+        let mut first_minute = time.hour() * 60 + time.minute() + 1;
+
+        for _ in 0..MAX_DAYS_AHEAD {
+            if self.matches_date(date) {
+                let minute = (first_minute..24 * 60)
+                    .find(|m| contains(self.hours, m / 60) && contains(self.minutes, m % 60));
+
+                if let Some(minute) = minute {
+                    return date.and_hms_opt(minute / 60, minute % 60, 0);
+                }
+            }
+
+            date = date.succ_opt()?;
+            first_minute = 0;
+        }
+
+        None
+    }
+}
+
+/// Parse the rules, skipping (and complaining about) the invalid ones
+fn parse_rules(rules: &[ScheduleRule]) -> Vec<(Schedule, ScheduleRule)> {
+    rules
+        .iter()
+        .filter_map(|rule| match Schedule::parse(&rule.cron) {
+            Ok(schedule) => Some((schedule, rule.clone())),
+            Err(e) => {
+                warn!("Ignoring invalid schedule rule: {e}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Find the rule that is going to trigger next
+fn next_action(rules: &[(Schedule, ScheduleRule)], now: NaiveDateTime) -> Option<ScheduledAction> {
+    let (at, rule) = rules
+        .iter()
+        .filter_map(|(schedule, rule)| schedule.next_after(now).map(|at| (at, rule)))
+        .min_by_key(|(at, _)| *at)?;
+
+    let at = Local
+        .from_local_datetime(&at)
+        .earliest()
+        .map(|at| at.to_rfc3339())
+        .unwrap_or_else(|| at.to_string());
+
+    Some(ScheduledAction {
+        target: rule.target,
+        on: rule.on,
+        at,
+    })
+}
+
+pub struct Scheduler {
+    pub next: Arc<Topic<Option<ScheduledAction>>>,
+}
+
+impl Scheduler {
+    /// Switch the DUT and IOBus power at user defined times, e.g. for
+    /// nightly burn-in tests.
+    pub fn new(bb: &mut BrokerBuilder, dut_pwr: &DutPwrThread, regulators: &Regulators) -> Self {
+        let rules = bb.topic("/v1/tac/schedule", true, true, true, Some(Vec::new()), 1);
+        let next = bb.topic_ro("/v1/tac/schedule/next", Some(None));
+
+        let dut_pwr_request = dut_pwr.request.clone();
+        let iobus_pwr_en = regulators.iobus_pwr_en.clone();
+        let next_task = next.clone();
+
+        let (rules_events, _) = rules.subscribe_unbounded();
+
+        spawn(async move {
+            let mut rules: Vec<(Schedule, ScheduleRule)> = Vec::new();
+            let mut last_minute: Option<NaiveDateTime> = None;
+
+            loop {
+                let mut changed = false;
+
+                while let Ok(new_rules) = rules_events.try_recv() {
+                    rules = parse_rules(&new_rules);
+                    changed = true;
+                }
+
+                let now = Local::now().naive_local();
+                let minute = now.date().and_hms_opt(now.hour(), now.minute(), 0);
+
+                if minute != last_minute {
+                    // Do not fire the rules for the current minute on startup,
+                    // a restart of the tacd should not switch the outputs.
+                    if last_minute.is_some() {
+                        for (schedule, rule) in rules.iter() {
+                            if !schedule.matches(now) {
+                                continue;
+                            }
+
+                            info!("Scheduled switching of {:?} to {}", rule.target, rule.on);
+
+                            match (rule.target, rule.on) {
+                                (Target::DutPower, true) => dut_pwr_request.set(OutputRequest::On),
+                                (Target::DutPower, false) => {
+                                    dut_pwr_request.set(OutputRequest::Off)
+                                }
+                                (Target::IoBus, on) => iobus_pwr_en.set(on),
+                            }
+                        }
+                    }
+
+                    last_minute = minute;
+                    changed = true;
+                }
+
+                if changed {
+                    let action = next_action(&rules, now);
+
+                    next_task.modify(|prev| match prev != Some(action.clone()) {
+                        true => Some(action),
+                        false => None,
+                    });
+                }
+
+                sleep(POLL_INTERVAL).await;
+            }
+        });
+
+        Self { next }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use super::Schedule;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, mo, d)
+            .unwrap()
+            .and_hms_opt(h, mi, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert!(Schedule::parse("* * * * *").is_ok());
+        assert!(Schedule::parse("*/15 8-17 1,15 * 1-5").is_ok());
+        assert!(Schedule::parse("* * * *").is_err());
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("* * 0 * *").is_err());
+        assert!(Schedule::parse("*/0 * * * *").is_err());
+        assert!(Schedule::parse("5-1 * * * *").is_err());
+        assert!(Schedule::parse("a * * * *").is_err());
+    }
+
+    #[test]
+    fn next_after() {
+        // Every weekday at 22:00. 2023-06-02 is a friday.
+        let nightly = Schedule::parse("0 22 * * 1-5").unwrap();
+
+        assert!(nightly.matches(at(2023, 6, 2, 22, 0)));
+        assert!(!nightly.matches(at(2023, 6, 3, 22, 0)));
+
+        assert_eq!(
+            nightly.next_after(at(2023, 6, 2, 12, 0)),
+            Some(at(2023, 6, 2, 22, 0))
+        );
+        assert_eq!(
+            nightly.next_after(at(2023, 6, 2, 22, 0)),
+            Some(at(2023, 6, 5, 22, 0))
+        );
+
+        // Steps starting at a value
+        let quarterly = Schedule::parse("5/15 * * * *").unwrap();
+
+        assert_eq!(
+            quarterly.next_after(at(2023, 6, 2, 12, 6)),
+            Some(at(2023, 6, 2, 12, 20))
+        );
+        assert_eq!(
+            quarterly.next_after(at(2023, 6, 2, 23, 50)),
+            Some(at(2023, 6, 3, 0, 5))
+        );
+
+        // Day of month or day of week, sunday as 7
+        let either = Schedule::parse("0 0 13 * 7").unwrap();
+
+        assert_eq!(
+            either.next_after(at(2023, 6, 2, 0, 0)),
+            Some(at(2023, 6, 4, 0, 0))
+        );
+        assert_eq!(
+            either.next_after(at(2023, 6, 11, 0, 0)),
+            Some(at(2023, 6, 13, 0, 0))
+        );
+
+        // Leap days only
+        let leap = Schedule::parse("0 12 29 2 *").unwrap();
+
+        assert_eq!(
+            leap.next_after(at(2023, 6, 2, 0, 0)),
+            Some(at(2024, 2, 29, 12, 0))
+        );
+
+        // Dates that never happen
+        let never = Schedule::parse("0 0 31 2 *").unwrap();
+
+        assert_eq!(never.next_after(at(2023, 6, 2, 0, 0)), None);
+    }
+}
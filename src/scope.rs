@@ -0,0 +1,370 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use futures::stream::select;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tide::{Body, Request, Response, Server};
+
+use crate::adc::{Adc, CalibratedChannel, SampleBlock};
+use crate::broker::{BrokerBuilder, Topic};
+use crate::digital_io::DigitalIo;
+use crate::dut_power::{DutPwrThread, OutputState};
+use crate::measurement::Timestamp;
+
+/// Upper limit for the pre- and post-trigger windows, so that a capture
+/// can not use up all of the memory.
+const MAX_WINDOW: Duration = Duration::from_secs(10);
+
+/// What to watch for the trigger condition
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum TriggerSource {
+    /// The value of an ADC channel (by name, e.g. `pwr-curr`)
+    Channel(String),
+    /// The DUT power being on (1) or off (0)
+    DutPower,
+    /// The state of the digital outputs and the IOBus fault input (0 or 1)
+    Out0,
+    Out1,
+    IoBusFault,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+impl Edge {
+    /// Did the trigger source cross `level` in the direction of this edge?
+    fn crossed(&self, level: f32, prev: f32, now: f32) -> bool {
+        match self {
+            Self::Rising => prev <= level && now > level,
+            Self::Falling => prev >= level && now < level,
+        }
+    }
+}
+
+/// Arm the scope by publishing this config
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ScopeConfig {
+    /// The ADC channels to record
+    pub channels: Vec<String>,
+    pub trigger: TriggerSource,
+    pub edge: Edge,
+    /// The level to trigger at. Not used for the digital trigger sources.
+    #[serde(default)]
+    pub level: f32,
+    pub pre_trigger_ms: u64,
+    pub post_trigger_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ScopeState {
+    Idle,
+    Armed,
+    Triggered,
+    Done,
+}
+
+#[derive(Serialize)]
+struct CaptureSample {
+    /// Time relative to the trigger in milliseconds
+    offset_ms: f64,
+    values: Vec<f32>,
+}
+
+#[derive(Serialize)]
+struct Capture {
+    config: ScopeConfig,
+    trigger: Timestamp,
+    samples: Vec<CaptureSample>,
+}
+
+enum Probe {
+    /// An ADC channel that is recorded along with the other channels
+    Analog(CalibratedChannel),
+    Digital(Arc<Topic<bool>>),
+    DutPower(Arc<Topic<OutputState>>),
+}
+
+impl Probe {
+    /// The level of a digital probe. Analog probes are read from the
+    /// sample blocks instead.
+    fn digital_level(&self) -> Option<f32> {
+        match self {
+            Self::Analog(_) => None,
+            Self::Digital(topic) => topic.try_get().map(|val| val as u8 as f32),
+            Self::DutPower(topic) => topic
+                .try_get()
+                .map(|state| (state == OutputState::On) as u8 as f32),
+        }
+    }
+}
+
+struct Sources {
+    channels: Vec<(&'static str, CalibratedChannel)>,
+    dut_pwr: Arc<Topic<OutputState>>,
+    out_0: Arc<Topic<bool>>,
+    out_1: Arc<Topic<bool>>,
+    iobus_flt: Arc<Topic<bool>>,
+}
+
+impl Sources {
+    fn channel(&self, name: &str) -> Option<CalibratedChannel> {
+        self.channels
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, channel)| channel.clone())
+    }
+
+    fn probe(&self, config: &ScopeConfig) -> Option<(Probe, f32)> {
+        match &config.trigger {
+            TriggerSource::Channel(name) => {
+                Some((Probe::Analog(self.channel(name)?), config.level))
+            }
+            TriggerSource::DutPower => Some((Probe::DutPower(self.dut_pwr.clone()), 0.5)),
+            TriggerSource::Out0 => Some((Probe::Digital(self.out_0.clone()), 0.5)),
+            TriggerSource::Out1 => Some((Probe::Digital(self.out_1.clone()), 0.5)),
+            TriggerSource::IoBusFault => Some((Probe::Digital(self.iobus_flt.clone()), 0.5)),
+        }
+    }
+}
+
+/// Record a single capture according to `config`.
+///
+/// Returns the capture once it is complete, `Err(Some(new_config))` if the
+/// scope was re-armed in the meantime or `Err(None)` if the ADC stopped
+/// providing samples.
+async fn record(
+    config: ScopeConfig,
+    mut channels: Vec<CalibratedChannel>,
+    probe: Probe,
+    level: f32,
+    state: &Topic<ScopeState>,
+    arm_events: &async_std::channel::Receiver<ScopeConfig>,
+) -> Result<Capture, Option<ScopeConfig>> {
+    enum Event {
+        Samples(SampleBlock),
+        Arm(ScopeConfig),
+    }
+
+    let pre_trigger = Duration::from_millis(config.pre_trigger_ms).min(MAX_WINDOW);
+    let post_trigger = Duration::from_millis(config.post_trigger_ms).min(MAX_WINDOW);
+
+    // An analog trigger channel is sampled along with the recorded ones,
+    // so that every one of its samples is checked for the trigger
+    // condition.
+    let recorded = channels.len();
+
+    if let Probe::Analog(channel) = &probe {
+        channels.push(channel.clone());
+    }
+
+    let mut events = select(
+        CalibratedChannel::subscribe(&channels).map(Event::Samples),
+        arm_events.clone().map(Event::Arm),
+    );
+
+    let mut samples: VecDeque<(Instant, Vec<f32>)> = VecDeque::new();
+    let mut prev_level = None;
+    let mut trigger: Option<Instant> = None;
+
+    state.set(ScopeState::Armed);
+
+    while let Some(event) = events.next().await {
+        let block = match event {
+            Event::Samples(block) => block,
+            Event::Arm(config) => return Err(Some(config)),
+        };
+
+        // The digital probes only change slowly compared to the ADC
+        // samples, so they are only looked at once per block.
+        let digital_level = probe.digital_level();
+
+        for i in 0..block.len() {
+            let ts = block.ts(i).as_instant();
+
+            samples.push_back((ts, block.sample(i).take(recorded).collect()));
+
+            match trigger {
+                None => {
+                    // Only keep what is needed for the pre-trigger window
+                    while samples
+                        .front()
+                        .map(|(sample_ts, _)| {
+                            ts.saturating_duration_since(*sample_ts) > pre_trigger
+                        })
+                        .unwrap_or(false)
+                    {
+                        samples.pop_front();
+                    }
+
+                    let now = match probe {
+                        Probe::Analog(_) => Some(block.values[recorded][i]),
+                        _ => digital_level,
+                    };
+
+                    if let (Some(prev), Some(now)) = (prev_level, now) {
+                        if config.edge.crossed(level, prev, now) {
+                            trigger = Some(ts);
+                            state.set(ScopeState::Triggered);
+                        }
+                    }
+
+                    prev_level = now;
+                }
+                Some(trigger) if ts.saturating_duration_since(trigger) >= post_trigger => {
+                    let samples = samples
+                        .into_iter()
+                        .map(|(sample_ts, values)| {
+                            let offset_ms = match sample_ts.checked_duration_since(trigger) {
+                                Some(after) => after.as_secs_f64() * 1000.0,
+                                None => -trigger.duration_since(sample_ts).as_secs_f64() * 1000.0,
+                            };
+
+                            CaptureSample { offset_ms, values }
+                        })
+                        .collect();
+
+                    return Ok(Capture {
+                        config,
+                        trigger: Timestamp::new(trigger),
+                        samples,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    Err(None)
+}
+
+/// Record the ADC channels around a trigger event, like an oscilloscope
+/// would, e.g. to analyze inrush currents when the DUT is powered on.
+pub fn serve(
+    bb: &mut BrokerBuilder,
+    server: &mut Server<()>,
+    adc: &Adc,
+    dut_pwr: &DutPwrThread,
+    dig_io: &DigitalIo,
+) {
+    let arm = bb.topic_wo::<ScopeConfig>("/v1/tac/adc/scope/arm", None);
+    let state = bb.topic_ro("/v1/tac/adc/scope/state", Some(ScopeState::Idle));
+
+    let capture: Arc<Mutex<Option<Capture>>> = Arc::new(Mutex::new(None));
+
+    let sources = Sources {
+        channels: adc
+            .channels()
+            .iter()
+            .map(|(name, channel)| (*name, channel.fast.clone()))
+            .collect(),
+        dut_pwr: dut_pwr.state.clone(),
+        out_0: dig_io.out_0.clone(),
+        out_1: dig_io.out_1.clone(),
+        iobus_flt: dig_io.iobus_flt_fb.clone(),
+    };
+
+    let (arm_events, _) = arm.subscribe_unbounded();
+    let capture_task = capture.clone();
+
+    spawn(async move {
+        let mut next_config = None;
+
+        loop {
+            let config = match next_config.take() {
+                Some(config) => config,
+                None => match arm_events.recv().await {
+                    Ok(config) => config,
+                    Err(_) => break,
+                },
+            };
+
+            let channels: Option<Vec<_>> = config
+                .channels
+                .iter()
+                .map(|name| sources.channel(name))
+                .collect();
+
+            let (channels, (probe, level)) = match (channels, sources.probe(&config)) {
+                (Some(channels), Some(probe)) if !channels.is_empty() => (channels, probe),
+                _ => {
+                    warn!("Scope config contains unknown channels: {config:?}");
+                    state.set(ScopeState::Idle);
+                    continue;
+                }
+            };
+
+            match record(config, channels, probe, level, &state, &arm_events).await {
+                Ok(capture) => {
+                    *capture_task.lock().unwrap() = Some(capture);
+                    state.set(ScopeState::Done);
+                }
+                Err(Some(config)) => next_config = Some(config),
+                Err(None) => {
+                    warn!("The ADC stopped providing samples for the scope");
+                    state.set(ScopeState::Idle);
+                }
+            }
+        }
+    });
+
+    server
+        .at("/v1/tac/adc/scope/capture")
+        .get(move |_req: Request<()>| {
+            let capture = capture.clone();
+
+            async move {
+                let capture = capture.lock().unwrap();
+
+                let res = match capture.as_ref() {
+                    Some(capture) => Response::builder(200)
+                        .body(Body::from_json(capture)?)
+                        .build(),
+                    None => Response::new(404),
+                };
+
+                Ok(res)
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Edge;
+
+    #[test]
+    fn edges() {
+        assert!(Edge::Rising.crossed(1.0, 0.5, 1.5));
+        assert!(Edge::Rising.crossed(1.0, 1.0, 1.5));
+        assert!(!Edge::Rising.crossed(1.0, 1.5, 0.5));
+        assert!(!Edge::Rising.crossed(1.0, 1.5, 2.0));
+
+        assert!(Edge::Falling.crossed(0.5, 1.0, 0.0));
+        assert!(!Edge::Falling.crossed(0.5, 0.0, 1.0));
+        assert!(!Edge::Falling.crossed(0.5, 0.4, 0.3));
+    }
+}
@@ -0,0 +1,270 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::{Duration, Instant};
+
+use async_std::sync::Arc;
+use async_std::task::{sleep, spawn};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::adc::{Adc, AdcChannel};
+use crate::broker::{BrokerBuilder, Topic};
+use crate::digital_io::{DigitalIo, OutputMode};
+use crate::dut_power::{DutPwrThread, OutputState};
+use crate::regulators::Regulators;
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Outputs need some time to settle after being switched, e.g. to charge
+/// or discharge capacitors. Only report mismatches that last longer.
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+
+/// Voltage limits (in V) the measured outputs are checked against
+const DUT_PWR_ON_MIN: f32 = 1.0;
+const DUT_PWR_OFF_MAX: f32 = 1.0;
+const OUT_ASSERTED_MAX: f32 = 0.7;
+const IOBUS_ON_MIN: f32 = 10.0;
+const IOBUS_OFF_MAX: f32 = 1.0;
+
+/// The range a measured value is expected in given the commanded state
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Expectation {
+    Above(f32),
+    Below(f32),
+}
+
+impl Expectation {
+    fn met(&self, value: f32) -> bool {
+        match self {
+            Self::Above(limit) => value > *limit,
+            Self::Below(limit) => value < *limit,
+        }
+    }
+}
+
+/// An output whose measured value does not match the commanded state
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Mismatch {
+    /// Name of the output, e.g. `iobus`
+    pub output: String,
+    pub expected: Expectation,
+    pub measured: f32,
+}
+
+/// Decides whether the current mismatch is long-lasting enough to report
+#[derive(Default)]
+struct Debounce {
+    expectation: Option<Expectation>,
+    mismatch_since: Option<Instant>,
+}
+
+impl Debounce {
+    fn update(&mut self, expectation: Option<Expectation>, ts: Instant, value: f32) -> bool {
+        // Give the output some time to settle whenever it is switched
+        if expectation != self.expectation {
+            self.expectation = expectation;
+            self.mismatch_since = None;
+        }
+
+        match expectation {
+            Some(exp) if !exp.met(value) => {
+                let since = *self.mismatch_since.get_or_insert(ts);
+                ts.duration_since(since) >= SETTLE_TIME
+            }
+            _ => {
+                self.mismatch_since = None;
+                false
+            }
+        }
+    }
+}
+
+type ExpectFn = Box<dyn Fn() -> Option<Expectation> + Send + Sync>;
+
+struct Check {
+    output: &'static str,
+    channel: AdcChannel,
+    /// Compare the magnitude of the value instead of the value itself
+    abs: bool,
+    expect: ExpectFn,
+    debounce: Debounce,
+}
+
+pub struct SelfCheck {
+    pub mismatches: Arc<Topic<Vec<Mismatch>>>,
+}
+
+impl SelfCheck {
+    /// Cross-check the commanded state of the outputs against the measured
+    /// voltages, e.g. to detect broken cabling or a stuck power switch.
+    pub fn new(
+        bb: &mut BrokerBuilder,
+        adc: &Adc,
+        dut_pwr: &DutPwrThread,
+        dig_io: &DigitalIo,
+        regulators: &Regulators,
+    ) -> Self {
+        let mismatches = bb.topic_ro("/v1/tac/selfcheck/mismatches", Some(Vec::<Mismatch>::new()));
+
+        let dut_state = dut_pwr.state.clone();
+        let out_0 = dig_io.out_0.clone();
+        let out_1 = dig_io.out_1.clone();
+        let out_0_mode = dig_io.out_0_mode.clone();
+        let out_1_mode = dig_io.out_1_mode.clone();
+        let iobus_pwr_en = regulators.iobus_pwr_en.clone();
+        let iobus_flt = dig_io.iobus_flt_fb.clone();
+
+        // The outputs are open drain, so only the asserted state results
+        // in a known voltage. The voltage of an output in PWM mode follows
+        // the signal and can not be checked.
+        let out_check = |output,
+                         channel: &AdcChannel,
+                         asserted: Arc<Topic<bool>>,
+                         mode: Arc<Topic<OutputMode>>| Check {
+            output,
+            channel: channel.clone(),
+            abs: true,
+            expect: Box::new(move || {
+                if mode.try_get() == Some(OutputMode::Pwm) {
+                    return None;
+                }
+
+                asserted
+                    .try_get()
+                    .unwrap_or(false)
+                    .then_some(Expectation::Below(OUT_ASSERTED_MAX))
+            }),
+            debounce: Debounce::default(),
+        };
+
+        let mut checks = vec![
+            Check {
+                output: "dut-power",
+                channel: adc.pwr_volt.clone(),
+                abs: false,
+                expect: Box::new(move || match dut_state.try_get() {
+                    Some(OutputState::On) => Some(Expectation::Above(DUT_PWR_ON_MIN)),
+                    Some(OutputState::Off) => Some(Expectation::Below(DUT_PWR_OFF_MAX)),
+                    _ => None,
+                }),
+                debounce: Debounce::default(),
+            },
+            out_check("out-0", &adc.out0_volt, out_0, out_0_mode),
+            out_check("out-1", &adc.out1_volt, out_1, out_1_mode),
+            Check {
+                output: "iobus",
+                channel: adc.iobus_volt.clone(),
+                abs: false,
+                expect: Box::new(move || {
+                    // A fault is already reported by the IOBus fault feedback
+                    if iobus_flt.try_get().unwrap_or(false) {
+                        return None;
+                    }
+
+                    match iobus_pwr_en.try_get() {
+                        Some(true) => Some(Expectation::Above(IOBUS_ON_MIN)),
+                        Some(false) => Some(Expectation::Below(IOBUS_OFF_MAX)),
+                        None => None,
+                    }
+                }),
+                debounce: Debounce::default(),
+            },
+        ];
+
+        let mismatches_task = mismatches.clone();
+
+        spawn(async move {
+            loop {
+                sleep(CHECK_INTERVAL).await;
+
+                let now = Instant::now();
+                let mut found = Vec::new();
+
+                for check in checks.iter_mut() {
+                    let expected = (check.expect)();
+                    let meas = check.channel.fast.get();
+
+                    let measured = match check.abs {
+                        true => meas.value.abs(),
+                        false => meas.value,
+                    };
+
+                    if check.debounce.update(expected, now, measured) {
+                        found.push(Mismatch {
+                            output: check.output.to_string(),
+                            // update() only returns true if there is an expectation
+                            expected: expected.unwrap(),
+                            measured,
+                        });
+                    }
+                }
+
+                let prev = mismatches_task.try_get().unwrap_or_default();
+                let prev_outputs: Vec<&str> = prev.iter().map(|m| m.output.as_str()).collect();
+
+                for mismatch in found.iter() {
+                    if !prev_outputs.contains(&mismatch.output.as_str()) {
+                        warn!(
+                            "Output {} does not match its commanded state: expected {:?}, measured {}",
+                            mismatch.output, mismatch.expected, mismatch.measured
+                        );
+                    }
+                }
+
+                if found != prev {
+                    mismatches_task.set(found);
+                }
+            }
+        });
+
+        Self { mismatches }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Debounce, Expectation, SETTLE_TIME};
+
+    #[test]
+    fn debounce() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let on = Some(Expectation::Above(10.0));
+        let off = Some(Expectation::Below(1.0));
+
+        let mut debounce = Debounce::default();
+
+        // No expectation, no mismatch
+        assert!(!debounce.update(None, at(0), 5.0));
+
+        // The output needs to settle after being switched
+        assert!(!debounce.update(on, at(100), 0.0));
+        assert!(!debounce.update(on, at(1000), 5.0));
+        assert!(debounce.update(on, at(100) + SETTLE_TIME, 5.0));
+
+        // Meeting the expectation clears the mismatch immediately
+        assert!(!debounce.update(on, at(5000), 12.0));
+
+        // Switching restarts the settle time
+        assert!(!debounce.update(off, at(6000), 12.0));
+        assert!(!debounce.update(off, at(7000), 8.0));
+        assert!(debounce.update(off, at(6000) + SETTLE_TIME, 4.0));
+    }
+}
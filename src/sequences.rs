@@ -0,0 +1,316 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::{Duration, Instant};
+
+use async_std::channel::Receiver;
+use async_std::sync::Arc;
+use async_std::task::{sleep, spawn};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::adc::{Adc, CalibratedChannel};
+use crate::broker::{BrokerBuilder, Topic};
+use crate::digital_io::DigitalIo;
+use crate::dut_power::{DutPwrThread, OutputRequest};
+use crate::measurement::{Measurement, Validity};
+use crate::regulators::Regulators;
+
+/// Time between two looks at a measurement in a check step and between
+/// two looks at the abort topic while waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum Step {
+    /// Switch an output (`dut-power`, `iobus`, `out-0` or `out-1`) on or off
+    SetOutput { output: String, on: bool },
+    /// Do nothing for some time
+    Wait { ms: u64 },
+    /// Wait until a measurement (e.g. `iobus-volt` or the name of an
+    /// external sensor) is within the given limits.
+    /// The sequence fails if this does not happen within `timeout_ms`.
+    Check {
+        channel: String,
+        #[serde(default)]
+        min: Option<f32>,
+        #[serde(default)]
+        max: Option<f32>,
+        timeout_ms: u64,
+    },
+}
+
+/// A named list of steps that is run in order, e.g. to bring up a DUT that
+/// needs its supplies to be enabled in a specific order.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Sequence {
+    pub name: String,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum SequenceState {
+    Idle,
+    Running {
+        sequence: String,
+        step: usize,
+    },
+    Finished {
+        sequence: String,
+    },
+    Failed {
+        sequence: String,
+        step: usize,
+        error: String,
+    },
+}
+
+enum Probe {
+    Analog(CalibratedChannel),
+    Sensor(Arc<Topic<Measurement>>),
+}
+
+impl Probe {
+    fn value(&self) -> Option<f32> {
+        match self {
+            Self::Analog(channel) => Some(channel.get().value),
+            Self::Sensor(topic) => topic
+                .try_get()
+                .filter(|meas| meas.validity != Validity::ReadError)
+                .map(|meas| meas.value),
+        }
+    }
+}
+
+/// Everything a sequence can act on or look at
+struct Targets {
+    dut_pwr: Arc<Topic<OutputRequest>>,
+    iobus_pwr_en: Arc<Topic<bool>>,
+    out_0: Arc<Topic<bool>>,
+    out_1: Arc<Topic<bool>>,
+    probes: Vec<(String, Probe)>,
+}
+
+struct Runner {
+    targets: Targets,
+    state: Arc<Topic<SequenceState>>,
+    abort_events: Receiver<bool>,
+}
+
+impl Runner {
+    fn set_output(&self, output: &str, on: bool) -> Result<(), String> {
+        let targets = &self.targets;
+
+        match output {
+            "dut-power" => targets.dut_pwr.set(match on {
+                true => OutputRequest::On,
+                false => OutputRequest::Off,
+            }),
+            "iobus" => targets.iobus_pwr_en.set(on),
+            "out-0" => targets.out_0.set(on),
+            "out-1" => targets.out_1.set(on),
+            _ => return Err(format!("Unknown output \"{output}\"")),
+        }
+
+        Ok(())
+    }
+
+    fn aborted(&self) -> bool {
+        let mut aborted = false;
+
+        while let Ok(ev) = self.abort_events.try_recv() {
+            aborted |= ev;
+        }
+
+        aborted
+    }
+
+    /// Sleep for `duration` while keeping an eye on the abort topic
+    async fn wait(&self, duration: Duration) -> Result<(), String> {
+        let deadline = Instant::now() + duration;
+
+        loop {
+            if self.aborted() {
+                return Err("Aborted".to_string());
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                return Ok(());
+            }
+
+            sleep(POLL_INTERVAL.min(deadline - now)).await;
+        }
+    }
+
+    async fn check(
+        &self,
+        channel: &str,
+        min: Option<f32>,
+        max: Option<f32>,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        let probe = self
+            .targets
+            .probes
+            .iter()
+            .find(|(name, _)| name == channel)
+            .map(|(_, probe)| probe)
+            .ok_or_else(|| format!("Unknown channel \"{channel}\""))?;
+
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let value = probe.value();
+
+            let within = value
+                .map(|v| min.map_or(true, |min| v >= min) && max.map_or(true, |max| v <= max))
+                .unwrap_or(false);
+
+            if within {
+                return Ok(());
+            }
+
+            if Instant::now() >= deadline {
+                return Err(match value {
+                    Some(value) => format!("{channel} is {value}, expected {min:?} to {max:?}"),
+                    None => format!("{channel} could not be read"),
+                });
+            }
+
+            self.wait(POLL_INTERVAL).await?;
+        }
+    }
+
+    async fn run_step(&self, step: &Step) -> Result<(), String> {
+        if self.aborted() {
+            return Err("Aborted".to_string());
+        }
+
+        match step {
+            Step::SetOutput { output, on } => self.set_output(output, *on),
+            Step::Wait { ms } => self.wait(Duration::from_millis(*ms)).await,
+            Step::Check {
+                channel,
+                min,
+                max,
+                timeout_ms,
+            } => {
+                self.check(channel, *min, *max, Duration::from_millis(*timeout_ms))
+                    .await
+            }
+        }
+    }
+
+    async fn run(&self, sequence: &Sequence) {
+        info!("Running sequence \"{}\"", sequence.name);
+
+        // Only aborts that arrive while the sequence is running count
+        self.aborted();
+
+        for (step, action) in sequence.steps.iter().enumerate() {
+            self.state.set(SequenceState::Running {
+                sequence: sequence.name.clone(),
+                step,
+            });
+
+            if let Err(error) = self.run_step(action).await {
+                warn!(
+                    "Sequence \"{}\" failed in step {step}: {error}",
+                    sequence.name
+                );
+
+                self.state.set(SequenceState::Failed {
+                    sequence: sequence.name.clone(),
+                    step,
+                    error,
+                });
+
+                return;
+            }
+        }
+
+        self.state.set(SequenceState::Finished {
+            sequence: sequence.name.clone(),
+        });
+    }
+}
+
+/// Run user defined sequences of output changes and checks, so that the
+/// timing does not depend on the network connection to a client.
+pub fn setup(
+    bb: &mut BrokerBuilder,
+    adc: &Adc,
+    dut_pwr: &DutPwrThread,
+    dig_io: &DigitalIo,
+    regulators: &Regulators,
+) {
+    let sequences: Arc<Topic<Vec<Sequence>>> =
+        bb.topic("/v1/tac/sequences", true, true, true, Some(Vec::new()), 1);
+    let run = bb.topic_wo::<String>("/v1/tac/sequences/run", None);
+    let abort = bb.topic_wo::<bool>("/v1/tac/sequences/abort", None);
+    let state = bb.topic_ro("/v1/tac/sequences/state", Some(SequenceState::Idle));
+
+    let mut probes: Vec<(String, Probe)> = adc
+        .channels()
+        .iter()
+        .map(|(name, channel)| (name.to_string(), Probe::Analog(channel.fast.clone())))
+        .collect();
+
+    for sensor in adc.sensors.iter() {
+        probes.push((sensor.name.clone(), Probe::Sensor(sensor.topic.clone())));
+    }
+
+    let (run_events, _) = run.subscribe_unbounded();
+    let (abort_events, _) = abort.subscribe_unbounded();
+
+    let runner = Runner {
+        targets: Targets {
+            dut_pwr: dut_pwr.request.clone(),
+            iobus_pwr_en: regulators.iobus_pwr_en.clone(),
+            out_0: dig_io.out_0.clone(),
+            out_1: dig_io.out_1.clone(),
+            probes,
+        },
+        state,
+        abort_events,
+    };
+
+    spawn(async move {
+        while let Ok(name) = run_events.recv().await {
+            let sequence = sequences
+                .try_get()
+                .unwrap_or_default()
+                .into_iter()
+                .find(|sequence| sequence.name == name);
+
+            match sequence {
+                Some(sequence) => runner.run(&sequence).await,
+                None => {
+                    warn!("Can not run unknown sequence \"{name}\"");
+                    continue;
+                }
+            }
+
+            // Sequences are not queued, so requests that came in while
+            // the sequence was running are dropped.
+            while let Ok(name) = run_events.try_recv() {
+                warn!("Not running sequence \"{name}\" as another one was running");
+            }
+        }
+    });
+}
@@ -15,8 +15,9 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
-use std::fs::{create_dir_all, read, write};
+use std::fs::{read, set_permissions, write, DirBuilder, Permissions};
 use std::io::ErrorKind;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
 use std::path::Path;
 
 use async_std::prelude::*;
@@ -25,6 +26,7 @@ use async_std::task::spawn;
 use tide::{http::mime, Request, Response, Server};
 
 use crate::broker::{BrokerBuilder, Topic};
+use crate::dbus::networkmanager::dot1x::{CA_CERT_PATH, CLIENT_CERT_PATH, PRIVATE_KEY_PATH};
 
 #[cfg(feature = "demo_mode")]
 const AUTHORIZED_KEYS_PATH: &str = "demo_files/home/root/ssh/authorized_keys";
@@ -32,6 +34,27 @@ const AUTHORIZED_KEYS_PATH: &str = "demo_files/home/root/ssh/authorized_keys";
 #[cfg(not(feature = "demo_mode"))]
 const AUTHORIZED_KEYS_PATH: &str = "/home/root/.ssh/authorized_keys";
 
+#[cfg(feature = "demo_mode")]
+const ADMIN_TOKEN_PATH: &str = "demo_files/etc/tacd/admin_token";
+
+#[cfg(not(feature = "demo_mode"))]
+const ADMIN_TOKEN_PATH: &str = "/etc/tacd/admin_token";
+
+/// Check if `token` grants the admin role, which is required for disruptive
+/// actions like rebooting the TAC.
+///
+/// The admin token can only be set in setup mode. As long as none is set
+/// nobody has the admin role.
+pub async fn is_admin(token: &str) -> bool {
+    match async_std::fs::read_to_string(ADMIN_TOKEN_PATH).await {
+        Ok(admin_token) => {
+            let admin_token = admin_token.trim();
+            !admin_token.is_empty() && admin_token == token
+        }
+        Err(_) => false,
+    }
+}
+
 pub struct SetupMode {
     pub setup_mode: Arc<Topic<bool>>,
 }
@@ -42,6 +65,7 @@ impl SetupMode {
         server: &mut Server<()>,
         fs_path: &'static str,
         web_path: &str,
+        readable: bool,
     ) {
         let setup_mode_task = self.setup_mode.clone();
         server.at(web_path).put(move |mut req: Request<()>| {
@@ -52,12 +76,22 @@ impl SetupMode {
                     let fs_path = Path::new(fs_path);
                     let parent = fs_path.parent().unwrap();
 
-                    if !parent.exists() {
-                        create_dir_all(parent)?;
-                    }
+                    // Secrets are only accessible to the owner of the file
+                    let (dir_mode, file_mode) = match readable {
+                        true => (0o755, 0o644),
+                        false => (0o700, 0o600),
+                    };
+
+                    // E.g. /etc/tacd/8021x does not exist before the first
+                    // certificate is uploaded.
+                    DirBuilder::new()
+                        .recursive(true)
+                        .mode(dir_mode)
+                        .create(parent)?;
 
                     let content = req.body_bytes().await?;
                     write(fs_path, content)?;
+                    set_permissions(fs_path, Permissions::from_mode(file_mode))?;
 
                     Response::new(204)
                 } else {
@@ -71,6 +105,11 @@ impl SetupMode {
             }
         });
 
+        // Secrets can be replaced but are never handed out again
+        if !readable {
+            return;
+        }
+
         let setup_mode_task = self.setup_mode.clone();
         server.at(web_path).get(move |_| {
             let setup_mode = setup_mode_task.clone();
@@ -131,7 +170,33 @@ impl SetupMode {
         };
 
         this.handle_leave_requests(bb);
-        this.expose_file_conditionally(server, AUTHORIZED_KEYS_PATH, "/v1/tac/ssh/authorized_keys");
+        this.expose_file_conditionally(
+            server,
+            AUTHORIZED_KEYS_PATH,
+            "/v1/tac/ssh/authorized_keys",
+            true,
+        );
+        this.expose_file_conditionally(server, ADMIN_TOKEN_PATH, "/v1/tac/admin_token", false);
+
+        // Certificates and keys for 802.1X authentication on the uplink
+        this.expose_file_conditionally(
+            server,
+            CA_CERT_PATH,
+            "/v1/tac/network/uplink/8021x/ca_cert",
+            true,
+        );
+        this.expose_file_conditionally(
+            server,
+            CLIENT_CERT_PATH,
+            "/v1/tac/network/uplink/8021x/client_cert",
+            true,
+        );
+        this.expose_file_conditionally(
+            server,
+            PRIVATE_KEY_PATH,
+            "/v1/tac/network/uplink/8021x/private_key",
+            false,
+        );
 
         this
     }
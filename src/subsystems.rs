@@ -0,0 +1,74 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use async_std::sync::Arc;
+use serde::{Deserialize, Serialize};
+
+use crate::broker::Topic;
+
+/// The health of a part of the tacd that does its work in the background,
+/// like the thread that reads the ADC.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum SubsystemStatus {
+    Ok,
+    /// The subsystem keeps working, but e.g. some of its inputs are missing
+    Degraded {
+        error: String,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+impl SubsystemStatus {
+    pub fn degraded(error: &str) -> Self {
+        Self::Degraded {
+            error: error.to_string(),
+        }
+    }
+
+    pub fn failed(error: &str) -> Self {
+        Self::Failed {
+            error: error.to_string(),
+        }
+    }
+}
+
+/// Tells the background tasks of a subsystem to stop, e.g. once the struct
+/// that owns them is dropped
+#[derive(Clone, Default)]
+pub struct ShutdownToken(Arc<AtomicBool>);
+
+impl ShutdownToken {
+    pub fn shutdown(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Publish the status of a subsystem, but only if it changed
+pub fn report(topic: &Topic<SubsystemStatus>, status: SubsystemStatus) {
+    topic.modify(|prev| match prev.as_ref() == Some(&status) {
+        true => None,
+        false => Some(status),
+    });
+}
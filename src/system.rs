@@ -21,10 +21,13 @@ use serde::{Deserialize, Serialize};
 
 use crate::broker::{BrokerBuilder, Topic};
 
+mod cpufreq;
+
 #[cfg(feature = "demo_mode")]
 mod read_dt_props {
     const DEMO_DATA_STR: &[(&str, &str)] = &[
         ("barebox-version", "barebox-2022.11.0-20221121-1"),
+        ("baseboard-factory-data/pcba-serial-number", "LXATAC-00042"),
         (
             "baseboard-factory-data/pcba-hardware-release",
             "lxatac-S01-R03-B02-C00",
@@ -42,10 +45,15 @@ mod read_dt_props {
         ("powerboard-factory-data/factory-timestamp", 1678086418),
     ];
 
-    pub fn read_dt_property(path: &str) -> String {
-        let (_, content) = DEMO_DATA_STR.iter().find(|(p, _)| *p == path).unwrap();
+    pub fn try_read_dt_property(path: &str) -> Option<String> {
+        DEMO_DATA_STR
+            .iter()
+            .find(|(p, _)| *p == path)
+            .map(|(_, content)| content.to_string())
+    }
 
-        content.to_string()
+    pub fn read_dt_property(path: &str) -> String {
+        try_read_dt_property(path).unwrap()
     }
 
     pub fn read_dt_property_u32(path: &str) -> u32 {
@@ -60,6 +68,15 @@ mod read_dt_props {
 
     const DT_CHOSEN: &str = "/sys/firmware/devicetree/base/chosen/";
 
+    /// Read a property that may not be present, e.g. because it was only
+    /// added to the factory data of newer devices
+    pub fn try_read_dt_property(path: &str) -> Option<String> {
+        let bytes = read([DT_CHOSEN, path].join("/")).ok()?;
+        let content = bytes.strip_suffix(&[0]).unwrap_or(&bytes);
+
+        from_utf8(content).ok().map(|s| s.to_string())
+    }
+
     pub fn read_dt_property(path: &str) -> String {
         let bytes = read([DT_CHOSEN, path].join("/")).unwrap();
         from_utf8(bytes.strip_suffix(&[0]).unwrap())
@@ -72,7 +89,7 @@ mod read_dt_props {
     }
 }
 
-use read_dt_props::{read_dt_property, read_dt_property_u32};
+use read_dt_props::{read_dt_property, read_dt_property_u32, try_read_dt_property};
 
 #[derive(Serialize, Deserialize)]
 pub struct Uname {
@@ -100,6 +117,7 @@ impl Uname {
 #[derive(Serialize, Deserialize)]
 pub struct Barebox {
     pub version: String,
+    pub serial_number: Option<String>,
     pub baseboard_release: String,
     pub powerboard_release: String,
     pub baseboard_timestamp: u32,
@@ -111,6 +129,7 @@ impl Barebox {
         // Get info from devicetree chosen
         Self {
             version: read_dt_property("barebox-version"),
+            serial_number: try_read_dt_property("baseboard-factory-data/pcba-serial-number"),
             baseboard_release: {
                 let template = read_dt_property("baseboard-factory-data/pcba-hardware-release");
                 let changeset = read_dt_property_u32("baseboard-factory-data/modification");
@@ -143,6 +162,8 @@ impl System {
     pub fn new(bb: &mut BrokerBuilder) -> Self {
         let version = env!("VERSION_STRING").to_string();
 
+        cpufreq::setup(bb);
+
         Self {
             uname: bb.topic_ro("/v1/tac/info/uname", Some(Arc::new(Uname::get()))),
             barebox: bb.topic_ro("/v1/tac/info/bootloader", Some(Arc::new(Barebox::get()))),
@@ -0,0 +1,234 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::path::Path;
+use std::time::Duration;
+
+use async_std::sync::Arc;
+use async_std::task::{sleep, spawn};
+use serde::{Deserialize, Serialize};
+
+use crate::broker::{BrokerBuilder, Topic};
+
+#[cfg(feature = "demo_mode")]
+mod sysfs {
+    use std::io::{Error, ErrorKind, Result};
+    use std::path::Path;
+
+    const FILES: &[(&str, &str)] = &[
+        (
+            "/sys/devices/system/cpu/cpu0/cpufreq/scaling_cur_freq",
+            "650000",
+        ),
+        (
+            "/sys/devices/system/cpu/cpu0/cpufreq/scaling_min_freq",
+            "400000",
+        ),
+        (
+            "/sys/devices/system/cpu/cpu0/cpufreq/scaling_max_freq",
+            "650000",
+        ),
+        (
+            "/sys/devices/system/cpu/cpu0/cpufreq/cpuinfo_max_freq",
+            "650000",
+        ),
+        (
+            "/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor",
+            "schedutil",
+        ),
+        (
+            "/sys/devices/system/cpu/cpu1/cpufreq/scaling_cur_freq",
+            "650000",
+        ),
+        (
+            "/sys/devices/system/cpu/cpu1/cpufreq/scaling_min_freq",
+            "400000",
+        ),
+        (
+            "/sys/devices/system/cpu/cpu1/cpufreq/scaling_max_freq",
+            "650000",
+        ),
+        (
+            "/sys/devices/system/cpu/cpu1/cpufreq/cpuinfo_max_freq",
+            "650000",
+        ),
+        (
+            "/sys/devices/system/cpu/cpu1/cpufreq/scaling_governor",
+            "schedutil",
+        ),
+        ("/sys/class/thermal/cooling_device0/type", "cpufreq-cpu0"),
+        ("/sys/class/thermal/cooling_device0/cur_state", "0"),
+        ("/sys/class/thermal/cooling_device0/max_state", "1"),
+    ];
+
+    pub fn read_to_string(path: &Path) -> Result<String> {
+        FILES
+            .iter()
+            .find(|(p, _)| Path::new(p) == path)
+            .map(|(_, content)| content.to_string())
+            .ok_or_else(|| Error::from(ErrorKind::NotFound))
+    }
+
+    pub fn list_dir(path: &Path) -> Result<Vec<String>> {
+        let mut names: Vec<String> = FILES
+            .iter()
+            .filter_map(|(p, _)| Path::new(p).strip_prefix(path).ok())
+            .filter_map(|rest| rest.iter().next())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+
+        names.dedup();
+
+        Ok(names)
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod sysfs {
+    use std::io::Result;
+    use std::path::Path;
+
+    pub use std::fs::read_to_string;
+
+    pub fn list_dir(path: &Path) -> Result<Vec<String>> {
+        std::fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect()
+    }
+}
+
+use sysfs::{list_dir, read_to_string};
+
+const CPU_DEVICES: &str = "/sys/devices/system/cpu";
+const COOLING_DEVICES: &str = "/sys/class/thermal";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The frequency scaling state of a single CPU core. Frequencies are in kHz.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct CpuFrequency {
+    pub cpu: String,
+    pub current: u32,
+    pub min: u32,
+    pub max: u32,
+    /// The highest frequency the core supports, `max` may be lower if
+    /// the frequency is capped, e.g. for thermal reasons
+    pub hardware_max: u32,
+    pub governor: String,
+}
+
+/// A thermal cooling device, like the CPU frequency being throttled
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct CoolingDevice {
+    pub name: String,
+    pub state: u32,
+    pub max_state: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Throttling {
+    /// Whether any cooling device is active or the CPU frequency is capped
+    pub throttled: bool,
+    pub cooling_devices: Vec<CoolingDevice>,
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn read_u32(path: &Path) -> Option<u32> {
+    read_trimmed(path)?.parse().ok()
+}
+
+/// List the entries of `dir` that consist of `prefix` and a number,
+/// like `cpu0` or `cooling_device1`, in numerical order
+fn numbered_entries(dir: &str, prefix: &str) -> Vec<String> {
+    let mut entries: Vec<(u32, String)> = list_dir(Path::new(dir))
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|name| {
+            let num = name.strip_prefix(prefix)?.parse().ok()?;
+            Some((num, name))
+        })
+        .collect();
+
+    entries.sort();
+    entries.into_iter().map(|(_, name)| name).collect()
+}
+
+fn cpu_frequencies() -> Vec<CpuFrequency> {
+    numbered_entries(CPU_DEVICES, "cpu")
+        .into_iter()
+        .filter_map(|cpu| {
+            let dir = Path::new(CPU_DEVICES).join(&cpu).join("cpufreq");
+
+            Some(CpuFrequency {
+                current: read_u32(&dir.join("scaling_cur_freq"))?,
+                min: read_u32(&dir.join("scaling_min_freq"))?,
+                max: read_u32(&dir.join("scaling_max_freq"))?,
+                hardware_max: read_u32(&dir.join("cpuinfo_max_freq"))?,
+                governor: read_trimmed(&dir.join("scaling_governor")).unwrap_or_default(),
+                cpu,
+            })
+        })
+        .collect()
+}
+
+fn cooling_devices() -> Vec<CoolingDevice> {
+    numbered_entries(COOLING_DEVICES, "cooling_device")
+        .into_iter()
+        .filter_map(|dev| {
+            let dir = Path::new(COOLING_DEVICES).join(dev);
+
+            Some(CoolingDevice {
+                name: read_trimmed(&dir.join("type"))?,
+                state: read_u32(&dir.join("cur_state"))?,
+                max_state: read_u32(&dir.join("max_state"))?,
+            })
+        })
+        .collect()
+}
+
+/// Publish the CPU frequencies and whether the CPU is throttled, so that
+/// slow test runs can be attributed to the TAC itself running hot.
+pub(super) fn setup(bb: &mut BrokerBuilder) {
+    let frequencies: Arc<Topic<Vec<CpuFrequency>>> = bb.topic_ro("/v1/tac/cpu/frequency", None);
+    let throttling: Arc<Topic<Throttling>> = bb.topic_ro("/v1/tac/cpu/throttling", None);
+
+    spawn(async move {
+        loop {
+            let freqs = cpu_frequencies();
+            let devices = cooling_devices();
+
+            let throttling_now = Throttling {
+                throttled: devices.iter().any(|d| d.state > 0)
+                    || freqs.iter().any(|f| f.max < f.hardware_max),
+                cooling_devices: devices,
+            };
+
+            if frequencies.try_get().as_ref() != Some(&freqs) {
+                frequencies.set(freqs);
+            }
+
+            if throttling.try_get().as_ref() != Some(&throttling_now) {
+                throttling.set(throttling_now);
+            }
+
+            sleep(POLL_INTERVAL).await;
+        }
+    });
+}
@@ -15,89 +15,579 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::thread::sleep;
-use std::time::Duration;
+use std::io::Result;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use async_std::prelude::*;
 use async_std::sync::Arc;
-use async_std::task::spawn_blocking;
+use async_std::task::{sleep, spawn};
+use log::warn;
+use serde::{Deserialize, Serialize};
 
+use crate::adc::History;
 use crate::broker::{BrokerBuilder, Topic};
-use crate::measurement::Measurement;
+use crate::measurement::{Measurement, Validity};
+use crate::subsystems::{report, ShutdownToken, SubsystemStatus};
 
 #[cfg(feature = "demo_mode")]
 mod hw {
-    pub trait SysClass {
-        fn input(&self) -> Result<u32, ()>;
+    use std::io::{Error, ErrorKind, Result};
+    use std::path::Path;
+
+    const FILES: &[(&str, &str)] = &[
+        ("/sys/class/hwmon/hwmon0/name", "cpu_thermal"),
+        ("/sys/class/hwmon/hwmon0/temp1_input", "30000"),
+        ("/sys/class/hwmon/hwmon1/name", "lm75"),
+        ("/sys/class/hwmon/hwmon1/temp1_input", "27500"),
+        ("/sys/class/hwmon/hwmon1/temp1_label", "board"),
+        ("/sys/class/hwmon/hwmon2/name", "pwmfan"),
+        ("/sys/class/hwmon/hwmon2/pwm1", "0"),
+    ];
+
+    pub fn read_to_string(path: &Path) -> Result<String> {
+        FILES
+            .iter()
+            .find(|(p, _)| Path::new(p) == path)
+            .map(|(_, content)| content.to_string())
+            .ok_or_else(|| Error::from(ErrorKind::NotFound))
     }
 
-    pub struct HwMon;
-    pub struct TempDecoy;
+    pub fn list_dir(path: &Path) -> Result<Vec<String>> {
+        let mut names: Vec<String> = FILES
+            .iter()
+            .filter_map(|(p, _)| Path::new(p).strip_prefix(path).ok())
+            .filter_map(|rest| rest.iter().next())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
 
-    impl SysClass for TempDecoy {
-        fn input(&self) -> Result<u32, ()> {
-            Ok(30_000)
-        }
+        names.dedup();
+
+        Ok(names)
     }
 
-    impl HwMon {
-        pub fn new(_: &'static str) -> Result<Self, ()> {
-            Ok(Self)
-        }
+    pub async fn read_to_string_async(path: &str) -> Result<String> {
+        read_to_string(Path::new(path))
+    }
 
-        pub fn temp(&self, _: u64) -> Result<TempDecoy, ()> {
-            Ok(TempDecoy)
-        }
+    pub async fn write_async(_path: &str, _content: &str) -> Result<()> {
+        Ok(())
     }
 }
 
 #[cfg(not(feature = "demo_mode"))]
 mod hw {
-    pub use sysfs_class::*;
+    use std::io::Result;
+    use std::path::Path;
+
+    pub use std::fs::read_to_string;
+
+    pub async fn read_to_string_async(path: &str) -> Result<String> {
+        async_std::fs::read_to_string(path).await
+    }
+
+    pub async fn write_async(path: &str, content: &str) -> Result<()> {
+        async_std::fs::write(path, content).await
+    }
+
+    pub fn list_dir(path: &Path) -> Result<Vec<String>> {
+        std::fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect()
+    }
 }
 
-use hw::{HwMon, SysClass};
+use hw::{list_dir, read_to_string, read_to_string_async};
+
+mod fan;
 
 const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
 
+const HWMON_DEVICES: &str = "/sys/class/hwmon";
+
+/// The hwmon device and channel the SoC temperature is read from
+const SOC_DEVICE: &str = "cpu_thermal";
+const SOC_CHANNEL: &str = "temp1";
+
+/// Temperatures (in degrees Celsius) at which the SoC is considered too hot
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ThermalThresholds {
+    pub warning: f32,
+    pub critical: f32,
+    /// How far the temperature has to drop below a threshold for the state
+    /// to be lowered again
+    pub hysteresis: f32,
+    /// How long (in milliseconds) a new state has to persist before it is
+    /// published
+    #[serde(default = "default_min_duration_ms")]
+    pub min_duration_ms: u64,
+}
+
+fn default_min_duration_ms() -> u64 {
+    2000
+}
+
+impl Default for ThermalThresholds {
+    fn default() -> Self {
+        Self {
+            warning: 90.0,
+            critical: 105.0,
+            hysteresis: 5.0,
+            min_duration_ms: default_min_duration_ms(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum ThermalState {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl ThermalState {
+    /// Get the state for a new temperature reading, with some hysteresis
+    /// so that the state does not flicker around a threshold
+    fn next(self, thresholds: &ThermalThresholds, temperature: f32) -> Self {
+        let above = |threshold: f32, active: bool| match active {
+            true => temperature >= threshold - thresholds.hysteresis,
+            false => temperature >= threshold,
+        };
+
+        if above(thresholds.critical, self == Self::Critical) {
+            Self::Critical
+        } else if above(thresholds.warning, self >= Self::Warning) {
+            Self::Warning
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+/// Only accept a new thermal state once it was seen continuously for some
+/// time, so that a single noisy reading does not result in an alert
+#[derive(Default)]
+struct StateDebounce {
+    pending: Option<(ThermalState, Instant)>,
+}
+
+impl StateDebounce {
+    fn update(
+        &mut self,
+        current: ThermalState,
+        next: ThermalState,
+        ts: Instant,
+        min_duration: Duration,
+    ) -> Option<ThermalState> {
+        if next == current {
+            self.pending = None;
+            return None;
+        }
+
+        let since = match self.pending {
+            Some((state, since)) if state == next => since,
+            _ => ts,
+        };
+
+        if ts.duration_since(since) >= min_duration {
+            self.pending = None;
+            Some(next)
+        } else {
+            self.pending = Some((next, since));
+            None
+        }
+    }
+}
+
+/// A channel of a hwmon device as published on the `/v1/tac/hwmon` topic
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct HwmonChannel {
+    pub device: String,
+    pub channel: String,
+    /// The content of the `<channel>_label` attribute (if any)
+    pub label: Option<String>,
+    pub unit: String,
+    pub topic: String,
+}
+
+struct Input {
+    path: String,
+    scale: f32,
+    topic: Arc<Topic<Measurement>>,
+    history: Arc<Mutex<History>>,
+    is_soc: bool,
+    last: Option<f32>,
+    failed: bool,
+}
+
+/// Split a hwmon attribute name like `temp1_input` into the channel name
+/// and the unit and scale of its values
+fn parse_attribute(name: &str) -> Option<(&str, &'static str, f32)> {
+    let channel = name.strip_suffix("_input")?;
+    let kind = channel.trim_end_matches(|c: char| c.is_ascii_digit());
+
+    if kind.len() == channel.len() {
+        return None;
+    }
+
+    // See the sysfs-interface documentation of the hwmon subsystem
+    let (unit, scale) = match kind {
+        "temp" => ("°C", 0.001),
+        "in" => ("V", 0.001),
+        "curr" => ("A", 0.001),
+        "power" => ("W", 0.000_001),
+        "humidity" => ("%", 0.001),
+        "fan" => ("RPM", 1.0),
+        _ => return None,
+    };
+
+    Some((channel, unit, scale))
+}
+
+/// PWM outputs are named e.g. `pwm1`, with the other attributes of the
+/// output (like `pwm1_enable`) having suffixes
+fn is_pwm(attribute: &str) -> bool {
+    match attribute.strip_prefix("pwm") {
+        Some(num) => !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+async fn read_value(path: &str) -> Result<f32> {
+    read_to_string_async(path)
+        .await?
+        .trim()
+        .parse()
+        .map_err(|_| std::io::ErrorKind::InvalidData.into())
+}
+
+/// Find all channels of all hwmon devices. The numbering of the devices
+/// depends on the probe order, so they are identified by their names.
+/// Returns the channels, the inputs to poll and the paths of the PWM
+/// outputs that control fans.
+fn discover(bb: &mut BrokerBuilder) -> (Vec<HwmonChannel>, Vec<Input>, Vec<String>) {
+    let mut channels = Vec::new();
+    let mut inputs = Vec::new();
+    let mut fans = Vec::new();
+
+    let mut dirs = list_dir(Path::new(HWMON_DEVICES)).unwrap_or_else(|e| {
+        warn!("Failed to list hwmon devices: {e}");
+        Vec::new()
+    });
+
+    dirs.sort();
+
+    for dir in dirs {
+        let dir_path = Path::new(HWMON_DEVICES).join(&dir);
+
+        let name = match read_to_string(&dir_path.join("name")) {
+            Ok(name) => name.trim().to_string(),
+            Err(_) => dir.clone(),
+        };
+
+        // Multiple instances of the same sensor chip share a name
+        let device = match channels.iter().any(|c: &HwmonChannel| c.device == name) {
+            true => format!("{name}-{dir}"),
+            false => name,
+        };
+
+        let mut attributes = list_dir(&dir_path).unwrap_or_default();
+        attributes.sort();
+
+        for attribute in attributes {
+            if is_pwm(&attribute) {
+                fans.push(dir_path.join(&attribute).to_string_lossy().into_owned());
+                continue;
+            }
+
+            let (channel, unit, scale) = match parse_attribute(&attribute) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+
+            let label = read_to_string(&dir_path.join(format!("{channel}_label")))
+                .ok()
+                .map(|l| l.trim().to_string());
+
+            let path = format!("/v1/tac/hwmon/{device}/{channel}");
+
+            inputs.push(Input {
+                path: dir_path.join(&attribute).to_string_lossy().into_owned(),
+                scale,
+                topic: bb.topic_ro(&path, None),
+                history: Arc::new(Mutex::new(History::new())),
+                is_soc: device == SOC_DEVICE && channel == SOC_CHANNEL,
+                last: None,
+                failed: false,
+            });
+
+            channels.push(HwmonChannel {
+                device: device.clone(),
+                channel: channel.to_string(),
+                label,
+                unit: unit.to_string(),
+                topic: path,
+            });
+        }
+    }
+
+    (channels, inputs, fans)
+}
+
 pub struct Temperatures {
     pub soc_temperature: Arc<Topic<Measurement>>,
-    run: Option<Arc<AtomicBool>>,
+    pub thermal_state: Arc<Topic<ThermalState>>,
+    pub status: Arc<Topic<SubsystemStatus>>,
+    histories: Vec<(String, String, Arc<Mutex<History>>)>,
+    history_aliases: Vec<(String, Arc<Mutex<History>>)>,
+    shutdown: ShutdownToken,
 }
 
 impl Temperatures {
     pub fn new(bb: &mut BrokerBuilder) -> Self {
-        let run = Arc::new(AtomicBool::new(true));
-        let soc_temperature = bb.topic_ro("/v1/tac/temperatures/soc", None);
+        let shutdown = ShutdownToken::default();
+        let status = bb.topic_ro("/v1/tac/subsystems/temperatures", None);
+        let soc_temperature = bb.topic_ro::<Measurement>("/v1/tac/temperatures/soc", None);
+
+        let (channels, mut inputs, fans) = discover(bb);
+
+        if !fans.is_empty() {
+            fan::setup(bb, fans, soc_temperature.clone());
+        }
+
+        // Keep a downsampled history of every channel. The SoC temperature
+        // is recorded under its own name and its hwmon endpoint is only an
+        // alias, so that it is not stored and exported twice.
+        let mut histories = Vec::new();
+        let mut history_aliases = Vec::new();
+
+        for (channel, input) in channels.iter().zip(inputs.iter()) {
+            let path = format!("{}/history", channel.topic);
+
+            if input.is_soc {
+                histories.push((
+                    "soc-temperature".to_string(),
+                    "/v1/tac/temperatures/soc/history".to_string(),
+                    input.history.clone(),
+                ));
+                history_aliases.push((path, input.history.clone()));
+            } else {
+                histories.push((
+                    format!("{}/{}", channel.device, channel.channel),
+                    path,
+                    input.history.clone(),
+                ));
+            }
+        }
+
+        if !inputs.iter().any(|input| input.is_soc) {
+            warn!("Found no hwmon channel {SOC_DEVICE}/{SOC_CHANNEL} for the SoC temperature");
+        }
+
+        bb.topic_ro("/v1/tac/hwmon", Some(channels));
+
+        let thresholds = bb.topic(
+            "/v1/tac/temperatures/thresholds",
+            true,
+            true,
+            true,
+            Some(ThermalThresholds::default()),
+            1,
+        );
+        let thermal_state = bb.topic_ro("/v1/tac/temperatures/state", Some(ThermalState::Normal));
+
+        let thermal_state_task = thermal_state.clone();
+        let (mut soc_events, _) = soc_temperature.clone().subscribe_unbounded();
+
+        spawn(async move {
+            let mut debounce = StateDebounce::default();
+
+            while let Some(meas) = soc_events.next().await {
+                if meas.validity != Validity::Valid {
+                    continue;
+                }
+
+                let thresholds = thresholds.try_get().unwrap_or_default();
+                let min_duration = Duration::from_millis(thresholds.min_duration_ms);
+                let prev = thermal_state_task.try_get().unwrap_or(ThermalState::Normal);
+                let next = prev.next(&thresholds, meas.value);
 
-        let run_thread = run.clone();
-        let soc_temperature_thread = soc_temperature.clone();
+                if let Some(next) = debounce.update(prev, next, meas.ts.as_instant(), min_duration)
+                {
+                    warn!(
+                        "SoC temperature is {} degrees. State is {next:?}",
+                        meas.value
+                    );
+                    thermal_state_task.set(next);
+                }
+            }
+        });
+
+        let shutdown_task = shutdown.clone();
+        let soc_temperature_task = soc_temperature.clone();
+        let status_task = status.clone();
+
+        spawn(async move {
+            while !shutdown_task.is_shutdown() {
+                for input in inputs.iter_mut() {
+                    let meas = match read_value(&input.path).await {
+                        Ok(val) => {
+                            let val = val * input.scale;
+                            input.last = Some(val);
+                            input.failed = false;
+
+                            let meas = Measurement::now(val);
+                            input.history.lock().unwrap().add(&meas);
+                            meas
+                        }
+                        Err(e) => {
+                            // Only warn once and not on every update
+                            if !input.failed {
+                                warn!("Failed to read {}: {e}", input.path);
+                                input.failed = true;
+                            }
+
+                            Measurement::read_error(input.last)
+                        }
+                    };
+
+                    if input.is_soc {
+                        soc_temperature_task.set(meas);
+                    }
+
+                    input.topic.set(meas);
+                }
 
-        spawn_blocking(move || {
-            while run_thread.load(Ordering::Relaxed) {
-                let val = HwMon::new("hwmon0")
-                    .unwrap()
-                    .temp(1)
-                    .unwrap()
-                    .input()
-                    .unwrap();
+                // A missing channel should not take the rest of the
+                // temperatures down with it, so only report a degraded state.
+                // Without the SoC temperature the thermal protection is blind
+                // however, which is reported as a failure.
+                let failed = inputs.iter().filter(|input| input.failed).count();
+                let soc_failed = inputs.iter().any(|input| input.is_soc && input.failed);
 
-                let meas = Measurement::now(val as f32 / 1000.0);
-                soc_temperature_thread.set(meas);
+                let current = match (soc_failed, failed) {
+                    (true, _) => SubsystemStatus::failed("Failed to read the SoC temperature"),
+                    (false, 0) => SubsystemStatus::Ok,
+                    (false, n) => {
+                        SubsystemStatus::degraded(&format!("Failed to read {n} hwmon channels"))
+                    }
+                };
 
-                sleep(UPDATE_INTERVAL);
+                report(&status_task, current);
+
+                sleep(UPDATE_INTERVAL).await;
             }
         });
 
         Self {
             soc_temperature,
-            run: Some(run),
+            thermal_state,
+            status,
+            histories,
+            history_aliases,
+            shutdown,
         }
     }
+
+    /// The histories of the temperatures (and other hwmon channels) by name
+    /// and with the path of the endpoint to query them
+    pub fn histories(&self) -> Vec<(String, String, Arc<Mutex<History>>)> {
+        self.histories.clone()
+    }
+
+    /// Additional endpoint paths under which some of the histories are
+    /// available as well
+    pub fn history_aliases(&self) -> Vec<(String, Arc<Mutex<History>>)> {
+        self.history_aliases.clone()
+    }
 }
 
 impl Drop for Temperatures {
     fn drop(&mut self) {
-        self.run.take().unwrap().store(false, Ordering::Relaxed);
+        self.shutdown.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{is_pwm, parse_attribute, StateDebounce, ThermalState, ThermalThresholds};
+
+    #[test]
+    fn attributes() {
+        assert_eq!(parse_attribute("temp1_input"), Some(("temp1", "°C", 0.001)));
+        assert_eq!(parse_attribute("in12_input"), Some(("in12", "V", 0.001)));
+        assert_eq!(parse_attribute("fan2_input"), Some(("fan2", "RPM", 1.0)));
+        assert_eq!(parse_attribute("temp1_label"), None);
+        assert_eq!(parse_attribute("temp_input"), None);
+        assert_eq!(parse_attribute("intrusion0_input"), None);
+        assert_eq!(parse_attribute("name"), None);
+
+        assert!(is_pwm("pwm1"));
+        assert!(!is_pwm("pwm1_enable"));
+        assert!(!is_pwm("pwm"));
+    }
+
+    #[test]
+    fn thermal_states() {
+        let thresholds = ThermalThresholds {
+            warning: 80.0,
+            critical: 100.0,
+            hysteresis: 5.0,
+            min_duration_ms: 0,
+        };
+
+        let steps = [
+            (70.0, ThermalState::Normal),
+            (80.0, ThermalState::Warning),
+            (76.0, ThermalState::Warning),
+            (74.0, ThermalState::Normal),
+            (101.0, ThermalState::Critical),
+            (96.0, ThermalState::Critical),
+            (94.0, ThermalState::Warning),
+            (60.0, ThermalState::Normal),
+        ];
+
+        let mut state = ThermalState::Normal;
+
+        for (temperature, expected) in steps.iter() {
+            state = state.next(&thresholds, *temperature);
+            assert_eq!(state, *expected, "at {temperature}");
+        }
+    }
+
+    #[test]
+    fn thermal_state_debounce() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let min_duration = Duration::from_millis(100);
+        let mut debounce = StateDebounce::default();
+
+        let normal = ThermalState::Normal;
+        let warning = ThermalState::Warning;
+
+        // A short excursion is ignored
+        assert_eq!(debounce.update(normal, warning, at(0), min_duration), None);
+        assert_eq!(debounce.update(normal, normal, at(50), min_duration), None);
+        assert_eq!(
+            debounce.update(normal, warning, at(100), min_duration),
+            None
+        );
+
+        // A persistent one is not
+        assert_eq!(
+            debounce.update(normal, warning, at(150), min_duration),
+            None
+        );
+        assert_eq!(
+            debounce.update(normal, warning, at(200), min_duration),
+            Some(warning)
+        );
+        assert_eq!(
+            debounce.update(warning, warning, at(250), min_duration),
+            None
+        );
     }
 }
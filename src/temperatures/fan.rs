@@ -0,0 +1,205 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::hw::write_async;
+use crate::broker::{BrokerBuilder, Topic};
+use crate::measurement::{Measurement, Validity};
+
+/// The raw value hwmon PWM outputs use for full speed
+const PWM_MAX: f32 = 255.0;
+
+/// `pwmN_enable` value that puts the output under manual (our) control
+const PWM_ENABLE_MANUAL: &str = "1";
+
+/// The fan duty cycle (in percent) to use at a given temperature
+/// (in degrees Celsius)
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct CurvePoint {
+    pub temperature: f32,
+    pub duty: f32,
+}
+
+fn default_curve() -> Vec<CurvePoint> {
+    vec![
+        CurvePoint {
+            temperature: 40.0,
+            duty: 0.0,
+        },
+        CurvePoint {
+            temperature: 60.0,
+            duty: 50.0,
+        },
+        CurvePoint {
+            temperature: 80.0,
+            duty: 100.0,
+        },
+    ]
+}
+
+/// Check that all values of the curve are finite and that its temperatures
+/// are strictly increasing, so that there is a single duty cycle for every
+/// temperature.
+fn curve_valid(curve: &[CurvePoint]) -> bool {
+    let finite = curve
+        .iter()
+        .all(|point| point.temperature.is_finite() && point.duty.is_finite());
+
+    let increasing = curve
+        .windows(2)
+        .all(|pair| pair[0].temperature < pair[1].temperature);
+
+    finite && increasing
+}
+
+/// Interpolate the duty cycle linearly between the points of the curve.
+/// An empty or invalid curve runs the fan at full speed to be on the safe
+/// side.
+fn curve_duty(curve: &[CurvePoint], temperature: f32) -> f32 {
+    if !curve_valid(curve) {
+        return 100.0;
+    }
+
+    let (first, last) = match (curve.first(), curve.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return 100.0,
+    };
+
+    if temperature <= first.temperature {
+        return first.duty;
+    }
+
+    for pair in curve.windows(2) {
+        let (lo, hi) = (pair[0], pair[1]);
+
+        if temperature <= hi.temperature {
+            let pos = (temperature - lo.temperature) / (hi.temperature - lo.temperature);
+            return lo.duty + pos * (hi.duty - lo.duty);
+        }
+    }
+
+    last.duty
+}
+
+/// Control the speed of the fans based on the SoC temperature
+pub(super) fn setup(
+    bb: &mut BrokerBuilder,
+    fans: Vec<String>,
+    soc_temperature: Arc<Topic<Measurement>>,
+) {
+    let curve = bb.topic(
+        "/v1/tac/fan/curve",
+        true,
+        true,
+        true,
+        Some(default_curve()),
+        1,
+    );
+    let manual: Arc<Topic<Option<f32>>> = bb.topic_rw("/v1/tac/fan/override", Some(None));
+    let duty = bb.topic_ro("/v1/tac/fan/duty", None);
+
+    let (mut temperature_events, _) = soc_temperature.subscribe_unbounded();
+    let (mut curve_events, _) = curve.clone().subscribe_unbounded();
+    let curve_task = curve.clone();
+
+    // Reject curves that do not map every temperature to a single duty
+    // cycle by going back to the last valid one.
+    spawn(async move {
+        let mut prev_valid = default_curve();
+
+        while let Some(new) = curve_events.next().await {
+            if curve_valid(&new) {
+                prev_valid = new;
+            } else {
+                warn!("Rejecting fan curve with unsorted, duplicate or invalid points: {new:?}");
+                curve_task.set(prev_valid.clone());
+            }
+        }
+    });
+
+    spawn(async move {
+        for fan in fans.iter() {
+            if let Err(e) = write_async(&format!("{fan}_enable"), PWM_ENABLE_MANUAL).await {
+                warn!("Failed to put fan {fan} into manual mode: {e}");
+            }
+        }
+
+        let mut prev_raw = None;
+
+        while let Some(meas) = temperature_events.next().await {
+            let auto = match meas.validity {
+                Validity::ReadError => 100.0,
+                _ => curve_duty(&curve.try_get().unwrap_or_default(), meas.value),
+            };
+
+            let new_duty = manual.try_get().flatten().unwrap_or(auto).clamp(0.0, 100.0);
+            let raw = (new_duty / 100.0 * PWM_MAX).round() as u8;
+
+            if prev_raw == Some(raw) {
+                continue;
+            }
+
+            for fan in fans.iter() {
+                if let Err(e) = write_async(fan, &raw.to_string()).await {
+                    warn!("Failed to set the speed of fan {fan}: {e}");
+                }
+            }
+
+            duty.set(new_duty);
+            prev_raw = Some(raw);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{curve_duty, curve_valid, default_curve, CurvePoint};
+
+    #[test]
+    fn curve() {
+        let curve = default_curve();
+
+        assert_eq!(curve_duty(&curve, 20.0), 0.0);
+        assert_eq!(curve_duty(&curve, 50.0), 25.0);
+        assert_eq!(curve_duty(&curve, 60.0), 50.0);
+        assert_eq!(curve_duty(&curve, 70.0), 75.0);
+        assert_eq!(curve_duty(&curve, 95.0), 100.0);
+
+        assert_eq!(curve_duty(&[], 20.0), 100.0);
+    }
+
+    #[test]
+    fn invalid_curve() {
+        let point = |temperature, duty| CurvePoint { temperature, duty };
+
+        let duplicate = [point(40.0, 0.0), point(60.0, 20.0), point(60.0, 80.0)];
+        let unsorted = [point(60.0, 50.0), point(40.0, 0.0)];
+        let nan = [point(40.0, 0.0), point(f32::NAN, 50.0)];
+
+        for curve in [&duplicate[..], &unsorted[..], &nan[..]] {
+            assert!(!curve_valid(curve));
+            assert_eq!(curve_duty(curve, 60.0), 100.0);
+        }
+
+        assert!(curve_valid(&default_curve()));
+    }
+}
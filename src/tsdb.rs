@@ -0,0 +1,382 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::HashMap;
+use std::fs::{create_dir_all, read_dir, remove_file, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::{sleep, spawn, spawn_blocking};
+use chrono::{Duration as ChronoDuration, NaiveDate, TimeZone, Utc};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tide::{Body, Request, Response, Server};
+
+use crate::adc::{Adc, Bucket, History, Resolution};
+use crate::alarms::Alarms;
+use crate::broker::Topic;
+use crate::dut_power::DutPwrThread;
+use crate::temperatures::Temperatures;
+
+#[cfg(feature = "demo_mode")]
+const STORE_PATH: &str = "demo_files/srv/tacd/tsdb";
+
+#[cfg(not(feature = "demo_mode"))]
+const STORE_PATH: &str = "/srv/tacd/tsdb";
+
+/// Number of daily files (including the current one) to keep on disk
+const RETENTION_DAYS: i64 = 7;
+
+/// How often completed minute buckets are written to disk
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Something that happened on the TAC, like the DUT being powered on or an
+/// alarm being triggered
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Event {
+    /// Seconds since the unix epoch
+    time: u64,
+    source: String,
+    value: Value,
+}
+
+/// A line in one of the daily files of the store
+#[derive(Serialize, Deserialize)]
+enum Record {
+    Bucket { channel: String, bucket: Bucket },
+    Event(Event),
+}
+
+impl Record {
+    fn time(&self) -> u64 {
+        match self {
+            Self::Bucket { bucket, .. } => bucket.start,
+            Self::Event(event) => event.time,
+        }
+    }
+}
+
+/// Query parameters for the events endpoint.
+/// `from` and `to` are given in seconds since the unix epoch.
+#[derive(Deserialize)]
+struct EventsQuery {
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn day(secs: u64) -> Option<NaiveDate> {
+    Utc.timestamp_opt(secs as i64, 0)
+        .single()
+        .map(|ts| ts.date_naive())
+}
+
+fn day_path(day: NaiveDate) -> PathBuf {
+    Path::new(STORE_PATH).join(day.format("%Y-%m-%d.ndjson").to_string())
+}
+
+fn path_day(path: &Path) -> Option<NaiveDate> {
+    let name = path.file_name()?.to_str()?.strip_suffix(".ndjson")?;
+
+    NaiveDate::parse_from_str(name, "%Y-%m-%d").ok()
+}
+
+/// Measurements and events stored in one file of newline delimited JSON
+/// records per (UTC) day, so that old data can be dropped by deleting files
+/// and a record that was cut short by a power loss only affects itself.
+struct Store {
+    lock: Mutex<()>,
+}
+
+impl Store {
+    fn new() -> Self {
+        Self {
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn append(&self, records: &[Record]) -> Result<()> {
+        let _guard = self.lock.lock().unwrap();
+
+        create_dir_all(STORE_PATH)?;
+
+        for record in records {
+            let day = match day(record.time()) {
+                Some(day) => day,
+                None => continue,
+            };
+
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(day_path(day))?;
+
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// The daily files sorted by day
+    fn files(&self) -> Vec<(NaiveDate, PathBuf)> {
+        let mut files: Vec<_> = match read_dir(STORE_PATH) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter_map(|path| path_day(&path).map(|day| (day, path)))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        files.sort();
+        files
+    }
+
+    /// Read the records from the time span from `from` to `to`
+    /// (in seconds since the unix epoch)
+    fn read(&self, from: u64, to: u64) -> Vec<Record> {
+        let _guard = self.lock.lock().unwrap();
+
+        let first = day(from);
+        let last = day(to);
+
+        let mut records = Vec::new();
+        let mut broken = 0;
+
+        for (day, path) in self.files() {
+            if first.map(|f| day < f).unwrap_or(false) || last.map(|l| day > l).unwrap_or(false) {
+                continue;
+            }
+
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    warn!("Failed to open {}: {e}", path.display());
+                    continue;
+                }
+            };
+
+            for line in BufReader::new(file).lines() {
+                match line.map(|l| serde_json::from_str::<Record>(&l)) {
+                    Ok(Ok(record)) if record.time() >= from && record.time() <= to => {
+                        records.push(record)
+                    }
+                    Ok(Ok(_)) => {}
+                    _ => broken += 1,
+                }
+            }
+        }
+
+        if broken > 0 {
+            warn!("Skipped {broken} unreadable records in {STORE_PATH}");
+        }
+
+        records
+    }
+
+    /// Remove the daily files that are older than the retention period
+    fn cleanup(&self) {
+        let _guard = self.lock.lock().unwrap();
+
+        let oldest = match day(now_secs()) {
+            Some(today) => today - ChronoDuration::days(RETENTION_DAYS - 1),
+            None => return,
+        };
+
+        for (day, path) in self.files() {
+            if day < oldest {
+                if let Err(e) = remove_file(&path) {
+                    warn!("Failed to remove {}: {e}", path.display());
+                }
+            }
+        }
+    }
+}
+
+/// Load the minute buckets from disk into the histories of the channels.
+///
+/// Returns the start of the newest bucket on disk per channel, so that
+/// they are not written again.
+fn restore(store: &Store, histories: &[(String, Arc<Mutex<History>>)]) -> HashMap<String, u64> {
+    let mut newest = HashMap::new();
+    let mut count = 0;
+
+    for record in store.read(0, u64::MAX) {
+        if let Record::Bucket { channel, bucket } = record {
+            if let Some((_, history)) = histories.iter().find(|(name, _)| *name == channel) {
+                history.lock().unwrap().restore(&bucket);
+
+                let start = newest.entry(channel).or_insert(bucket.start);
+                *start = (*start).max(bucket.start);
+
+                count += 1;
+            }
+        }
+    }
+
+    info!("Restored {count} history buckets from {STORE_PATH}");
+
+    newest
+}
+
+/// Periodically write the minute buckets that are complete to disk
+async fn flush(store: Arc<Store>, histories: Vec<(String, Arc<Mutex<History>>)>) {
+    let mut newest = {
+        let store = store.clone();
+        let histories = histories.clone();
+
+        spawn_blocking(move || restore(&store, &histories)).await
+    };
+
+    loop {
+        sleep(FLUSH_INTERVAL).await;
+
+        let now = now_secs();
+        let mut records = Vec::new();
+
+        for (name, history) in histories.iter() {
+            let buckets = history
+                .lock()
+                .unwrap()
+                .query(Resolution::Minute, 0, u64::MAX);
+
+            let written = newest.get(name).copied();
+
+            for bucket in buckets {
+                let complete = bucket.start + 60 <= now;
+                let is_new = written.map(|w| bucket.start > w).unwrap_or(true);
+
+                if complete && is_new {
+                    newest.insert(name.clone(), bucket.start);
+
+                    records.push(Record::Bucket {
+                        channel: name.clone(),
+                        bucket,
+                    });
+                }
+            }
+        }
+
+        // The file I/O may block for a while, e.g. on a slow SD card
+        let store = store.clone();
+
+        spawn_blocking(move || {
+            if let Err(e) = store.append(&records) {
+                warn!("Failed to write measurements to {STORE_PATH}: {e}");
+            }
+
+            store.cleanup();
+        })
+        .await;
+    }
+}
+
+/// Store every change of a topic as event
+fn record_events<E>(store: Arc<Store>, source: &'static str, topic: Arc<Topic<E>>)
+where
+    E: Serialize + DeserializeOwned + Send + Sync + Clone + 'static,
+{
+    spawn(async move {
+        let (mut values, _) = topic.subscribe_unbounded();
+
+        while let Some(value) = values.next().await {
+            let event = Event {
+                time: now_secs(),
+                source: source.to_string(),
+                value: serde_json::to_value(&value).unwrap_or(Value::Null),
+            };
+
+            let store = store.clone();
+
+            spawn_blocking(move || {
+                if let Err(e) = store.append(&[Record::Event(event)]) {
+                    warn!("Failed to write {source} event to {STORE_PATH}: {e}");
+                }
+            })
+            .await;
+        }
+    });
+}
+
+/// Keep downsampled measurements, alarms and DUT power events on disk,
+/// so that they survive a restart of the tacd and can be retrieved for
+/// longer time spans than the in-memory histories provide.
+pub fn setup(
+    server: &mut Server<()>,
+    adc: &Adc,
+    temperatures: &Temperatures,
+    alarms: &Alarms,
+    dut_pwr: &DutPwrThread,
+) {
+    let store = Arc::new(Store::new());
+
+    let histories = adc
+        .histories()
+        .into_iter()
+        .chain(temperatures.histories())
+        .map(|(name, _, history)| (name, history))
+        .collect();
+
+    spawn(flush(store.clone(), histories));
+
+    record_events(store.clone(), "dut-power", dut_pwr.state.clone());
+    record_events(store.clone(), "alarms", alarms.triggered.clone());
+
+    server.at("/v1/tac/events").get(move |req: Request<()>| {
+        let store = store.clone();
+
+        async move {
+            let query: EventsQuery = match req.query() {
+                Ok(query) => query,
+                Err(e) => {
+                    return Ok(Response::builder(400)
+                        .body(format!("Failed to parse query parameters: {e}"))
+                        .build())
+                }
+            };
+
+            let from = query.from.unwrap_or(0);
+            let to = query.to.unwrap_or(u64::MAX);
+
+            let events: Vec<Event> = spawn_blocking(move || store.read(from, to))
+                .await
+                .into_iter()
+                .filter_map(|record| match record {
+                    Record::Event(event) => Some(event),
+                    Record::Bucket { .. } => None,
+                })
+                .collect();
+
+            Ok(Response::builder(200)
+                .body(Body::from_json(&events)?)
+                .build())
+        }
+    });
+}
@@ -15,38 +15,63 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use async_std::prelude::*;
 use async_std::sync::{Arc, Mutex};
 use async_std::task::{sleep, spawn};
+use futures::stream::select;
 use tide::{Response, Server};
 
-use crate::broker::{BrokerBuilder, Topic};
-use crate::led::{BlinkPattern, BlinkPatternBuilder};
+use crate::backlight::IdleLevel;
+use crate::broker::{BrokerBuilder, Topic, WebSession};
+use crate::led::BlinkPattern;
 
+mod alerts;
 mod buttons;
+mod decimation;
 mod draw_fb;
+mod encoder;
+mod i18n;
+mod kiosk;
+mod layout;
+mod logo;
+mod mirror;
 mod screens;
+mod splash;
+mod touch;
 mod widgets;
 
-use buttons::{handle_buttons, ButtonEvent};
-use draw_fb::FramebufferDrawTarget;
-use screens::{MountableScreen, Screen};
+use buttons::{handle_authorized_events, handle_buttons, ButtonEvent, Source};
+use draw_fb::{FramebufferDrawTarget, Rotation, Theme, FRAME_INTERVAL};
+use i18n::Language;
+use kiosk::KioskMode;
+use screens::{MountableScreen, Screen, ScreenSaverAnimation};
+use widgets::TouchRegions;
+
+pub use splash::Splash;
 
 pub struct UiResources {
     pub adc: crate::adc::Adc,
+    pub alarms: crate::alarms::Alarms,
+    pub backlight: crate::backlight::Backlight,
+    pub cellular: crate::dbus::Cellular,
+    pub dhcp: crate::dhcp::Dhcp,
     pub dig_io: crate::digital_io::DigitalIo,
     pub dut_pwr: crate::dut_power::DutPwrThread,
     pub iobus: crate::iobus::IoBus,
     pub led: crate::led::Led,
+    pub logind: crate::dbus::Logind,
     pub network: crate::dbus::Network,
     pub rauc: crate::dbus::Rauc,
     pub regulators: crate::regulators::Regulators,
+    pub scheduler: crate::scheduler::Scheduler,
+    pub selfcheck: crate::selfcheck::SelfCheck,
     pub setup_mode: crate::setup_mode::SetupMode,
     pub system: crate::system::System,
     pub systemd: crate::dbus::Systemd,
     pub temperatures: crate::temperatures::Temperatures,
+    pub timedate: crate::dbus::TimeDate,
     pub usb_hub: crate::usb_hub::UsbHub,
 }
 
@@ -56,6 +81,11 @@ pub struct Ui {
     locator: Arc<Topic<bool>>,
     locator_dance: Arc<Topic<i32>>,
     buttons: Arc<Topic<ButtonEvent>>,
+    language: Arc<Topic<Language>>,
+    touch_regions: TouchRegions,
+    last_screen: Arc<Topic<Screen>>,
+    kiosk: Arc<Topic<Option<KioskMode>>>,
+    web_sessions: Arc<Topic<Vec<WebSession>>>,
     screens: Vec<Box<dyn MountableScreen>>,
     res: UiResources,
 }
@@ -75,19 +105,246 @@ fn serve_framebuffer(server: &mut Server<()>, draw_target: Arc<Mutex<Framebuffer
     });
 }
 
+/// The on/off cadence of the locator. A double blink, so it stands out from
+/// other blinking lights in a rack.
+const LOCATOR_CADENCE: [(bool, Duration); 4] = [
+    (true, Duration::from_millis(150)),
+    (false, Duration::from_millis(150)),
+    (true, Duration::from_millis(150)),
+    (false, Duration::from_millis(550)),
+];
+
+/// Blink the status LED and (if enabled via `flash`) flash the whole screen
+/// in the locator cadence while the locator is active.
+///
+/// Both are driven from the same task, so that they stay in sync.
+fn blink_locator(
+    res: &UiResources,
+    locator: &Arc<Topic<bool>>,
+    flash: &Arc<Topic<bool>>,
+    draw_target: &Arc<Mutex<FramebufferDrawTarget>>,
+) {
+    let led_status_pattern = res.led.status.clone();
+    let led_status_color = res.led.status_color.clone();
+    let locator = locator.clone();
+    let flash = flash.clone();
+    let draw_target = draw_target.clone();
+
+    spawn(async move {
+        let (mut locator_stream, _) = locator.clone().subscribe_unbounded();
+
+        while let Some(active) = locator_stream.next().await {
+            if !active {
+                continue;
+            }
+
+            // White blinking when locator is on
+            led_status_color.set((1.0, 1.0, 1.0));
+
+            // Use absolute deadlines so the cadence does not drift
+            let mut deadline = Instant::now();
+
+            while locator.try_get().unwrap_or(false) {
+                for (on, duration) in LOCATOR_CADENCE {
+                    let flash_screen = flash.try_get().unwrap_or(false);
+
+                    led_status_pattern.set(BlinkPattern::solid(if on { 1.0 } else { 0.0 }));
+                    draw_target.lock().await.set_flash(on && flash_screen);
+
+                    deadline += duration;
+                    sleep(deadline.saturating_duration_since(Instant::now())).await;
+                }
+            }
+
+            // Green light when locator is off
+            draw_target.lock().await.set_flash(false);
+            led_status_color.set((0.0, 1.0, 0.0));
+            led_status_pattern.set(BlinkPattern::solid(1.0));
+        }
+    });
+}
+
+/// Pressing both buttons at once acknowledges the alerts shown in the
+/// banner or, if there are none, returns to the menu.
+/// Only chords from the buttons on the TAC itself are handled, so that alerts
+/// can not be acknowledged remotely without anybody looking at them.
+fn handle_chord(
+    buttons: &Arc<Topic<ButtonEvent>>,
+    alerts: &Arc<Topic<Vec<alerts::Alert>>>,
+    screen: &Arc<Topic<Screen>>,
+) {
+    let (mut button_events, _) = buttons.clone().subscribe_unbounded();
+    let alerts = alerts.clone();
+    let screen = screen.clone();
+
+    spawn(async move {
+        while let Some(ev) = button_events.next().await {
+            if !matches!(ev, ButtonEvent::Chord { src: Source::Local }) {
+                continue;
+            }
+
+            if alerts::acknowledge(&alerts) {
+                continue;
+            }
+
+            // Do not leave screens that wait for something to finish,
+            // like an update being installed.
+            let curr = screen.try_get().unwrap_or(Screen::ScreenSaver);
+
+            if curr.use_screensaver() {
+                screen.set(Screen::Menu);
+            }
+        }
+    });
+}
+
+/// Remember the shown screen in the persistent `last_screen` topic.
+///
+/// Nothing is written while the kiosk mode is active, as it would cycle
+/// through screens (and write to disk) all day and will start over after
+/// a restart anyways.
+fn remember_screen(
+    screen: &Arc<Topic<Screen>>,
+    last_screen: &Arc<Topic<Screen>>,
+    kiosk: &Arc<Topic<Option<KioskMode>>>,
+) {
+    let (mut screen_events, _) = screen.clone().subscribe_unbounded();
+    let last_screen = last_screen.clone();
+    let kiosk = kiosk.clone();
+
+    spawn(async move {
+        while let Some(screen) = screen_events.next().await {
+            let in_kiosk_mode = kiosk.try_get().flatten().is_some();
+
+            if !screen.restorable() || in_kiosk_mode {
+                continue;
+            }
+
+            last_screen.modify(|prev| match prev != Some(screen) {
+                true => Some(screen),
+                false => None,
+            });
+        }
+    });
+}
+
 impl Ui {
-    pub fn new(bb: &mut BrokerBuilder, res: UiResources, server: &mut Server<()>) -> Self {
+    pub fn new(
+        bb: &mut BrokerBuilder,
+        res: UiResources,
+        server: &mut Server<()>,
+        splash: Splash,
+    ) -> Self {
         let screen = bb.topic_rw("/v1/tac/display/screen", Some(Screen::ScreenSaver));
         let locator = bb.topic_rw("/v1/tac/display/locator", Some(false));
         let locator_dance = bb.topic_ro("/v1/tac/display/locator_dance", Some(0));
         let buttons = bb.topic("/v1/tac/display/buttons", true, true, false, None, 0);
+        handle_authorized_events(bb, buttons.clone());
+
+        // Time in seconds without button presses before the screensaver is
+        // activated. `None` disables the screensaver.
+        let screensaver_timeout = bb.topic(
+            "/v1/tac/display/screensaver_timeout",
+            true,
+            true,
+            true,
+            Some(Some(600)),
+            1,
+        );
+
+        // What to show while the screensaver is active
+        let screensaver_animation = bb.topic(
+            "/v1/tac/display/screensaver_animation",
+            true,
+            true,
+            true,
+            Some(ScreenSaverAnimation::BouncingHostname),
+            1,
+        );
+
+        // The language the screens are shown in
+        let language = bb.topic(
+            "/v1/tac/display/language",
+            true,
+            true,
+            true,
+            Some(Language::English),
+            1,
+        );
+
+        // Conditions that should be brought to the user's attention no
+        // matter which screen is currently shown.
+        let alerts = bb.topic_ro("/v1/tac/display/alerts", Some(Vec::new()));
+        alerts::watch(&res, &alerts);
+        handle_chord(&buttons, &alerts, &screen);
+
+        // Cycle through a list of screens without user interaction,
+        // e.g. for TACs mounted in a demo wall. `None` disables the kiosk mode.
+        let kiosk = bb.topic("/v1/tac/display/kiosk", true, true, true, Some(None), 1);
+        kiosk::cycle(&kiosk, &screen, &buttons);
+
+        // The screen that was shown last, so it can be shown again after
+        // a restart. It is restored once the persistent topics are loaded,
+        // which is why this happens in `run()`.
+        let last_screen = bb.topic("/v1/tac/display/last_screen", false, false, true, None, 1);
+
+        // The screensaver is not used while the kiosk mode is active
+        let idle_timeout = kiosk::idle_timeout(&screensaver_timeout, &kiosk);
+
+        // Dim the display a while before the screensaver kicks in
+        res.backlight.handle_idle(&buttons, &idle_timeout);
 
         // Initialize all the screens now so they can be mounted later
-        let screens: Vec<Box<dyn MountableScreen>> = screens::init(&res, &screen, &buttons);
+        let screens: Vec<Box<dyn MountableScreen>> = screens::init(
+            &res,
+            &screen,
+            &buttons,
+            &screensaver_timeout,
+            &idle_timeout,
+            &screensaver_animation,
+            &language,
+        );
+
+        // Rotate the display content for enclosures that mount the TAC
+        // e.g. upside down.
+        let rotation = bb.topic(
+            "/v1/tac/display/rotation",
+            true,
+            true,
+            true,
+            Some(Rotation::Rotate0),
+            1,
+        );
+
+        // Inverted colors and thicker text for better readability
+        let theme = bb.topic(
+            "/v1/tac/display/theme",
+            true,
+            true,
+            true,
+            Some(Theme::default()),
+            1,
+        );
 
         handle_buttons(
             "/dev/input/by-path/platform-gpio-keys-event",
             buttons.clone(),
+            rotation.clone(),
+        );
+
+        // Taps and swipes on a touch panel (if one is connected).
+        // Taps on widgets with a touch region trigger the widget's action,
+        // everything else is translated into button events.
+        let touch = bb.topic("/v1/tac/display/touch", true, true, false, None, 0);
+        let touch_regions = TouchRegions::default();
+
+        touch::handle_touch("/dev/input/touchscreen0", touch.clone(), rotation.clone());
+        touch::translate(&touch, &touch_regions, &buttons);
+
+        // A rotary encoder on an add-on front panel (if one is connected)
+        encoder::handle_encoder(
+            "/dev/input/by-path/platform-rotary-encoder-event",
+            buttons.clone(),
         );
 
         // Animated Locator for the locator widget
@@ -119,51 +376,119 @@ impl Ui {
             }
         });
 
-        // Blink the status LED when locator is active
-        let led_status_pattern = res.led.status.clone();
-        let led_status_color = res.led.status_color.clone();
-        let (mut locator_stream, _) = locator.clone().subscribe_unbounded();
+        // Take over the display from the startup splash screen.
+        // It is cleared once the first screen is mounted.
+        let draw_target = splash.into_draw_target();
+
+        // Write the changes made to the screen content out to the
+        // framebuffer once per frame.
+        let draw_target_task = draw_target.clone();
         spawn(async move {
-            let pattern_locator_on = BlinkPatternBuilder::new(0.0)
-                .fade_to(1.0, Duration::from_millis(100))
-                .stay_for(Duration::from_millis(300))
-                .fade_to(0.0, Duration::from_millis(100))
-                .stay_for(Duration::from_millis(500))
-                .forever();
-
-            let pattern_locator_off = BlinkPattern::solid(1.0);
-
-            while let Some(ev) = locator_stream.next().await {
-                if ev {
-                    // White blinking when locator is on
-                    led_status_color.set((1.0, 1.0, 1.0));
-                    led_status_pattern.set(pattern_locator_on.clone());
-                } else {
-                    // Green light when locator is off
-                    led_status_color.set((0.0, 1.0, 0.0));
-                    led_status_pattern.set(pattern_locator_off.clone());
-                }
+            loop {
+                sleep(FRAME_INTERVAL).await;
+                draw_target_task.lock().await.flush();
             }
         });
 
-        let draw_target = Arc::new(Mutex::new(FramebufferDrawTarget::new()));
-
         // Expose the framebuffer as png via the web interface
         serve_framebuffer(server, draw_target.clone());
 
+        // Allow replacing the logo on the splash screen and screensaver
+        logo::serve(server, res.setup_mode.setup_mode.clone());
+
+        // Stream changes to the display content to the web interface
+        let frames = bb.topic_ro("/v1/tac/display/frames", None);
+        mirror::run(&frames, draw_target.clone());
+
+        // Draw alert banners on top of the screens
+        alerts::draw_banner(&alerts, &language, draw_target.clone());
+
+        // Blink the status LED and flash the screen in sync when the
+        // locator is active
+        let locator_flash = bb.topic(
+            "/v1/tac/display/locator_flash",
+            true,
+            true,
+            true,
+            Some(true),
+            1,
+        );
+
+        blink_locator(&res, &locator, &locator_flash, &draw_target);
+
+        // Apply rotation changes to the whole screen content
+        let draw_target_task = draw_target.clone();
+        let (mut rotation_events, _) = rotation.subscribe_unbounded();
+        spawn(async move {
+            while let Some(rotation) = rotation_events.next().await {
+                draw_target_task.lock().await.set_rotation(rotation);
+            }
+        });
+
+        // Apply theme changes. Inverting the colors takes effect immediately,
+        // while static text is drawn in the large font once the next screen is mounted.
+        let draw_target_task = draw_target.clone();
+        let (mut theme_events, _) = theme.subscribe_unbounded();
+        spawn(async move {
+            while let Some(theme) = theme_events.next().await {
+                draw_target_task.lock().await.set_theme(theme);
+            }
+        });
+
         Self {
             draw_target,
             screen,
             locator,
             locator_dance,
             buttons,
+            language,
+            touch_regions,
+            last_screen,
+            kiosk,
+            web_sessions: bb.web_sessions(),
             screens,
             res,
         }
     }
 
+    /// The language the screens should be shown in
+    fn language(&self) -> Language {
+        self.language.try_get().unwrap_or_default()
+    }
+
     pub async fn run(mut self) -> Result<(), std::io::Error> {
-        let (mut screen_rx, _) = self.screen.clone().subscribe_unbounded();
+        enum Event {
+            Screen(Screen),
+            Idle(IdleLevel),
+            Button(ButtonEvent),
+            Language,
+        }
+
+        // Show the screen that was shown before the tacd was restarted,
+        // unless something else (like the setup mode) already took over.
+        if let Some(last) = self.last_screen.try_get() {
+            if last.restorable() {
+                self.screen.modify(|curr| match curr {
+                    None | Some(Screen::ScreenSaver) => Some(last),
+                    Some(_) => None,
+                });
+            }
+        }
+
+        remember_screen(&self.screen, &self.last_screen, &self.kiosk);
+
+        let (screen_rx, _) = self.screen.clone().subscribe_unbounded();
+        let (idle_rx, _) = self.res.backlight.idle.clone().subscribe_unbounded();
+        let (buttons_rx, _) = self.buttons.clone().subscribe_unbounded();
+        let (language_rx, _) = self.language.clone().subscribe_unbounded();
+
+        let mut events = select(
+            select(
+                screen_rx.map(Event::Screen),
+                language_rx.map(|_| Event::Language),
+            ),
+            select(idle_rx.map(Event::Idle), buttons_rx.map(Event::Button)),
+        );
 
         // Take the screens out of self so we can hand out references to self
         // to the screen mounting methods.
@@ -175,34 +500,86 @@ impl Ui {
 
         let mut curr_screen_type = None;
 
-        while let Some(next_screen_type) = screen_rx.next().await {
-            // Only unmount / mount the shown screen if a change was requested
-            let should_change = curr_screen_type
-                .map(|c| c != next_screen_type)
-                .unwrap_or(true);
-
-            if should_change {
-                // Find the currently shown screen (if any) and unmount it
-                if let Some(curr) = curr_screen_type {
-                    if let Some(screen) = screens.iter_mut().find(|s| s.is_my_type(curr)) {
-                        screen.unmount().await;
+        // Is the display panel powered down due to prolonged inactivity?
+        // No screen is mounted while it is, so that no updates are drawn
+        // and the button press that wakes the display does not trigger an
+        // action.
+        let mut asleep = false;
+
+        while let Some(ev) = events.next().await {
+            match ev {
+                Event::Screen(next_screen_type) => {
+                    // Only unmount / mount the shown screen if a change was requested
+                    let should_change = curr_screen_type
+                        .map(|c| c != next_screen_type)
+                        .unwrap_or(true);
+
+                    if should_change {
+                        // A screen change while asleep is only remembered and
+                        // performed when waking up.
+                        if !asleep {
+                            unmount_screen(&mut screens, curr_screen_type).await;
+                            self.mount_screen(&mut screens, next_screen_type).await;
+                        }
+
+                        curr_screen_type = Some(next_screen_type);
                     }
                 }
+                Event::Language if !asleep => {
+                    // Mount the current screen again to redraw the static
+                    // text in the new language.
+                    if let Some(curr) = curr_screen_type {
+                        unmount_screen(&mut screens, Some(curr)).await;
+                        self.mount_screen(&mut screens, curr).await;
+                    }
+                }
+                Event::Idle(IdleLevel::Off) if !asleep => {
+                    unmount_screen(&mut screens, curr_screen_type).await;
+                    self.draw_target.lock().await.set_panel_power(false);
+                    asleep = true;
+                }
+                Event::Button(ev) if asleep => {
+                    // Power up the panel right away, but only start handling
+                    // button events once the press that woke it up is over.
+                    self.draw_target.lock().await.set_panel_power(true);
 
-                // Clear the screen as static elements are not cleared by the
-                // widget framework magic
-                self.draw_target.lock().await.clear();
+                    if let ButtonEvent::Release { .. }
+                    | ButtonEvent::Chord { .. }
+                    | ButtonEvent::Scroll { .. } = ev
+                    {
+                        if let Some(curr) = curr_screen_type {
+                            self.mount_screen(&mut screens, curr).await;
+                        }
 
-                // Find the screen to show (if any) and "mount" it
-                // (e.g. tell it to handle the screen by itself).
-                if let Some(screen) = screens.iter_mut().find(|s| s.is_my_type(next_screen_type)) {
-                    screen.mount(&self).await;
+                        asleep = false;
+                    }
                 }
-
-                curr_screen_type = Some(next_screen_type);
+                _ => {}
             }
         }
 
         Ok(())
     }
+
+    /// Clear the display and let the screen of type `screen_type` take over
+    async fn mount_screen(&self, screens: &mut [Box<dyn MountableScreen>], screen_type: Screen) {
+        // Clear the screen as static elements are not cleared by the
+        // widget framework magic
+        self.draw_target.lock().await.clear();
+
+        // Find the screen to show (if any) and "mount" it
+        // (e.g. tell it to handle the screen by itself).
+        if let Some(screen) = screens.iter_mut().find(|s| s.is_my_type(screen_type)) {
+            screen.mount(self).await;
+        }
+    }
+}
+
+/// Find the currently shown screen (if any) and unmount it
+async fn unmount_screen(screens: &mut [Box<dyn MountableScreen>], screen_type: Option<Screen>) {
+    if let Some(curr) = screen_type {
+        if let Some(screen) = screens.iter_mut().find(|s| s.is_my_type(curr)) {
+            screen.unmount().await;
+        }
+    }
 }
@@ -0,0 +1,316 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::{Arc, Mutex};
+use async_std::task::spawn;
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Text},
+};
+use serde::{Deserialize, Serialize};
+
+use super::draw_fb::Overlay;
+use super::i18n::Language;
+use super::widgets::UI_TEXT_FONT;
+use super::{FramebufferDrawTarget, UiResources};
+use crate::alarms::{Condition, TriggeredAlarm};
+use crate::broker::Topic;
+use crate::dut_power::OutputState;
+use crate::temperatures::ThermalState;
+
+/// The area at the top of the screen (covering the screen title) that alert
+/// banners are drawn to.
+const BANNER_AREA: Rectangle = Rectangle::new(Point::new(0, 0), Size::new(240, 46));
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum AlertLevel {
+    Info,
+    Warning,
+    Critical,
+}
+
+/// The condition an alert was raised for.
+/// There is at most one active alert per source.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum AlertSource {
+    Alarm,
+    CurrentLimit,
+    DutPower,
+    Readback,
+    SelfCheck,
+    SocTemperature,
+    Update,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Alert {
+    pub source: AlertSource,
+    pub level: AlertLevel,
+    pub message: String,
+    /// Acknowledged alerts stay active but are no longer shown in the banner
+    pub acknowledged: bool,
+}
+
+/// Raise (`Some(alert)`) or clear (`None`) the alert for `source`
+fn set_alert(alerts: &Topic<Vec<Alert>>, source: AlertSource, alert: Option<(AlertLevel, &str)>) {
+    alerts.modify(|prev| {
+        let prev = prev.unwrap_or_default();
+
+        let mut next: Vec<Alert> = prev
+            .iter()
+            .filter(|a| a.source != source)
+            .cloned()
+            .collect();
+
+        if let Some((level, message)) = alert {
+            // Keep the acknowledgement as long as the alert does not change
+            let acknowledged = prev
+                .iter()
+                .find(|a| a.source == source)
+                .map(|a| a.acknowledged && a.level == level && a.message == message)
+                .unwrap_or(false);
+
+            next.push(Alert {
+                source,
+                level,
+                message: message.to_string(),
+                acknowledged,
+            });
+        }
+
+        // Show the most severe alerts first
+        next.sort_by(|a, b| b.level.partial_cmp(&a.level).unwrap());
+
+        match next == prev {
+            true => None,
+            false => Some(next),
+        }
+    })
+}
+
+/// Acknowledge all active alerts, which hides them from the banner.
+///
+/// Returns whether there were any alerts left to acknowledge.
+pub(super) fn acknowledge(alerts: &Topic<Vec<Alert>>) -> bool {
+    let mut any = false;
+
+    alerts.modify(|prev| {
+        let mut alerts = prev.unwrap_or_default();
+
+        any = alerts.iter().any(|a| !a.acknowledged);
+
+        if !any {
+            return None;
+        }
+
+        for alert in alerts.iter_mut() {
+            alert.acknowledged = true;
+        }
+
+        Some(alerts)
+    });
+
+    any
+}
+
+/// Raise and clear alerts based on the state of the other parts of the tacd
+pub(super) fn watch(res: &UiResources, alerts: &Arc<Topic<Vec<Alert>>>) {
+    let alerts_task = alerts.clone();
+    let (mut dut_pwr_events, _) = res.dut_pwr.state.clone().subscribe_unbounded();
+
+    spawn(async move {
+        while let Some(state) = dut_pwr_events.next().await {
+            let alert = match state {
+                OutputState::InvertedPolarity => Some("DUT power:\nInverted polarity"),
+                OutputState::OverCurrent => Some("DUT power:\nOvercurrent"),
+                OutputState::OverVoltage => Some("DUT power:\nOvervoltage"),
+                OutputState::RealtimeViolation => Some("DUT power:\nRealtime violation"),
+                _ => None,
+            };
+
+            set_alert(
+                &alerts_task,
+                AlertSource::DutPower,
+                alert.map(|msg| (AlertLevel::Critical, msg)),
+            );
+        }
+    });
+
+    let alerts_task = alerts.clone();
+    let (mut current_limit_events, _) = res
+        .dut_pwr
+        .current_limit_warning
+        .clone()
+        .subscribe_unbounded();
+
+    spawn(async move {
+        while let Some(over_limit) = current_limit_events.next().await {
+            let alert = match over_limit {
+                true => Some((AlertLevel::Warning, "DUT power:\nCurrent limit exceeded")),
+                false => None,
+            };
+
+            set_alert(&alerts_task, AlertSource::CurrentLimit, alert);
+        }
+    });
+
+    let alerts_task = alerts.clone();
+    let (mut thermal_events, _) = res.temperatures.thermal_state.clone().subscribe_unbounded();
+
+    spawn(async move {
+        while let Some(state) = thermal_events.next().await {
+            let alert = match state {
+                ThermalState::Normal => None,
+                ThermalState::Warning => Some((AlertLevel::Warning, "SoC temperature\nis high")),
+                ThermalState::Critical => {
+                    Some((AlertLevel::Critical, "SoC temperature\nis critical"))
+                }
+            };
+
+            set_alert(&alerts_task, AlertSource::SocTemperature, alert);
+        }
+    });
+
+    let alerts_task = alerts.clone();
+    let (mut alarm_events, _) = res.alarms.triggered.clone().subscribe_unbounded();
+
+    spawn(async move {
+        while let Some(triggered) = alarm_events.next().await {
+            // Only the first alarm fits into the banner
+            let message = triggered.first().map(|TriggeredAlarm { rule, value: _ }| {
+                let condition = match rule.condition {
+                    Condition::Over => "over",
+                    Condition::Under => "under",
+                };
+
+                format!("Alarm: {}\n{condition} {}", rule.channel, rule.limit)
+            });
+
+            set_alert(
+                &alerts_task,
+                AlertSource::Alarm,
+                message.as_deref().map(|msg| (AlertLevel::Critical, msg)),
+            );
+        }
+    });
+
+    let alerts_task = alerts.clone();
+    let (mut mismatch_events, _) = res.selfcheck.mismatches.clone().subscribe_unbounded();
+
+    spawn(async move {
+        while let Some(mismatches) = mismatch_events.next().await {
+            // Only the first mismatch fits into the banner
+            let message = mismatches.first().map(|m| match m.output.as_str() {
+                "dut-power" => "Output mismatch:\nDUT power",
+                "out-0" => "Output mismatch:\nOUT 0",
+                "out-1" => "Output mismatch:\nOUT 1",
+                _ => "Output mismatch:\nIOBus",
+            });
+
+            set_alert(
+                &alerts_task,
+                AlertSource::SelfCheck,
+                message.map(|msg| (AlertLevel::Critical, msg)),
+            );
+        }
+    });
+
+    let alerts_task = alerts.clone();
+    let (mut readback_events, _) = res.dig_io.readback_mismatches.clone().subscribe_unbounded();
+
+    spawn(async move {
+        while let Some(mismatches) = readback_events.next().await {
+            let alert = match mismatches.is_empty() {
+                true => None,
+                false => Some((AlertLevel::Critical, "Output readback\nmismatch")),
+            };
+
+            set_alert(&alerts_task, AlertSource::Readback, alert);
+        }
+    });
+
+    let alerts_task = alerts.clone();
+    let (mut update_events, _) = res.rauc.update_available.clone().subscribe_unbounded();
+
+    spawn(async move {
+        while let Some(update) = update_events.next().await {
+            set_alert(
+                &alerts_task,
+                AlertSource::Update,
+                update.map(|_| (AlertLevel::Info, "Software update\navailable")),
+            );
+        }
+    });
+}
+
+/// Draw a banner for the most severe active alert on top of whatever screen
+/// is currently shown.
+pub(super) fn draw_banner(
+    alerts: &Arc<Topic<Vec<Alert>>>,
+    language: &Arc<Topic<Language>>,
+    draw_target: Arc<Mutex<FramebufferDrawTarget>>,
+) {
+    let (mut alert_events, _) = alerts.clone().subscribe_unbounded();
+    let language = language.clone();
+
+    spawn(async move {
+        while let Some(alerts) = alert_events.next().await {
+            let mut draw_target = draw_target.lock().await;
+
+            let pending: Vec<&Alert> = alerts.iter().filter(|a| !a.acknowledged).collect();
+
+            let alert = match pending.first() {
+                Some(alert) => alert,
+                None => {
+                    draw_target.set_overlay_area(Overlay::Alert, None);
+                    continue;
+                }
+            };
+
+            draw_target.set_overlay_area(Overlay::Alert, Some(BANNER_AREA));
+
+            let mut overlay = draw_target.overlay(Overlay::Alert);
+
+            // Draw the banner inverted so it stands out from the screen below
+            BANNER_AREA
+                .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+                .draw(&mut overlay)
+                .unwrap();
+
+            let lang = language.try_get().unwrap_or_default();
+            let message = lang.tr(&alert.message);
+
+            let text = match pending.len() {
+                1 => message.to_string(),
+                n => format!("{} (+{})", message, n - 1),
+            };
+
+            Text::with_alignment(
+                &text,
+                Point::new(120, 18),
+                MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::Off),
+                Alignment::Center,
+            )
+            .draw(&mut overlay)
+            .unwrap();
+        }
+    });
+}
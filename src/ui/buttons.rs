@@ -17,11 +17,15 @@
 
 use std::time::Duration;
 
+use async_std::prelude::*;
 use async_std::sync::Arc;
-use async_std::task::spawn_blocking;
+use async_std::task::{spawn, spawn_blocking};
+use log::warn;
 use serde::{Deserialize, Serialize};
 
-use crate::broker::Topic;
+use super::draw_fb::Rotation;
+use crate::broker::{BrokerBuilder, Topic};
+use crate::setup_mode::is_admin;
 
 pub const LONG_PRESS: Duration = Duration::from_millis(750);
 
@@ -75,7 +79,7 @@ pub enum PressDuration {
 }
 
 impl PressDuration {
-    fn from_duration(d: Duration) -> Self {
+    pub fn from_duration(d: Duration) -> Self {
         if d >= LONG_PRESS {
             Self::Long
         } else {
@@ -89,6 +93,8 @@ impl PressDuration {
 // E.g. going back to setup mode.
 // The #[default] together with the serde(skip) below prevents the web ui
 // from ever being able to simulate a local button press.
+// Only clients with the admin role can inject events that are handled like
+// local ones (see `handle_authorized_events`).
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
 pub enum Source {
     Local,
@@ -109,6 +115,19 @@ pub enum ButtonEvent {
         #[serde(skip)]
         src: Source,
     },
+    /// Both buttons were pressed at the same time.
+    /// The releases that end a chord are not reported as `Release` events.
+    Chord {
+        #[serde(skip)]
+        src: Source,
+    },
+    /// A rotary encoder was turned by `steps` detents.
+    /// Positive for clockwise, negative for counter-clockwise rotation.
+    Scroll {
+        steps: i32,
+        #[serde(skip)]
+        src: Source,
+    },
 }
 
 impl ButtonEvent {
@@ -126,17 +145,74 @@ impl ButtonEvent {
             src: Source::Local,
         }
     }
+
+    /// Treat this event as if it was caused by a local button press
+    fn as_local(self) -> Self {
+        match self {
+            Self::Press { btn, src: _ } => Self::Press {
+                btn,
+                src: Source::Local,
+            },
+            Self::Release { btn, dur, src: _ } => Self::Release {
+                btn,
+                dur,
+                src: Source::Local,
+            },
+            Self::Chord { src: _ } => Self::Chord { src: Source::Local },
+            Self::Scroll { steps, src: _ } => Self::Scroll {
+                steps,
+                src: Source::Local,
+            },
+        }
+    }
+}
+
+/// A button event injected by a client with the admin role
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuthorizedButtonEvent {
+    pub event: ButtonEvent,
+    /// The admin token set via /v1/tac/admin_token
+    pub token: String,
+}
+
+/// Forward button events injected by clients with the admin role into the
+/// `buttons` topic, as if the buttons on the TAC were pressed.
+pub fn handle_authorized_events(bb: &mut BrokerBuilder, buttons: Arc<Topic<ButtonEvent>>) {
+    let (mut events, _) = bb
+        .topic_wo::<AuthorizedButtonEvent>("/v1/tac/display/buttons/authorized", None)
+        .subscribe_unbounded();
+
+    spawn(async move {
+        while let Some(ev) = events.next().await {
+            if is_admin(&ev.token).await {
+                buttons.set(ev.event.as_local());
+            } else {
+                warn!("Ignoring injected button event without admin role");
+            }
+        }
+    });
 }
 
 /// Spawn a thread that blockingly reads user input and pushes them into
 /// a broker framework topic.
-pub fn handle_buttons(path: &'static str, topic: Arc<Topic<ButtonEvent>>) {
+///
+/// The upper and lower button are swapped if the display is rotated in a
+/// way that puts the upper button below (or right of) the lower one.
+pub fn handle_buttons(
+    path: &'static str,
+    topic: Arc<Topic<ButtonEvent>>,
+    rotation: Arc<Topic<Rotation>>,
+) {
     use super::*;
 
     spawn_blocking(move || {
         let mut device = Device::open(path).unwrap();
         let mut start_time = [None, None];
 
+        // Set once both buttons are held down at the same time and cleared
+        // once both are released again.
+        let mut in_chord = false;
+
         loop {
             for ev in device.fetch_events().unwrap() {
                 if ev.event_type() != EventType::KEY {
@@ -149,18 +225,36 @@ pub fn handle_buttons(path: &'static str, topic: Arc<Topic<ButtonEvent>>) {
                     _ => continue,
                 };
 
+                let swapped = rotation
+                    .try_get()
+                    .map(|r| r.swaps_buttons())
+                    .unwrap_or(false);
+
+                let btn_id = if swapped { 1 - id } else { id };
+
                 if ev.value() == 0 {
                     // Button release -> send event
                     if let Some(start) = start_time[id].take() {
+                        if in_chord {
+                            in_chord = start_time[1 - id].is_some();
+                            continue;
+                        }
+
                         if let Ok(duration) = ev.timestamp().duration_since(start) {
-                            let button_event = ButtonEvent::release_from_id_duration(id, duration);
+                            let button_event =
+                                ButtonEvent::release_from_id_duration(btn_id, duration);
                             topic.set(button_event);
                         }
                     }
                 } else {
                     // Button press -> register start time and send event
                     start_time[id] = Some(ev.timestamp());
-                    topic.set(ButtonEvent::press_from_id(id));
+                    topic.set(ButtonEvent::press_from_id(btn_id));
+
+                    if start_time[1 - id].is_some() && !in_chord {
+                        in_chord = true;
+                        topic.set(ButtonEvent::Chord { src: Source::Local });
+                    }
                 }
             }
         }
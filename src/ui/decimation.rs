@@ -0,0 +1,104 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use serde::{Deserialize, Serialize};
+
+use super::widgets::Envelope;
+use crate::adc::CalibratedChannel;
+use crate::broker::Topic;
+
+/// The envelopes of all channels and their most recent values
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct EnvelopeHistory {
+    /// One entry per column and oldest first.
+    /// The inner `Vec` contains one envelope per channel.
+    pub columns: Vec<Vec<Envelope>>,
+    /// The most recent sample of each channel
+    pub latest: Vec<f32>,
+}
+
+/// Record the min/max envelope of the fast ADC `channels` in buckets of
+/// `column_interval`, keeping the last `columns` of them.
+///
+/// Picking a single sample per graph column would hide short spikes
+/// (e.g. inrush currents) entirely, while the envelope of all samples in
+/// the bucket keeps them visible at any display resolution.
+pub fn envelope_history(
+    channels: Vec<CalibratedChannel>,
+    column_interval: Duration,
+    columns: usize,
+) -> Arc<Topic<EnvelopeHistory>> {
+    let history = Topic::anonymous(Some(EnvelopeHistory::default()));
+    let history_task = history.clone();
+    let blocks = CalibratedChannel::subscribe(&channels);
+
+    spawn(async move {
+        let mut finished = VecDeque::with_capacity(columns);
+        let mut column: Vec<Option<Envelope>> = vec![None; channels.len()];
+        let mut column_start: Option<Instant> = None;
+
+        while let Ok(block) = blocks.recv().await {
+            for i in 0..block.len() {
+                let ts = block.ts(i).as_instant();
+                let start = *column_start.get_or_insert(ts);
+
+                // The columns are defined by the time the samples were
+                // taken at, not by when they are received.
+                if ts.saturating_duration_since(start) >= column_interval {
+                    let next = start + column_interval;
+
+                    // Start over after gaps in the samples instead of
+                    // catching up column by column.
+                    column_start = match ts.saturating_duration_since(next) < column_interval {
+                        true => Some(next),
+                        false => Some(ts),
+                    };
+
+                    let envelopes: Option<Vec<Envelope>> =
+                        column.iter_mut().map(|envelope| envelope.take()).collect();
+
+                    if let Some(envelopes) = envelopes {
+                        if finished.len() >= columns {
+                            finished.pop_front();
+                        }
+
+                        finished.push_back(envelopes);
+
+                        history_task.set(EnvelopeHistory {
+                            columns: finished.iter().cloned().collect(),
+                            latest: block.sample(i).collect(),
+                        });
+                    }
+                }
+
+                for (envelope, value) in column.iter_mut().zip(block.sample(i)) {
+                    match envelope {
+                        Some(envelope) => envelope.add(value),
+                        None => *envelope = Some(Envelope::new(value)),
+                    }
+                }
+            }
+        }
+    });
+
+    history
+}
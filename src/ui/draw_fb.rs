@@ -15,10 +15,18 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
+use std::convert::TryInto;
 use std::io::Cursor;
+use std::time::{Duration, Instant};
 
-use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle};
 use png::{BitDepth, ColorType, Encoder};
+use serde::{Deserialize, Serialize};
+
+/// Time between two writes of the screen content to the framebuffer (10 fps).
+/// All widget updates in between are written out at once and every widget
+/// is drawn at most once per frame.
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(100);
 
 #[cfg(feature = "demo_mode")]
 mod backend {
@@ -53,17 +61,98 @@ mod backend {
             Ok(())
         }
     }
+
+    pub fn set_panel_power(_: &(), on: bool) -> Result<(), String> {
+        log::info!("Display: Set panel power to {on}");
+        Ok(())
+    }
 }
 
 #[cfg(not(feature = "demo_mode"))]
 mod backend {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
     pub use framebuffer::*;
+
+    const FB_BLANK_UNBLANK: i32 = 0;
+    const FB_BLANK_POWERDOWN: i32 = 4;
+
+    nix::ioctl_write_int_bad!(fbioblank, 0x4611);
+
+    /// Power the display controller and backlight up or down via FBIOBLANK
+    pub fn set_panel_power(device: &File, on: bool) -> Result<(), nix::Error> {
+        let level = if on {
+            FB_BLANK_UNBLANK
+        } else {
+            FB_BLANK_POWERDOWN
+        };
+
+        unsafe { fbioblank(device.as_raw_fd(), level) }.map(|_| ())
+    }
 }
 
 use backend::Framebuffer;
 
+/// Clockwise rotation of the displayed content, e.g. for enclosures that
+/// mount the TAC upside down.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Rotation {
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    /// Should the meaning of the upper and lower button be swapped?
+    ///
+    /// When rotating by 180 or 270 degrees the upper button ends up below
+    /// or right of the lower button.
+    pub fn swaps_buttons(&self) -> bool {
+        matches!(self, Self::Rotate180 | Self::Rotate270)
+    }
+}
+
+/// User selectable variations on how content is shown on the screen
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct Theme {
+    /// Show dark text on a bright background
+    pub inverted: bool,
+    /// Draw text in a larger font, so it can be read from a distance
+    pub large_text: bool,
+}
+
+/// The parts of the user interface that are drawn on top of the screens
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Overlay {
+    /// Banners for active alerts
+    Alert,
+    /// Modal dialogs, e.g. to confirm an action
+    Dialog,
+}
+
 pub struct FramebufferDrawTarget {
     fb: Framebuffer,
+    rotation: Rotation,
+    /// What the screen shows without the overlay (if any) on top
+    /// and what was drawn to the overlay.
+    /// One byte per pixel in the rotated coordinate system, so that the
+    /// screen can be redrawn when the rotation or the overlay area change.
+    background: Vec<u8>,
+    foreground: Vec<u8>,
+    overlays: Vec<(Overlay, Rectangle)>,
+    /// Show the whole screen inverted, e.g. to make the locator visible
+    flash: bool,
+    theme: Theme,
+    /// The top left and bottom right corner of the area that changed since
+    /// the last `flush()`
+    dirty: Option<(Point, Point)>,
+    /// The number of the frame that is currently being drawn and when it
+    /// will be written out
+    frame: u64,
+    next_frame: Instant,
 }
 
 impl FramebufferDrawTarget {
@@ -72,27 +161,264 @@ impl FramebufferDrawTarget {
         fb.var_screen_info.activate = 128; // FB_ACTIVATE_FORCE
         Framebuffer::put_var_screeninfo(&fb.device, &fb.var_screen_info).unwrap();
 
-        FramebufferDrawTarget { fb }
+        let res = fb.var_screen_info.xres * fb.var_screen_info.yres;
+        let background = vec![0; res as usize];
+        let foreground = vec![0; res as usize];
+
+        FramebufferDrawTarget {
+            fb,
+            rotation: Rotation::Rotate0,
+            background,
+            foreground,
+            overlays: Vec::new(),
+            flash: false,
+            theme: Theme::default(),
+            dirty: None,
+            frame: 0,
+            next_frame: Instant::now() + FRAME_INTERVAL,
+        }
     }
 
     pub fn clear(&mut self) {
-        self.fb.frame.iter_mut().for_each(|p| *p = 0x00);
+        self.background.iter_mut().for_each(|p| *p = 0x00);
+        self.invalidate();
     }
 
-    pub fn as_png(&self) -> Vec<u8> {
-        let mut dst = Cursor::new(Vec::new());
+    /// Rotate everything that is (and will be) shown on the screen
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+        self.invalidate();
+    }
 
-        let bpp = (self.fb.var_screen_info.bits_per_pixel / 8) as usize;
+    /// Change the theme of everything that is (and will be) shown on the screen
+    pub fn set_theme(&mut self, theme: Theme) {
+        if self.theme != theme {
+            self.theme = theme;
+            self.invalidate();
+        }
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.theme
+    }
+
+    /// Power the display panel down completely (or back up again).
+    /// The content is kept and shown again once the panel is powered up.
+    pub fn set_panel_power(&mut self, on: bool) {
+        if let Err(e) = backend::set_panel_power(&self.fb.device, on) {
+            log::warn!("Failed to set display panel power: {e}");
+        }
+    }
+
+    /// Invert everything that is shown on the screen (or stop doing so)
+    pub fn set_flash(&mut self, flash: bool) {
+        if self.flash != flash {
+            self.flash = flash;
+            self.invalidate();
+        }
+    }
+
+    /// Reserve an area of the screen for an overlay, which is drawn via
+    /// `overlay()` and is not affected by regular drawing operations.
+    ///
+    /// Passing `None` removes the overlay and reveals what was drawn below it
+    /// in the meantime.
+    pub fn set_overlay_area(&mut self, overlay: Overlay, area: Option<Rectangle>) {
+        // Forget what was drawn into the previous area of this overlay
+        if let Some(pos) = self.overlays.iter().position(|(o, _)| *o == overlay) {
+            let (_, prev) = self.overlays.remove(pos);
+
+            for coord in prev.points() {
+                if let Some(idx) = self.buffer_index(coord) {
+                    self.foreground[idx] = 0x00;
+                }
+            }
+        }
+
+        if let Some(area) = area {
+            self.overlays.push((overlay, area));
+        }
+
+        self.invalidate();
+    }
+
+    /// Get a draw target that only draws into the area reserved for
+    /// `overlay` via `set_overlay_area()`.
+    pub fn overlay(&mut self, overlay: Overlay) -> OverlayDrawTarget<'_> {
+        let area = self
+            .overlays
+            .iter()
+            .find(|(o, _)| *o == overlay)
+            .map(|(_, area)| *area);
+
+        OverlayDrawTarget { inner: self, area }
+    }
+
+    /// Get the index of a pixel in the background / foreground buffers,
+    /// or `None` if it is off-screen.
+    fn buffer_index(&self, coord: Point) -> Option<usize> {
+        let size = self.size();
+
+        let x: u32 = coord.x.try_into().ok()?;
+        let y: u32 = coord.y.try_into().ok()?;
+
+        if x >= size.width || y >= size.height {
+            return None;
+        }
+
+        Some((size.width * y + x) as usize)
+    }
+
+    /// Get the byte offset of a pixel in the frame buffer,
+    /// or `None` if it is off-screen.
+    fn frame_offset(&self, coord: Point) -> Option<usize> {
+        let bpp = self.fb.var_screen_info.bits_per_pixel / 8;
         let xres = self.fb.var_screen_info.xres;
         let yres = self.fb.var_screen_info.yres;
-        let res = (xres as usize) * (yres as usize);
+        let line_length = self.fb.fix_screen_info.line_length;
+
+        let x: u32 = coord.x.try_into().ok()?;
+        let y: u32 = coord.y.try_into().ok()?;
+
+        let (x, y) = match self.rotation {
+            Rotation::Rotate0 => (x, y),
+            Rotation::Rotate90 => (xres.checked_sub(y + 1)?, x),
+            Rotation::Rotate180 => (xres.checked_sub(x + 1)?, yres.checked_sub(y + 1)?),
+            Rotation::Rotate270 => (y, yres.checked_sub(x + 1)?),
+        };
+
+        if x >= xres || y >= yres {
+            return None;
+        }
 
-        let image: Vec<u8> = (0..res)
-            .map(|i| if self.fb.frame[i * bpp] != 0 { 0xff } else { 0 })
+        Some((line_length * y + bpp * x) as usize)
+    }
+
+    fn set_frame_pixel(&mut self, coord: Point, val: u8) {
+        let bpp = (self.fb.var_screen_info.bits_per_pixel / 8) as usize;
+        let val = if self.flash != self.theme.inverted {
+            !val
+        } else {
+            val
+        };
+
+        if let Some(offset) = self.frame_offset(coord) {
+            let pixel = &mut self.fb.frame[offset..(offset + bpp)];
+
+            // Only write to the framebuffer memory if something changed
+            if pixel.iter().any(|p| *p != val) {
+                pixel.fill(val);
+            }
+        }
+    }
+
+    fn in_overlay(&self, coord: Point) -> bool {
+        self.overlays.iter().any(|(_, area)| area.contains(coord))
+    }
+
+    /// Remember that `coord` has to be updated on the next `flush()`
+    fn mark_dirty(&mut self, coord: Point) {
+        self.dirty = Some(match self.dirty {
+            Some((top_left, bottom_right)) => (
+                top_left.component_min(coord),
+                bottom_right.component_max(coord),
+            ),
+            None => (coord, coord),
+        });
+    }
+
+    /// Update the whole screen on the next `flush()`
+    fn invalidate(&mut self) {
+        let size = self.size();
+
+        self.mark_dirty(Point::zero());
+        self.mark_dirty(Point::new(size.width as i32 - 1, size.height as i32 - 1));
+    }
+
+    /// Copy everything that changed since the last call to the framebuffer:
+    /// the overlay content to the overlay area of the screen and the
+    /// background everywhere else.
+    ///
+    /// Drawing only modifies an in-memory copy of the screen content, so that
+    /// the updates of multiple widgets can be written out at once and
+    /// pixels that were cleared and drawn again in the meantime are not
+    /// touched at all.
+    pub fn flush(&mut self) {
+        self.frame += 1;
+        self.next_frame = Instant::now() + FRAME_INTERVAL;
+
+        let (top_left, bottom_right) = match self.dirty.take() {
+            Some(dirty) => dirty,
+            None => return,
+        };
+
+        for y in top_left.y..=bottom_right.y {
+            for x in top_left.x..=bottom_right.x {
+                let coord = Point::new(x, y);
+
+                if let Some(idx) = self.buffer_index(coord) {
+                    let val = match self.in_overlay(coord) {
+                        true => self.foreground[idx],
+                        false => self.background[idx],
+                    };
+
+                    self.set_frame_pixel(coord, val);
+                }
+            }
+        }
+    }
+
+    /// The number of the frame that is currently being drawn.
+    /// It is incremented on every `flush()`.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// The time until the current frame is written out
+    pub fn until_next_frame(&self) -> Duration {
+        self.next_frame.saturating_duration_since(Instant::now())
+    }
+
+    /// Get the screen content the way it is meant to be looked at (like
+    /// `as_png()`), one `Vec` per row with one bit per pixel (MSB first).
+    pub fn as_packed_rows(&self) -> Vec<Vec<u8>> {
+        let size = self.size();
+
+        (0..(size.height as i32))
+            .map(|y| {
+                let mut row = vec![0u8; (size.width as usize + 7) / 8];
+
+                for x in 0..(size.width as i32) {
+                    match self.frame_offset(Point::new(x, y)) {
+                        Some(offset) if self.fb.frame[offset] != 0 => {
+                            row[(x / 8) as usize] |= 0x80 >> (x % 8)
+                        }
+                        _ => {}
+                    }
+                }
+
+                row
+            })
+            .collect()
+    }
+
+    pub fn as_png(&self) -> Vec<u8> {
+        let mut dst = Cursor::new(Vec::new());
+
+        // Export the image the way it is meant to be looked at,
+        // not the way it is stored in the framebuffer.
+        let size = self.size();
+
+        let image: Vec<u8> = (0..(size.height as i32))
+            .flat_map(|y| (0..(size.width as i32)).map(move |x| Point::new(x, y)))
+            .map(|coord| match self.frame_offset(coord) {
+                Some(offset) if self.fb.frame[offset] != 0 => 0xff,
+                _ => 0,
+            })
             .collect();
 
         let mut writer = {
-            let mut enc = Encoder::new(&mut dst, xres, yres);
+            let mut enc = Encoder::new(&mut dst, size.width, size.height);
             enc.set_color(ColorType::Grayscale);
             enc.set_depth(BitDepth::Eight);
             enc.write_header().unwrap()
@@ -105,6 +431,13 @@ impl FramebufferDrawTarget {
     }
 }
 
+fn color_byte(color: BinaryColor) -> u8 {
+    match color {
+        BinaryColor::Off => 0x00,
+        BinaryColor::On => 0xff,
+    }
+}
+
 impl DrawTarget for FramebufferDrawTarget {
     type Color = BinaryColor;
     type Error = core::convert::Infallible;
@@ -113,25 +446,64 @@ impl DrawTarget for FramebufferDrawTarget {
     where
         I: IntoIterator<Item = Pixel<Self::Color>>,
     {
-        let bpp = self.fb.var_screen_info.bits_per_pixel / 8;
+        for Pixel(coord, color) in pixels {
+            let idx = match self.buffer_index(coord) {
+                Some(idx) => idx,
+                None => continue,
+            };
+
+            let val = color_byte(color);
+
+            if self.background[idx] != val {
+                self.background[idx] = val;
+
+                // Keep the overlay on top of everything else
+                if !self.in_overlay(coord) {
+                    self.mark_dirty(coord);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl OriginDimensions for FramebufferDrawTarget {
+    fn size(&self) -> Size {
         let xres = self.fb.var_screen_info.xres;
         let yres = self.fb.var_screen_info.yres;
-        let line_length = self.fb.fix_screen_info.line_length;
 
-        for Pixel(coord, color) in pixels {
-            let x = coord.x as u32;
-            let y = coord.y as u32;
+        match self.rotation {
+            Rotation::Rotate0 | Rotation::Rotate180 => Size::new(xres, yres),
+            Rotation::Rotate90 | Rotation::Rotate270 => Size::new(yres, xres),
+        }
+    }
+}
+
+pub struct OverlayDrawTarget<'a> {
+    inner: &'a mut FramebufferDrawTarget,
+    area: Option<Rectangle>,
+}
 
-            if x >= xres || y >= yres {
+impl<'a> DrawTarget for OverlayDrawTarget<'a> {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(coord, color) in pixels {
+            if !self.area.map(|a| a.contains(coord)).unwrap_or(false) {
                 continue;
             }
 
-            let offset = line_length * y + bpp * x;
+            if let Some(idx) = self.inner.buffer_index(coord) {
+                let val = color_byte(color);
 
-            for b in 0..bpp {
-                self.fb.frame[(offset + b) as usize] = match color {
-                    BinaryColor::Off => 0x00,
-                    BinaryColor::On => 0xff,
+                if self.inner.foreground[idx] != val {
+                    self.inner.foreground[idx] = val;
+                    self.inner.mark_dirty(coord);
                 }
             }
         }
@@ -140,8 +512,8 @@ impl DrawTarget for FramebufferDrawTarget {
     }
 }
 
-impl OriginDimensions for FramebufferDrawTarget {
+impl<'a> OriginDimensions for OverlayDrawTarget<'a> {
     fn size(&self) -> Size {
-        Size::new(self.fb.var_screen_info.xres, self.fb.var_screen_info.yres)
+        self.inner.size()
     }
 }
@@ -0,0 +1,110 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::sync::Arc;
+use async_std::task::spawn_blocking;
+use log::info;
+
+use super::buttons::{Button, ButtonEvent, PressDuration, Source};
+use crate::broker::Topic;
+
+#[cfg(feature = "demo_mode")]
+mod evd {
+    use evdev::FetchEventsSynced;
+    pub use evdev::{EventType, InputEventKind, Key, RelativeAxisType};
+
+    pub struct Device {}
+
+    impl Device {
+        pub fn open(_path: &'static str) -> Result<Self, String> {
+            Err("There is no rotary encoder in demo mode".to_string())
+        }
+
+        pub fn fetch_events(&mut self) -> Result<FetchEventsSynced, ()> {
+            loop {
+                std::thread::park()
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod evd {
+    pub use evdev::*;
+}
+
+use evd::{Device, EventType, InputEventKind, Key, RelativeAxisType};
+
+/// Spawn a thread that blockingly reads input from a rotary encoder and
+/// pushes it into the button events topic.
+///
+/// Turning the encoder results in `ButtonEvent::Scroll` events (positive
+/// steps for clockwise rotation), pushing it acts like the lower button.
+///
+/// The encoder is part of an optional add-on front panel, so it is not an
+/// error if there is no input device at `path`.
+pub fn handle_encoder(path: &'static str, topic: Arc<Topic<ButtonEvent>>) {
+    let mut device = match Device::open(path) {
+        Ok(dev) => dev,
+        Err(e) => {
+            info!("Not handling rotary encoder input: {e}");
+            return;
+        }
+    };
+
+    spawn_blocking(move || {
+        let mut start_time = None;
+
+        loop {
+            for ev in device.fetch_events().unwrap() {
+                match (ev.event_type(), ev.kind()) {
+                    (EventType::RELATIVE, InputEventKind::RelAxis(RelativeAxisType::REL_DIAL))
+                    | (EventType::RELATIVE, InputEventKind::RelAxis(RelativeAxisType::REL_WHEEL)) =>
+                    {
+                        topic.set(ButtonEvent::Scroll {
+                            steps: ev.value(),
+                            src: Source::Local,
+                        });
+                    }
+                    (EventType::KEY, InputEventKind::Key(Key::KEY_ENTER)) => {
+                        if ev.value() != 0 {
+                            start_time = Some(ev.timestamp());
+                            topic.set(ButtonEvent::Press {
+                                btn: Button::Lower,
+                                src: Source::Local,
+                            });
+                            continue;
+                        }
+
+                        let duration = start_time
+                            .take()
+                            .and_then(|start| ev.timestamp().duration_since(start).ok());
+
+                        if let Some(duration) = duration {
+                            topic.set(ButtonEvent::Release {
+                                btn: Button::Lower,
+                                dur: PressDuration::from_duration(duration),
+                                src: Source::Local,
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    });
+}
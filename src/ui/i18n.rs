@@ -0,0 +1,299 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use serde::{Deserialize, Serialize};
+
+/// The language the text on the screen is shown in
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum Language {
+    #[default]
+    English,
+    German,
+}
+
+impl Language {
+    fn catalog(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::English => &[],
+            Self::German => GERMAN,
+        }
+    }
+
+    /// Translate an english UI string into this language.
+    /// Strings without a translation are shown in english.
+    pub fn tr<'a>(&self, text: &'a str) -> &'a str {
+        self.catalog()
+            .iter()
+            .find(|(en, _)| *en == text)
+            .map(|(_, translated)| *translated)
+            .unwrap_or(text)
+    }
+}
+
+/// Translations of the english UI strings into german.
+///
+/// The UI font covers ISO 8859-1, so umlauts can be used.
+const GERMAN: &[(&str, &str)] = &[
+    // Menu
+    ("Menu", "Menü"),
+    ("Power", "Stromversorgung"),
+    ("Network", "Netzwerk"),
+    ("System", "System"),
+    ("Settings", "Einstellungen"),
+    (
+        "Short press: move\nLong press: enter",
+        "Kurz: weiter\nLang: öffnen",
+    ),
+    // DUT power
+    ("DUT Power", "DUT-Versorgung"),
+    ("DUT Power Graph", "DUT-Leistungsverlauf"),
+    ("On", "An"),
+    ("Off", "Aus"),
+    ("Changing", "Schaltet"),
+    ("Off (Float.)", "Aus (Float.)"),
+    ("Next:", "Nächste:"),
+    ("Inv. Pol.", "Verpolt"),
+    ("Ov. Curr.", "Überstrom"),
+    ("Ov. Volt.", "Überspannung"),
+    ("Rt Err.", "Echtzeitfehler"),
+    ("Power off DUT?", "DUT ausschalten?"),
+    ("Lower: yes\nUpper: no", "Unten: ja\nOben: nein"),
+    (
+        "Lower: +  Upper: -\nHold lower to save",
+        "Unten: +  Oben: -\nUnten halten: sichern",
+    ),
+    // USB and digital outputs
+    ("Assert OUT 0?", "OUT 0 setzen?"),
+    ("Release OUT 0?", "OUT 0 freigeben?"),
+    ("Assert OUT 1?", "OUT 1 setzen?"),
+    ("Release OUT 1?", "OUT 1 freigeben?"),
+    ("USB Host", "USB-Host"),
+    ("Total", "Gesamt"),
+    ("Digital Out", "Digitalausgänge"),
+    ("Asserted:", "Aktiv:"),
+    ("Volt:", "Spg.:"),
+    // Network
+    ("DUT DHCP Leases", "DUT DHCP-Leases"),
+    ("No leases", "Keine Leases"),
+    ("Down", "Getrennt"),
+    ("No IP address", "Keine IP-Adresse"),
+    ("Page", "Seite"),
+    ("Web Interface", "Weboberfläche"),
+    // System
+    ("System Status", "Systemstatus"),
+    ("System Info", "Systeminfo"),
+    ("tacd version:", "tacd-Version:"),
+    ("Bundle version:", "Bundle-Version:"),
+    ("Booted slot:", "Gebootet:"),
+    ("Uptime:", "Laufzeit:"),
+    ("Serial number:", "Seriennummer:"),
+    ("Baseboard:", "Hauptplatine:"),
+    ("Powerboard:", "Leistungsplatine:"),
+    ("Bootloader:", "Bootloader:"),
+    ("Kernel:", "Kernel:"),
+    ("Time:", "Zeit:"),
+    ("Synced", "Synchronisiert"),
+    ("Not synced", "Nicht synchr."),
+    ("Reboot", "Neustart"),
+    ("Help", "Hilfe"),
+    ("Setup Mode", "Einrichtung"),
+    ("CAN Status:", "CAN-Status:"),
+    ("LSS Scan Status:", "LSS-Scan-Status:"),
+    ("Power Fault:", "Stromfehler:"),
+    ("> Power On:", "> Strom an:"),
+    ("Connected Nodes:", "Verb. Knoten:"),
+    ("SoC Temperature", "SoC-Temperatur"),
+    ("SoC peak", "SoC-Spitze"),
+    ("Recording ...", "Aufzeichnung ..."),
+    // Settings
+    ("Brightness", "Helligkeit"),
+    ("Screensaver", "Bildschirmschoner"),
+    ("Animation", "Animation"),
+    ("DUT current limit", "DUT-Strombegrenzung"),
+    ("after", "nach"),
+    ("never", "nie"),
+    ("Hostname", "Hostname"),
+    ("Measurements", "Messwerte"),
+    ("Blank", "Leer"),
+    ("DUT Voltage", "DUT-Spannung"),
+    ("DUT Current", "DUT-Strom"),
+    ("SoC Temp.", "SoC-Temp."),
+    // Reboot, power off and updates
+    (
+        "Really reboot?\nLong press to confirm",
+        "Wirklich neustarten?\nLang drücken zum\nBestätigen",
+    ),
+    (
+        "Hold tight\nBe right back",
+        "Einen Moment\nBin gleich zurück",
+    ),
+    ("Reboot requested", "Neustart angefordert"),
+    ("Power off requested", "Abschaltung angefragt"),
+    ("Code", "Code"),
+    ("Rebooting in", "Neustart in"),
+    ("Powering off in", "Ausschalten in"),
+    ("Dismissing in", "Schließe in"),
+    (
+        "Press any button\nto cancel",
+        "Beliebige Taste\nzum Abbrechen",
+    ),
+    ("Update failed!", "Update fehlgeschlagen!"),
+    (
+        "Update installed\nLong press: reboot\nShort press: later",
+        "Update installiert\nLang: Neustart\nKurz: Später",
+    ),
+    // Alerts
+    ("DUT power:\nInverted polarity", "DUT-Versorgung:\nVerpolt"),
+    ("DUT power:\nOvercurrent", "DUT-Versorgung:\nÜberstrom"),
+    (
+        "DUT power:\nCurrent limit exceeded",
+        "DUT-Versorgung:\nStrom zu hoch",
+    ),
+    ("DUT power:\nOvervoltage", "DUT-Versorgung:\nÜberspannung"),
+    (
+        "DUT power:\nRealtime violation",
+        "DUT-Versorgung:\nEchtzeitfehler",
+    ),
+    (
+        "Output mismatch:\nDUT power",
+        "Ausgangsfehler:\nDUT-Versorgung",
+    ),
+    ("Output mismatch:\nOUT 0", "Ausgangsfehler:\nOUT 0"),
+    ("Output mismatch:\nOUT 1", "Ausgangsfehler:\nOUT 1"),
+    ("Output mismatch:\nIOBus", "Ausgangsfehler:\nIOBus"),
+    (
+        "Output readback\nmismatch",
+        "Ausgangsrücklesung\nfehlerhaft",
+    ),
+    ("SoC temperature\nis high", "SoC-Temperatur\nist hoch"),
+    (
+        "SoC temperature\nis critical",
+        "SoC-Temperatur\nist kritisch",
+    ),
+    ("Software update\navailable", "Software-Update\nverfügbar"),
+    // Subsystem failures
+    ("Subsystem failure", "Subsystemfehler"),
+    ("DUT power", "DUT-Versorgung"),
+    ("Temperatures", "Temperaturen"),
+    ("No new ADC values", "Keine neuen ADC-Werte"),
+    ("Power thread stalled", "Power-Thread hängt"),
+    (
+        "Press any button\nto dismiss",
+        "Zum Schließen eine\nTaste drücken",
+    ),
+    // Setup
+    ("Welcome to your TAC!", "Willkommen!"),
+    (
+        "Please connect\nto a network\nto continue\n the setup",
+        "Bitte verbinden Sie\nden TAC mit einem\nNetzwerk, um die\nEinrichtung\nfortzusetzen",
+    ),
+    (
+        "Please continue the\nsetup at:",
+        "Bitte setzen Sie die\nEinrichtung fort:",
+    ),
+    ("or", "oder"),
+    // Help
+    ("Scroll up", "Nach oben blättern"),
+    ("Scroll down", "Nach unten blättern"),
+    (
+        "Hey there!
+
+A short guide on how
+this interface works:
+Long presses on the
+lower button perform
+actions.
+...",
+        "Hallo!
+
+Eine kurze Anleitung
+zur Bedienung:
+Lange Drücke auf die
+untere Taste führen
+Aktionen aus.
+...",
+    ),
+    (
+        "...
+
+Short presses on the
+lower button toggle
+between actions.
+
+...",
+        "...
+
+Kurze Drücke auf die
+untere Taste wechseln
+zwischen Aktionen.
+
+...",
+    ),
+    (
+        "...
+
+Short presses on the
+upper button switch
+screens, long presses
+open the menu.
+
+Press it to leave
+this guide",
+        "...
+
+Kurze Drücke auf die
+obere Taste wechseln
+Bildschirme, lange
+öffnen das Menü.
+
+Drücken Sie sie, um
+die Anleitung zu
+verlassen",
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{Language, GERMAN};
+
+    /// The number of characters that fit into a line on the screen
+    const MAX_LINE_LEN: usize = 22;
+
+    #[test]
+    fn translations_fit_on_screen() {
+        for (en, de) in GERMAN {
+            for line in de.lines() {
+                assert!(
+                    line.chars().count() <= MAX_LINE_LEN,
+                    "Translation of {:?} is too long: {:?}",
+                    en,
+                    line
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fallback_to_english() {
+        assert_eq!(Language::German.tr("Settings"), "Einstellungen");
+        assert_eq!(
+            Language::German.tr("Not in the catalog"),
+            "Not in the catalog"
+        );
+        assert_eq!(Language::English.tr("Settings"), "Settings");
+    }
+}
@@ -0,0 +1,163 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::Duration;
+
+use async_std::future::timeout;
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use futures::stream::select;
+use serde::{Deserialize, Serialize};
+
+use super::buttons::ButtonEvent;
+use super::screens::Screen;
+use crate::broker::Topic;
+
+/// Lower bound for the time a screen is shown in kiosk mode, so that
+/// there is a chance to actually read it.
+const MIN_KIOSK_INTERVAL: u64 = 5;
+
+/// Automatically cycle through a list of screens, e.g. for TACs that are
+/// mounted in a demo wall.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct KioskMode {
+    /// The screens to cycle through, in order
+    pub screens: Vec<Screen>,
+    /// The time (in seconds) each screen is shown
+    pub interval: u64,
+}
+
+impl KioskMode {
+    fn interval(&self) -> Duration {
+        Duration::from_secs(u64::max(self.interval, MIN_KIOSK_INTERVAL))
+    }
+
+    /// The screen to show after `current`.
+    /// Starts over at the first screen if `current` is not in the list.
+    fn next_screen(&self, current: Option<Screen>) -> Option<Screen> {
+        let idx = current
+            .and_then(|c| self.screens.iter().position(|s| *s == c))
+            .map(|idx| idx + 1)
+            .unwrap_or(0);
+
+        self.screens.get(idx % self.screens.len().max(1)).copied()
+    }
+}
+
+/// Show the next screen in the kiosk list every `interval` seconds.
+///
+/// Button presses restart the interval, so that a user interacting with
+/// the TAC is not interrupted. Screens that should not be left
+/// automatically (e.g. an update in progress) are not cycled away from.
+pub fn cycle(
+    kiosk: &Arc<Topic<Option<KioskMode>>>,
+    screen: &Arc<Topic<Screen>>,
+    buttons: &Arc<Topic<ButtonEvent>>,
+) {
+    enum Event {
+        Button,
+        Config(Option<KioskMode>),
+    }
+
+    let (buttons_events, _) = buttons.clone().subscribe_unbounded();
+    let (kiosk_events, _) = kiosk.clone().subscribe_unbounded();
+    let mut events = select(
+        buttons_events.map(|_| Event::Button),
+        kiosk_events.map(Event::Config),
+    );
+
+    let screen = screen.clone();
+
+    spawn(async move {
+        // The current setting is the first thing we receive via the
+        // kiosk subscription.
+        let mut kiosk: Option<KioskMode> = None;
+
+        loop {
+            let ev = match &kiosk {
+                Some(k) => timeout(k.interval(), events.next()).await,
+                None => Ok(events.next().await),
+            };
+
+            match ev {
+                Ok(None) => break,
+                Ok(Some(Event::Button)) => {}
+                Ok(Some(Event::Config(k))) => {
+                    kiosk = k.filter(|k| !k.screens.is_empty());
+
+                    // Start showing the list right away
+                    if let Some(next) = kiosk.as_ref().and_then(|k| k.next_screen(None)) {
+                        screen.set(next);
+                    }
+                }
+                Err(_) => {
+                    if let Some(kiosk) = &kiosk {
+                        screen.modify(|current| match current {
+                            Some(c) if !c.use_screensaver() => None,
+                            c => kiosk.next_screen(c),
+                        });
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// The idle timeout after which the display is dimmed and the screensaver
+/// is shown. Unattended kiosk TACs should keep showing their screens, so
+/// the timeout is disabled while the kiosk mode is active.
+pub fn idle_timeout(
+    screensaver_timeout: &Arc<Topic<Option<u64>>>,
+    kiosk: &Arc<Topic<Option<KioskMode>>>,
+) -> Arc<Topic<Option<u64>>> {
+    enum Event {
+        Timeout(Option<u64>),
+        Kiosk(bool),
+    }
+
+    let idle_timeout = Topic::anonymous(None);
+
+    let (timeout_events, _) = screensaver_timeout.clone().subscribe_unbounded();
+    let (kiosk_events, _) = kiosk.clone().subscribe_unbounded();
+    let mut events = select(
+        timeout_events.map(Event::Timeout),
+        kiosk_events.map(|k| Event::Kiosk(k.map(|k| !k.screens.is_empty()).unwrap_or(false))),
+    );
+
+    let idle_timeout_task = idle_timeout.clone();
+
+    spawn(async move {
+        let mut screensaver_timeout = None;
+        let mut kiosk_active = false;
+
+        while let Some(ev) = events.next().await {
+            match ev {
+                Event::Timeout(t) => screensaver_timeout = t,
+                Event::Kiosk(active) => kiosk_active = active,
+            }
+
+            idle_timeout_task.set(if kiosk_active {
+                None
+            } else {
+                screensaver_timeout
+            });
+        }
+    });
+
+    idle_timeout
+}
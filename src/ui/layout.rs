@@ -0,0 +1,174 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+/// The height of a line of text in the UI font
+pub const LINE_HEIGHT: u32 = 20;
+
+/// The number of lines of text that fit below the screen title
+pub const NUM_LINES: u32 = 8;
+
+/// Distance from the top of a line of text to its baseline
+const BASELINE: i32 = 15;
+
+/// The width of a column in a `Area::columns()` split
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Width {
+    /// This many pixels wide
+    Pixels(u32),
+    /// Whatever is left after the other columns were placed.
+    /// Multiple `Fill` columns share the remaining width equally.
+    Fill,
+}
+
+/// A rectangular area of the screen that widgets are placed in.
+///
+/// Screens start with the `content()` area and split it into lines,
+/// columns or grid cells, instead of placing widgets at hardcoded pixel
+/// coordinates. This way adding a widget does not require re-tuning the
+/// position of every other widget on the screen.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Area(Rectangle);
+
+impl Area {
+    /// The area of the screen below the title
+    pub fn content() -> Self {
+        Self(Rectangle::new(
+            Point::new(8, 37),
+            Size::new(224, NUM_LINES * LINE_HEIGHT),
+        ))
+    }
+
+    /// The `n`th line of text in this area
+    pub fn line(&self, n: u32) -> Self {
+        let top_left = self.0.top_left + Point::new(0, (n * LINE_HEIGHT) as i32);
+
+        Self(Rectangle::new(
+            top_left,
+            Size::new(self.0.size.width, LINE_HEIGHT),
+        ))
+    }
+
+    /// Split the area into columns of the given widths, from left to right
+    pub fn columns<const N: usize>(&self, widths: [Width; N]) -> [Self; N] {
+        let fixed: u32 = widths
+            .iter()
+            .map(|w| match w {
+                Width::Pixels(p) => *p,
+                Width::Fill => 0,
+            })
+            .sum();
+
+        let num_fill = widths.iter().filter(|w| **w == Width::Fill).count() as u32;
+        let fill = self.0.size.width.saturating_sub(fixed) / num_fill.max(1);
+
+        let mut x = self.0.top_left.x;
+
+        widths.map(|w| {
+            let width = match w {
+                Width::Pixels(p) => p,
+                Width::Fill => fill,
+            };
+
+            let column = Rectangle::new(
+                Point::new(x, self.0.top_left.y),
+                Size::new(width, self.0.size.height),
+            );
+
+            x += width as i32;
+
+            Self(column)
+        })
+    }
+
+    /// Split the area into a grid of equally sized cells, row by row
+    pub fn grid(&self, rows: u32, cols: u32) -> Vec<Self> {
+        let size = Size::new(self.0.size.width / cols, self.0.size.height / rows);
+
+        (0..rows)
+            .flat_map(|row| (0..cols).map(move |col| (row, col)))
+            .map(|(row, col)| {
+                let offset = Point::new((col * size.width) as i32, (row * size.height) as i32);
+
+                Self(Rectangle::new(self.0.top_left + offset, size))
+            })
+            .collect()
+    }
+
+    /// An area of `size` in the center of this one
+    pub fn centered(&self, size: Size) -> Self {
+        Self(Rectangle::with_center(self.0.center(), size))
+    }
+
+    /// An area with the full width of this one but only `height` pixels
+    /// high, centered vertically
+    pub fn shrink_to_height(&self, height: u32) -> Self {
+        self.centered(Size::new(self.0.size.width, height))
+    }
+
+    /// Where to anchor left aligned text in the first line of this area
+    pub fn text_anchor(&self) -> Point {
+        self.0.top_left + Point::new(0, BASELINE)
+    }
+
+    /// The top left corner of this area, e.g. to place a bar or indicator
+    pub fn top_left(&self) -> Point {
+        self.0.top_left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::prelude::*;
+
+    use super::{Area, Width};
+
+    #[test]
+    fn lines_match_rows() {
+        // The first line should be where screens used to put their first row
+        assert_eq!(Area::content().line(0).text_anchor(), Point::new(8, 52));
+        assert_eq!(Area::content().line(3).text_anchor(), Point::new(8, 112));
+    }
+
+    #[test]
+    fn columns() {
+        let [name, fill_a, fixed, fill_b] = Area::content().line(0).columns([
+            Width::Pixels(60),
+            Width::Fill,
+            Width::Pixels(20),
+            Width::Fill,
+        ]);
+
+        assert_eq!(name.top_left(), Point::new(8, 37));
+        assert_eq!(fill_a.top_left(), Point::new(68, 37));
+        assert_eq!(fixed.top_left(), Point::new(140, 37));
+        assert_eq!(fill_b.top_left(), Point::new(160, 37));
+        assert_eq!(fill_b.shrink_to_height(10).top_left(), Point::new(160, 42));
+    }
+
+    #[test]
+    fn grid() {
+        let cells = Area::content().grid(2, 2);
+
+        assert_eq!(cells.len(), 4);
+        assert_eq!(cells[1].top_left(), Point::new(120, 37));
+        assert_eq!(cells[2].top_left(), Point::new(8, 117));
+        assert_eq!(cells[3].top_left(), Point::new(120, 117));
+    }
+}
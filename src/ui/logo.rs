@@ -0,0 +1,238 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::fs::{create_dir_all, read, remove_file, rename, write};
+use std::io::ErrorKind;
+use std::path::Path;
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use embedded_graphics::{image::ImageRaw, pixelcolor::BinaryColor};
+use log::warn;
+use tide::{http::mime, Request, Response, Server};
+
+use crate::broker::Topic;
+
+#[cfg(feature = "demo_mode")]
+const LOGO_PATH: &str = "demo_files/srv/tacd/logo.pbm";
+
+#[cfg(not(feature = "demo_mode"))]
+const LOGO_PATH: &str = "/srv/tacd/logo.pbm";
+
+/// The logo may cover at most the whole display
+const MAX_SIZE: u32 = 240;
+
+/// Uploads larger than this can not be a valid logo.
+/// (A full-screen bitmap takes up 7200 bytes, plus a header with comments).
+const MAX_FILE_SIZE: u64 = 16 * 1024;
+
+/// A user provided monochrome image that is shown on the splash screen and
+/// (optionally) as screensaver, e.g. to integrate the TAC into a branded
+/// test station.
+pub struct Logo {
+    width: u32,
+    data: Vec<u8>,
+}
+
+/// Get the next whitespace separated token from a netpbm header,
+/// skipping comments.
+fn header_token<'a>(content: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    loop {
+        match content.get(*pos)? {
+            b'#' => {
+                while content.get(*pos).map(|c| *c != b'\n').unwrap_or(false) {
+                    *pos += 1;
+                }
+            }
+            c if c.is_ascii_whitespace() => *pos += 1,
+            _ => break,
+        }
+    }
+
+    let start = *pos;
+
+    while content
+        .get(*pos)
+        .map(|c| !c.is_ascii_whitespace())
+        .unwrap_or(false)
+    {
+        *pos += 1;
+    }
+
+    Some(&content[start..*pos])
+}
+
+impl Logo {
+    /// Parse a binary portable bitmap (P4) file.
+    ///
+    /// Set bits in the bitmap (black in most image viewers) are drawn as
+    /// lit pixels on the display.
+    pub fn from_pbm(content: &[u8]) -> Result<Self, String> {
+        let mut pos = 0;
+
+        if header_token(content, &mut pos) != Some(b"P4") {
+            return Err("Not a binary portable bitmap (P4) file".to_string());
+        }
+
+        let mut dimension = || {
+            header_token(content, &mut pos)
+                .and_then(|token| std::str::from_utf8(token).ok())
+                .and_then(|token| token.parse::<u32>().ok())
+                .filter(|val| (1..=MAX_SIZE).contains(val))
+                .ok_or_else(|| format!("Width and height must be between 1 and {MAX_SIZE}"))
+        };
+
+        let width = dimension()?;
+        let height = dimension()?;
+
+        // The header is terminated by a single whitespace character
+        pos += 1;
+
+        // Every row starts at a new byte
+        let len = ((width as usize + 7) / 8) * height as usize;
+
+        let data = content
+            .get(pos..pos + len)
+            .ok_or_else(|| "The file is too short for its size".to_string())?
+            .to_vec();
+
+        Ok(Self { width, data })
+    }
+
+    /// Load the logo uploaded via the web interface (if there is one)
+    pub fn load() -> Option<Self> {
+        let content = read(LOGO_PATH).ok()?;
+
+        match Self::from_pbm(&content) {
+            Ok(logo) => Some(logo),
+            Err(e) => {
+                warn!("Failed to load logo from {LOGO_PATH}: {e}");
+                None
+            }
+        }
+    }
+
+    pub fn image(&self) -> ImageRaw<BinaryColor> {
+        ImageRaw::new(&self.data, self.width)
+    }
+}
+
+fn not_in_setup_mode() -> Response {
+    Response::builder(403)
+        .body("The logo may only be changed in setup mode")
+        .content_type(mime::PLAIN)
+        .build()
+}
+
+/// Allow uploading, downloading and removing the logo via the web API.
+/// Changing the logo is only allowed in setup mode.
+pub fn serve(server: &mut Server<()>, setup_mode: Arc<Topic<bool>>) {
+    let setup_mode_put = setup_mode.clone();
+    let setup_mode_delete = setup_mode;
+
+    server
+        .at("/v1/tac/display/logo")
+        .put(move |mut req: Request<()>| {
+            let setup_mode = setup_mode_put.clone();
+
+            async move {
+                if !setup_mode.get().await {
+                    return Ok(not_in_setup_mode());
+                }
+
+                // Do not buffer arbitrarily large uploads in memory
+                let mut content = Vec::new();
+                req.take_body()
+                    .take(MAX_FILE_SIZE + 1)
+                    .read_to_end(&mut content)
+                    .await?;
+
+                if content.len() as u64 > MAX_FILE_SIZE {
+                    return Ok(Response::builder(413)
+                        .body(format!("The logo may be at most {MAX_FILE_SIZE} bytes"))
+                        .content_type(mime::PLAIN)
+                        .build());
+                }
+
+                if let Err(e) = Logo::from_pbm(&content) {
+                    return Ok(Response::builder(400)
+                        .body(e)
+                        .content_type(mime::PLAIN)
+                        .build());
+                }
+
+                let path = Path::new(LOGO_PATH);
+                let path_tmp = path.with_extension("tmp");
+
+                if let Some(parent) = path.parent() {
+                    create_dir_all(parent)?;
+                }
+
+                write(&path_tmp, content)?;
+                rename(path_tmp, path)?;
+
+                Ok(Response::new(204))
+            }
+        })
+        .get(|_| async move {
+            let res = match read(LOGO_PATH) {
+                Ok(content) => Response::builder(200)
+                    .body(content)
+                    .content_type("image/x-portable-bitmap")
+                    .build(),
+                Err(e) if e.kind() == ErrorKind::NotFound => Response::new(404),
+                Err(e) => return Err(e.into()),
+            };
+
+            Ok(res)
+        })
+        .delete(move |_| {
+            let setup_mode = setup_mode_delete.clone();
+
+            async move {
+                if !setup_mode.get().await {
+                    return Ok(not_in_setup_mode());
+                }
+
+                let res = match remove_file(LOGO_PATH) {
+                    Ok(()) => Response::new(204),
+                    Err(e) if e.kind() == ErrorKind::NotFound => Response::new(404),
+                    Err(e) => return Err(e.into()),
+                };
+
+                Ok(res)
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Logo;
+
+    #[test]
+    fn parse_pbm() {
+        let logo = Logo::from_pbm(b"P4\n# A comment\n10 2\n\xff\xc0\x80\x40").unwrap();
+
+        assert_eq!(logo.width, 10);
+        assert_eq!(logo.data, vec![0xff, 0xc0, 0x80, 0x40]);
+
+        // ASCII bitmaps, truncated data and oversized images are rejected
+        assert!(Logo::from_pbm(b"P1\n1 1\n1").is_err());
+        assert!(Logo::from_pbm(b"P4\n10 2\n\xff\xc0\x80").is_err());
+        assert!(Logo::from_pbm(b"P4\n241 1\n").is_err());
+    }
+}
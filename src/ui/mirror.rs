@@ -0,0 +1,103 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::Duration;
+
+use async_std::sync::{Arc, Mutex};
+use async_std::task::{sleep, spawn};
+use base64::Engine;
+use embedded_graphics::prelude::OriginDimensions;
+use serde::{Deserialize, Serialize};
+
+use super::FramebufferDrawTarget;
+use crate::broker::Topic;
+
+/// How often to check the display content for changes
+const FRAME_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Send all rows every couple of frames, even if they did not change,
+/// so that viewers that connect later can catch up.
+const KEYFRAME_INTERVAL: u32 = 25;
+
+/// A change to the content of the display
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct FrameUpdate {
+    pub width: u32,
+    pub height: u32,
+    /// This update contains every row of the display, not just the ones
+    /// that changed since the previous update.
+    pub keyframe: bool,
+    /// The changed rows as row number and base64 encoded pixels with one
+    /// bit per pixel (MSB first)
+    pub rows: Vec<(u32, String)>,
+}
+
+/// Publish changes to the display content, so that the web interface can
+/// show exactly what is on the screen.
+pub(super) fn run(
+    frames: &Arc<Topic<FrameUpdate>>,
+    draw_target: Arc<Mutex<FramebufferDrawTarget>>,
+) {
+    let frames = frames.clone();
+
+    spawn(async move {
+        let mut prev: Vec<Vec<u8>> = Vec::new();
+        let mut since_keyframe = KEYFRAME_INTERVAL;
+
+        loop {
+            sleep(FRAME_INTERVAL).await;
+
+            let (rows, size) = {
+                let draw_target = draw_target.lock().await;
+                (draw_target.as_packed_rows(), draw_target.size())
+            };
+
+            since_keyframe += 1;
+
+            // Rotating the display changes the number of rows, which also
+            // requires a full update.
+            let keyframe = since_keyframe >= KEYFRAME_INTERVAL || rows.len() != prev.len();
+
+            let changed: Vec<(u32, String)> = rows
+                .iter()
+                .enumerate()
+                .filter(|(y, row)| keyframe || prev.get(*y) != Some(*row))
+                .map(|(y, row)| {
+                    let encoded = base64::engine::general_purpose::STANDARD.encode(row);
+                    (y as u32, encoded)
+                })
+                .collect();
+
+            if changed.is_empty() {
+                continue;
+            }
+
+            if keyframe {
+                since_keyframe = 0;
+            }
+
+            frames.set(FrameUpdate {
+                width: size.width,
+                height: size.height,
+                keyframe,
+                rows: changed,
+            });
+
+            prev = rows;
+        }
+    });
+}
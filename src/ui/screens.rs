@@ -18,81 +18,206 @@
 use async_std::sync::{Arc, Mutex};
 use async_trait::async_trait;
 use embedded_graphics::{
-    mono_font::MonoTextStyle,
     pixelcolor::BinaryColor,
     prelude::*,
     primitives::{Line, PrimitiveStyle},
-    text::Text,
+    text::Alignment,
 };
 use serde::{Deserialize, Serialize};
 
+mod dhcp;
 mod dig_out;
+mod failure;
 mod help;
 mod iobus;
+mod menu;
+mod network;
 mod power;
+mod power_action;
+mod power_graph;
+mod qr_code;
 mod rauc;
 mod reboot;
 mod screensaver;
+mod settings;
 mod setup;
 mod system;
+mod system_info;
+mod temperature_graph;
 mod uart;
 mod usb;
 
+use dhcp::DhcpScreen;
 use dig_out::DigOutScreen;
+use failure::SubsystemFailureScreen;
 use help::HelpScreen;
 use iobus::IoBusScreen;
+use menu::MenuScreen;
+use network::NetworkScreen;
 use power::PowerScreen;
+use power_action::PowerActionScreen;
+use power_graph::PowerGraphScreen;
+use qr_code::QrCodeScreen;
 use rauc::RaucScreen;
 use reboot::RebootConfirmScreen;
 use screensaver::ScreenSaverScreen;
+use settings::SettingsScreen;
 use setup::SetupScreen;
 use system::SystemScreen;
+use system_info::SystemInfoScreen;
+use temperature_graph::TemperatureGraphScreen;
 use uart::UartScreen;
 use usb::UsbScreen;
 
+pub use screensaver::ScreenSaverAnimation;
+
 use super::buttons;
+use super::decimation;
+use super::i18n::Language;
+use super::layout;
+use super::logo;
 use super::widgets;
 use super::{FramebufferDrawTarget, Ui, UiResources};
 use crate::broker::Topic;
 use buttons::ButtonEvent;
-use widgets::UI_TEXT_FONT;
+use widgets::draw_text;
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
 pub enum Screen {
     DutPower,
+    DutPowerGraph,
     Usb,
     DigOut,
     System,
+    Network,
+    Dhcp,
     IoBus,
     Uart,
+    QrCode,
+    Settings,
+    Menu,
     ScreenSaver,
     RebootConfirm,
     Rauc,
     Setup,
     Help,
+    PowerAction,
+    SubsystemFailure,
+    TemperatureGraph,
+    SystemInfo,
+}
+
+/// The categories in the main menu that the regular screens are grouped in
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+pub enum MenuCategory {
+    Power,
+    Network,
+    System,
+    Settings,
+}
+
+impl MenuCategory {
+    const ALL: [Self; 4] = [Self::Power, Self::Network, Self::System, Self::Settings];
+
+    /// The next category to highlight in the menu
+    fn next(&self) -> Self {
+        match self {
+            Self::Power => Self::Network,
+            Self::Network => Self::System,
+            Self::System => Self::Settings,
+            Self::Settings => Self::Power,
+        }
+    }
+
+    /// The category `steps` entries further down (or up for negative
+    /// steps) in the menu, e.g. when turning a rotary encoder.
+    fn scrolled(&self, steps: i32) -> Self {
+        let len = Self::ALL.len() as i32;
+        let idx = Self::ALL.iter().position(|c| c == self).unwrap_or(0) as i32;
+
+        Self::ALL[(idx + steps).rem_euclid(len) as usize]
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Power => "Power",
+            Self::Network => "Network",
+            Self::System => "System",
+            Self::Settings => "Settings",
+        }
+    }
+
+    /// The screens in this category, in the order they are cycled through
+    fn screens(&self) -> &'static [Screen] {
+        match self {
+            Self::Power => &[
+                Screen::DutPower,
+                Screen::DutPowerGraph,
+                Screen::Usb,
+                Screen::DigOut,
+            ],
+            Self::Network => &[Screen::Network, Screen::Dhcp, Screen::QrCode],
+            Self::System => &[
+                Screen::System,
+                Screen::SystemInfo,
+                Screen::TemperatureGraph,
+                Screen::IoBus,
+                Screen::Uart,
+            ],
+            Self::Settings => &[Screen::Settings],
+        }
+    }
 }
 
 impl Screen {
+    /// The menu category this screen is listed in (if any).
+    /// Screens without a category are only shown as a reaction to some
+    /// event, e.g. an update being installed.
+    fn category(&self) -> Option<MenuCategory> {
+        MenuCategory::ALL
+            .iter()
+            .copied()
+            .find(|cat| cat.screens().contains(self))
+    }
+
     /// What is the next screen to transition to when e.g. the button is  pressed?
+    ///
+    /// For screens in the menu this cycles through the screens of the
+    /// same category.
     fn next(&self) -> Self {
+        if let Some(category) = self.category() {
+            let screens = category.screens();
+            let idx = screens.iter().position(|s| s == self).unwrap_or(0);
+
+            return screens[(idx + 1) % screens.len()];
+        }
+
         match self {
-            Self::DutPower => Self::Usb,
-            Self::Usb => Self::DigOut,
-            Self::DigOut => Self::System,
-            Self::System => Self::IoBus,
-            Self::IoBus => Self::Uart,
-            Self::Uart => Self::ScreenSaver,
-            Self::ScreenSaver => Self::DutPower,
+            Self::Menu => Self::ScreenSaver,
+            Self::ScreenSaver => Self::Menu,
             Self::RebootConfirm => Self::System,
             Self::Rauc => Self::ScreenSaver,
             Self::Setup => Self::ScreenSaver,
             Self::Help => Self::ScreenSaver,
+            Self::PowerAction => Self::ScreenSaver,
+            _ => Self::Menu,
         }
     }
 
+    /// Should this screen be shown again after a restart if it was the
+    /// last one shown?
+    /// Screens that are shown as a reaction to some event are not.
+    pub(super) fn restorable(&self) -> bool {
+        self.category().is_some() || matches!(self, Self::Menu | Self::ScreenSaver)
+    }
+
     /// Should screensaver be automatically enabled when in this screen?
-    fn use_screensaver(&self) -> bool {
-        !matches!(self, Self::Rauc | Self::Setup | Self::Help)
+    /// This also means that the screen may be left automatically in kiosk mode.
+    pub(super) fn use_screensaver(&self) -> bool {
+        !matches!(
+            self,
+            Self::Rauc | Self::Setup | Self::Help | Self::PowerAction | Self::SubsystemFailure
+        )
     }
 }
 
@@ -104,25 +229,25 @@ pub(super) trait MountableScreen: Sync + Send {
 }
 
 /// Draw static screen border containing a title and an indicator for the
-/// position of the screen in the list of screens.
+/// position of the screen in the list of screens of its menu category.
 async fn draw_border(text: &str, screen: Screen, draw_target: &Arc<Mutex<FramebufferDrawTarget>>) {
     let mut draw_target = draw_target.lock().await;
 
-    Text::new(
-        text,
-        Point::new(8, 17),
-        MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On),
-    )
-    .draw(&mut *draw_target)
-    .unwrap();
+    draw_text(&mut draw_target, text, Point::new(8, 17), Alignment::Left);
 
     Line::new(Point::new(0, 24), Point::new(230, 24))
         .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 2))
         .draw(&mut *draw_target)
         .unwrap();
 
-    let screen_idx = screen as i32;
-    let num_screens = Screen::ScreenSaver as i32;
+    let screens = screen.category().map(|c| c.screens()).unwrap_or(&[]);
+
+    let screen_idx = match screens.iter().position(|s| *s == screen) {
+        Some(idx) => idx as i32,
+        None => return,
+    };
+
+    let num_screens = screens.len() as i32;
     let x_start = screen_idx * 240 / num_screens;
     let x_end = (screen_idx + 1) * 240 / num_screens;
 
@@ -142,17 +267,44 @@ pub(super) fn init(
     res: &UiResources,
     screen: &Arc<Topic<Screen>>,
     buttons: &Arc<Topic<ButtonEvent>>,
+    screensaver_timeout: &Arc<Topic<Option<u64>>>,
+    idle_timeout: &Arc<Topic<Option<u64>>>,
+    screensaver_animation: &Arc<Topic<ScreenSaverAnimation>>,
+    language: &Arc<Topic<Language>>,
 ) -> Vec<Box<dyn MountableScreen>> {
     vec![
+        Box::new(DhcpScreen::new()),
         Box::new(DigOutScreen::new()),
         Box::new(HelpScreen::new()),
         Box::new(IoBusScreen::new()),
+        Box::new(MenuScreen::new(screen, buttons)),
+        Box::new(NetworkScreen::new(res, language)),
         Box::new(PowerScreen::new()),
-        Box::new(RaucScreen::new(screen, &res.rauc.operation)),
+        Box::new(PowerActionScreen::new(screen, &res.logind.state)),
+        Box::new(PowerGraphScreen::new(res)),
+        Box::new(QrCodeScreen::new(res)),
+        Box::new(RaucScreen::new(
+            screen,
+            &res.rauc,
+            &res.systemd.reboot,
+            language,
+        )),
         Box::new(RebootConfirmScreen::new()),
-        Box::new(ScreenSaverScreen::new(buttons, screen)),
+        Box::new(ScreenSaverScreen::new(
+            buttons,
+            screen,
+            idle_timeout,
+            screensaver_animation,
+        )),
+        Box::new(SettingsScreen::new(
+            screensaver_timeout,
+            screensaver_animation,
+        )),
         Box::new(SetupScreen::new(screen, &res.setup_mode.setup_mode)),
+        Box::new(SubsystemFailureScreen::new(screen, res)),
         Box::new(SystemScreen::new()),
+        Box::new(SystemInfoScreen::new()),
+        Box::new(TemperatureGraphScreen::new(res)),
         Box::new(UartScreen::new()),
         Box::new(UsbScreen::new()),
     ]
@@ -0,0 +1,115 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::task::spawn;
+use async_trait::async_trait;
+
+use super::buttons::*;
+use super::widgets::*;
+use super::{draw_border, row_anchor, MountableScreen, Screen, Ui};
+use crate::broker::{Native, SubscriptionHandle};
+use crate::dhcp::DhcpLease;
+
+const SCREEN_TYPE: Screen = Screen::Dhcp;
+const NUM_ROWS: u8 = 8;
+const LINE_WIDTH: u32 = 220;
+const SCROLL_SPEED: u32 = 40;
+
+pub struct DhcpScreen {
+    widgets: Vec<Box<dyn AnyWidget>>,
+    buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
+}
+
+impl DhcpScreen {
+    pub fn new() -> Self {
+        Self {
+            widgets: Vec::new(),
+            buttons_handle: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MountableScreen for DhcpScreen {
+    fn is_my_type(&self, screen: Screen) -> bool {
+        screen == SCREEN_TYPE
+    }
+
+    async fn mount(&mut self, ui: &Ui) {
+        let lang = ui.language();
+
+        draw_border(lang.tr("DUT DHCP Leases"), SCREEN_TYPE, &ui.draw_target).await;
+
+        self.widgets.push(Box::new(DynamicWidget::locator(
+            ui.locator_dance.clone(),
+            ui.draw_target.clone(),
+        )));
+
+        for row in 0..NUM_ROWS {
+            self.widgets.push(Box::new(DynamicWidget::marquee(
+                ui.res.dhcp.leases.clone(),
+                ui.draw_target.clone(),
+                row_anchor(row),
+                LINE_WIDTH,
+                SCROLL_SPEED,
+                Box::new(move |leases: &Vec<DhcpLease>| {
+                    if row == 0 && leases.is_empty() {
+                        return lang.tr("No leases").to_string();
+                    }
+
+                    match leases.get(row as usize) {
+                        Some(lease) => {
+                            let name = lease.hostname.as_ref().unwrap_or(&lease.mac);
+
+                            format!("{} {}", lease.ip, name)
+                        }
+                        None => String::new(),
+                    }
+                }),
+            )));
+        }
+
+        let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
+        let screen = ui.screen.clone();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                if let ButtonEvent::Release {
+                    btn: Button::Upper,
+                    dur: PressDuration::Short,
+                    src: _,
+                } = ev
+                {
+                    screen.set(SCREEN_TYPE.next())
+                }
+            }
+        });
+
+        self.buttons_handle = Some(buttons_handle);
+    }
+
+    async fn unmount(&mut self) {
+        if let Some(handle) = self.buttons_handle.take() {
+            handle.unsubscribe();
+        }
+
+        for mut widget in self.widgets.drain(..) {
+            widget.unmount().await
+        }
+    }
+}
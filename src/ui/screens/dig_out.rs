@@ -20,22 +20,28 @@ use async_std::sync::Arc;
 use async_std::task::spawn;
 use async_trait::async_trait;
 
-use embedded_graphics::{
-    mono_font::MonoTextStyle, pixelcolor::BinaryColor, prelude::*, text::Text,
-};
+use embedded_graphics::{prelude::*, text::Alignment};
 
 use super::buttons::*;
+use super::layout::{Area, Width};
 use super::widgets::*;
-use super::{draw_border, row_anchor, MountableScreen, Screen, Ui};
+use super::{draw_border, MountableScreen, Screen, Ui};
 use crate::broker::{Native, SubscriptionHandle, Topic};
 use crate::measurement::Measurement;
 
 const SCREEN_TYPE: Screen = Screen::DigOut;
-const VOLTAGE_MAX: f32 = 5.0;
-const OFFSET_INDICATOR: Point = Point::new(170, -10);
-const OFFSET_BAR: Point = Point::new(140, -14);
+const WIDTH_ASSERT: u32 = 170;
+const WIDTH_VOLTAGE: u32 = 140;
 const WIDTH_BAR: u32 = 72;
 const HEIGHT_BAR: u32 = 18;
+const SIZE_INDICATOR: Size = Size::new(10, 10);
+
+/// The outputs are often wired to reset lines of the DUT, so changing them
+/// is confirmed first. Indexed by the output and its current state.
+const QUESTIONS: [[&str; 2]; 2] = [
+    ["Assert OUT 0?", "Release OUT 0?"],
+    ["Assert OUT 1?", "Release OUT 1?"],
+];
 
 pub struct DigOutScreen {
     highlighted: Arc<Topic<u8>>,
@@ -60,7 +66,9 @@ impl MountableScreen for DigOutScreen {
     }
 
     async fn mount(&mut self, ui: &Ui) {
-        draw_border("Digital Out", SCREEN_TYPE, &ui.draw_target).await;
+        let lang = ui.language();
+
+        draw_border(lang.tr("Digital Out"), SCREEN_TYPE, &ui.draw_target).await;
 
         self.widgets.push(Box::new(DynamicWidget::locator(
             ui.locator_dance.clone(),
@@ -68,37 +76,37 @@ impl MountableScreen for DigOutScreen {
         )));
 
         let ports = [
-            (
-                0,
-                "OUT 0:",
-                &ui.res.dig_io.out_0,
-                &ui.res.adc.out0_volt.topic,
-            ),
-            (
-                1,
-                "OUT 1:",
-                &ui.res.dig_io.out_1,
-                &ui.res.adc.out1_volt.topic,
-            ),
+            (0, "OUT 0:", &ui.res.dig_io.out_0, &ui.res.adc.out0_volt),
+            (1, "OUT 1:", &ui.res.dig_io.out_1, &ui.res.adc.out1_volt),
         ];
 
-        for (idx, name, status, voltage) in ports {
-            let anchor_name = row_anchor(idx * 4);
-            let anchor_assert = row_anchor(idx * 4 + 1);
-            let anchor_indicator = anchor_assert + OFFSET_INDICATOR;
-
-            let anchor_voltage = row_anchor(idx * 4 + 2);
-            let anchor_bar = anchor_voltage + OFFSET_BAR;
+        // Each output gets its own block of lines in the content area
+        let blocks = Area::content().grid(ports.len() as u32, 1);
+
+        for (&(idx, name, status, voltage), block) in ports.iter().zip(blocks) {
+            let [assert, indicator, _] = block.line(1).columns([
+                Width::Pixels(WIDTH_ASSERT),
+                Width::Pixels(SIZE_INDICATOR.width),
+                Width::Fill,
+            ]);
+            let [volt, bar, _] = block.line(2).columns([
+                Width::Pixels(WIDTH_VOLTAGE),
+                Width::Pixels(WIDTH_BAR),
+                Width::Fill,
+            ]);
+
+            let anchor_name = block.line(0).text_anchor();
+            let anchor_assert = assert.text_anchor();
+            let anchor_indicator = indicator.centered(SIZE_INDICATOR).top_left();
+
+            let anchor_voltage = volt.text_anchor();
+            let anchor_bar = bar.shrink_to_height(HEIGHT_BAR).top_left();
+            let voltage_max = voltage.info.max;
 
             {
                 let mut draw_target = ui.draw_target.lock().await;
 
-                let ui_text_style: MonoTextStyle<BinaryColor> =
-                    MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On);
-
-                Text::new(name, anchor_name, ui_text_style)
-                    .draw(&mut *draw_target)
-                    .unwrap();
+                draw_text(&mut draw_target, name, anchor_name, Alignment::Left);
             }
 
             self.widgets.push(Box::new(DynamicWidget::text(
@@ -106,11 +114,8 @@ impl MountableScreen for DigOutScreen {
                 ui.draw_target.clone(),
                 anchor_assert,
                 Box::new(move |highlight: &u8| {
-                    if *highlight == idx {
-                        "> Asserted:".into()
-                    } else {
-                        "  Asserted:".into()
-                    }
+                    let marker = if *highlight == idx { ">" } else { " " };
+                    format!("{marker} {}", lang.tr("Asserted:"))
                 }),
             )));
 
@@ -125,22 +130,32 @@ impl MountableScreen for DigOutScreen {
             )));
 
             self.widgets.push(Box::new(DynamicWidget::text(
-                voltage.clone(),
+                voltage.topic.clone(),
                 ui.draw_target.clone(),
                 anchor_voltage,
-                Box::new(|meas: &Measurement| format!("  Volt: {:>4.1}V", meas.value)),
+                Box::new(move |meas: &Measurement| {
+                    format!("  {} {:>4.1}V", lang.tr("Volt:"), meas.value)
+                }),
             )));
 
             self.widgets.push(Box::new(DynamicWidget::bar(
-                voltage.clone(),
+                voltage.topic.clone(),
                 ui.draw_target.clone(),
                 anchor_bar,
                 WIDTH_BAR,
                 HEIGHT_BAR,
-                Box::new(|meas: &Measurement| meas.value.abs() / VOLTAGE_MAX),
+                Box::new(move |meas: &Measurement| meas.value.abs() / voltage_max),
             )));
         }
 
+        let question = Topic::anonymous(Some(None));
+
+        self.widgets.push(Box::new(ConfirmDialog::new(
+            question.clone(),
+            ui.draw_target.clone(),
+            lang,
+        )));
+
         let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
         let port_enables = [ui.res.dig_io.out_0.clone(), ui.res.dig_io.out_1.clone()];
         let port_highlight = self.highlighted.clone();
@@ -149,6 +164,15 @@ impl MountableScreen for DigOutScreen {
         spawn(async move {
             while let Some(ev) = button_events.next().await {
                 let highlighted = port_highlight.get().await;
+                let port = &port_enables[highlighted as usize];
+
+                if let Some(confirmed) = ConfirmDialog::answer(&question, &ev) {
+                    if confirmed {
+                        port.modify(|prev| Some(!prev.unwrap_or(true)));
+                    }
+
+                    continue;
+                }
 
                 match ev {
                     ButtonEvent::Release {
@@ -156,9 +180,10 @@ impl MountableScreen for DigOutScreen {
                         dur: PressDuration::Long,
                         src: _,
                     } => {
-                        let port = &port_enables[highlighted as usize];
+                        let asserted = port.try_get().unwrap_or(false);
+                        let text = QUESTIONS[highlighted as usize][asserted as usize];
 
-                        port.modify(|prev| Some(!prev.unwrap_or(true)));
+                        question.set(Some(lang.tr(text).to_string()));
                     }
                     ButtonEvent::Release {
                         btn: Button::Lower,
@@ -169,7 +194,7 @@ impl MountableScreen for DigOutScreen {
                     }
                     ButtonEvent::Release {
                         btn: Button::Upper,
-                        dur: _,
+                        dur: PressDuration::Short,
                         src: _,
                     } => {
                         screen.set(SCREEN_TYPE.next());
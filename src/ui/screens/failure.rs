@@ -0,0 +1,156 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use async_trait::async_trait;
+use embedded_graphics::text::Alignment;
+
+use super::buttons::*;
+use super::widgets::*;
+use super::{draw_border, row_anchor, MountableScreen, Screen, Ui, UiResources};
+use crate::broker::{Native, SubscriptionHandle, Topic};
+use crate::subsystems::SubsystemStatus;
+
+const SCREEN_TYPE: Screen = Screen::SubsystemFailure;
+
+/// The name of a failed subsystem and the last error it reported
+type Failure = (String, String);
+
+pub struct SubsystemFailureScreen {
+    failures: Arc<Topic<Vec<Failure>>>,
+    widgets: Vec<Box<dyn AnyWidget>>,
+    buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
+}
+
+impl SubsystemFailureScreen {
+    pub fn new(screen: &Arc<Topic<Screen>>, res: &UiResources) -> Self {
+        let failures = Topic::anonymous(Some(Vec::new()));
+
+        // Degraded subsystems keep working, so only failed ones are shown
+        let subsystems = [
+            ("ADC", &res.adc.status),
+            ("DUT power", &res.dut_pwr.status),
+            ("Temperatures", &res.temperatures.status),
+        ];
+
+        // Collect the failed subsystems from their status topics
+        for (name, status) in subsystems {
+            let failures_task = failures.clone();
+            let (mut status_events, _) = status.clone().subscribe_unbounded();
+
+            spawn(async move {
+                while let Some(status) = status_events.next().await {
+                    failures_task.modify(|prev| {
+                        let prev: Vec<Failure> = prev.unwrap_or_default();
+
+                        let mut next: Vec<Failure> =
+                            prev.iter().filter(|(n, _)| n != name).cloned().collect();
+
+                        if let SubsystemStatus::Failed { error } = status {
+                            next.push((name.to_string(), error));
+                        }
+
+                        match next == prev {
+                            true => None,
+                            false => Some(next),
+                        }
+                    });
+                }
+            });
+        }
+
+        // The other screens would show stale or bogus values once a
+        // subsystem has failed, so switch to this screen instead.
+        // Go back once all subsystems have recovered.
+        let screen_task = screen.clone();
+        let (mut failure_events, _) = failures.clone().subscribe_unbounded();
+
+        spawn(async move {
+            while let Some(failures) = failure_events.next().await {
+                screen_task.modify(|screen| match (failures.is_empty(), screen) {
+                    (false, Some(Screen::SubsystemFailure)) => None,
+                    (false, _) => Some(Screen::SubsystemFailure),
+                    (true, Some(Screen::SubsystemFailure)) => Some(SCREEN_TYPE.next()),
+                    (true, _) => None,
+                });
+            }
+        });
+
+        Self {
+            failures,
+            widgets: Vec::new(),
+            buttons_handle: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MountableScreen for SubsystemFailureScreen {
+    fn is_my_type(&self, screen: Screen) -> bool {
+        screen == SCREEN_TYPE
+    }
+
+    async fn mount(&mut self, ui: &Ui) {
+        let lang = ui.language();
+
+        draw_border(lang.tr("Subsystem failure"), SCREEN_TYPE, &ui.draw_target).await;
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            self.failures.clone(),
+            ui.draw_target.clone(),
+            row_anchor(0),
+            Box::new(move |failures: &Vec<Failure>| {
+                failures
+                    .iter()
+                    .map(|(name, error)| format!("{}:\n {}\n", lang.tr(name), lang.tr(error)))
+                    .collect()
+            }),
+        )));
+
+        draw_text(
+            &mut *ui.draw_target.lock().await,
+            lang.tr("Press any button\nto dismiss"),
+            row_anchor(6),
+            Alignment::Left,
+        );
+
+        let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
+        let screen = ui.screen.clone();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                if let ButtonEvent::Release { .. } = ev {
+                    screen.set(SCREEN_TYPE.next());
+                }
+            }
+        });
+
+        self.buttons_handle = Some(buttons_handle);
+    }
+
+    async fn unmount(&mut self) {
+        if let Some(handle) = self.buttons_handle.take() {
+            handle.unsubscribe();
+        }
+
+        for mut widget in self.widgets.drain(..) {
+            widget.unmount().await
+        }
+    }
+}
@@ -44,9 +44,10 @@ between actions.
 ...",
     "...
 
-And the upper button
-switches to the next
-screen.
+Short presses on the
+upper button switch
+screens, long presses
+open the menu.
 
 Press it to leave
 this guide",
@@ -73,6 +74,7 @@ impl MountableScreen for HelpScreen {
     }
 
     async fn mount(&mut self, ui: &Ui) {
+        let lang = ui.language();
         let up = Topic::anonymous(Some(false));
         let page = Topic::anonymous(Some(0));
 
@@ -80,16 +82,16 @@ impl MountableScreen for HelpScreen {
             page.clone(),
             ui.draw_target.clone(),
             Point::new(8, 24),
-            Box::new(|page| PAGES[*page].into()),
+            Box::new(move |page| lang.tr(PAGES[*page]).into()),
         )));
 
         self.widgets.push(Box::new(DynamicWidget::text(
             up.clone(),
             ui.draw_target.clone(),
             Point::new(8, 200),
-            Box::new(|up| match up {
-                false => "  Scroll up".into(),
-                true => "> Scroll up".into(),
+            Box::new(move |up| match up {
+                false => format!("  {}", lang.tr("Scroll up")),
+                true => format!("> {}", lang.tr("Scroll up")),
             }),
         )));
 
@@ -97,9 +99,9 @@ impl MountableScreen for HelpScreen {
             up.clone(),
             ui.draw_target.clone(),
             Point::new(8, 220),
-            Box::new(|up| match up {
-                false => "> Scroll down".into(),
-                true => "  Scroll down".into(),
+            Box::new(move |up| match up {
+                false => format!("> {}", lang.tr("Scroll down")),
+                true => format!("  {}", lang.tr("Scroll down")),
             }),
         )));
 
@@ -136,6 +138,8 @@ impl MountableScreen for HelpScreen {
                         screen.set(SCREEN_TYPE.next());
                     }
                     ButtonEvent::Press { btn: _, src: _ } => {}
+                    ButtonEvent::Chord { src: _ } => {}
+                    ButtonEvent::Scroll { steps: _, src: _ } => {}
                 }
             }
         });
@@ -19,9 +19,7 @@ use async_std::prelude::*;
 use async_std::task::spawn;
 use async_trait::async_trait;
 
-use embedded_graphics::{
-    mono_font::MonoTextStyle, pixelcolor::BinaryColor, prelude::*, text::Text,
-};
+use embedded_graphics::{prelude::*, text::Alignment};
 
 use crate::broker::{Native, SubscriptionHandle};
 use crate::iobus::{LSSState, Nodes, ServerInfo};
@@ -54,36 +52,32 @@ impl MountableScreen for IoBusScreen {
     }
 
     async fn mount(&mut self, ui: &Ui) {
+        let lang = ui.language();
+
         draw_border("IOBus", SCREEN_TYPE, &ui.draw_target).await;
 
         {
             let mut draw_target = ui.draw_target.lock().await;
 
-            let ui_text_style: MonoTextStyle<BinaryColor> =
-                MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On);
-
-            Text::new("CAN Status:", row_anchor(0), ui_text_style)
-                .draw(&mut *draw_target)
-                .unwrap();
-
-            Text::new("LSS Scan Status:", row_anchor(1), ui_text_style)
-                .draw(&mut *draw_target)
-                .unwrap();
-
-            Text::new("Power Fault:", row_anchor(2), ui_text_style)
-                .draw(&mut *draw_target)
-                .unwrap();
+            let labels = [
+                ("CAN Status:", row_anchor(0)),
+                ("LSS Scan Status:", row_anchor(1)),
+                ("Power Fault:", row_anchor(2)),
+                ("> Power On:", row_anchor(5)),
+            ];
 
-            Text::new("> Power On:", row_anchor(5), ui_text_style)
-                .draw(&mut *draw_target)
-                .unwrap();
+            for (label, anchor) in labels {
+                draw_text(&mut draw_target, lang.tr(label), anchor, Alignment::Left);
+            }
         }
 
         self.widgets.push(Box::new(DynamicWidget::text(
             ui.res.iobus.nodes.clone(),
             ui.draw_target.clone(),
             row_anchor(3),
-            Box::new(move |nodes: &Nodes| format!("Connected Nodes:  {}", nodes.result.len())),
+            Box::new(move |nodes: &Nodes| {
+                format!("{}  {}", lang.tr("Connected Nodes:"), nodes.result.len())
+            }),
         )));
 
         self.widgets.push(Box::new(DynamicWidget::locator(
@@ -145,7 +139,7 @@ impl MountableScreen for IoBusScreen {
                     } => iobus_pwr_en.modify(|prev| Some(!prev.unwrap_or(true))),
                     ButtonEvent::Release {
                         btn: Button::Upper,
-                        dur: _,
+                        dur: PressDuration::Short,
                         src: _,
                     } => screen.set(SCREEN_TYPE.next()),
                     ButtonEvent::Release {
@@ -153,7 +147,7 @@ impl MountableScreen for IoBusScreen {
                         dur: PressDuration::Short,
                         src: _,
                     } => {}
-                    ButtonEvent::Press { btn: _, src: _ } => {}
+                    _ => {}
                 }
             }
         });
@@ -0,0 +1,169 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use async_trait::async_trait;
+use embedded_graphics::text::Alignment;
+
+use super::buttons::*;
+use super::widgets::*;
+use super::{draw_border, row_anchor, MenuCategory, MountableScreen, Screen, Ui};
+use crate::broker::{Native, SubscriptionHandle, Topic};
+
+const SCREEN_TYPE: Screen = Screen::Menu;
+
+pub struct MenuScreen {
+    highlighted: Arc<Topic<MenuCategory>>,
+    widgets: Vec<Box<dyn AnyWidget>>,
+    buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
+}
+
+impl MenuScreen {
+    pub fn new(screen: &Arc<Topic<Screen>>, buttons: &Arc<Topic<ButtonEvent>>) -> Self {
+        let highlighted = Topic::anonymous(Some(MenuCategory::Power));
+
+        // Remember the category of the screen that was shown last,
+        // so that we can highlight it when going back to the menu.
+        let highlighted_task = highlighted.clone();
+        let (mut screen_events, _) = screen.clone().subscribe_unbounded();
+
+        spawn(async move {
+            while let Some(screen) = screen_events.next().await {
+                if let Some(category) = screen.category() {
+                    highlighted_task.set(category);
+                }
+            }
+        });
+
+        // Long presses are handled here instead of in the mounted screens,
+        // so that entering a category from the menu and going back to the
+        // menu can not race with one another.
+        // A long press on the upper button goes back to the menu from any
+        // screen that is listed in it.
+        // Screens that are not part of the menu (like the update screen) are
+        // left alone, as they may not want to be left.
+        let screen_task = screen.clone();
+        let highlighted_task = highlighted.clone();
+        let (mut button_events, _) = buttons.clone().subscribe_unbounded();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                if let ButtonEvent::Release {
+                    btn,
+                    dur: PressDuration::Long,
+                    src: _,
+                } = ev
+                {
+                    let category = highlighted_task.try_get().unwrap_or(MenuCategory::Power);
+
+                    screen_task.modify(|screen| match (screen, btn) {
+                        (Some(s), _) if s == SCREEN_TYPE => Some(category.screens()[0]),
+                        (Some(s), Button::Upper) if s.category().is_some() => Some(SCREEN_TYPE),
+                        _ => None,
+                    });
+                }
+            }
+        });
+
+        Self {
+            highlighted,
+            widgets: Vec::new(),
+            buttons_handle: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MountableScreen for MenuScreen {
+    fn is_my_type(&self, screen: Screen) -> bool {
+        screen == SCREEN_TYPE
+    }
+
+    async fn mount(&mut self, ui: &Ui) {
+        let lang = ui.language();
+
+        draw_border(lang.tr("Menu"), SCREEN_TYPE, &ui.draw_target).await;
+
+        self.widgets.push(Box::new(DynamicWidget::locator(
+            ui.locator_dance.clone(),
+            ui.draw_target.clone(),
+        )));
+
+        for (row, category) in MenuCategory::ALL.iter().copied().enumerate() {
+            // Tapping a category on a touch panel enters it right away
+            let screen = ui.screen.clone();
+
+            self.widgets.push(Box::new(
+                DynamicWidget::text(
+                    self.highlighted.clone(),
+                    ui.draw_target.clone(),
+                    row_anchor(row as u8),
+                    Box::new(move |highlighted: &MenuCategory| {
+                        let marker = if *highlighted == category { ">" } else { " " };
+                        format!("{marker} {}", lang.tr(category.name()))
+                    }),
+                )
+                .on_tap(
+                    &ui.touch_regions,
+                    Box::new(move || screen.set(category.screens()[0])),
+                ),
+            ));
+        }
+
+        draw_text(
+            &mut *ui.draw_target.lock().await,
+            lang.tr("Short press: move\nLong press: enter"),
+            row_anchor(6),
+            Alignment::Left,
+        );
+
+        let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
+        let highlighted = self.highlighted.clone();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                // Long presses (entering the highlighted category) are
+                // handled in MenuScreen::new().
+                match ev {
+                    ButtonEvent::Release {
+                        btn: _,
+                        dur: PressDuration::Short,
+                        src: _,
+                    } => highlighted.modify(|category| category.map(|c| c.next())),
+                    ButtonEvent::Scroll { steps, src: _ } => {
+                        highlighted.modify(|category| category.map(|c| c.scrolled(steps)))
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        self.buttons_handle = Some(buttons_handle);
+    }
+
+    async fn unmount(&mut self) {
+        if let Some(handle) = self.buttons_handle.take() {
+            handle.unsubscribe();
+        }
+
+        for mut widget in self.widgets.drain(..) {
+            widget.unmount().await
+        }
+    }
+}
@@ -0,0 +1,250 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use async_trait::async_trait;
+use futures::stream::{select, select_all, BoxStream};
+
+use super::buttons::*;
+use super::widgets::*;
+use super::{draw_border, row_anchor, Language, MountableScreen, Screen, Ui, UiResources};
+use crate::broker::{Native, SubscriptionHandle, Topic};
+use crate::dbus::modemmanager::CellularState;
+use crate::dbus::networkmanager::LinkInfo;
+
+const SCREEN_TYPE: Screen = Screen::Network;
+const NUM_ROWS: usize = 8;
+const ROWS_PER_PAGE: usize = NUM_ROWS - 1;
+const MAX_LINE_LEN: usize = 22;
+
+pub struct NetworkScreen {
+    page: Arc<Topic<usize>>,
+    rows: Arc<Topic<Vec<String>>>,
+    widgets: Vec<Box<dyn AnyWidget>>,
+    buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
+}
+
+fn link_line(lang: Language, name: &str, info: Option<LinkInfo>) -> String {
+    let status = match info {
+        Some(LinkInfo {
+            carrier: true,
+            speed,
+        }) => format!("{speed} MBit/s"),
+        Some(LinkInfo { carrier: false, .. }) => lang.tr("Down").to_string(),
+        None => "-".to_string(),
+    };
+
+    format!("{:<10} {}", format!("{name}:"), status)
+}
+
+/// Collect the status of all interfaces into lines of text, which are then
+/// split into pages that fit on the screen.
+fn all_lines(lang: Language, res: &NetworkTopics) -> Vec<String> {
+    let mut lines = vec![
+        link_line(lang, "Uplink", res.uplink.try_get()),
+        link_line(lang, "DUT", res.dut.try_get()),
+    ];
+
+    for (name, topic) in res.monitored.iter() {
+        lines.push(link_line(lang, name, topic.try_get()));
+    }
+
+    match res.cellular_state.try_get() {
+        None | Some(CellularState::Absent) => {}
+        Some(state) => {
+            let quality = res.cellular_signal.try_get().unwrap_or(0);
+            lines.push(format!("{:<10} {quality}%", "Cellular:"));
+            lines.push(format!("  {state:?}"));
+        }
+    }
+
+    lines.push("tac-bridge:".to_string());
+
+    match res.bridge.try_get() {
+        Some(ips) if !ips.is_empty() => lines.extend(ips.iter().map(|ip| format!("  {ip}"))),
+        _ => lines.push(format!("  {}", lang.tr("No IP address"))),
+    }
+
+    lines
+}
+
+fn page_rows(lang: Language, lines: &[String], page: usize) -> Vec<String> {
+    let num_pages = (lines.len() + ROWS_PER_PAGE - 1) / ROWS_PER_PAGE;
+    let page = page % num_pages.max(1);
+
+    let mut rows: Vec<String> = lines
+        .iter()
+        .skip(page * ROWS_PER_PAGE)
+        .take(ROWS_PER_PAGE)
+        .map(|l| l.chars().take(MAX_LINE_LEN).collect())
+        .collect();
+
+    if num_pages > 1 {
+        rows.resize(ROWS_PER_PAGE, String::new());
+        rows.push(format!("{} {}/{}", lang.tr("Page"), page + 1, num_pages));
+    }
+
+    rows
+}
+
+#[derive(Clone)]
+struct NetworkTopics {
+    uplink: Arc<Topic<LinkInfo>>,
+    dut: Arc<Topic<LinkInfo>>,
+    bridge: Arc<Topic<Vec<String>>>,
+    monitored: Vec<(String, Arc<Topic<LinkInfo>>)>,
+    cellular_state: Arc<Topic<CellularState>>,
+    cellular_signal: Arc<Topic<u32>>,
+}
+
+impl NetworkScreen {
+    pub fn new(res: &UiResources, language: &Arc<Topic<Language>>) -> Self {
+        let page = Topic::anonymous(Some(0));
+        let rows = Topic::anonymous(Some(Vec::new()));
+
+        let topics = NetworkTopics {
+            uplink: res.network.uplink_interface.clone(),
+            dut: res.network.dut_interface.clone(),
+            bridge: res.network.bridge_interface.clone(),
+            monitored: res.network.monitored_interfaces.clone(),
+            cellular_state: res.cellular.state.clone(),
+            cellular_signal: res.cellular.signal_quality.clone(),
+        };
+
+        // Re-render the rows of the current page whenever the page or any of
+        // the interfaces change.
+        let mut streams: Vec<BoxStream<'static, ()>> = vec![
+            Box::pin(topics.uplink.clone().subscribe_unbounded().0.map(|_| ())),
+            Box::pin(topics.dut.clone().subscribe_unbounded().0.map(|_| ())),
+            Box::pin(topics.bridge.clone().subscribe_unbounded().0.map(|_| ())),
+            Box::pin(
+                topics
+                    .cellular_state
+                    .clone()
+                    .subscribe_unbounded()
+                    .0
+                    .map(|_| ()),
+            ),
+            Box::pin(
+                topics
+                    .cellular_signal
+                    .clone()
+                    .subscribe_unbounded()
+                    .0
+                    .map(|_| ()),
+            ),
+        ];
+
+        for (_, topic) in topics.monitored.iter() {
+            streams.push(Box::pin(topic.clone().subscribe_unbounded().0.map(|_| ())));
+        }
+
+        streams.push(Box::pin(
+            language.clone().subscribe_unbounded().0.map(|_| ()),
+        ));
+
+        let (page_events, _) = page.clone().subscribe_unbounded();
+        let mut events = select(page_events.map(|_| ()), select_all(streams));
+
+        let page_task = page.clone();
+        let rows_task = rows.clone();
+        let language = language.clone();
+
+        spawn(async move {
+            while events.next().await.is_some() {
+                let page = page_task.try_get().unwrap_or(0);
+                let lang = language.try_get().unwrap_or_default();
+                let new_rows = page_rows(lang, &all_lines(lang, &topics), page);
+
+                rows_task.modify(|prev| match prev {
+                    Some(prev) if prev == new_rows => None,
+                    _ => Some(new_rows),
+                });
+            }
+        });
+
+        Self {
+            page,
+            rows,
+            widgets: Vec::new(),
+            buttons_handle: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MountableScreen for NetworkScreen {
+    fn is_my_type(&self, screen: Screen) -> bool {
+        screen == SCREEN_TYPE
+    }
+
+    async fn mount(&mut self, ui: &Ui) {
+        draw_border(ui.language().tr("Network"), SCREEN_TYPE, &ui.draw_target).await;
+
+        self.page.set(0);
+
+        self.widgets.push(Box::new(DynamicWidget::locator(
+            ui.locator_dance.clone(),
+            ui.draw_target.clone(),
+        )));
+
+        for row in 0..NUM_ROWS {
+            self.widgets.push(Box::new(DynamicWidget::text(
+                self.rows.clone(),
+                ui.draw_target.clone(),
+                row_anchor(row as u8),
+                Box::new(move |rows: &Vec<String>| rows.get(row).cloned().unwrap_or_default()),
+            )));
+        }
+
+        let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
+        let screen = ui.screen.clone();
+        let page = self.page.clone();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                match ev {
+                    ButtonEvent::Release {
+                        btn: Button::Lower,
+                        dur: _,
+                        src: _,
+                    } => page.modify(|p| Some(p.unwrap_or(0) + 1)),
+                    ButtonEvent::Release {
+                        btn: Button::Upper,
+                        dur: PressDuration::Short,
+                        src: _,
+                    } => screen.set(SCREEN_TYPE.next()),
+                    _ => {}
+                }
+            }
+        });
+
+        self.buttons_handle = Some(buttons_handle);
+    }
+
+    async fn unmount(&mut self) {
+        if let Some(handle) = self.buttons_handle.take() {
+            handle.unsubscribe();
+        }
+
+        for mut widget in self.widgets.drain(..) {
+            widget.unmount().await
+        }
+    }
+}
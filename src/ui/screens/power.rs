@@ -18,23 +18,25 @@
 use async_std::prelude::*;
 use async_std::task::spawn;
 use async_trait::async_trait;
+use chrono::DateTime;
 
 use embedded_graphics::prelude::*;
 
 use super::buttons::*;
+use super::layout::{Area, Width};
 use super::widgets::*;
-use super::{draw_border, row_anchor, MountableScreen, Screen, Ui};
-use crate::broker::{Native, SubscriptionHandle};
+use super::{draw_border, MountableScreen, Screen, Ui};
+use crate::broker::{Native, SubscriptionHandle, Topic};
 use crate::dut_power::{OutputRequest, OutputState};
 use crate::measurement::Measurement;
+use crate::scheduler::{ScheduledAction, Target};
 
 const SCREEN_TYPE: Screen = Screen::DutPower;
-const CURRENT_LIMIT: f32 = 5.0;
-const VOLTAGE_LIMIT: f32 = 48.0;
-const OFFSET_INDICATOR: Point = Point::new(155, -10);
-const OFFSET_BAR: Point = Point::new(112, -14);
+const WIDTH_LABEL: u32 = 112;
 const WIDTH_BAR: u32 = 100;
 const HEIGHT_BAR: u32 = 18;
+const WIDTH_STATE: u32 = 155;
+const SIZE_INDICATOR: Size = Size::new(10, 10);
 
 pub struct PowerScreen {
     widgets: Vec<Box<dyn AnyWidget>>,
@@ -57,65 +59,88 @@ impl MountableScreen for PowerScreen {
     }
 
     async fn mount(&mut self, ui: &Ui) {
-        draw_border("DUT Power", SCREEN_TYPE, &ui.draw_target).await;
+        let lang = ui.language();
+
+        draw_border(lang.tr("DUT Power"), SCREEN_TYPE, &ui.draw_target).await;
 
         self.widgets.push(Box::new(DynamicWidget::locator(
             ui.locator_dance.clone(),
             ui.draw_target.clone(),
         )));
 
+        let content = Area::content();
+        let [volt_label, volt_bar] = content
+            .line(0)
+            .columns([Width::Pixels(WIDTH_LABEL), Width::Pixels(WIDTH_BAR)]);
+        let [curr_label, curr_bar] = content
+            .line(1)
+            .columns([Width::Pixels(WIDTH_LABEL), Width::Pixels(WIDTH_BAR)]);
+        let [state_label, state_indicator, _] = content.line(3).columns([
+            Width::Pixels(WIDTH_STATE),
+            Width::Pixels(SIZE_INDICATOR.width),
+            Width::Fill,
+        ]);
+
+        // Scale the bars to the range the channels are designed to measure
+        let volt_max = ui.res.adc.pwr_volt.info.max;
+        let curr_max = ui.res.adc.pwr_curr.info.max;
+
         self.widgets.push(Box::new(DynamicWidget::text(
             ui.res.adc.pwr_volt.topic.clone(),
             ui.draw_target.clone(),
-            row_anchor(0),
+            volt_label.text_anchor(),
             Box::new(|meas: &Measurement| format!("V: {:-6.3}V", meas.value)),
         )));
 
         self.widgets.push(Box::new(DynamicWidget::bar(
             ui.res.adc.pwr_volt.topic.clone(),
             ui.draw_target.clone(),
-            row_anchor(0) + OFFSET_BAR,
+            volt_bar.shrink_to_height(HEIGHT_BAR).top_left(),
             WIDTH_BAR,
             HEIGHT_BAR,
-            Box::new(|meas: &Measurement| meas.value / VOLTAGE_LIMIT),
+            Box::new(move |meas: &Measurement| meas.value / volt_max),
         )));
 
         self.widgets.push(Box::new(DynamicWidget::text(
             ui.res.adc.pwr_curr.topic.clone(),
             ui.draw_target.clone(),
-            row_anchor(1),
+            curr_label.text_anchor(),
             Box::new(|meas: &Measurement| format!("I: {:-6.3}A", meas.value)),
         )));
 
         self.widgets.push(Box::new(DynamicWidget::bar(
             ui.res.adc.pwr_curr.topic.clone(),
             ui.draw_target.clone(),
-            row_anchor(1) + OFFSET_BAR,
+            curr_bar.shrink_to_height(HEIGHT_BAR).top_left(),
             WIDTH_BAR,
             HEIGHT_BAR,
-            Box::new(|meas: &Measurement| meas.value / CURRENT_LIMIT),
+            Box::new(move |meas: &Measurement| meas.value / curr_max),
         )));
 
         self.widgets.push(Box::new(DynamicWidget::text(
             ui.res.dut_pwr.state.clone(),
             ui.draw_target.clone(),
-            row_anchor(3),
-            Box::new(|state: &OutputState| match state {
-                OutputState::On => "> On".into(),
-                OutputState::Off => "> Off".into(),
-                OutputState::Changing => "> Changing".into(),
-                OutputState::OffFloating => "> Off (Float.)".into(),
-                OutputState::InvertedPolarity => "> Inv. Pol.".into(),
-                OutputState::OverCurrent => "> Ov. Curr.".into(),
-                OutputState::OverVoltage => "> Ov. Volt.".into(),
-                OutputState::RealtimeViolation => "> Rt Err.".into(),
+            state_label.text_anchor(),
+            Box::new(move |state: &OutputState| {
+                let text = match state {
+                    OutputState::On => "On",
+                    OutputState::Off => "Off",
+                    OutputState::Changing => "Changing",
+                    OutputState::OffFloating => "Off (Float.)",
+                    OutputState::InvertedPolarity => "Inv. Pol.",
+                    OutputState::OverCurrent => "Ov. Curr.",
+                    OutputState::OverVoltage => "Ov. Volt.",
+                    OutputState::RealtimeViolation => "Rt Err.",
+                };
+
+                format!("> {}", lang.tr(text))
             }),
         )));
 
         self.widgets.push(Box::new(DynamicWidget::indicator(
             ui.res.dut_pwr.state.clone(),
             ui.draw_target.clone(),
-            row_anchor(3) + OFFSET_INDICATOR,
+            state_indicator.centered(SIZE_INDICATOR).top_left(),
             Box::new(|state: &OutputState| match state {
                 OutputState::On => IndicatorState::On,
                 OutputState::Off | OutputState::OffFloating => IndicatorState::Off,
@@ -124,6 +149,42 @@ impl MountableScreen for PowerScreen {
             }),
         )));
 
+        // Show what the scheduler is going to do next, if anything
+        self.widgets.push(Box::new(DynamicWidget::text(
+            ui.res.scheduler.next.clone(),
+            ui.draw_target.clone(),
+            content.line(5).text_anchor(),
+            Box::new(move |action: &Option<ScheduledAction>| {
+                let action = match action {
+                    Some(action) => action,
+                    None => return String::new(),
+                };
+
+                let at = DateTime::parse_from_rfc3339(&action.at)
+                    .map(|at| at.format("%a %H:%M").to_string())
+                    .unwrap_or_else(|_| action.at.clone());
+
+                let target = match action.target {
+                    Target::DutPower => "DUT",
+                    Target::IoBus => "IOBus",
+                };
+
+                let state = lang.tr(if action.on { "On" } else { "Off" });
+
+                format!("{} {at} {target} {state}", lang.tr("Next:"))
+            }),
+        )));
+
+        // Turning the DUT off may end a long running test session,
+        // so ask before doing so.
+        let question = Topic::anonymous(Some(None));
+
+        self.widgets.push(Box::new(ConfirmDialog::new(
+            question.clone(),
+            ui.draw_target.clone(),
+            lang,
+        )));
+
         let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
         let power_state = ui.res.dut_pwr.state.clone();
         let power_request = ui.res.dut_pwr.request.clone();
@@ -131,22 +192,28 @@ impl MountableScreen for PowerScreen {
 
         spawn(async move {
             while let Some(ev) = button_events.next().await {
+                if let Some(confirmed) = ConfirmDialog::answer(&question, &ev) {
+                    if confirmed {
+                        power_request.set(OutputRequest::Off);
+                    }
+
+                    continue;
+                }
+
                 match ev {
                     ButtonEvent::Release {
                         btn: Button::Lower,
                         dur: PressDuration::Long,
                         src: _,
-                    } => {
-                        let req = match power_state.get().await {
-                            OutputState::On => OutputRequest::Off,
-                            _ => OutputRequest::On,
-                        };
-
-                        power_request.set(req);
-                    }
+                    } => match power_state.get().await {
+                        OutputState::On => {
+                            question.set(Some(lang.tr("Power off DUT?").to_string()))
+                        }
+                        _ => power_request.set(OutputRequest::On),
+                    },
                     ButtonEvent::Release {
                         btn: Button::Upper,
-                        dur: _,
+                        dur: PressDuration::Short,
                         src: _,
                     } => screen.set(SCREEN_TYPE.next()),
                     ButtonEvent::Release {
@@ -154,7 +221,7 @@ impl MountableScreen for PowerScreen {
                         dur: PressDuration::Short,
                         src: _,
                     } => {}
-                    ButtonEvent::Press { btn: _, src: _ } => {}
+                    _ => {}
                 }
             }
         });
@@ -0,0 +1,142 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use async_trait::async_trait;
+
+use embedded_graphics::prelude::*;
+
+use crate::broker::{Native, SubscriptionHandle, Topic};
+use crate::dbus::logind::{PowerAction, PowerConfirmation, PowerState};
+
+use super::buttons::*;
+use super::widgets::*;
+use super::{MountableScreen, Screen, Ui};
+
+const SCREEN_TYPE: Screen = Screen::PowerAction;
+
+pub struct PowerActionScreen {
+    widgets: Vec<Box<dyn AnyWidget>>,
+    buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
+}
+
+impl PowerActionScreen {
+    pub fn new(screen: &Arc<Topic<Screen>>, power_state: &Arc<Topic<PowerState>>) -> Self {
+        // Show the confirmation code once a reboot/power off was requested,
+        // then the countdown and leave the screen again if it was cancelled.
+        let screen_task = screen.clone();
+        let (mut state_events, _) = power_state.clone().subscribe_unbounded();
+
+        spawn(async move {
+            while let Some(state) = state_events.next().await {
+                match state {
+                    PowerState::AwaitingConfirmation { .. } | PowerState::CountingDown { .. } => {
+                        screen_task.set(SCREEN_TYPE)
+                    }
+                    PowerState::Idle => screen_task
+                        .modify(|screen| screen.filter(|s| *s == SCREEN_TYPE).map(|s| s.next())),
+                }
+            }
+        });
+
+        Self {
+            widgets: Vec::new(),
+            buttons_handle: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MountableScreen for PowerActionScreen {
+    fn is_my_type(&self, screen: Screen) -> bool {
+        screen == SCREEN_TYPE
+    }
+
+    async fn mount(&mut self, ui: &Ui) {
+        let lang = ui.language();
+
+        self.widgets.push(Box::new(DynamicWidget::text_center(
+            ui.res.logind.state.clone(),
+            ui.draw_target.clone(),
+            Point::new(120, 100),
+            Box::new(move |state: &PowerState| match state {
+                PowerState::AwaitingConfirmation { action, .. } => {
+                    let what = match action {
+                        PowerAction::Reboot => "Reboot requested",
+                        PowerAction::PowerOff => "Power off requested",
+                    };
+
+                    format!(
+                        "{}\n\n\n\n{}",
+                        lang.tr(what),
+                        lang.tr("Press any button\nto cancel")
+                    )
+                }
+                PowerState::CountingDown { action, remaining } => {
+                    let verb = match action {
+                        PowerAction::Reboot => "Rebooting in",
+                        PowerAction::PowerOff => "Powering off in",
+                    };
+
+                    format!(
+                        "{} {remaining}s\n\n{}",
+                        lang.tr(verb),
+                        lang.tr("Press any button\nto cancel")
+                    )
+                }
+                _ => String::new(),
+            }),
+        )));
+
+        // The code is only shown here, so confirming a request requires
+        // a look at the display.
+        self.widgets.push(Box::new(DynamicWidget::text_center(
+            ui.res.logind.code.clone(),
+            ui.draw_target.clone(),
+            Point::new(120, 130),
+            Box::new(move |code: &Option<u32>| match code {
+                Some(code) => format!("{}: {code:06}", lang.tr("Code")),
+                None => String::new(),
+            }),
+        )));
+
+        let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
+        let confirm = ui.res.logind.confirm.clone();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                if let ButtonEvent::Release { .. } = ev {
+                    confirm.set(PowerConfirmation::Cancel);
+                }
+            }
+        });
+
+        self.buttons_handle = Some(buttons_handle);
+    }
+
+    async fn unmount(&mut self) {
+        if let Some(handle) = self.buttons_handle.take() {
+            handle.unsubscribe();
+        }
+
+        for mut widget in self.widgets.drain(..) {
+            widget.unmount().await
+        }
+    }
+}
@@ -0,0 +1,191 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::Duration;
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use async_trait::async_trait;
+
+use embedded_graphics::prelude::*;
+
+use super::buttons::*;
+use super::decimation::{envelope_history, EnvelopeHistory};
+use super::widgets::*;
+use super::{draw_border, row_anchor, MountableScreen, Screen, Ui, UiResources};
+use crate::broker::{Native, SubscriptionHandle, Topic};
+
+const SCREEN_TYPE: Screen = Screen::DutPowerGraph;
+
+/// 200 columns of 150ms each give a history of 30s
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(150);
+const NUM_SAMPLES: usize = 200;
+
+const GRAPH_WIDTH: u32 = NUM_SAMPLES as u32;
+const GRAPH_HEIGHT: u32 = 64;
+const OFFSET_GRAPH: Point = Point::new(12, 6);
+
+/// Smallest full scale values of the graphs, so that noise around zero
+/// does not fill the whole graph.
+const MIN_VOLTAGE_SCALE: f32 = 1.0;
+const MIN_CURRENT_SCALE: f32 = 0.1;
+
+/// Index of the channels in the envelope history
+const VOLTAGE: usize = 0;
+const CURRENT: usize = 1;
+
+pub struct PowerGraphScreen {
+    history: Arc<Topic<EnvelopeHistory>>,
+    widgets: Vec<Box<dyn AnyWidget>>,
+    buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
+}
+
+/// Get the full scale value of a graph with some headroom above the
+/// largest value in the history.
+fn full_scale(history: &EnvelopeHistory, channel: usize, min: f32) -> f32 {
+    history
+        .columns
+        .iter()
+        .map(|column| column[channel].max)
+        .fold(min, f32::max)
+        * 1.2
+}
+
+/// The envelopes of one channel scaled to the range of the graph
+fn scaled(history: &EnvelopeHistory, channel: usize, scale: f32) -> Vec<Envelope> {
+    history
+        .columns
+        .iter()
+        .map(|column| column[channel].map(|val| val / scale))
+        .collect()
+}
+
+impl PowerGraphScreen {
+    pub fn new(res: &UiResources) -> Self {
+        // Keep recording even if the screen is not shown, so that there is
+        // something to look at right away when switching to it.
+        let history = envelope_history(
+            vec![res.adc.pwr_volt.fast.clone(), res.adc.pwr_curr.fast.clone()],
+            SAMPLE_INTERVAL,
+            NUM_SAMPLES,
+        );
+
+        Self {
+            history,
+            widgets: Vec::new(),
+            buttons_handle: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MountableScreen for PowerGraphScreen {
+    fn is_my_type(&self, screen: Screen) -> bool {
+        screen == SCREEN_TYPE
+    }
+
+    async fn mount(&mut self, ui: &Ui) {
+        draw_border(
+            ui.language().tr("DUT Power Graph"),
+            SCREEN_TYPE,
+            &ui.draw_target,
+        )
+        .await;
+
+        self.widgets.push(Box::new(DynamicWidget::locator(
+            ui.locator_dance.clone(),
+            ui.draw_target.clone(),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            self.history.clone(),
+            ui.draw_target.clone(),
+            row_anchor(0),
+            Box::new(|history: &EnvelopeHistory| {
+                let now = history.latest.get(VOLTAGE).copied().unwrap_or(0.0);
+                let scale = full_scale(history, VOLTAGE, MIN_VOLTAGE_SCALE);
+
+                format!("V: {now:-6.3}V /{scale:4.1}V")
+            }),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::graph(
+            self.history.clone(),
+            ui.draw_target.clone(),
+            row_anchor(0) + OFFSET_GRAPH,
+            GRAPH_WIDTH,
+            GRAPH_HEIGHT,
+            Box::new(|history: &EnvelopeHistory| {
+                let scale = full_scale(history, VOLTAGE, MIN_VOLTAGE_SCALE);
+                scaled(history, VOLTAGE, scale)
+            }),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            self.history.clone(),
+            ui.draw_target.clone(),
+            row_anchor(4),
+            Box::new(|history: &EnvelopeHistory| {
+                let now = history.latest.get(CURRENT).copied().unwrap_or(0.0);
+                let scale = full_scale(history, CURRENT, MIN_CURRENT_SCALE);
+
+                format!("I: {now:-6.3}A /{scale:4.1}A")
+            }),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::graph(
+            self.history.clone(),
+            ui.draw_target.clone(),
+            row_anchor(4) + OFFSET_GRAPH,
+            GRAPH_WIDTH,
+            GRAPH_HEIGHT,
+            Box::new(|history: &EnvelopeHistory| {
+                let scale = full_scale(history, CURRENT, MIN_CURRENT_SCALE);
+                scaled(history, CURRENT, scale)
+            }),
+        )));
+
+        let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
+        let screen = ui.screen.clone();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                if let ButtonEvent::Release {
+                    btn: Button::Upper,
+                    dur: PressDuration::Short,
+                    src: _,
+                } = ev
+                {
+                    screen.set(SCREEN_TYPE.next())
+                }
+            }
+        });
+
+        self.buttons_handle = Some(buttons_handle);
+    }
+
+    async fn unmount(&mut self) {
+        if let Some(handle) = self.buttons_handle.take() {
+            handle.unsubscribe();
+        }
+
+        for mut widget in self.widgets.drain(..) {
+            widget.unmount().await
+        }
+    }
+}
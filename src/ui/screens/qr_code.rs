@@ -0,0 +1,197 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use async_trait::async_trait;
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::Alignment,
+};
+use futures::stream::select;
+use qrcodegen::{QrCode, QrCodeEcc};
+
+use super::buttons::*;
+use super::widgets::*;
+use super::{draw_border, FramebufferDrawTarget, MountableScreen, Screen, Ui, UiResources};
+use crate::broker::{Native, SubscriptionHandle, Topic};
+
+const SCREEN_TYPE: Screen = Screen::QrCode;
+
+/// Maximum edge length of the QR code (including the quiet zone) in pixels
+const QR_AREA_SIZE: i32 = 170;
+const QR_AREA_TOP: i32 = 30;
+const QUIET_ZONE: i32 = 2;
+
+pub struct QrCodeScreen {
+    url: Arc<Topic<String>>,
+    widgets: Vec<Box<dyn AnyWidget>>,
+    buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
+}
+
+/// Link to the IP address if we have one, as phones often can not resolve
+/// the hostname, and fall back to the hostname otherwise.
+fn web_url(hostname: Option<String>, ips: Option<Vec<String>>) -> String {
+    let host = ips
+        .and_then(|ips| ips.into_iter().next())
+        .or(hostname)
+        .unwrap_or_else(|| "lxatac".to_string());
+
+    format!("http://{host}/")
+}
+
+fn draw_qr_code(url: &str, target: &mut FramebufferDrawTarget) -> Option<Rectangle> {
+    let qr = QrCode::encode_text(url, QrCodeEcc::Medium).ok()?;
+
+    let modules = qr.size() + 2 * QUIET_ZONE;
+
+    // Codes for very long URLs have more modules than the area has pixels.
+    // Draw them at one pixel per module anyways, even if they overflow it.
+    let scale = (QR_AREA_SIZE / modules).max(1);
+    let edge = modules * scale;
+    let top_left = Point::new((240 - edge) / 2, QR_AREA_TOP);
+
+    let area = Rectangle::new(top_left, Size::new(edge as u32, edge as u32));
+
+    // The QR code is drawn in inverted colors on a white background,
+    // as not all scanners handle white on black codes.
+    area.into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(target)
+        .ok()?;
+
+    for y in 0..qr.size() {
+        for x in 0..qr.size() {
+            if qr.get_module(x, y) {
+                let pos = top_left + Point::new((x + QUIET_ZONE) * scale, (y + QUIET_ZONE) * scale);
+
+                Rectangle::new(pos, Size::new(scale as u32, scale as u32))
+                    .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+                    .draw(target)
+                    .ok()?;
+            }
+        }
+    }
+
+    let area_size = QR_AREA_SIZE.max(edge);
+
+    draw_text(
+        target,
+        url,
+        Point::new(120, QR_AREA_TOP + area_size + 24),
+        Alignment::Center,
+    );
+
+    // Clear the whole area below the border on the next update, as the
+    // size of the QR code depends on the length of the URL.
+    Some(Rectangle::new(
+        Point::new(0, QR_AREA_TOP),
+        Size::new(240, (area_size + 30) as u32),
+    ))
+}
+
+impl QrCodeScreen {
+    pub fn new(res: &UiResources) -> Self {
+        let url = Topic::anonymous(None);
+
+        let hostname = res.network.hostname.clone();
+        let bridge_ips = res.network.bridge_interface.clone();
+
+        let (hostname_events, _) = hostname.clone().subscribe_unbounded();
+        let (bridge_ips_events, _) = bridge_ips.clone().subscribe_unbounded();
+
+        let url_task = url.clone();
+
+        // Keep the URL up to date, even if the screen is not shown
+        spawn(async move {
+            let mut events = select(hostname_events.map(|_| ()), bridge_ips_events.map(|_| ()));
+
+            while events.next().await.is_some() {
+                let url = web_url(hostname.try_get(), bridge_ips.try_get());
+
+                url_task.modify(|prev| match prev {
+                    Some(prev) if prev == url => None,
+                    _ => Some(url),
+                });
+            }
+        });
+
+        Self {
+            url,
+            widgets: Vec::new(),
+            buttons_handle: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MountableScreen for QrCodeScreen {
+    fn is_my_type(&self, screen: Screen) -> bool {
+        screen == SCREEN_TYPE
+    }
+
+    async fn mount(&mut self, ui: &Ui) {
+        draw_border(
+            ui.language().tr("Web Interface"),
+            SCREEN_TYPE,
+            &ui.draw_target,
+        )
+        .await;
+
+        self.widgets.push(Box::new(DynamicWidget::locator(
+            ui.locator_dance.clone(),
+            ui.draw_target.clone(),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::new(
+            self.url.clone(),
+            ui.draw_target.clone(),
+            Box::new(|url: &String, target: &mut FramebufferDrawTarget| draw_qr_code(url, target)),
+        )));
+
+        let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
+        let screen = ui.screen.clone();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                if let ButtonEvent::Release {
+                    btn: Button::Upper,
+                    dur: PressDuration::Short,
+                    src: _,
+                } = ev
+                {
+                    screen.set(SCREEN_TYPE.next())
+                }
+            }
+        });
+
+        self.buttons_handle = Some(buttons_handle);
+    }
+
+    async fn unmount(&mut self) {
+        if let Some(handle) = self.buttons_handle.take() {
+            handle.unsubscribe();
+        }
+
+        for mut widget in self.widgets.drain(..) {
+            widget.unmount().await
+        }
+    }
+}
@@ -15,48 +15,161 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
+use std::time::Duration;
+
 use async_std::prelude::*;
 use async_std::sync::Arc;
-use async_std::task::spawn;
+use async_std::task::{sleep, spawn};
 use async_trait::async_trait;
 
 use embedded_graphics::prelude::*;
 
-use crate::broker::Topic;
-use crate::dbus::rauc::Progress;
+use crate::broker::{Native, SubscriptionHandle, Topic};
+use crate::dbus::rauc::{InstallResult, Progress, Rauc};
 
+use super::buttons::*;
 use super::widgets::*;
-use super::{MountableScreen, Screen, Ui};
+use super::{Language, MountableScreen, Screen, Ui};
 
 const SCREEN_TYPE: Screen = Screen::Rauc;
 
+/// Time in seconds the user has to approve the reboot into the freshly
+/// installed slot before the timeout fallback kicks in.
+const APPROVAL_TIMEOUT: u32 = 120;
+
 pub struct RaucScreen {
     widgets: Vec<Box<dyn AnyWidget>>,
+    buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
+    result_text: Arc<Topic<String>>,
+    approval: Arc<Topic<Option<u32>>>,
+}
+
+/// Count down the time left to approve the reboot after an update.
+///
+/// Once the countdown runs out we reboot if unattended updates are enabled,
+/// as there is likely no one around to press a button in that case.
+/// Otherwise the update is left to be activated by the next reboot.
+/// Pressing a button in the meantime (which sets `approval` to `None`)
+/// always takes precedence.
+async fn approval_countdown(
+    screen: Arc<Topic<Screen>>,
+    approval: Arc<Topic<Option<u32>>>,
+    result_text: Arc<Topic<String>>,
+    auto_install: Arc<Topic<bool>>,
+    reboot: Arc<Topic<bool>>,
+    language: Arc<Topic<Language>>,
+) {
+    while let Some(Some(remaining)) = approval.try_get() {
+        let unattended = auto_install.try_get().unwrap_or(false);
+
+        if remaining == 0 {
+            approval.set(None);
+            result_text.set(String::new());
+
+            if unattended {
+                reboot.set(true);
+            } else {
+                screen.modify(|screen| screen.filter(|s| *s == SCREEN_TYPE).map(|s| s.next()));
+            }
+
+            break;
+        }
+
+        let lang = language.try_get().unwrap_or_default();
+
+        let text = match unattended {
+            true => format!("{} {remaining}s", lang.tr("Rebooting in")),
+            false => format!("{} {remaining}s", lang.tr("Dismissing in")),
+        };
+
+        result_text.set(text);
+
+        sleep(Duration::from_secs(1)).await;
+
+        approval.modify(|prev| match prev {
+            Some(Some(remaining)) => Some(Some(remaining.saturating_sub(1))),
+            _ => None,
+        });
+    }
 }
 
 impl RaucScreen {
-    pub fn new(screen: &Arc<Topic<Screen>>, operation: &Arc<Topic<String>>) -> Self {
-        // Activate the rauc screen if an update is started and deactivate
-        // if it is done
-        let screen = screen.clone();
-        let (mut operation_events, _) = operation.clone().subscribe_unbounded();
+    pub fn new(
+        screen: &Arc<Topic<Screen>>,
+        rauc: &Rauc,
+        reboot: &Arc<Topic<bool>>,
+        language: &Arc<Topic<Language>>,
+    ) -> Self {
+        let result_text = Topic::anonymous(Some(String::new()));
+        let approval = Topic::anonymous(Some(None));
+
+        // Activate the rauc screen if an update is started
+        let screen_task = screen.clone();
+        let result_text_task = result_text.clone();
+        let approval_task = approval.clone();
+        let (mut operation_events, _) = rauc.operation.clone().subscribe_unbounded();
 
         spawn(async move {
             let mut operation_prev = operation_events.next().await.unwrap();
 
             while let Some(ev) = operation_events.next().await {
-                match (operation_prev.as_str(), ev.as_str()) {
-                    (_, "installing") => screen.set(SCREEN_TYPE),
-                    ("installing", _) => screen.set(SCREEN_TYPE.next()),
-                    _ => {}
-                };
+                if operation_prev != "installing" && ev == "installing" {
+                    result_text_task.set(String::new());
+                    approval_task.set(None);
+                    screen_task.set(SCREEN_TYPE);
+                }
 
                 operation_prev = ev;
             }
         });
 
+        // Once the update completed successfully ask the user on-site to
+        // approve the reboot into the new slot.
+        // If it failed we stay on the screen until the user acknowledges the
+        // error by pressing a button.
+        let screen_task = screen.clone();
+        let result_text_task = result_text.clone();
+        let approval_task = approval.clone();
+        let auto_install = rauc.auto_install.clone();
+        let reboot = reboot.clone();
+        let language = language.clone();
+        let (mut result_events, _) = rauc.result.clone().subscribe_unbounded();
+
+        spawn(async move {
+            while let Some(ev) = result_events.next().await {
+                match ev {
+                    InstallResult::Success => {
+                        // RAUC may report the success more than once.
+                        // Keep the countdown that is already running.
+                        if let Some(Some(_)) = approval_task.try_get() {
+                            continue;
+                        }
+
+                        screen_task.set(SCREEN_TYPE);
+                        approval_task.set(Some(APPROVAL_TIMEOUT));
+
+                        spawn(approval_countdown(
+                            screen_task.clone(),
+                            approval_task.clone(),
+                            result_text_task.clone(),
+                            auto_install.clone(),
+                            reboot.clone(),
+                            language.clone(),
+                        ));
+                    }
+                    InstallResult::Failure => {
+                        let lang = language.try_get().unwrap_or_default();
+                        result_text_task.set(lang.tr("Update failed!").to_string())
+                    }
+                }
+            }
+        });
+
         Self {
             widgets: Vec::new(),
+            buttons_handle: None,
+            result_text,
+            approval,
         }
     }
 }
@@ -68,11 +181,25 @@ impl MountableScreen for RaucScreen {
     }
 
     async fn mount(&mut self, ui: &Ui) {
+        let lang = ui.language();
+
         self.widgets.push(Box::new(DynamicWidget::locator(
             ui.locator_dance.clone(),
             ui.draw_target.clone(),
         )));
 
+        self.widgets.push(Box::new(DynamicWidget::text_center(
+            self.approval.clone(),
+            ui.draw_target.clone(),
+            Point::new(120, 30),
+            Box::new(move |approval: &Option<u32>| match approval {
+                Some(_) => lang
+                    .tr("Update installed\nLong press: reboot\nShort press: later")
+                    .to_string(),
+                None => String::new(),
+            }),
+        )));
+
         self.widgets.push(Box::new(DynamicWidget::text_center(
             ui.res.rauc.progress.clone(),
             ui.draw_target.clone(),
@@ -110,9 +237,63 @@ impl MountableScreen for RaucScreen {
             18,
             Box::new(|progress: &Progress| progress.percentage as f32 / 100.0),
         )));
+
+        self.widgets.push(Box::new(DynamicWidget::text_center(
+            self.result_text.clone(),
+            ui.draw_target.clone(),
+            Point::new(120, 220),
+            Box::new(|text: &String| text.clone()),
+        )));
+
+        let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
+        let operation = ui.res.rauc.operation.clone();
+        let screen = ui.screen.clone();
+        let approval = self.approval.clone();
+        let result_text = self.result_text.clone();
+        let reboot = ui.res.systemd.reboot.clone();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                // Do not allow leaving the screen while an update is running
+                let installing = operation
+                    .try_get()
+                    .map(|op| op == "installing")
+                    .unwrap_or(false);
+
+                let awaiting_approval = approval.try_get().flatten().is_some();
+
+                match ev {
+                    ButtonEvent::Release {
+                        btn: Button::Lower,
+                        dur: PressDuration::Long,
+                        src: _,
+                    } if awaiting_approval => {
+                        approval.set(None);
+                        reboot.set(true);
+                    }
+                    ButtonEvent::Release { .. } if !installing => {
+                        // Any other button press postpones the reboot,
+                        // even if it would have happened automatically.
+                        if awaiting_approval {
+                            approval.set(None);
+                            result_text.set(String::new());
+                        }
+
+                        screen.set(SCREEN_TYPE.next());
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        self.buttons_handle = Some(buttons_handle);
     }
 
     async fn unmount(&mut self) {
+        if let Some(handle) = self.buttons_handle.take() {
+            handle.unsubscribe();
+        }
+
         for mut widget in self.widgets.drain(..) {
             widget.unmount().await
         }
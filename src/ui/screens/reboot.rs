@@ -20,16 +20,11 @@ use async_std::task::spawn;
 use async_trait::async_trait;
 
 use crate::broker::{Native, SubscriptionHandle};
-use embedded_graphics::{
-    mono_font::MonoTextStyle,
-    pixelcolor::BinaryColor,
-    prelude::*,
-    text::{Alignment, Text},
-};
+use embedded_graphics::{prelude::*, text::Alignment};
 
 use super::buttons::*;
 use super::widgets::*;
-use super::{FramebufferDrawTarget, MountableScreen, Screen, Ui};
+use super::{FramebufferDrawTarget, Language, MountableScreen, Screen, Ui};
 
 const SCREEN_TYPE: Screen = Screen::RebootConfirm;
 
@@ -45,32 +40,24 @@ impl RebootConfirmScreen {
     }
 }
 
-fn rly(draw_target: &mut FramebufferDrawTarget) {
-    let text_style: MonoTextStyle<BinaryColor> = MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On);
-
-    Text::with_alignment(
-        "Really reboot?\nLong press to confirm",
+fn rly(draw_target: &mut FramebufferDrawTarget, lang: Language) {
+    draw_text(
+        draw_target,
+        lang.tr("Really reboot?\nLong press to confirm"),
         Point::new(120, 120),
-        text_style,
         Alignment::Center,
-    )
-    .draw(draw_target)
-    .unwrap();
+    );
 }
 
-fn brb(draw_target: &mut FramebufferDrawTarget) {
-    let text_style: MonoTextStyle<BinaryColor> = MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On);
-
+fn brb(draw_target: &mut FramebufferDrawTarget, lang: Language) {
     draw_target.clear();
 
-    Text::with_alignment(
-        "Hold tight\nBe right back",
+    draw_text(
+        draw_target,
+        lang.tr("Hold tight\nBe right back"),
         Point::new(120, 120),
-        text_style,
         Alignment::Center,
-    )
-    .draw(draw_target)
-    .unwrap();
+    );
 }
 
 #[async_trait]
@@ -80,8 +67,9 @@ impl MountableScreen for RebootConfirmScreen {
     }
 
     async fn mount(&mut self, ui: &Ui) {
+        let lang = ui.language();
         let draw_target = ui.draw_target.clone();
-        rly(&mut *draw_target.lock().await);
+        rly(&mut *draw_target.lock().await, lang);
 
         let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
         let screen = ui.screen.clone();
@@ -95,11 +83,13 @@ impl MountableScreen for RebootConfirmScreen {
                         dur: PressDuration::Long,
                         src: _,
                     } => {
-                        brb(&mut *draw_target.lock().await);
+                        brb(&mut *draw_target.lock().await, lang);
                         reboot.set(true);
                         break;
                     }
                     ButtonEvent::Press { btn: _, src: _ } => {}
+                    ButtonEvent::Chord { src: _ } => {}
+                    ButtonEvent::Scroll { steps: _, src: _ } => {}
                     _ => screen.set(SCREEN_TYPE.next()),
                 }
             }
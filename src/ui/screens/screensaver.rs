@@ -16,6 +16,7 @@
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
 use std::convert::TryInto;
+use std::sync::Mutex as StdMutex;
 use std::time::{Duration, SystemTime};
 
 use async_std::future::timeout;
@@ -24,32 +25,98 @@ use async_std::sync::Arc;
 use async_std::task::spawn;
 
 use async_trait::async_trait;
+use futures::stream::select;
+use serde::{Deserialize, Serialize};
 
 use embedded_graphics::{
-    mono_font::{ascii::FONT_10X20, MonoFont, MonoTextStyle},
+    image::Image,
+    mono_font::{iso_8859_1::FONT_10X20, MonoFont, MonoTextStyle},
     pixelcolor::BinaryColor,
     prelude::*,
     primitives::Rectangle,
-    text::Text,
+    text::{Alignment, Text},
 };
 
 use super::buttons::*;
 use super::widgets::*;
-use super::{MountableScreen, Screen, Ui};
+use super::{logo::Logo, MountableScreen, Screen, Ui};
 
 use crate::broker::{Native, SubscriptionHandle, Topic};
 
 const UI_TEXT_FONT: MonoFont = FONT_10X20;
 const SCREEN_TYPE: Screen = Screen::ScreenSaver;
-const SCREENSAVER_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Lower bound for the user-configurable screensaver timeout, so that the
+/// display stays usable for at least a couple of seconds.
+const MIN_SCREENSAVER_TIMEOUT: u64 = 10;
+
+/// Time each measurement is shown for in the `MeasurementTicker` animation
+const TICKER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of bounces off the left and right edge of the screen before the
+/// `BouncingHostname` animation switches between hostname and IP address
+const BOUNCES_PER_TEXT: u32 = 4;
+
+/// What to show while the screensaver is active
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ScreenSaverAnimation {
+    /// The hostname bouncing around the screen, alternating with the IP
+    /// address of the TAC every few bounces
+    BouncingHostname,
+    /// DUT voltage, current and SoC temperature, one after the other
+    MeasurementTicker,
+    /// The logo uploaded via the web API bouncing around the screen
+    /// (or nothing if there is none)
+    Logo,
+    /// Nothing but the locator (if active)
+    Blank,
+}
+
+impl ScreenSaverAnimation {
+    pub fn next(&self) -> Self {
+        match self {
+            Self::BouncingHostname => Self::MeasurementTicker,
+            Self::MeasurementTicker => Self::Logo,
+            Self::Logo => Self::Blank,
+            Self::Blank => Self::BouncingHostname,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::BouncingHostname => "Hostname",
+            Self::MeasurementTicker => "Measurements",
+            Self::Logo => "Logo",
+            Self::Blank => "Blank",
+        }
+    }
+}
+
+/// The horizontal position and direction of the last bounce() and the
+/// number of times the direction changed
+#[derive(Default)]
+struct BounceState {
+    x: i32,
+    direction: i32,
+    bounces: u32,
+}
 
 struct BounceAnimation {
     bounding_box: Rectangle,
+    state: StdMutex<BounceState>,
 }
 
 impl BounceAnimation {
     pub fn new(bounding_box: Rectangle) -> Self {
-        Self { bounding_box }
+        Self {
+            bounding_box,
+            state: StdMutex::new(BounceState::default()),
+        }
+    }
+
+    /// The number of times the object bounced off the left or right edge
+    pub fn bounces(&self) -> u32 {
+        self.state.lock().unwrap().bounces
     }
 
     fn offset(&self, obj_size: Size) -> Point {
@@ -84,26 +151,73 @@ impl BounceAnimation {
 
     pub fn bounce<O: Transform + Dimensions>(&self, obj: O) -> O {
         let obj_size = obj.bounding_box().size;
-        obj.translate(self.offset(obj_size))
+        let offset = self.offset(obj_size);
+
+        let mut state = self.state.lock().unwrap();
+        let direction = (offset.x - state.x).signum();
+
+        if direction != 0 {
+            if direction == -state.direction {
+                state.bounces = state.bounces.wrapping_add(1);
+            }
+
+            state.direction = direction;
+        }
+
+        state.x = offset.x;
+
+        obj.translate(offset)
     }
 }
 
 pub struct ScreenSaverScreen {
+    animation: Arc<Topic<ScreenSaverAnimation>>,
     widgets: Vec<Box<dyn AnyWidget>>,
     buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
 }
 
 impl ScreenSaverScreen {
-    pub fn new(buttons: &Arc<Topic<ButtonEvent>>, screen: &Arc<Topic<Screen>>) -> Self {
+    pub fn new(
+        buttons: &Arc<Topic<ButtonEvent>>,
+        screen: &Arc<Topic<Screen>>,
+        screensaver_timeout: &Arc<Topic<Option<u64>>>,
+        animation: &Arc<Topic<ScreenSaverAnimation>>,
+    ) -> Self {
+        enum Event {
+            Button,
+            Timeout(Option<u64>),
+        }
+
         // Activate screensaver if no button is pressed for some time
-        let (mut buttons_events, _) = buttons.clone().subscribe_unbounded();
+        let (buttons_events, _) = buttons.clone().subscribe_unbounded();
+        let (timeout_events, _) = screensaver_timeout.clone().subscribe_unbounded();
+        let mut events = select(
+            buttons_events.map(|_| Event::Button),
+            timeout_events.map(Event::Timeout),
+        );
+
         let screen_task = screen.clone();
         spawn(async move {
+            // The current setting is the first thing we receive via the
+            // timeout subscription.
+            let mut screensaver_timeout = None;
+
             loop {
-                let ev = timeout(SCREENSAVER_TIMEOUT, buttons_events.next()).await;
+                let ev = match screensaver_timeout {
+                    Some(secs) => {
+                        let secs = u64::max(secs, MIN_SCREENSAVER_TIMEOUT);
+                        timeout(Duration::from_secs(secs), events.next()).await
+                    }
+                    None => Ok(events.next().await),
+                };
+
                 let activate_screensaver = match ev {
                     Ok(None) => break,
-                    Ok(Some(_)) => false,
+                    Ok(Some(Event::Button)) => false,
+                    Ok(Some(Event::Timeout(t))) => {
+                        screensaver_timeout = t;
+                        false
+                    }
                     Err(_) => true,
                 };
 
@@ -122,6 +236,7 @@ impl ScreenSaverScreen {
         });
 
         Self {
+            animation: animation.clone(),
             widgets: Vec::new(),
             buttons_handle: None,
         }
@@ -135,32 +250,112 @@ impl MountableScreen for ScreenSaverScreen {
     }
 
     async fn mount(&mut self, ui: &Ui) {
-        let hostname = ui.res.network.hostname.get().await;
-        let bounce = BounceAnimation::new(Rectangle::with_corners(
+        let bounce = Arc::new(BounceAnimation::new(Rectangle::with_corners(
             Point::new(0, 8),
             Point::new(230, 240),
-        ));
+        )));
 
         self.widgets.push(Box::new(DynamicWidget::locator(
             ui.locator_dance.clone(),
             ui.draw_target.clone(),
         )));
 
-        self.widgets.push(Box::new(DynamicWidget::new(
-            ui.res.adc.time.clone(),
-            ui.draw_target.clone(),
-            Box::new(move |_, target| {
-                let ui_text_style: MonoTextStyle<BinaryColor> =
-                    MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On);
+        let animation = self
+            .animation
+            .try_get()
+            .unwrap_or(ScreenSaverAnimation::BouncingHostname);
+
+        // Changes to the animation take effect the next time the
+        // screensaver is shown.
+        let text_fn: Option<Box<dyn Fn() -> String + Sync + Send>> = match animation {
+            ScreenSaverAnimation::BouncingHostname => {
+                // The IP address is what one has to type in at the rack,
+                // so show it every few bounces (if there is one).
+                let hostname = ui.res.network.hostname.clone();
+                let bridge_interface = ui.res.network.bridge_interface.clone();
+                let bounce = bounce.clone();
+
+                Some(Box::new(move || {
+                    let show_ip = (bounce.bounces() / BOUNCES_PER_TEXT) % 2 == 1;
+                    let ip = bridge_interface
+                        .try_get()
+                        .and_then(|ips| ips.first().cloned());
+
+                    match (show_ip, ip) {
+                        (true, Some(ip)) => ip,
+                        _ => hostname.try_get().unwrap_or_default(),
+                    }
+                }))
+            }
+            ScreenSaverAnimation::MeasurementTicker => {
+                let pwr_volt = ui.res.adc.pwr_volt.topic.clone();
+                let pwr_curr = ui.res.adc.pwr_curr.topic.clone();
+                let soc_temperature = ui.res.temperatures.soc_temperature.clone();
+                let lang = ui.language();
+
+                Some(Box::new(move || {
+                    let ticks = SystemTime::UNIX_EPOCH
+                        .elapsed()
+                        .map(|t| t.as_secs() / TICKER_INTERVAL.as_secs())
+                        .unwrap_or(0);
+
+                    let (label, meas, unit) = match ticks % 3 {
+                        0 => ("DUT Voltage", pwr_volt.try_get(), "V"),
+                        1 => ("DUT Current", pwr_curr.try_get(), "A"),
+                        _ => ("SoC Temp.", soc_temperature.try_get(), "C"),
+                    };
+
+                    let label = lang.tr(label);
+
+                    match meas {
+                        Some(meas) => format!("{label}\n{:.2}{unit}", meas.value),
+                        None => format!("{label}\n-"),
+                    }
+                }))
+            }
+            ScreenSaverAnimation::Logo | ScreenSaverAnimation::Blank => None,
+        };
 
-                let text = Text::new(&hostname, Point::new(0, 0), ui_text_style);
-                let text = bounce.bounce(text);
+        // The logo is loaded on every mount so that a newly uploaded one
+        // is shown without having to restart the tacd.
+        let logo = match animation {
+            ScreenSaverAnimation::Logo => Logo::load(),
+            _ => None,
+        };
 
-                text.draw(target).unwrap();
+        if let Some(logo) = logo {
+            let bounce = bounce.clone();
 
-                Some(text.bounding_box())
-            }),
-        )));
+            self.widgets.push(Box::new(DynamicWidget::new(
+                ui.res.adc.time.clone(),
+                ui.draw_target.clone(),
+                Box::new(move |_, target| {
+                    let raw = logo.image();
+                    let image = bounce.bounce(Image::new(&raw, Point::zero()));
+
+                    image.draw(target).unwrap();
+
+                    Some(image.bounding_box())
+                }),
+            )));
+        }
+
+        if let Some(text_fn) = text_fn {
+            self.widgets.push(Box::new(DynamicWidget::new(
+                ui.res.adc.time.clone(),
+                ui.draw_target.clone(),
+                Box::new(move |_, target| {
+                    let ui_text_style: MonoTextStyle<BinaryColor> =
+                        MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On);
+
+                    let content = text_fn();
+                    let text = Text::new(&content, Point::new(0, 0), ui_text_style);
+                    let text = bounce.bounce(text);
+
+                    Some(draw_text(target, &content, text.position, Alignment::Left))
+                }),
+            )));
+        }
 
         let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
         let locator = ui.locator.clone();
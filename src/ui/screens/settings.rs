@@ -0,0 +1,310 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::buttons::*;
+use super::widgets::*;
+use super::{draw_border, row_anchor, Language, MountableScreen, Screen, ScreenSaverAnimation, Ui};
+use crate::broker::{Native, SubscriptionHandle, Topic};
+
+const SCREEN_TYPE: Screen = Screen::Settings;
+
+/// The backlight brightness levels to cycle through
+const BRIGHTNESS_STEPS: &[f32] = &[1.0, 0.75, 0.5, 0.25];
+
+/// The longest screensaver timeout (in minutes) that can be set on the device.
+/// A timeout of zero minutes disables the screensaver.
+const SCREENSAVER_MAX_MINUTES: f32 = 60.0;
+
+/// The range (in Ampere) the DUT power overcurrent threshold can be set to
+const CURRENT_LIMIT_MIN: f32 = 0.1;
+const CURRENT_LIMIT_MAX: f32 = 5.0;
+const CURRENT_LIMIT_STEP: f32 = 0.1;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum Setting {
+    Brightness,
+    Screensaver,
+    Animation,
+    CurrentLimit,
+}
+
+impl Setting {
+    fn next(&self) -> Self {
+        match self {
+            Self::Brightness => Self::Screensaver,
+            Self::Screensaver => Self::Animation,
+            Self::Animation => Self::CurrentLimit,
+            Self::CurrentLimit => Self::Brightness,
+        }
+    }
+}
+
+/// Get the step following the current brightness, wrapping around to the
+/// brightest setting once the darkest one is reached.
+fn next_brightness(current: f32) -> f32 {
+    BRIGHTNESS_STEPS
+        .iter()
+        .copied()
+        .find(|step| *step < current - 0.01)
+        .unwrap_or(BRIGHTNESS_STEPS[0])
+}
+
+fn format_screensaver_timeout(lang: Language, timeout: Option<u64>) -> String {
+    match timeout {
+        Some(secs) if secs % 60 == 0 => format!("{} {}min", lang.tr("after"), secs / 60),
+        Some(secs) => format!("{} {secs}s", lang.tr("after")),
+        None => lang.tr("never").to_string(),
+    }
+}
+
+pub struct SettingsScreen {
+    screensaver_timeout: Arc<Topic<Option<u64>>>,
+    screensaver_animation: Arc<Topic<ScreenSaverAnimation>>,
+    widgets: Vec<Box<dyn AnyWidget>>,
+    buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
+}
+
+impl SettingsScreen {
+    pub fn new(
+        screensaver_timeout: &Arc<Topic<Option<u64>>>,
+        screensaver_animation: &Arc<Topic<ScreenSaverAnimation>>,
+    ) -> Self {
+        Self {
+            screensaver_timeout: screensaver_timeout.clone(),
+            screensaver_animation: screensaver_animation.clone(),
+            widgets: Vec::new(),
+            buttons_handle: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MountableScreen for SettingsScreen {
+    fn is_my_type(&self, screen: Screen) -> bool {
+        screen == SCREEN_TYPE
+    }
+
+    async fn mount(&mut self, ui: &Ui) {
+        let lang = ui.language();
+
+        draw_border(lang.tr("Settings"), SCREEN_TYPE, &ui.draw_target).await;
+
+        let highlighted = Topic::anonymous(Some(Setting::Brightness));
+
+        self.widgets.push(Box::new(DynamicWidget::locator(
+            ui.locator_dance.clone(),
+            ui.draw_target.clone(),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            highlighted.clone(),
+            ui.draw_target.clone(),
+            row_anchor(0),
+            Box::new(move |setting| match setting {
+                Setting::Brightness => format!("> {}", lang.tr("Brightness")),
+                _ => format!("  {}", lang.tr("Brightness")),
+            }),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            ui.res.backlight.brightness.clone(),
+            ui.draw_target.clone(),
+            row_anchor(1),
+            Box::new(|brightness: &f32| format!("    {:.0}%", brightness * 100.0)),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            highlighted.clone(),
+            ui.draw_target.clone(),
+            row_anchor(2),
+            Box::new(move |setting| match setting {
+                Setting::Screensaver => format!("> {}", lang.tr("Screensaver")),
+                _ => format!("  {}", lang.tr("Screensaver")),
+            }),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            self.screensaver_timeout.clone(),
+            ui.draw_target.clone(),
+            row_anchor(3),
+            Box::new(move |timeout: &Option<u64>| {
+                format!("    {}", format_screensaver_timeout(lang, *timeout))
+            }),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            highlighted.clone(),
+            ui.draw_target.clone(),
+            row_anchor(4),
+            Box::new(move |setting| match setting {
+                Setting::Animation => format!("> {}", lang.tr("Animation")),
+                _ => format!("  {}", lang.tr("Animation")),
+            }),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            self.screensaver_animation.clone(),
+            ui.draw_target.clone(),
+            row_anchor(5),
+            Box::new(move |animation: &ScreenSaverAnimation| {
+                format!("    {}", lang.tr(animation.name()))
+            }),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            highlighted.clone(),
+            ui.draw_target.clone(),
+            row_anchor(6),
+            Box::new(move |setting| match setting {
+                Setting::CurrentLimit => format!("> {}", lang.tr("DUT current limit")),
+                _ => format!("  {}", lang.tr("DUT current limit")),
+            }),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            ui.res.dut_pwr.current_limit.clone(),
+            ui.draw_target.clone(),
+            row_anchor(7),
+            Box::new(|limit: &f32| format!("    {limit:.1}A")),
+        )));
+
+        // The numeric settings are changed in an editor dialog on top of
+        // the screen.
+        let screensaver_edit = Topic::anonymous(Some(None));
+        let current_limit_edit = Topic::anonymous(Some(None));
+
+        self.widgets.push(Box::new(ValueEditor::new(
+            screensaver_edit.clone(),
+            ui.draw_target.clone(),
+            lang,
+            "Screensaver",
+            Box::new(move |minutes| {
+                let timeout = (minutes > 0.0).then(|| minutes as u64 * 60);
+                format_screensaver_timeout(lang, timeout)
+            }),
+        )));
+
+        self.widgets.push(Box::new(ValueEditor::new(
+            current_limit_edit.clone(),
+            ui.draw_target.clone(),
+            lang,
+            "DUT current limit",
+            Box::new(|limit| format!("{limit:.1}A")),
+        )));
+
+        let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
+        let brightness = ui.res.backlight.brightness.clone();
+        let screensaver_timeout = self.screensaver_timeout.clone();
+        let screensaver_animation = self.screensaver_animation.clone();
+        let current_limit = ui.res.dut_pwr.current_limit.clone();
+        let screen = ui.screen.clone();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                if let Some(res) = ValueEditor::input(&screensaver_edit, &ev) {
+                    if let EditResult::Accepted(minutes) = res {
+                        let timeout = (minutes > 0.0).then(|| minutes as u64 * 60);
+                        screensaver_timeout.set(timeout);
+                    }
+
+                    continue;
+                }
+
+                if let Some(res) = ValueEditor::input(&current_limit_edit, &ev) {
+                    if let EditResult::Accepted(limit) = res {
+                        current_limit.set(limit);
+                    }
+
+                    continue;
+                }
+
+                let setting = highlighted.get().await;
+
+                match ev {
+                    ButtonEvent::Release {
+                        btn: Button::Lower,
+                        dur: PressDuration::Long,
+                        src: _,
+                    } => match setting {
+                        Setting::Brightness => {
+                            brightness.modify(|prev| Some(next_brightness(prev.unwrap_or(1.0))))
+                        }
+                        Setting::Screensaver => {
+                            let minutes = screensaver_timeout
+                                .try_get()
+                                .flatten()
+                                .map(|secs| secs as f32 / 60.0)
+                                .unwrap_or(0.0);
+
+                            screensaver_edit.set(Some(ValueEdit::new(
+                                minutes,
+                                0.0,
+                                SCREENSAVER_MAX_MINUTES,
+                                1.0,
+                            )))
+                        }
+                        Setting::Animation => screensaver_animation.modify(|prev| {
+                            Some(
+                                prev.unwrap_or(ScreenSaverAnimation::BouncingHostname)
+                                    .next(),
+                            )
+                        }),
+                        Setting::CurrentLimit => {
+                            let limit = current_limit.try_get().unwrap_or(CURRENT_LIMIT_MAX);
+
+                            current_limit_edit.set(Some(ValueEdit::new(
+                                limit,
+                                CURRENT_LIMIT_MIN,
+                                CURRENT_LIMIT_MAX,
+                                CURRENT_LIMIT_STEP,
+                            )))
+                        }
+                    },
+                    ButtonEvent::Release {
+                        btn: Button::Lower,
+                        dur: PressDuration::Short,
+                        src: _,
+                    } => highlighted.set(setting.next()),
+                    ButtonEvent::Release {
+                        btn: Button::Upper,
+                        dur: PressDuration::Short,
+                        src: _,
+                    } => screen.set(SCREEN_TYPE.next()),
+                    _ => {}
+                }
+            }
+        });
+
+        self.buttons_handle = Some(buttons_handle);
+    }
+
+    async fn unmount(&mut self) {
+        if let Some(handle) = self.buttons_handle.take() {
+            handle.unsubscribe();
+        }
+
+        for mut widget in self.widgets.drain(..) {
+            widget.unmount().await
+        }
+    }
+}
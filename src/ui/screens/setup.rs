@@ -96,6 +96,7 @@ impl MountableScreen for SetupScreen {
          * It would most likely be too long to practically read it and type into a
          * browser anyways. */
         let connectivity_topic = Topic::anonymous(Some(Connectivity::Nothing));
+        let lang = ui.language();
 
         let connectivity_topic_task = connectivity_topic.clone();
         let (mut hostname_stream, hostname_update_handle) =
@@ -143,26 +144,30 @@ impl MountableScreen for SetupScreen {
             }
         });
 
-        self.widgets.push(Box::new(
-            DynamicWidget::text_aligned(
-                connectivity_topic,
-                ui.draw_target.clone(),
-                Point::new(120, 55),
-                Box::new(|connectivity| match connectivity {
-                    Connectivity::Nothing => {
-                        "Welcome to your TAC!\n\n\nPlease connect\nto a network\nto continue\n the setup".into()
-                    }
+        self.widgets.push(Box::new(DynamicWidget::text_aligned(
+            connectivity_topic,
+            ui.draw_target.clone(),
+            Point::new(120, 55),
+            Box::new(move |connectivity| {
+                let welcome = lang.tr("Welcome to your TAC!");
+                let continue_at = lang.tr("Please continue the\nsetup at:");
+
+                match connectivity {
+                    Connectivity::Nothing => format!(
+                        "{welcome}\n\n\n{}",
+                        lang.tr("Please connect\nto a network\nto continue\n the setup")
+                    ),
                     Connectivity::HostnameOnly(c) | Connectivity::IpOnly(c) => {
-                        format!("Welcome to your TAC!\n\nPlease continue the\nsetup at:\n\n\nhttp://{c}")
+                        format!("{welcome}\n\n{continue_at}\n\n\nhttp://{c}")
                     }
                     Connectivity::Both(ip, hn) => format!(
-                        "Welcome to your TAC!\n\nPlease continue the\nsetup at:\n\nhttp://{hn}\nor\nhttp://{ip}"
+                        "{welcome}\n\n{continue_at}\n\nhttp://{hn}\n{}\nhttp://{ip}",
+                        lang.tr("or")
                     ),
-                }),
-                Alignment::Center,
-            )
-            ,
-        ));
+                }
+            }),
+            Alignment::Center,
+        )));
 
         self.hostname_update_handle = Some(hostname_update_handle);
         self.ip_update_handle = Some(ip_update_handle);
@@ -18,16 +18,18 @@
 use async_std::prelude::*;
 use async_std::task::spawn;
 use async_trait::async_trait;
+use embedded_graphics::{prelude::*, text::Alignment};
 use serde::{Deserialize, Serialize};
 
 use super::buttons::*;
 use super::widgets::*;
 use super::{draw_border, row_anchor, MountableScreen, Screen, Ui};
-use crate::broker::{Native, SubscriptionHandle, Topic};
+use crate::broker::{Native, SubscriptionHandle, Topic, WebSession};
 use crate::dbus::networkmanager::LinkInfo;
-use crate::measurement::Measurement;
 
 const SCREEN_TYPE: Screen = Screen::System;
+const SOC_TEMPERATURE_MIN: f32 = 20.0;
+const SOC_TEMPERATURE_MAX: f32 = 100.0;
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
 enum Action {
@@ -67,7 +69,9 @@ impl MountableScreen for SystemScreen {
     }
 
     async fn mount(&mut self, ui: &Ui) {
-        draw_border("System Status", SCREEN_TYPE, &ui.draw_target).await;
+        let lang = ui.language();
+
+        draw_border(lang.tr("System Status"), SCREEN_TYPE, &ui.draw_target).await;
 
         let highlighted = Topic::anonymous(Some(Action::Reboot));
 
@@ -76,20 +80,36 @@ impl MountableScreen for SystemScreen {
             ui.draw_target.clone(),
         )));
 
-        self.widgets.push(Box::new(DynamicWidget::text(
+        // Let the user know if someone may be controlling the TAC via the
+        // web interface right now.
+        self.widgets.push(Box::new(DynamicWidget::text_aligned(
+            ui.web_sessions.clone(),
+            ui.draw_target.clone(),
+            Point::new(228, 17),
+            Box::new(|sessions: &Vec<WebSession>| match sessions.len() {
+                0 => String::new(),
+                n => format!("Web:{n}"),
+            }),
+            Alignment::Right,
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::bar_graph(
             ui.res.temperatures.soc_temperature.clone(),
             ui.draw_target.clone(),
             row_anchor(0),
-            Box::new(|meas: &Measurement| format!("SoC:    {:.0}C", meas.value)),
+            "SoC:",
+            "C",
+            SOC_TEMPERATURE_MIN,
+            SOC_TEMPERATURE_MAX,
         )));
 
         self.widgets.push(Box::new(DynamicWidget::text(
             ui.res.network.uplink_interface.clone(),
             ui.draw_target.clone(),
             row_anchor(1),
-            Box::new(|info: &LinkInfo| match info.carrier {
+            Box::new(move |info: &LinkInfo| match info.carrier {
                 true => format!("Uplink: {}MBit/s", info.speed),
-                false => "Uplink: Down".to_string(),
+                false => format!("Uplink: {}", lang.tr("Down")),
             }),
         )));
 
@@ -97,9 +117,9 @@ impl MountableScreen for SystemScreen {
             ui.res.network.dut_interface.clone(),
             ui.draw_target.clone(),
             row_anchor(2),
-            Box::new(|info: &LinkInfo| match info.carrier {
+            Box::new(move |info: &LinkInfo| match info.carrier {
                 true => format!("DUT:    {}MBit/s", info.speed),
-                false => "DUT:    Down".to_string(),
+                false => format!("DUT:    {}", lang.tr("Down")),
             }),
         )));
 
@@ -113,13 +133,27 @@ impl MountableScreen for SystemScreen {
             }),
         )));
 
+        self.widgets.push(Box::new(DynamicWidget::text(
+            ui.res.timedate.ntp_synchronized.clone(),
+            ui.draw_target.clone(),
+            row_anchor(4),
+            Box::new(move |synced: &bool| {
+                let status = match synced {
+                    true => lang.tr("Synced"),
+                    false => lang.tr("Not synced"),
+                };
+
+                format!("{:<8}{status}", lang.tr("Time:"))
+            }),
+        )));
+
         self.widgets.push(Box::new(DynamicWidget::text(
             highlighted.clone(),
             ui.draw_target.clone(),
             row_anchor(5),
-            Box::new(|action| match action {
-                Action::Reboot => "> Reboot".into(),
-                _ => "  Reboot".into(),
+            Box::new(move |action| match action {
+                Action::Reboot => format!("> {}", lang.tr("Reboot")),
+                _ => format!("  {}", lang.tr("Reboot")),
             }),
         )));
 
@@ -127,9 +161,9 @@ impl MountableScreen for SystemScreen {
             highlighted.clone(),
             ui.draw_target.clone(),
             row_anchor(6),
-            Box::new(|action| match action {
-                Action::Help => "> Help".into(),
-                _ => "  Help".into(),
+            Box::new(move |action| match action {
+                Action::Help => format!("> {}", lang.tr("Help")),
+                _ => format!("  {}", lang.tr("Help")),
             }),
         )));
 
@@ -137,9 +171,9 @@ impl MountableScreen for SystemScreen {
             highlighted.clone(),
             ui.draw_target.clone(),
             row_anchor(7),
-            Box::new(|action| match action {
-                Action::SetupMode => "> Setup Mode".into(),
-                _ => "  Setup Mode".into(),
+            Box::new(move |action| match action {
+                Action::SetupMode => format!("> {}", lang.tr("Setup Mode")),
+                _ => format!("  {}", lang.tr("Setup Mode")),
             }),
         )));
 
@@ -182,12 +216,12 @@ impl MountableScreen for SystemScreen {
                     } => highlighted.set(action.next()),
                     ButtonEvent::Release {
                         btn: Button::Upper,
-                        dur: _,
+                        dur: PressDuration::Short,
                         src: _,
                     } => {
                         screen.set(SCREEN_TYPE.next());
                     }
-                    ButtonEvent::Press { btn: _, src: _ } => {}
+                    _ => {}
                 }
             }
         });
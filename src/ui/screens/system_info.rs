@@ -0,0 +1,204 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::fs::read_to_string;
+
+use async_std::prelude::*;
+use async_std::task::spawn;
+use async_trait::async_trait;
+use embedded_graphics::{prelude::*, text::Alignment};
+
+use super::buttons::*;
+use super::widgets::*;
+use super::{draw_border, row_anchor, MountableScreen, Screen, Ui};
+use crate::broker::{Native, SubscriptionHandle, Topic};
+use crate::measurement::Timestamp;
+
+const SCREEN_TYPE: Screen = Screen::SystemInfo;
+const NUM_PAGES: usize = 3;
+
+/// Format the time since boot like "3d 04:12:55"
+fn format_uptime(secs: u64) -> String {
+    let days = secs / (24 * 60 * 60);
+    let hours = (secs / (60 * 60)) % 24;
+    let minutes = (secs / 60) % 60;
+    let seconds = secs % 60;
+
+    match days {
+        0 => format!("{hours:02}:{minutes:02}:{seconds:02}"),
+        d => format!("{d}d {hours:02}:{minutes:02}:{seconds:02}"),
+    }
+}
+
+/// Get the system uptime in seconds from the kernel
+fn uptime() -> Option<u64> {
+    let content = read_to_string("/proc/uptime").ok()?;
+    let secs: f64 = content.split_whitespace().next()?.parse().ok()?;
+
+    Some(secs as u64)
+}
+
+/// Show what exactly is running on this TAC, so that it can be answered
+/// without having to log in via SSH.
+pub struct SystemInfoScreen {
+    widgets: Vec<Box<dyn AnyWidget>>,
+    buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
+}
+
+impl SystemInfoScreen {
+    pub fn new() -> Self {
+        Self {
+            widgets: Vec::new(),
+            buttons_handle: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MountableScreen for SystemInfoScreen {
+    fn is_my_type(&self, screen: Screen) -> bool {
+        screen == SCREEN_TYPE
+    }
+
+    async fn mount(&mut self, ui: &Ui) {
+        let lang = ui.language();
+
+        draw_border(lang.tr("System Info"), SCREEN_TYPE, &ui.draw_target).await;
+
+        let page = Topic::anonymous(Some(0));
+
+        self.widgets.push(Box::new(DynamicWidget::locator(
+            ui.locator_dance.clone(),
+            ui.draw_target.clone(),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text_aligned(
+            page.clone(),
+            ui.draw_target.clone(),
+            Point::new(228, 17),
+            Box::new(|page: &usize| format!("{}/{NUM_PAGES}", page + 1)),
+            Alignment::Right,
+        )));
+
+        let tacd_version = ui.res.system.tacd_version.try_get().unwrap_or_default();
+        let uname = ui.res.system.uname.try_get();
+        let barebox = ui.res.system.barebox.try_get();
+        let booted_slot = ui.res.rauc.booted_slot.clone();
+        let slot_status = ui.res.rauc.slot_status.clone();
+        let page_content = page.clone();
+
+        // The time topic is updated periodically, which keeps the uptime
+        // and the information from RAUC current.
+        self.widgets.push(Box::new(DynamicWidget::text(
+            ui.res.adc.time.clone(),
+            ui.draw_target.clone(),
+            row_anchor(0),
+            Box::new(move |_: &Timestamp| {
+                let or_dash = |val: Option<String>| val.unwrap_or_else(|| "-".to_string());
+
+                match page_content.try_get().unwrap_or(0) {
+                    0 => {
+                        let bundle_version = slot_status.try_get().and_then(|slots| {
+                            slots
+                                .values()
+                                .find(|slot| {
+                                    slot.get("state").map(|s| s == "booted").unwrap_or(false)
+                                })
+                                .and_then(|slot| slot.get("bundle_version").cloned())
+                        });
+
+                        format!(
+                            "{}\n  {tacd_version}\n{}\n  {}\n{} {}\n{} {}",
+                            lang.tr("tacd version:"),
+                            lang.tr("Bundle version:"),
+                            or_dash(bundle_version),
+                            lang.tr("Booted slot:"),
+                            or_dash(booted_slot.try_get()),
+                            lang.tr("Uptime:"),
+                            or_dash(uptime().map(format_uptime)),
+                        )
+                    }
+                    1 => format!(
+                        "{}\n  {}\n{}\n  {}\n{}\n  {}",
+                        lang.tr("Serial number:"),
+                        or_dash(barebox.as_ref().and_then(|bb| bb.serial_number.clone())),
+                        lang.tr("Baseboard:"),
+                        or_dash(barebox.as_ref().map(|bb| bb.baseboard_release.clone())),
+                        lang.tr("Powerboard:"),
+                        or_dash(barebox.as_ref().map(|bb| bb.powerboard_release.clone())),
+                    ),
+                    _ => format!(
+                        "{}\n  {}\n{}\n  {}",
+                        lang.tr("Bootloader:"),
+                        or_dash(barebox.as_ref().map(|bb| bb.version.clone())),
+                        lang.tr("Kernel:"),
+                        or_dash(uname.as_ref().map(|un| un.release.clone())),
+                    ),
+                }
+            }),
+        )));
+
+        let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
+        let screen = ui.screen.clone();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                match ev {
+                    ButtonEvent::Release {
+                        btn: Button::Lower,
+                        dur: PressDuration::Short,
+                        src: _,
+                    } => page.modify(|page| Some((page.unwrap_or(0) + 1) % NUM_PAGES)),
+                    ButtonEvent::Scroll { steps, src: _ } => page.modify(|page| {
+                        let page = page.unwrap_or(0) as i32 + steps;
+                        Some(page.rem_euclid(NUM_PAGES as i32) as usize)
+                    }),
+                    ButtonEvent::Release {
+                        btn: Button::Upper,
+                        dur: PressDuration::Short,
+                        src: _,
+                    } => screen.set(SCREEN_TYPE.next()),
+                    _ => {}
+                }
+            }
+        });
+
+        self.buttons_handle = Some(buttons_handle);
+    }
+
+    async fn unmount(&mut self) {
+        if let Some(handle) = self.buttons_handle.take() {
+            handle.unsubscribe();
+        }
+
+        for mut widget in self.widgets.drain(..) {
+            widget.unmount().await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_uptime;
+
+    #[test]
+    fn uptime_format() {
+        assert_eq!(format_uptime(59), "00:00:59");
+        assert_eq!(format_uptime(3 * 3600 + 25 * 60 + 7), "03:25:07");
+        assert_eq!(format_uptime(2 * 86400 + 3600), "2d 01:00:00");
+    }
+}
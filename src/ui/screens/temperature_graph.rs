@@ -0,0 +1,201 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::spawn;
+use async_trait::async_trait;
+
+use embedded_graphics::prelude::*;
+
+use super::buttons::*;
+use super::widgets::*;
+use super::{draw_border, row_anchor, MountableScreen, Screen, Ui, UiResources};
+use crate::broker::{Native, SubscriptionHandle, Topic};
+
+const SCREEN_TYPE: Screen = Screen::TemperatureGraph;
+
+/// 200 samples at 18s intervals give a history of one hour
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(18);
+const NUM_SAMPLES: usize = 200;
+
+const GRAPH_WIDTH: u32 = NUM_SAMPLES as u32;
+const GRAPH_HEIGHT: u32 = 120;
+const OFFSET_GRAPH: Point = Point::new(12, 6);
+
+/// The graph is scaled to the recorded temperatures, rounded to this step
+/// and spanning at least MIN_SPAN degrees, so that small fluctuations do not
+/// look dramatic.
+const SCALE_STEP: f32 = 5.0;
+const MIN_SPAN: f32 = 10.0;
+
+/// The hottest SoC temperature seen in each sample interval, oldest first
+type History = Vec<f32>;
+
+pub struct TemperatureGraphScreen {
+    history: Arc<Topic<History>>,
+    widgets: Vec<Box<dyn AnyWidget>>,
+    buttons_handle: Option<SubscriptionHandle<ButtonEvent, Native>>,
+}
+
+/// Get the (lowest, highest) temperature shown in the graph
+fn scale(history: &History) -> (f32, f32) {
+    let min = history.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = history.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+    if history.is_empty() {
+        return (0.0, MIN_SPAN);
+    }
+
+    let low = (min / SCALE_STEP).floor() * SCALE_STEP;
+    let high = ((max / SCALE_STEP).ceil() * SCALE_STEP).max(low + MIN_SPAN);
+
+    (low, high)
+}
+
+impl TemperatureGraphScreen {
+    pub fn new(res: &UiResources) -> Self {
+        let history = Topic::anonymous(Some(Vec::new()));
+
+        let history_task = history.clone();
+        let (mut temperature_events, _) = res
+            .temperatures
+            .soc_temperature
+            .clone()
+            .subscribe_unbounded();
+
+        // Keep recording even if the screen is not shown, as thermal issues
+        // tend to happen while no one is looking.
+        // Only the peak temperature of each interval is kept, so that short
+        // spikes are not averaged away.
+        spawn(async move {
+            let mut samples = VecDeque::with_capacity(NUM_SAMPLES);
+            let mut interval_start = Instant::now();
+            let mut peak: Option<f32> = None;
+
+            while let Some(meas) = temperature_events.next().await {
+                peak = Some(peak.map_or(meas.value, |p| p.max(meas.value)));
+
+                if interval_start.elapsed() < SAMPLE_INTERVAL {
+                    continue;
+                }
+
+                if samples.len() >= NUM_SAMPLES {
+                    samples.pop_front();
+                }
+
+                samples.extend(peak.take());
+                history_task.set(samples.iter().cloned().collect());
+
+                interval_start = Instant::now();
+            }
+        });
+
+        Self {
+            history,
+            widgets: Vec::new(),
+            buttons_handle: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MountableScreen for TemperatureGraphScreen {
+    fn is_my_type(&self, screen: Screen) -> bool {
+        screen == SCREEN_TYPE
+    }
+
+    async fn mount(&mut self, ui: &Ui) {
+        let lang = ui.language();
+
+        draw_border(lang.tr("SoC Temperature"), SCREEN_TYPE, &ui.draw_target).await;
+
+        self.widgets.push(Box::new(DynamicWidget::locator(
+            ui.locator_dance.clone(),
+            ui.draw_target.clone(),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            self.history.clone(),
+            ui.draw_target.clone(),
+            row_anchor(0),
+            Box::new(
+                move |history: &History| match history.iter().copied().reduce(f32::max) {
+                    Some(peak) => format!("{}: {peak:4.1}°C", lang.tr("SoC peak")),
+                    None => lang.tr("Recording ...").to_string(),
+                },
+            ),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::graph(
+            self.history.clone(),
+            ui.draw_target.clone(),
+            row_anchor(0) + OFFSET_GRAPH,
+            GRAPH_WIDTH,
+            GRAPH_HEIGHT,
+            Box::new(|history: &History| {
+                let (low, high) = scale(history);
+                history
+                    .iter()
+                    .map(|t| Envelope::new((t - low) / (high - low)))
+                    .collect()
+            }),
+        )));
+
+        self.widgets.push(Box::new(DynamicWidget::text(
+            self.history.clone(),
+            ui.draw_target.clone(),
+            row_anchor(7),
+            Box::new(|history: &History| {
+                let (low, high) = scale(history);
+
+                format!("{low:.0}-{high:.0}°C / 1h")
+            }),
+        )));
+
+        let (mut button_events, buttons_handle) = ui.buttons.clone().subscribe_unbounded();
+        let screen = ui.screen.clone();
+
+        spawn(async move {
+            while let Some(ev) = button_events.next().await {
+                if let ButtonEvent::Release {
+                    btn: Button::Upper,
+                    dur: PressDuration::Short,
+                    src: _,
+                } = ev
+                {
+                    screen.set(SCREEN_TYPE.next())
+                }
+            }
+        });
+
+        self.buttons_handle = Some(buttons_handle);
+    }
+
+    async fn unmount(&mut self) {
+        if let Some(handle) = self.buttons_handle.take() {
+            handle.unsubscribe();
+        }
+
+        for mut widget in self.widgets.drain(..) {
+            widget.unmount().await
+        }
+    }
+}
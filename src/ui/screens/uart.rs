@@ -118,10 +118,10 @@ impl MountableScreen for UartScreen {
                     }
                     ButtonEvent::Release {
                         btn: Button::Upper,
-                        dur: _,
+                        dur: PressDuration::Short,
                         src: _,
                     } => screen.set(SCREEN_TYPE.next()),
-                    ButtonEvent::Press { btn: _, src: _ } => {}
+                    _ => {}
                 }
             }
         });
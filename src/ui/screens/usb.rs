@@ -20,23 +20,20 @@ use async_std::sync::Arc;
 use async_std::task::spawn;
 use async_trait::async_trait;
 
-use embedded_graphics::{
-    mono_font::MonoTextStyle, pixelcolor::BinaryColor, prelude::*, text::Text,
-};
+use embedded_graphics::{prelude::*, text::Alignment};
 
 use super::buttons::*;
+use super::layout::{Area, Width};
 use super::widgets::*;
-use super::{draw_border, row_anchor, MountableScreen, Screen, Ui};
+use super::{draw_border, MountableScreen, Screen, Ui};
 use crate::broker::{Native, SubscriptionHandle, Topic};
 use crate::measurement::Measurement;
 
 const SCREEN_TYPE: Screen = Screen::Usb;
-const CURRENT_LIMIT_PER_PORT: f32 = 0.5;
-const CURRENT_LIMIT_TOTAL: f32 = 0.7;
-const OFFSET_INDICATOR: Point = Point::new(92, -10);
-const OFFSET_BAR: Point = Point::new(122, -14);
+const WIDTH_LABEL: u32 = 92;
 const WIDTH_BAR: u32 = 90;
 const HEIGHT_BAR: u32 = 18;
+const SIZE_INDICATOR: Size = Size::new(10, 10);
 
 pub struct UsbScreen {
     highlighted: Arc<Topic<u8>>,
@@ -61,58 +58,76 @@ impl MountableScreen for UsbScreen {
     }
 
     async fn mount(&mut self, ui: &Ui) {
-        draw_border("USB Host", SCREEN_TYPE, &ui.draw_target).await;
+        let lang = ui.language();
+
+        draw_border(lang.tr("USB Host"), SCREEN_TYPE, &ui.draw_target).await;
 
         self.widgets.push(Box::new(DynamicWidget::locator(
             ui.locator_dance.clone(),
             ui.draw_target.clone(),
         )));
 
+        let total_max = ui.res.adc.usb_host_curr.info.max;
+
         let ports = [
             (
                 0,
                 "Port 1",
                 &ui.res.usb_hub.port1.powered,
-                &ui.res.adc.usb_host1_curr.topic,
+                &ui.res.adc.usb_host1_curr,
             ),
             (
                 1,
                 "Port 2",
                 &ui.res.usb_hub.port2.powered,
-                &ui.res.adc.usb_host2_curr.topic,
+                &ui.res.adc.usb_host2_curr,
             ),
             (
                 2,
                 "Port 3",
                 &ui.res.usb_hub.port3.powered,
-                &ui.res.adc.usb_host3_curr.topic,
+                &ui.res.adc.usb_host3_curr,
             ),
         ];
 
+        // Every line has the same columns: label, power indicator and
+        // current bar.
+        let line = |n| {
+            Area::content().line(n).columns([
+                Width::Pixels(WIDTH_LABEL),
+                Width::Pixels(SIZE_INDICATOR.width),
+                Width::Fill,
+                Width::Pixels(WIDTH_BAR),
+            ])
+        };
+
         {
+            let [total_label, _, _, _] = line(0);
             let mut draw_target = ui.draw_target.lock().await;
 
-            let ui_text_style: MonoTextStyle<BinaryColor> =
-                MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On);
-
-            Text::new("Total", row_anchor(0), ui_text_style)
-                .draw(&mut *draw_target)
-                .unwrap();
+            draw_text(
+                &mut draw_target,
+                lang.tr("Total"),
+                total_label.text_anchor(),
+                Alignment::Left,
+            );
         }
 
         self.widgets.push(Box::new(DynamicWidget::bar(
             ui.res.adc.usb_host_curr.topic.clone(),
             ui.draw_target.clone(),
-            row_anchor(0) + OFFSET_BAR,
+            line(0)[3].shrink_to_height(HEIGHT_BAR).top_left(),
             WIDTH_BAR,
             HEIGHT_BAR,
-            Box::new(|meas: &Measurement| meas.value / CURRENT_LIMIT_TOTAL),
+            Box::new(move |meas: &Measurement| meas.value / total_max),
         )));
 
         for (idx, name, status, current) in ports {
-            let anchor_text = row_anchor(idx + 2);
-            let anchor_indicator = anchor_text + OFFSET_INDICATOR;
-            let anchor_bar = anchor_text + OFFSET_BAR;
+            let current_max = current.info.max;
+            let [label, indicator, _, bar] = line(idx as u32 + 2);
+            let anchor_text = label.text_anchor();
+            let anchor_indicator = indicator.centered(SIZE_INDICATOR).top_left();
+            let anchor_bar = bar.shrink_to_height(HEIGHT_BAR).top_left();
 
             self.widgets.push(Box::new(DynamicWidget::text(
                 self.highlighted.clone(),
@@ -134,12 +149,12 @@ impl MountableScreen for UsbScreen {
             )));
 
             self.widgets.push(Box::new(DynamicWidget::bar(
-                current.clone(),
+                current.topic.clone(),
                 ui.draw_target.clone(),
                 anchor_bar,
                 WIDTH_BAR,
                 HEIGHT_BAR,
-                Box::new(|meas: &Measurement| meas.value / CURRENT_LIMIT_PER_PORT),
+                Box::new(move |meas: &Measurement| meas.value / current_max),
             )));
         }
 
@@ -174,10 +189,10 @@ impl MountableScreen for UsbScreen {
                     }
                     ButtonEvent::Release {
                         btn: Button::Upper,
-                        dur: _,
+                        dur: PressDuration::Short,
                         src: _,
                     } => screen.set(SCREEN_TYPE.next()),
-                    ButtonEvent::Press { btn: _, src: _ } => {}
+                    _ => {}
                 }
             }
         });
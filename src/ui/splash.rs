@@ -0,0 +1,117 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use async_std::sync::{Arc, Mutex};
+use embedded_graphics::{
+    image::Image,
+    mono_font::MonoTextStyle,
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::{Alignment, Text},
+};
+
+use super::logo::Logo;
+use super::widgets::UI_TEXT_FONT;
+use super::FramebufferDrawTarget;
+
+/// Shows the progress of the tacd startup on the display until the user
+/// interface is up, so that a slow boot does not look like a dead TAC.
+pub struct Splash {
+    draw_target: Arc<Mutex<FramebufferDrawTarget>>,
+    steps_done: Vec<&'static str>,
+    logo: Option<Logo>,
+}
+
+impl Splash {
+    pub async fn new() -> Self {
+        let this = Self {
+            draw_target: Arc::new(Mutex::new(FramebufferDrawTarget::new())),
+            steps_done: Vec::new(),
+            logo: Logo::load(),
+        };
+
+        this.draw().await;
+
+        this
+    }
+
+    /// Mark a step of the startup process as done and show it on the display
+    pub async fn step_done(&mut self, step: &'static str) {
+        self.steps_done.push(step);
+        self.draw().await;
+    }
+
+    /// Hand over the display to the user interface
+    pub(super) fn into_draw_target(self) -> Arc<Mutex<FramebufferDrawTarget>> {
+        self.draw_target
+    }
+
+    async fn draw(&self) {
+        let mut draw_target = self.draw_target.lock().await;
+        let text_style: MonoTextStyle<BinaryColor> =
+            MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On);
+
+        draw_target.clear();
+
+        // Show the user provided logo instead of the list of steps (if there
+        // is one) and only mention the most recent step below it.
+        if let Some(logo) = &self.logo {
+            let raw = logo.image();
+            let size = raw.bounding_box().size;
+            let top_left = Point::new(120 - size.width as i32 / 2, 120 - size.height as i32 / 2);
+
+            Image::new(&raw, top_left).draw(&mut *draw_target).unwrap();
+
+            if let Some(step) = self.steps_done.last() {
+                Text::with_alignment(
+                    &format!("OK {step}"),
+                    Point::new(120, 232),
+                    text_style,
+                    Alignment::Center,
+                )
+                .draw(&mut *draw_target)
+                .unwrap();
+            }
+
+            draw_target.flush();
+
+            return;
+        }
+
+        Text::with_alignment(
+            "Starting up ...",
+            Point::new(120, 30),
+            text_style,
+            Alignment::Center,
+        )
+        .draw(&mut *draw_target)
+        .unwrap();
+
+        for (row, step) in self.steps_done.iter().enumerate() {
+            Text::new(
+                &format!("OK {step}"),
+                Point::new(8, 72 + (row as i32) * 20),
+                text_style,
+            )
+            .draw(&mut *draw_target)
+            .unwrap();
+        }
+
+        // There is no user interface yet that would do this periodically
+        draw_target.flush();
+    }
+}
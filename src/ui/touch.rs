@@ -0,0 +1,271 @@
+// This file is part of tacd, the LXA TAC system daemon
+// Copyright (C) 2023 Pengutronix e.K.
+//
+// This program is free software; you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation; either version 2 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program; if not, write to the Free Software Foundation, Inc.,
+// 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
+
+use std::time::SystemTime;
+
+use async_std::prelude::*;
+use async_std::sync::Arc;
+use async_std::task::{spawn, spawn_blocking};
+use embedded_graphics::prelude::Point;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use super::buttons::{Button, ButtonEvent, PressDuration, Source};
+use super::draw_fb::Rotation;
+use super::widgets::TouchRegions;
+use crate::broker::Topic;
+
+/// Movement (in pixels) between touching and releasing the screen above
+/// which a touch is treated as swipe instead of a tap.
+const SWIPE_DISTANCE: i32 = 40;
+
+/// Width and height of the touch panel, which covers the whole display
+const PANEL_SIZE: i32 = 240;
+
+#[cfg(feature = "demo_mode")]
+mod evd {
+    use evdev::FetchEventsSynced;
+    pub use evdev::{AbsoluteAxisType, EventType, InputEventKind, Key};
+
+    pub struct Device {}
+
+    impl Device {
+        pub fn open(_path: &'static str) -> Result<Self, String> {
+            Err("There is no touch panel in demo mode".to_string())
+        }
+
+        pub fn fetch_events(&mut self) -> Result<FetchEventsSynced, ()> {
+            loop {
+                std::thread::park()
+            }
+        }
+    }
+
+    pub fn axis_range(_dev: &Device, _axis: AbsoluteAxisType) -> Option<(i32, i32)> {
+        None
+    }
+}
+
+#[cfg(not(feature = "demo_mode"))]
+mod evd {
+    pub use evdev::*;
+
+    /// Get the minimum and maximum value the touch controller reports on `axis`
+    pub fn axis_range(dev: &Device, axis: AbsoluteAxisType) -> Option<(i32, i32)> {
+        let info = dev.get_abs_state().ok()?[axis.0 as usize];
+
+        Some((info.minimum, info.maximum))
+    }
+}
+
+use evd::{axis_range, AbsoluteAxisType, Device, EventType, InputEventKind, Key};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum TouchEvent {
+    Tap {
+        x: i32,
+        y: i32,
+        dur: PressDuration,
+        #[serde(skip)]
+        src: Source,
+    },
+    Swipe {
+        direction: SwipeDirection,
+        #[serde(skip)]
+        src: Source,
+    },
+}
+
+impl TouchEvent {
+    fn from_gesture(start: Point, end: Point, duration: std::time::Duration) -> Self {
+        let delta = end - start;
+
+        if delta.x.abs().max(delta.y.abs()) < SWIPE_DISTANCE {
+            return Self::Tap {
+                x: start.x,
+                y: start.y,
+                dur: PressDuration::from_duration(duration),
+                src: Source::Local,
+            };
+        }
+
+        let direction = match (delta.x.abs() > delta.y.abs(), delta.x > 0, delta.y > 0) {
+            (true, true, _) => SwipeDirection::Right,
+            (true, false, _) => SwipeDirection::Left,
+            (false, _, true) => SwipeDirection::Down,
+            (false, _, false) => SwipeDirection::Up,
+        };
+
+        Self::Swipe {
+            direction,
+            src: Source::Local,
+        }
+    }
+}
+
+/// Scale a raw value reported by the touch controller to a pixel position
+/// on the panel.
+fn to_pixels(value: i32, range: Option<(i32, i32)>) -> i32 {
+    let pixels = match range {
+        Some((min, max)) if max > min => (value - min) * (PANEL_SIZE - 1) / (max - min),
+        _ => value,
+    };
+
+    pixels.clamp(0, PANEL_SIZE - 1)
+}
+
+/// Map a point on the touch panel to the (possibly rotated) coordinate
+/// system the screen content is drawn in.
+fn to_logical(rotation: Rotation, point: Point) -> Point {
+    let max = PANEL_SIZE - 1;
+
+    match rotation {
+        Rotation::Rotate0 => point,
+        Rotation::Rotate90 => Point::new(point.y, max - point.x),
+        Rotation::Rotate180 => Point::new(max - point.x, max - point.y),
+        Rotation::Rotate270 => Point::new(max - point.y, point.x),
+    }
+}
+
+/// Spawn a thread that blockingly reads touch input, detects taps and
+/// swipes and pushes them into a broker framework topic.
+///
+/// Not all hardware revisions have a touch panel, so it is not an error
+/// if there is no input device at `path`.
+pub fn handle_touch(
+    path: &'static str,
+    topic: Arc<Topic<TouchEvent>>,
+    rotation: Arc<Topic<Rotation>>,
+) {
+    let mut device = match Device::open(path) {
+        Ok(dev) => dev,
+        Err(e) => {
+            info!("Not handling touch input: {e}");
+            return;
+        }
+    };
+
+    let x_range = axis_range(&device, AbsoluteAxisType::ABS_X);
+    let y_range = axis_range(&device, AbsoluteAxisType::ABS_Y);
+
+    spawn_blocking(move || {
+        let mut position = Point::zero();
+        let mut start: Option<(SystemTime, Option<Point>)> = None;
+
+        loop {
+            for ev in device.fetch_events().unwrap() {
+                match (ev.event_type(), ev.kind()) {
+                    (EventType::ABSOLUTE, InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X)) => {
+                        position.x = to_pixels(ev.value(), x_range)
+                    }
+                    (EventType::ABSOLUTE, InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y)) => {
+                        position.y = to_pixels(ev.value(), y_range)
+                    }
+                    (EventType::SYNCHRONIZATION, _) => {
+                        // The position of the touch is only complete once
+                        // the whole report was received.
+                        if let Some((_, pos @ None)) = &mut start {
+                            *pos = Some(position);
+                        }
+                    }
+                    (EventType::KEY, InputEventKind::Key(Key::BTN_TOUCH)) => {
+                        if ev.value() != 0 {
+                            start = Some((ev.timestamp(), None));
+                            continue;
+                        }
+
+                        if let Some((start_time, Some(start_pos))) = start.take() {
+                            let rotation = rotation.try_get().unwrap_or(Rotation::Rotate0);
+                            let duration = ev.timestamp().duration_since(start_time);
+
+                            if let Ok(duration) = duration {
+                                topic.set(TouchEvent::from_gesture(
+                                    to_logical(rotation, start_pos),
+                                    to_logical(rotation, position),
+                                    duration,
+                                ));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    });
+}
+
+/// Translate touch events into actions.
+///
+/// Local taps on a widget with a touch region trigger the action of that
+/// widget. Injected taps can not trigger them, as the widgets do not know
+/// whether an action is allowed from remote.
+/// Everything else is translated into the button event with the closest
+/// meaning: taps act like the lower button (toggle / perform an action),
+/// swiping sideways switches to the next screen like the upper button does
+/// and swiping up or down opens the menu.
+pub fn translate(
+    touch: &Arc<Topic<TouchEvent>>,
+    regions: &TouchRegions,
+    buttons: &Arc<Topic<ButtonEvent>>,
+) {
+    let (mut touch_events, _) = touch.clone().subscribe_unbounded();
+    let regions = regions.clone();
+    let buttons = buttons.clone();
+
+    spawn(async move {
+        while let Some(ev) = touch_events.next().await {
+            let button_event = match ev {
+                TouchEvent::Tap { x, y, dur, src } => {
+                    if let Source::Local = src {
+                        if let Some(on_tap) = regions.hit(Point::new(x, y)) {
+                            on_tap();
+                            continue;
+                        }
+                    }
+
+                    ButtonEvent::Release {
+                        btn: Button::Lower,
+                        dur,
+                        src,
+                    }
+                }
+                TouchEvent::Swipe { direction, src } => {
+                    let dur = match direction {
+                        SwipeDirection::Left | SwipeDirection::Right => PressDuration::Short,
+                        SwipeDirection::Up | SwipeDirection::Down => PressDuration::Long,
+                    };
+
+                    ButtonEvent::Release {
+                        btn: Button::Upper,
+                        dur,
+                        src,
+                    }
+                }
+            };
+
+            buttons.set(button_event);
+        }
+    });
+}
@@ -15,25 +15,144 @@
 // with this program; if not, write to the Free Software Foundation, Inc.,
 // 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use async_std::future::timeout;
 use async_std::prelude::*;
 use async_std::sync::{Arc, Mutex};
-use async_std::task::{spawn, JoinHandle};
+use async_std::task::{sleep, spawn, JoinHandle};
 use async_trait::async_trait;
 use embedded_graphics::{
-    mono_font::{ascii::FONT_10X20, MonoFont, MonoTextStyle},
+    mono_font::{
+        iso_8859_1::{FONT_10X20, FONT_6X10},
+        MonoFont, MonoTextStyle,
+    },
     pixelcolor::BinaryColor,
     prelude::*,
-    primitives::{Circle, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    primitives::{Circle, Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
     text::{Alignment, Text},
 };
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
+use super::buttons::{Button, ButtonEvent, PressDuration};
+use super::draw_fb::Overlay;
+use super::i18n::Language;
 use super::FramebufferDrawTarget;
 use crate::broker::{Native, SubscriptionHandle, Topic};
+use crate::measurement::Measurement;
 
 pub const UI_TEXT_FONT: MonoFont = FONT_10X20;
 
+/// Font used in the large text theme. Its glyphs are drawn at twice their
+/// size, resulting in 12x20 pixel characters with two pixel wide strokes.
+/// The line height stays the same as for `UI_TEXT_FONT`, so that the layout
+/// of the screens does not change.
+const LARGE_TEXT_FONT: MonoFont = FONT_6X10;
+const LARGE_TEXT_SCALE: i32 = 2;
+
+/// Time between two steps of a scrolling marquee text
+const MARQUEE_TICK: Duration = Duration::from_millis(100);
+
+/// Space between the end of a scrolling marquee text and its next start
+const MARQUEE_GAP: u32 = 40;
+
+/// The area in the middle of the screen that dialogs are drawn to
+const DIALOG_AREA: Rectangle = Rectangle::new(Point::new(10, 60), Size::new(220, 120));
+
+/// Draw `text` in the UI font using the style selected by the display theme
+/// and return its bounding box.
+pub fn draw_text(
+    target: &mut FramebufferDrawTarget,
+    text: &str,
+    anchor: Point,
+    alignment: Alignment,
+) -> Rectangle {
+    let large = target.theme().large_text;
+
+    draw_text_sized(target, text, anchor, alignment, large)
+}
+
+fn draw_text_sized<D>(
+    target: &mut D,
+    text: &str,
+    anchor: Point,
+    alignment: Alignment,
+    large: bool,
+) -> Rectangle
+where
+    D: DrawTarget<Color = BinaryColor>,
+    D::Error: core::fmt::Debug,
+{
+    if large {
+        let style: MonoTextStyle<BinaryColor> =
+            MonoTextStyle::new(&LARGE_TEXT_FONT, BinaryColor::On);
+
+        let text = Text::with_alignment(text, anchor, style, alignment);
+        text.draw(&mut Scaled { target, anchor }).unwrap();
+
+        let bounding_box = text.bounding_box();
+
+        Rectangle::new(
+            anchor + (bounding_box.top_left - anchor) * LARGE_TEXT_SCALE,
+            bounding_box.size * LARGE_TEXT_SCALE as u32,
+        )
+    } else {
+        let style: MonoTextStyle<BinaryColor> = MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On);
+
+        let text = Text::with_alignment(text, anchor, style, alignment);
+        text.draw(target).unwrap();
+
+        text.bounding_box()
+    }
+}
+
+/// The width of a single line of text in pixels
+fn line_width(text: &str, large: bool) -> u32 {
+    let char_width = match large {
+        true => LARGE_TEXT_FONT.character_size.width * LARGE_TEXT_SCALE as u32,
+        false => UI_TEXT_FONT.character_size.width,
+    };
+
+    text.chars().count() as u32 * char_width
+}
+
+/// A draw target that scales everything drawn to it up by `LARGE_TEXT_SCALE`,
+/// growing away from `anchor`.
+struct Scaled<'a, D> {
+    target: &'a mut D,
+    anchor: Point,
+}
+
+impl<D: DrawTarget> Dimensions for Scaled<'_, D> {
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+
+impl<D: DrawTarget> DrawTarget for Scaled<'_, D> {
+    type Color = D::Color;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let anchor = self.anchor;
+
+        let pixels = pixels.into_iter().flat_map(move |Pixel(point, color)| {
+            let top_left = anchor + (point - anchor) * LARGE_TEXT_SCALE;
+
+            (0..LARGE_TEXT_SCALE).flat_map(move |y| {
+                (0..LARGE_TEXT_SCALE).map(move |x| Pixel(top_left + Point::new(x, y), color))
+            })
+        });
+
+        self.target.draw_iter(pixels)
+    }
+}
+
 pub enum IndicatorState {
     On,
     Off,
@@ -53,8 +172,127 @@ impl<T, U> TextFormatFn<T> for U where U: Fn(&T) -> String {}
 pub trait FractionFormatFn<T>: Fn(&T) -> f32 {}
 impl<T, U> FractionFormatFn<T> for U where U: Fn(&T) -> f32 {}
 
+pub trait GraphFormatFn<T>: Fn(&T) -> Vec<Envelope> {}
+impl<T, U> GraphFormatFn<T> for U where U: Fn(&T) -> Vec<Envelope> {}
+
+/// The smallest and largest value shown in a single column of a graph
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Envelope {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Envelope {
+    pub fn new(value: f32) -> Self {
+        Self {
+            min: value,
+            max: value,
+        }
+    }
+
+    pub fn add(&mut self, value: f32) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Apply `f` to both ends of the envelope, e.g. to scale it to the
+    /// range of a graph. `f` must be monotonically increasing.
+    pub fn map<F: Fn(f32) -> f32>(self, f: F) -> Self {
+        Self {
+            min: f(self.min),
+            max: f(self.max),
+        }
+    }
+}
+
+/// Extend the (lowest, highest) pixel `span` of a graph column so that it
+/// touches the span of the column before it, keeping the graph connected.
+fn connect_span(span: (i32, i32), prev: Option<(i32, i32)>) -> (i32, i32) {
+    match prev {
+        Some((prev_low, prev_high)) => (span.0.min(prev_high), span.1.max(prev_low)),
+        None => span,
+    }
+}
+
+/// The area on the screen a widget has last drawn to
+type WidgetArea = Arc<StdMutex<Option<Rectangle>>>;
+
+struct TouchRegion {
+    area: WidgetArea,
+    on_tap: Arc<dyn Fn() + Sync + Send>,
+}
+
+/// The areas of the currently shown widgets that react to taps on a
+/// touch panel
+#[derive(Clone, Default)]
+pub struct TouchRegions {
+    regions: Arc<StdMutex<Vec<TouchRegion>>>,
+}
+
+impl TouchRegions {
+    fn add(&self, area: WidgetArea, on_tap: Arc<dyn Fn() + Sync + Send>) {
+        self.regions
+            .lock()
+            .unwrap()
+            .push(TouchRegion { area, on_tap });
+    }
+
+    fn remove(&self, area: &WidgetArea) {
+        self.regions
+            .lock()
+            .unwrap()
+            .retain(|region| !Arc::ptr_eq(&region.area, area));
+    }
+
+    /// Get the action of the widget that was drawn at `point` (if any)
+    pub fn hit(&self, point: Point) -> Option<Arc<dyn Fn() + Sync + Send>> {
+        self.regions
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|region| {
+                region
+                    .area
+                    .lock()
+                    .unwrap()
+                    .map(|area| area.contains(point))
+                    .unwrap_or(false)
+            })
+            .map(|region| region.on_tap.clone())
+    }
+}
+
+/// Wait until a widget that was last drawn in frame `drawn_in` may be drawn
+/// again.
+///
+/// The frames are shared by all widgets and advanced centrally by the
+/// draw target (see `FramebufferDrawTarget::flush()`), so that the screen
+/// is redrawn at most once per `FRAME_INTERVAL` no matter how often the
+/// topics the widgets show are updated.
+/// Returns immediately if the widget was not drawn in the current frame yet,
+/// so that e.g. reactions to button presses are not delayed.
+async fn next_frame(target: &Mutex<FramebufferDrawTarget>, drawn_in: Option<u64>) {
+    loop {
+        let wait = {
+            let target = target.lock().await;
+
+            if drawn_in != Some(target.frame()) {
+                break;
+            }
+
+            target.until_next_frame()
+        };
+
+        // The frame may be written out a little late, so do not spin while
+        // waiting for it.
+        sleep(wait.max(Duration::from_millis(1))).await;
+    }
+}
+
 pub struct DynamicWidget<T: Sync + Send + 'static> {
     handles: Option<(SubscriptionHandle<T, Native>, JoinHandle<()>)>,
+    area: WidgetArea,
+    touch_regions: Option<TouchRegions>,
 }
 
 impl<T: Serialize + DeserializeOwned + Send + Sync + Clone + 'static> DynamicWidget<T> {
@@ -72,17 +310,30 @@ impl<T: Serialize + DeserializeOwned + Send + Sync + Clone + 'static> DynamicWid
     ///   redrawn. The `draw_fn` should return a rectangle corresponding to the
     ///   bounding box it has drawn to.
     ///   The widget system takes care of clearing this area before redrawing.
+    ///   Only the pixels that differ after redrawing are written to the
+    ///   display, once per frame (see `FramebufferDrawTarget::flush()`).
     pub fn new(
         topic: Arc<Topic<T>>,
         target: Arc<Mutex<FramebufferDrawTarget>>,
         draw_fn: Box<dyn DrawFn<T> + Sync + Send>,
     ) -> Self {
         let (mut rx, sub_handle) = topic.subscribe_unbounded();
+        let area = WidgetArea::default();
+        let area_task = area.clone();
 
         let join_handle = spawn(async move {
             let mut prev_bb: Option<Rectangle> = None;
+            let mut drawn_in = None;
+
+            while let Some(mut val) = rx.next().await {
+                next_frame(&target, drawn_in).await;
+
+                // Drop the values the topic went through while waiting for
+                // the next frame and only draw the most recent one.
+                while let Ok(newer) = rx.try_recv() {
+                    val = newer;
+                }
 
-            while let Some(val) = rx.next().await {
                 let mut target = target.lock().await;
 
                 if let Some(bb) = prev_bb.take() {
@@ -93,14 +344,26 @@ impl<T: Serialize + DeserializeOwned + Send + Sync + Clone + 'static> DynamicWid
                 }
 
                 prev_bb = draw_fn(&val, &mut *target);
+                drawn_in = Some(target.frame());
+                *area_task.lock().unwrap() = prev_bb;
             }
         });
 
         Self {
             handles: Some((sub_handle, join_handle)),
+            area,
+            touch_regions: None,
         }
     }
 
+    /// Call `on_tap` when the area the widget has drawn to is tapped on a
+    /// touch panel.
+    pub fn on_tap(mut self, regions: &TouchRegions, on_tap: Box<dyn Fn() + Sync + Send>) -> Self {
+        regions.add(self.area.clone(), on_tap.into());
+        self.touch_regions = Some(regions.clone());
+        self
+    }
+
     /// Draw a self-updating status bar with a given `width` and `height`
     ///
     /// The `format_fn` should return a value between 0.0 and 1.0 indicating
@@ -138,6 +401,57 @@ impl<T: Serialize + DeserializeOwned + Send + Sync + Clone + 'static> DynamicWid
         )
     }
 
+    /// Draw a self-updating graph with a given `width` and `height`
+    ///
+    /// The `format_fn` should return a list of envelopes between 0.0 and 1.0,
+    /// one per horizontal pixel, with the oldest value first.
+    /// Each column is drawn as a vertical line spanning its envelope, so that
+    /// short spikes stay visible.
+    /// If there are more values than pixels only the newest ones are shown.
+    pub fn graph(
+        topic: Arc<Topic<T>>,
+        target: Arc<Mutex<FramebufferDrawTarget>>,
+        anchor: Point,
+        width: u32,
+        height: u32,
+        format_fn: Box<dyn GraphFormatFn<T> + Sync + Send>,
+    ) -> Self {
+        Self::new(
+            topic,
+            target,
+            Box::new(move |msg, target| {
+                let values = format_fn(msg);
+                let skip = values.len().saturating_sub(width as usize);
+                let bottom = anchor.y + (height as i32) - 1;
+                let to_px = |val: f32| (val.clamp(0.0, 1.0) * ((height - 1) as f32)) as i32;
+
+                let bounding = Rectangle::new(anchor, Size::new(width, height));
+
+                bounding
+                    .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+                    .draw(target)
+                    .unwrap();
+
+                let mut prev = None;
+
+                for (x, envelope) in values.iter().skip(skip).enumerate() {
+                    let span = (to_px(envelope.min), to_px(envelope.max));
+                    let (low, high) = connect_span(span, prev);
+                    let x = anchor.x + x as i32;
+
+                    prev = Some(span);
+
+                    Line::new(Point::new(x, bottom - low), Point::new(x, bottom - high))
+                        .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+                        .draw(target)
+                        .unwrap();
+                }
+
+                Some(bounding)
+            }),
+        )
+    }
+
     /// Draw an indicator bubble in an "On", "Off" or "Error" state
     pub fn indicator(
         topic: Arc<Topic<T>>,
@@ -218,13 +532,8 @@ impl<T: Serialize + DeserializeOwned + Send + Sync + Clone + 'static> DynamicWid
             Box::new(move |msg, target| {
                 let text = format_fn(msg);
 
-                let ui_text_style: MonoTextStyle<BinaryColor> =
-                    MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On);
-
                 if !text.is_empty() {
-                    let text = Text::with_alignment(&text, anchor, ui_text_style, alignment);
-                    text.draw(target).unwrap();
-                    Some(text.bounding_box())
+                    Some(draw_text(target, &text, anchor, alignment))
                 } else {
                     None
                 }
@@ -251,6 +560,122 @@ impl<T: Serialize + DeserializeOwned + Send + Sync + Clone + 'static> DynamicWid
     ) -> Self {
         Self::text_aligned(topic, target, anchor, format_fn, Alignment::Center)
     }
+
+    /// Draw self-updating left aligned text that scrolls horizontally with
+    /// `speed` pixels per second if it is wider than `width` pixels.
+    ///
+    /// Text that fits into `width` is shown like it would be by `text`.
+    pub fn marquee(
+        topic: Arc<Topic<T>>,
+        target: Arc<Mutex<FramebufferDrawTarget>>,
+        anchor: Point,
+        width: u32,
+        speed: u32,
+        format_fn: Box<dyn TextFormatFn<T> + Sync + Send>,
+    ) -> Self {
+        let (mut rx, sub_handle) = topic.subscribe_unbounded();
+        let widget_area = WidgetArea::default();
+        let widget_area_task = widget_area.clone();
+
+        let step = (speed * MARQUEE_TICK.as_millis() as u32 / 1000).max(1);
+
+        let join_handle = spawn(async move {
+            let mut prev_bb: Option<Rectangle> = None;
+            let mut text = String::new();
+            let mut text_width = 0;
+            let mut offset = 0;
+            let mut drawn_in = None;
+
+            loop {
+                // Only wake up periodically if the text has to be scrolled
+                let ev = if text_width > width {
+                    timeout(MARQUEE_TICK, rx.next()).await
+                } else {
+                    Ok(rx.next().await)
+                };
+
+                let mut val = match ev {
+                    Ok(Some(val)) => Some(val),
+                    Ok(None) => break,
+                    Err(_) => None,
+                };
+
+                next_frame(&target, drawn_in).await;
+
+                // Only show the most recent value the topic went through
+                // while waiting for the next frame.
+                while let Ok(newer) = rx.try_recv() {
+                    val = Some(newer);
+                }
+
+                match val {
+                    Some(val) => {
+                        text = format_fn(&val);
+                        offset = 0;
+                    }
+                    None => offset = (offset + step) % (text_width + MARQUEE_GAP),
+                }
+
+                let mut target = target.lock().await;
+                drawn_in = Some(target.frame());
+
+                // The theme may have changed since the last step,
+                // so the width of the text is re-calculated every time.
+                let large = target.theme().large_text;
+                text_width = line_width(&text, large);
+                offset %= text_width + MARQUEE_GAP;
+
+                if let Some(bb) = prev_bb.take() {
+                    bb.into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+                        .draw(&mut *target)
+                        .unwrap();
+                }
+
+                if text.is_empty() {
+                    continue;
+                }
+
+                let start = anchor - Point::new(offset as i32, 0);
+
+                // Only draw inside the area of the widget. The large font has
+                // the same line height as the normal one, so the bounding box
+                // of the normal font tells us the vertical extent of both.
+                let area = {
+                    let bb = Text::new(
+                        &text,
+                        start,
+                        MonoTextStyle::new(&UI_TEXT_FONT, BinaryColor::On),
+                    )
+                    .bounding_box();
+
+                    Rectangle::new(
+                        Point::new(anchor.x, bb.top_left.y),
+                        Size::new(width, bb.size.height),
+                    )
+                };
+
+                let mut clipped = target.clipped(&area);
+
+                draw_text_sized(&mut clipped, &text, start, Alignment::Left, large);
+
+                // Draw a second copy of the text that scrolls in from the
+                // right while the first one scrolls out to the left.
+                if text_width > width {
+                    let next = start + Point::new((text_width + MARQUEE_GAP) as i32, 0);
+                    draw_text_sized(&mut clipped, &text, next, Alignment::Left, large);
+                }
+
+                prev_bb = Some(area);
+                *widget_area_task.lock().unwrap() = prev_bb;
+            }
+        });
+
+        Self {
+            handles: Some((sub_handle, join_handle)),
+            area: widget_area,
+            touch_regions: None,
+        }
+    }
 }
 
 impl DynamicWidget<i32> {
@@ -283,6 +708,304 @@ impl DynamicWidget<i32> {
     }
 }
 
+impl DynamicWidget<Measurement> {
+    /// Draw a `label` and the self-updating value (in `unit`) followed by
+    /// a horizontal bar that extends to the right edge of the screen.
+    ///
+    /// The bar is empty for values at or below `min` and completely filled
+    /// for values at or above `max`.
+    pub fn bar_graph(
+        topic: Arc<Topic<Measurement>>,
+        target: Arc<Mutex<FramebufferDrawTarget>>,
+        anchor: Point,
+        label: &'static str,
+        unit: &'static str,
+        min: f32,
+        max: f32,
+    ) -> Self {
+        Self::new(
+            topic,
+            target,
+            Box::new(move |meas, target| {
+                // Keep the width of the text constant so the bar does not move
+                let text = format!("{label} {:>3.0}{unit}", meas.value);
+                let label_bb = draw_text(target, &text, anchor, Alignment::Left);
+
+                let bar_left = label_bb.top_left.x + label_bb.size.width as i32 + 8;
+                let bar_top = anchor.y - 14;
+                let width = (230 - bar_left).max(0) as u32;
+
+                let val = ((meas.value - min) / (max - min)).clamp(0.0, 1.0);
+                let fill_width = ((width as f32) * val) as u32;
+
+                let bounding = Rectangle::new(Point::new(bar_left, bar_top), Size::new(width, 18));
+                let filled = Rectangle::new(bounding.top_left, Size::new(fill_width, 18));
+
+                bounding
+                    .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+                    .draw(target)
+                    .unwrap();
+
+                filled
+                    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+                    .draw(target)
+                    .unwrap();
+
+                Some(Rectangle::with_corners(
+                    label_bb.top_left,
+                    bounding.bottom_right().unwrap_or(bounding.top_left),
+                ))
+            }),
+        )
+    }
+}
+
+/// A modal dialog asking the user to confirm an action before it is
+/// performed.
+///
+/// The dialog is drawn on top of the screen as long as `question` contains
+/// a question. The screen passes button events to `ConfirmDialog::answer()`
+/// while a question is pending.
+pub struct ConfirmDialog {
+    widget: DynamicWidget<Option<String>>,
+    target: Arc<Mutex<FramebufferDrawTarget>>,
+}
+
+impl ConfirmDialog {
+    pub fn new(
+        question: Arc<Topic<Option<String>>>,
+        target: Arc<Mutex<FramebufferDrawTarget>>,
+        lang: Language,
+    ) -> Self {
+        let widget = DynamicWidget::new(
+            question,
+            target.clone(),
+            Box::new(
+                move |question: &Option<String>, target: &mut FramebufferDrawTarget| {
+                    target
+                        .set_overlay_area(Overlay::Dialog, question.as_ref().map(|_| DIALOG_AREA));
+
+                    let question = question.as_ref()?;
+                    let large = target.theme().large_text;
+                    let mut overlay = target.overlay(Overlay::Dialog);
+
+                    DIALOG_AREA
+                        .into_styled(
+                            PrimitiveStyleBuilder::new()
+                                .stroke_color(BinaryColor::On)
+                                .stroke_width(2)
+                                .fill_color(BinaryColor::Off)
+                                .build(),
+                        )
+                        .draw(&mut overlay)
+                        .unwrap();
+
+                    let text = format!("{question}\n\n{}", lang.tr("Lower: yes\nUpper: no"));
+
+                    draw_text_sized(
+                        &mut overlay,
+                        &text,
+                        DIALOG_AREA.top_left + Point::new(110, 30),
+                        Alignment::Center,
+                        large,
+                    );
+
+                    // The dialog is drawn to an overlay, so there is nothing
+                    // to clear on the screen below it.
+                    None
+                },
+            ),
+        );
+
+        Self { widget, target }
+    }
+
+    /// Answer a pending question using a button event.
+    ///
+    /// Returns `None` if no question is pending or if the event does not
+    /// answer it, otherwise whether the action was confirmed.
+    /// Releasing the lower button confirms the action, the upper one
+    /// cancels it.
+    pub fn answer(question: &Topic<Option<String>>, ev: &ButtonEvent) -> Option<bool> {
+        question.try_get().flatten()?;
+
+        let confirmed = match ev {
+            ButtonEvent::Release {
+                btn: Button::Lower, ..
+            } => true,
+            ButtonEvent::Release { .. } => false,
+            ButtonEvent::Press { .. } | ButtonEvent::Chord { .. } | ButtonEvent::Scroll { .. } => {
+                return None
+            }
+        };
+
+        question.set(None);
+
+        Some(confirmed)
+    }
+}
+
+/// A value that is currently being edited in a `ValueEditor`
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ValueEdit {
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+}
+
+impl ValueEdit {
+    /// Start editing `value`, which may be changed between `min` and `max`
+    /// in increments of `step`.
+    pub fn new(value: f32, min: f32, max: f32, step: f32) -> Self {
+        Self {
+            value,
+            min,
+            max,
+            step,
+        }
+        .stepped(0)
+    }
+
+    /// Move the value by `steps` increments, while staying on the grid of
+    /// valid values between `min` and `max`.
+    fn stepped(self, steps: i32) -> Self {
+        let current = ((self.value - self.min) / self.step).round();
+        let last = ((self.max - self.min) / self.step).floor();
+        let idx = (current + steps as f32).clamp(0.0, last);
+
+        Self {
+            value: self.min + idx * self.step,
+            ..self
+        }
+    }
+}
+
+/// The result of passing a button event to `ValueEditor::input()`
+pub enum EditResult {
+    /// The value is still being edited
+    Editing,
+    /// The user accepted the value
+    Accepted(f32),
+    /// The user cancelled editing
+    Cancelled,
+}
+
+/// A dialog to change a numeric setting directly on the device.
+///
+/// The dialog is shown as long as `edit` contains a value.
+/// The screen passes button events to `ValueEditor::input()` while editing.
+pub struct ValueEditor {
+    widget: DynamicWidget<Option<ValueEdit>>,
+    target: Arc<Mutex<FramebufferDrawTarget>>,
+}
+
+impl ValueEditor {
+    pub fn new(
+        edit: Arc<Topic<Option<ValueEdit>>>,
+        target: Arc<Mutex<FramebufferDrawTarget>>,
+        lang: Language,
+        title: &'static str,
+        format_fn: Box<dyn Fn(f32) -> String + Sync + Send>,
+    ) -> Self {
+        let widget = DynamicWidget::new(
+            edit,
+            target.clone(),
+            Box::new(
+                move |edit: &Option<ValueEdit>, target: &mut FramebufferDrawTarget| {
+                    target.set_overlay_area(Overlay::Dialog, edit.as_ref().map(|_| DIALOG_AREA));
+
+                    let edit = edit.as_ref()?;
+                    let large = target.theme().large_text;
+                    let mut overlay = target.overlay(Overlay::Dialog);
+
+                    DIALOG_AREA
+                        .into_styled(
+                            PrimitiveStyleBuilder::new()
+                                .stroke_color(BinaryColor::On)
+                                .stroke_width(2)
+                                .fill_color(BinaryColor::Off)
+                                .build(),
+                        )
+                        .draw(&mut overlay)
+                        .unwrap();
+
+                    let text = format!(
+                        "{}\n< {} >\n\n{}",
+                        lang.tr(title),
+                        format_fn(edit.value),
+                        lang.tr("Lower: +  Upper: -\nHold lower to save"),
+                    );
+
+                    draw_text_sized(
+                        &mut overlay,
+                        &text,
+                        DIALOG_AREA.top_left + Point::new(110, 30),
+                        Alignment::Center,
+                        large,
+                    );
+
+                    None
+                },
+            ),
+        );
+
+        Self { widget, target }
+    }
+
+    /// Handle a button event while a value is being edited.
+    ///
+    /// Returns `None` if no value is being edited.
+    /// A short press on the lower/upper button increments/decrements the
+    /// value, as does turning a rotary encoder. Holding the lower button
+    /// accepts the value and holding the upper button cancels editing.
+    pub fn input(edit: &Topic<Option<ValueEdit>>, ev: &ButtonEvent) -> Option<EditResult> {
+        let current = edit.try_get().flatten()?;
+
+        let res = match ev {
+            ButtonEvent::Release {
+                btn: Button::Lower,
+                dur: PressDuration::Short,
+                ..
+            } => {
+                edit.set(Some(current.stepped(1)));
+                EditResult::Editing
+            }
+            ButtonEvent::Release {
+                btn: Button::Upper,
+                dur: PressDuration::Short,
+                ..
+            } => {
+                edit.set(Some(current.stepped(-1)));
+                EditResult::Editing
+            }
+            ButtonEvent::Release {
+                btn: Button::Lower,
+                dur: PressDuration::Long,
+                ..
+            } => {
+                edit.set(None);
+                EditResult::Accepted(current.value)
+            }
+            ButtonEvent::Release {
+                btn: Button::Upper,
+                dur: PressDuration::Long,
+                ..
+            } => {
+                edit.set(None);
+                EditResult::Cancelled
+            }
+            ButtonEvent::Scroll { steps, .. } => {
+                edit.set(Some(current.stepped(*steps)));
+                EditResult::Editing
+            }
+            ButtonEvent::Press { .. } | ButtonEvent::Chord { .. } => EditResult::Editing,
+        };
+
+        Some(res)
+    }
+}
+
 #[async_trait]
 pub trait AnyWidget: Send + Sync {
     async fn unmount(&mut self);
@@ -295,9 +1018,70 @@ impl<T: Sync + Send + Serialize + DeserializeOwned + 'static> AnyWidget for Dyna
     /// This has to be async, which is why it can not be performed by
     /// implementing the Drop trait.
     async fn unmount(&mut self) {
+        if let Some(regions) = self.touch_regions.take() {
+            regions.remove(&self.area);
+        }
+
         if let Some((sh, jh)) = self.handles.take() {
             sh.unsubscribe();
             jh.await;
         }
     }
 }
+
+#[async_trait]
+impl AnyWidget for ConfirmDialog {
+    async fn unmount(&mut self) {
+        self.widget.unmount().await;
+
+        // Do not leave a dialog behind that no one is going to answer
+        self.target
+            .lock()
+            .await
+            .set_overlay_area(Overlay::Dialog, None);
+    }
+}
+
+#[async_trait]
+impl AnyWidget for ValueEditor {
+    async fn unmount(&mut self) {
+        self.widget.unmount().await;
+
+        self.target
+            .lock()
+            .await
+            .set_overlay_area(Overlay::Dialog, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{connect_span, ValueEdit};
+
+    #[test]
+    fn value_edit_steps() {
+        let edit = ValueEdit::new(1.0, 0.5, 2.0, 0.5);
+
+        assert_eq!(edit.stepped(1).value, 1.5);
+        assert_eq!(edit.stepped(-1).value, 0.5);
+        assert_eq!(edit.stepped(-5).value, 0.5);
+        assert_eq!(edit.stepped(5).value, 2.0);
+
+        // Values set via the API may not be on the grid of steps
+        assert_eq!(ValueEdit::new(1.3, 0.5, 2.0, 0.5).value, 1.5);
+        assert_eq!(ValueEdit::new(9.0, 0.5, 2.0, 0.5).value, 2.0);
+    }
+
+    #[test]
+    fn graph_spans() {
+        // The first column is drawn as is
+        assert_eq!(connect_span((10, 12), None), (10, 12));
+
+        // Columns above or below the previous one are extended towards it
+        assert_eq!(connect_span((10, 10), Some((50, 60))), (10, 50));
+        assert_eq!(connect_span((50, 55), Some((10, 12))), (12, 55));
+
+        // Overlapping columns are left alone
+        assert_eq!(connect_span((10, 20), Some((15, 25))), (10, 20));
+    }
+}
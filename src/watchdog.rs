@@ -21,6 +21,7 @@ use std::time::Duration;
 use async_std::task::sleep;
 
 use crate::dut_power::TickReader;
+use crate::safe_state;
 
 #[cfg(any(test, feature = "demo_mode"))]
 mod sd {
@@ -91,6 +92,10 @@ impl Watchdog {
             }
 
             notify(false, [(STATE_WATCHDOG, "1")].iter())?;
+
+            // The watchdog is fed every half timeout. Leave the safe state
+            // thread a quarter of the timeout to act if we miss a feeding.
+            safe_state::watchdog_fed(self.interval * 3 / 2);
         }
     }
 }
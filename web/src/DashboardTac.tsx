@@ -22,10 +22,16 @@ import Container from "@cloudscape-design/components/container";
 import SpaceBetween from "@cloudscape-design/components/space-between";
 import ColumnLayout from "@cloudscape-design/components/column-layout";
 
-import { MqttBox, MqttToggle, MqttButton } from "./MqttComponents";
-import { RaucContainer } from "./TacComponents";
+import {
+  MqttBox,
+  MqttToggle,
+  MqttButton,
+  HistoryChart,
+} from "./MqttComponents";
+import { FleetContainer, RaucContainer } from "./TacComponents";
+import { useMqttAction, useMqttHandler } from "./mqtt";
 
-import { useEffect, useState } from "react";
+import { useCallback, useRef } from "react";
 
 type Measurement = {
   ts: number;
@@ -55,16 +61,80 @@ type Bootloader = {
   powerboard_timestamp: number;
 };
 
-export default function DashboardTac() {
-  const [counter, setCounter] = useState(0);
+type FrameUpdate = {
+  width: number;
+  height: number;
+  keyframe: boolean;
+  rows: Array<[number, string]>;
+};
+
+type TouchEvent = {
+  Tap: { x: number; y: number; dur: "Short" | "Long" };
+};
+
+function LiveDisplay() {
+  const canvas = useRef<HTMLCanvasElement>(null);
+  const synced = useRef(false);
+  const sendTouch = useMqttAction<TouchEvent>("/v1/tac/display/touch");
+
+  const applyUpdate = useCallback((update: FrameUpdate | undefined) => {
+    const ctx = canvas.current?.getContext("2d");
+
+    if (update === undefined || ctx === undefined || ctx === null) {
+      synced.current = false;
+      return;
+    }
+
+    // Updates that are not keyframes only make sense on top of the
+    // previous frame, so wait for a keyframe after (re-)connecting.
+    if (!update.keyframe && !synced.current) {
+      return;
+    }
 
-  useEffect(() => {
-    let i = 0;
-    let interval = window.setInterval(() => setCounter(i++), 500);
+    ctx.canvas.width = update.width;
+    ctx.canvas.height = update.height;
+    synced.current = true;
 
-    return () => window.clearInterval(interval);
+    const row = ctx.createImageData(update.width, 1);
+
+    for (const [y, encoded] of update.rows) {
+      const bits = atob(encoded);
+
+      for (let x = 0; x < update.width; x++) {
+        const on = (bits.charCodeAt(x >> 3) >> (7 - (x & 7))) & 1;
+        const idx = 4 * x;
+
+        row.data[idx] = row.data[idx + 1] = row.data[idx + 2] = on * 255;
+        row.data[idx + 3] = 255;
+      }
+
+      ctx.putImageData(row, 0, y);
+    }
   }, []);
 
+  useMqttHandler<FrameUpdate>("/v1/tac/display/frames", applyUpdate);
+
+  // Clicks on the display are forwarded as taps on the touch panel
+  function handleClick(ev: React.MouseEvent<HTMLCanvasElement>) {
+    const rect = ev.currentTarget.getBoundingClientRect();
+    const x = ((ev.clientX - rect.left) * ev.currentTarget.width) / rect.width;
+    const y = ((ev.clientY - rect.top) * ev.currentTarget.height) / rect.height;
+
+    sendTouch({ Tap: { x: Math.floor(x), y: Math.floor(y), dur: "Short" } });
+  }
+
+  return (
+    <canvas
+      className="live-display"
+      ref={canvas}
+      width={240}
+      height={240}
+      onClick={handleClick}
+    />
+  );
+}
+
+export default function DashboardTac() {
   return (
     <SpaceBetween size="m">
       <Header variant="h1" description="Observe the LXA TAC system status">
@@ -148,6 +218,23 @@ export default function DashboardTac() {
         />
       </Container>
 
+      <Container
+        header={
+          <Header
+            variant="h2"
+            description="See how the SoC temperature developed over the last day"
+          >
+            Temperature History
+          </Header>
+        }
+      >
+        <HistoryChart
+          path="/v1/tac/temperatures/soc/history"
+          resolution="Minute"
+          span={24 * 60 * 60}
+        />
+      </Container>
+
       <Container
         header={
           <Header
@@ -159,11 +246,7 @@ export default function DashboardTac() {
         }
       >
         <ColumnLayout columns={3} variant="text-grid">
-          <img
-            className="live-display"
-            src={"/v1/tac/display/content?c=" + counter}
-            alt="Live view"
-          />
+          <LiveDisplay />
           <SpaceBetween size="m">
             <Box>
               <Box variant="awsui-key-label">Upper Button</Box>
@@ -202,6 +285,9 @@ export default function DashboardTac() {
             <Box>
               <Box variant="awsui-key-label">Locator</Box>
               <MqttToggle topic="/v1/tac/display/locator">Locator</MqttToggle>
+              <MqttToggle topic="/v1/tac/display/locator_flash">
+                Flash screen
+              </MqttToggle>
             </Box>
           </SpaceBetween>
         </ColumnLayout>
@@ -252,6 +338,7 @@ export default function DashboardTac() {
           </Box>
         </ColumnLayout>
       </Container>
+      <FleetContainer />
     </SpaceBetween>
   );
 }
@@ -296,3 +296,62 @@ export function MqttChart(props: MqttChartProps) {
     </ApiPicker>
   );
 }
+
+type HistoryBucket = {
+  start: number;
+  min: number;
+  max: number;
+  mean: number;
+  count: number;
+};
+
+interface HistoryChartProps {
+  path: string;
+  resolution: "Second" | "Minute" | "Hour";
+  span: number;
+}
+
+export function HistoryChart(props: HistoryChartProps) {
+  const [values, setValues] = useState<Array<Point>>([]);
+
+  useEffect(() => {
+    function update() {
+      const from = Math.floor(Date.now() / 1000) - props.span;
+
+      fetch(`${props.path}?resolution=${props.resolution}&from=${from}`)
+        .then((response) => response.json())
+        .then((buckets: Array<HistoryBucket>) =>
+          setValues(
+            buckets.map((b) => ({ x: new Date(b.start * 1000), y: b.mean }))
+          )
+        )
+        .catch(() => setValues([]));
+    }
+
+    update();
+
+    const interval = setInterval(update, 10000);
+
+    return () => clearInterval(interval);
+  }, [props.path, props.resolution, props.span]);
+
+  let series: MixedLineBarChartProps.ChartSeries<Date> = {
+    type: "line",
+    title: "mean",
+    data: values,
+  };
+
+  return (
+    <LineChart
+      series={[series]}
+      visibleSeries={[series]}
+      xScaleType="time"
+      i18nStrings={{
+        xTickFormatter: (e) => e.toLocaleTimeString(),
+      }}
+      height={200}
+      hideFilter
+      hideLegend
+    />
+  );
+}
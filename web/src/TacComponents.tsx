@@ -26,6 +26,7 @@ import Form from "@cloudscape-design/components/form";
 import FormField from "@cloudscape-design/components/form-field";
 import Header from "@cloudscape-design/components/header";
 import Input from "@cloudscape-design/components/input";
+import Link from "@cloudscape-design/components/link";
 import ProgressBar from "@cloudscape-design/components/progress-bar";
 import SpaceBetween from "@cloudscape-design/components/space-between";
 import Spinner from "@cloudscape-design/components/spinner";
@@ -319,3 +320,58 @@ export function RaucContainer() {
     </Container>
   );
 }
+
+type DiscoveredTac = {
+  hostname: string;
+  address: string;
+  version: string | null;
+};
+
+export function FleetContainer() {
+  const tacs = useMqttSubscription<Array<DiscoveredTac>>(
+    "/v1/tac/discovery/tacs"
+  );
+
+  return (
+    <Container
+      header={
+        <Header
+          variant="h2"
+          description="Other TACs found on the network via mDNS"
+        >
+          Fleet
+        </Header>
+      }
+    >
+      <Cards
+        cardDefinition={{
+          header: (e) => (
+            <Link
+              external
+              externalIconAriaLabel="Opens in a new tab"
+              href={`http://${e.address}/`}
+            >
+              {e.hostname}
+            </Link>
+          ),
+          sections: [
+            {
+              id: "address",
+              header: "IP Address",
+              content: (e) => e.address,
+            },
+            {
+              id: "version",
+              header: "Version",
+              content: (e) => e.version ?? "-",
+            },
+          ],
+        }}
+        cardsPerRow={[{ cards: 1 }, { minWidth: 500, cards: 3 }]}
+        items={tacs ?? []}
+        loadingText="Loading resources"
+        empty={<Box textAlign="center">No other TACs found</Box>}
+      />
+    </Container>
+  );
+}
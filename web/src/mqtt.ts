@@ -141,6 +141,31 @@ export function useMqttAction<T>(topic: string) {
   return setPayload;
 }
 
+// Call handler for every message received on topic, instead of just
+// rendering the most recent one, e.g. for messages that build on one another.
+export function useMqttHandler<T>(
+  topic: string,
+  handler: (msg: T | undefined) => void
+) {
+  useEffect(() => {
+    function handleMessage(message: Message | undefined) {
+      if (message !== undefined) {
+        handler(JSON.parse(message.payloadString));
+      } else {
+        handler(undefined);
+      }
+    }
+
+    let unsub = subscribe(topic, handleMessage);
+
+    if (retained[topic] !== undefined) {
+      handleMessage(retained[topic]);
+    }
+
+    return unsub;
+  }, [topic, handler]);
+}
+
 type History<M> = {
   current: Array<M>;
 };